@@ -0,0 +1,21 @@
+export PATH=$PATH:$HOME/.cargo/bin
+export PATH=$PATH:$HOME/.wasmer/bin
+export WAPM_DISABLE_COLOR=true
+rm -f wapm.lock
+rm -f wapm.toml
+rm -rf wapm_packages
+chmod +x end-to-end-tests/quiet.sh
+echo "RUNNING SCRIPT..."
+./end-to-end-tests/quiet.sh &> /tmp/quiet-out.txt
+echo "GENERATED OUTPUT:"
+cat /tmp/quiet-out.txt
+echo "COMPARING..."
+diff -Bba end-to-end-tests/quiet.txt /tmp/quiet-out.txt
+export OUT=$?
+if ( [ -d globals ] || [ -f wapm.log ] ) then { echo "globals or wapm.log found; these files should not be in the working directory"; exit 1; } else { true; } fi
+rm -f wapm.lock
+rm -f wapm.toml
+rm -rf wapm_packages
+rm -f /tmp/quiet-out.txt
+if ( [ $OUT -ne 0 ] ) then { cat $HOME/.wasmer/wapm.log; } fi
+exit $OUT
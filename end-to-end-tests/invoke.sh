@@ -0,0 +1,9 @@
+#!/bin/sh
+
+# add.wasm exports `add(i32, i32) -> i32`
+printf '\000asm\001\000\000\000\001\007\001\140\002\177\177\001\177\003\002\001\000\007\007\001\003add\000\000\012\011\001\007\000\040\000\040\001\152\013' > add.wasm
+echo '[package]\nname="test"\nversion="0.0.0"\ndescription="this is a test"\n[[module]]\nname="add-module"\nsource="add.wasm"\n[[command]]\nname="add"\nmodule="add-module"' > wapm.toml
+wapm run add --invoke add 2 3
+# the arguments are checked against the export's params before it is called
+wapm run add --invoke add 2 3.5
+rm add.wasm
@@ -0,0 +1,9 @@
+#!/bin/sh
+
+echo '[package]\nname="test"\nversion="0.0.0"\ndescription="this is a test"' > wapm.toml
+# a successful install prints nothing to stdout
+wapm install --quiet 2> /dev/null
+echo "install exit code: $?"
+# a failing install still reports the error on stderr
+wapm install --global --quiet 2>&1 > /dev/null
+echo "global install exit code: $?"
@@ -0,0 +1,196 @@
+//! A content addressed cache of the interfaces extracted from wasm modules, so that the
+//! interface of the same module isn't extracted again every time it is needed.
+//!
+//! Entries are keyed by the SHA-256 of the module's bytes, so a module that changed gets a new entry.
+
+use crate::validate::{interface_from_wasm, validate_wasm_and_report_errors, WasmValidationError};
+use crate::{Export, Import, Interface};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::fs;
+use std::path::PathBuf;
+
+/// The stored form of an interface. `Interface` skips serializing an unset start function,
+/// which a non self-describing format like bincode can't read back.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    name: Option<String>,
+    imports: Vec<Import>,
+    exports: Vec<Export>,
+    start: Option<String>,
+}
+
+impl From<&Interface> for Entry {
+    fn from(interface: &Interface) -> Entry {
+        Entry {
+            name: interface.name.clone(),
+            imports: interface.imports.values().cloned().collect(),
+            exports: interface.exports.values().cloned().collect(),
+            start: interface.start.clone(),
+        }
+    }
+}
+
+impl From<Entry> for Interface {
+    fn from(entry: Entry) -> Interface {
+        Interface {
+            name: entry.name,
+            imports: entry
+                .imports
+                .into_iter()
+                .map(|import| (import.get_key(), import))
+                .collect(),
+            exports: entry
+                .exports
+                .into_iter()
+                .map(|export| (export.get_key(), export))
+                .collect(),
+            start: entry.start,
+        }
+    }
+}
+
+/// Caches the interfaces extracted from modules and the modules known to satisfy an interface,
+/// as files in a directory. Entries are never invalidated, since a changed module or interface
+/// has a different hash; clearing the directory clears the cache.
+#[derive(Debug)]
+pub struct ContractCache {
+    directory: PathBuf,
+    extractions: Cell<usize>,
+    validations: Cell<usize>,
+}
+
+impl ContractCache {
+    /// A cache that stores its entries in `directory`. The directory is created when the first
+    /// entry is written.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> ContractCache {
+        ContractCache {
+            directory: directory.into(),
+            extractions: Cell::new(0),
+            validations: Cell::new(0),
+        }
+    }
+
+    /// The interface the wasm module `wasm` implies, like `Interface::from_wasm`. It's only
+    /// extracted from the module if it isn't cached yet.
+    pub fn from_wasm(&self, wasm: &[u8]) -> Result<Interface, WasmValidationError> {
+        let path = self.directory.join(format!("{}.bin", sha256_hex(wasm)));
+        let cached = fs::read(&path)
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<Entry>(&bytes).ok());
+        if let Some(entry) = cached {
+            return Ok(entry.into());
+        }
+
+        self.extractions.set(self.extractions.get() + 1);
+        let interface = interface_from_wasm(wasm)?;
+        // if the entry can't be written the interface is extracted again next time
+        if let Ok(bytes) = bincode::serialize(&Entry::from(&interface)) {
+            let _ = fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, bytes));
+        }
+        Ok(interface)
+    }
+
+    /// Check that the wasm module `wasm` satisfies `interface`, like
+    /// `Interface::validate_module`. A module that satisfied the same interface before isn't
+    /// checked again; failures aren't cached, so they are reported the same way every time.
+    pub fn validate_module(
+        &self,
+        interface: &Interface,
+        wasm: &[u8],
+    ) -> Result<(), WasmValidationError> {
+        let path = self.directory.join(format!(
+            "{}-{}.ok",
+            sha256_hex(wasm),
+            sha256_hex(interface.canonical_text().as_bytes())
+        ));
+        if path.exists() {
+            return Ok(());
+        }
+
+        self.validations.set(self.validations.get() + 1);
+        validate_wasm_and_report_errors(wasm, interface)?;
+        let _ = fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, b""));
+        Ok(())
+    }
+
+    /// How many times an interface had to be extracted from a module because it wasn't cached
+    pub fn extractions(&self) -> usize {
+        self.extractions.get()
+    }
+
+    /// How many times a module had to be validated because it wasn't known to satisfy the
+    /// interface yet
+    pub fn validations(&self) -> usize {
+        self.validations.get()
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cache::ContractCache;
+    use crate::parser;
+
+    const LOGGER: &str = r#"(module
+        (import "env" "log" (func (param i32)))
+        (func (export "run") (param i32) (result i32) local.get 0))"#;
+
+    #[test]
+    fn identical_modules_are_only_extracted_once() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let wasm = wat::parse_str(LOGGER).unwrap();
+        let cache = ContractCache::new(tmp_dir.path().join("contracts"));
+
+        let interface = cache.from_wasm(&wasm).unwrap();
+        assert_eq!(cache.extractions(), 1);
+        assert_eq!(cache.from_wasm(&wasm.clone()).unwrap(), interface);
+        assert_eq!(cache.extractions(), 1);
+        assert!(interface.contains_import("env", "log"));
+
+        // the entries outlive the cache
+        let reopened = ContractCache::new(tmp_dir.path().join("contracts"));
+        assert_eq!(reopened.from_wasm(&wasm).unwrap(), interface);
+        assert_eq!(reopened.extractions(), 0);
+
+        // a changed module has a different hash
+        let changed = wat::parse_str(LOGGER.replace("\"run\"", "\"start\"")).unwrap();
+        let changed_interface = cache.from_wasm(&changed).unwrap();
+        assert_eq!(cache.extractions(), 2);
+        assert!(changed_interface.contains_export("start"));
+    }
+
+    #[test]
+    fn modules_that_satisfied_an_interface_are_only_validated_once() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let wasm = wat::parse_str(LOGGER).unwrap();
+        let cache = ContractCache::new(tmp_dir.path());
+
+        let satisfied = parser::parse_interface(
+            r#"(interface
+                (func (import "env" "log") (param i32))
+                (func (export "run") (param i32) (result i32)))"#,
+        )
+        .unwrap();
+        cache.validate_module(&satisfied, &wasm).unwrap();
+        cache.validate_module(&satisfied, &wasm).unwrap();
+        assert_eq!(cache.validations(), 1);
+
+        let violated = parser::parse_interface(
+            r#"(interface (func (export "run") (param i64) (result i32)))"#,
+        )
+        .unwrap();
+        let error = cache.validate_module(&violated, &wasm).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            violated.validate_module(&wasm).unwrap_err().to_string()
+        );
+        // failures are checked again
+        assert!(cache.validate_module(&violated, &wasm).is_err());
+        assert_eq!(cache.validations(), 3);
+    }
+}
@@ -10,18 +10,138 @@ pub struct Interface {
     pub name: Option<String>,
     /// Things that the module can import
     pub imports: HashMap<(String, String), Import>,
-    /// Things that the module must export
-    pub exports: HashMap<String, Export>,
+    /// Things that the module must export, keyed by kind and name so that a func and a global
+    /// can share a name
+    pub exports: HashMap<(ExportKind, String), Export>,
+    /// The export that the module's start function must be
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
 }
 
 impl Interface {
-    pub fn merge(&self, other: Interface) -> Result<Interface, String> {
-        let mut base = self.clone();
+    /// Merge `other` into a copy of this interface. Imports with the same namespace and name,
+    /// exports with the same kind and name and the start functions have to be equal, otherwise
+    /// they conflict. A func and a global exported under the same name don't conflict, both are
+    /// kept.
+    pub fn merge(&self, other: Interface) -> Result<Interface, MergeError> {
+        self.clone().merge_into(other)
+    }
+
+    /// Merge like [`Interface::merge`], but reuse `self` instead of cloning it, which is cheaper
+    /// when merging many interfaces in a loop
+    pub fn merge_into(self, other: Interface) -> Result<Interface, MergeError> {
+        self.merge_into_with(other, |base, other| {
+            if base == other {
+                Some(base.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Merge like [`Interface::merge_into`], with `merge_import` comparing the shared imports
+    /// unless `other` is a copy of `self`
+    fn merge_into_with<F>(self, other: Interface, merge_import: F) -> Result<Interface, MergeError>
+    where
+        F: Fn(&Import, &Import) -> Option<Import>,
+    {
+        // merging with a copy of itself is common and always succeeds. Comparing the counts
+        // first keeps most other merges from walking the entries twice
+        if self.import_count() == other.import_count()
+            && self.export_count() == other.export_count()
+            && self == other
+        {
+            return Ok(self);
+        }
+        self.merge_imports_with(other, merge_import)
+    }
+
+    /// Merge like [`Interface::merge`], but accept func imports whose results are compatible.
+    ///
+    /// Two func imports with the same namespace and name are compatible when their params are
+    /// exactly equal and the result list of one is a prefix of the result list of the other,
+    /// e.g. `(result)` and `(result i32)`, or `(result i32)` and `(result i32 i64)`. The merged
+    /// import has the longer result list. Everything else still has to match exactly: different
+    /// params, results that differ at any position (`(result i32)` and `(result i64 i32)`),
+    /// globals and all exports are conflicts.
+    pub fn merge_compatible(&self, other: Interface) -> Result<Interface, MergeError> {
+        self.clone()
+            .merge_imports_with(other, Import::merge_compatible)
+    }
+
+    /// Merge like [`Interface::merge`], but fail if the two interfaces import anything from the
+    /// same namespace, even when the imports themselves would merge
+    pub fn merge_disjoint_namespaces(&self, other: Interface) -> Result<Interface, MergeError> {
+        let namespaces: HashSet<&str> = self
+            .imports
+            .keys()
+            .map(|(namespace, _)| namespace.as_str())
+            .collect();
+        let mut shared: Vec<&str> = other
+            .imports
+            .keys()
+            .map(|(namespace, _)| namespace.as_str())
+            .filter(|namespace| namespaces.contains(namespace))
+            .collect();
+        shared.sort_unstable();
+        if let Some(namespace) = shared.first() {
+            return Err(MergeError::SharedNamespace {
+                namespace: namespace.to_string(),
+            });
+        }
+        self.merge(other)
+    }
+
+    /// Merge like [`Interface::merge`], but resolve conflicts on the keys in `allow` in favor of
+    /// `self` instead of returning an error. Imports are listed in `allow` as `namespace.name`
+    /// and exports by their name, which covers both a func and a global with that name.
+    pub fn merge_with_allowlist(
+        &self,
+        other: Interface,
+        allow: &HashSet<String>,
+    ) -> Result<Interface, MergeError> {
+        let mut other = other;
+        other.imports.retain(|(namespace, name), import| {
+            !(allow.contains(&format!("{}.{}", namespace, name))
+                && self
+                    .imports
+                    .get(&(namespace.clone(), name.clone()))
+                    .map_or(false, |existing| existing != import))
+        });
+        other.exports.retain(|key, export| {
+            !(allow.contains(&key.1)
+                && self
+                    .exports
+                    .get(key)
+                    .map_or(false, |existing| existing != export))
+        });
+        self.merge(other)
+    }
+
+    /// Merge with `merge_import` deciding what two imports with the same key merge into, or
+    /// `None` if they conflict
+    fn merge_imports_with<F>(
+        self,
+        other: Interface,
+        merge_import: F,
+    ) -> Result<Interface, MergeError>
+    where
+        F: Fn(&Import, &Import) -> Option<Import>,
+    {
+        let mut base = self;
 
         for (key, val) in other.imports.into_iter() {
             if base.imports.contains_key(&key) {
-                if val != base.imports[&key] {
-                    return Err(format!("Conflict detected: the import \"{}\" \"{}\" was found but the definitions were different: {:?} {:?}", &key.0, &key.1, base.imports[&key], val));
+                match merge_import(&base.imports[&key], &val) {
+                    Some(merged) => {
+                        base.imports.insert(key, merged);
+                    }
+                    None => {
+                        return Err(MergeError::ImportConflict {
+                            existing: Box::new(base.imports[&key].clone()),
+                            conflicting: Box::new(val),
+                        })
+                    }
                 }
             } else {
                 let res = base.imports.insert(key, val);
@@ -32,16 +152,543 @@ impl Interface {
         for (key, val) in other.exports.into_iter() {
             if base.exports.contains_key(&key) {
                 if val != base.exports[&key] {
-                    return Err(format!("Conflict detected: the key {} was found in exports but the definitions were different: {:?} {:?}", key, base.exports[&key], val));
+                    return Err(MergeError::ExportConflict {
+                        existing: Box::new(base.exports[&key].clone()),
+                        conflicting: Box::new(val),
+                    });
                 }
             } else {
                 let res = base.exports.insert(key, val);
                 debug_assert!(res.is_none());
             }
         }
+
+        if let Some(start) = other.start {
+            match base.start {
+                Some(ref existing) if *existing != start => {
+                    return Err(MergeError::StartConflict {
+                        existing: existing.clone(),
+                        conflicting: start,
+                    });
+                }
+                _ => base.start = Some(start),
+            }
+        }
         Ok(base)
     }
 
+    /// Derive the interface that the wasm module `wasm` implies
+    #[cfg(feature = "validation")]
+    pub fn from_wasm(wasm: &[u8]) -> Result<Interface, crate::validate::WasmValidationError> {
+        crate::validate::interface_from_wasm(wasm)
+    }
+
+    /// Assemble a module in the wasm text format and derive the interface it implies
+    #[cfg(all(feature = "validation", feature = "wat"))]
+    pub fn from_wat(wat: &str) -> Result<Interface, String> {
+        let wasm = wat::parse_str(wat).map_err(|e| e.to_string())?;
+        Self::from_wasm(&wasm).map_err(|e| e.to_string())
+    }
+
+    /// Read an interface written in the JSON format described in [`crate::json`]
+    #[cfg(feature = "json")]
+    pub fn from_interface_json(json: &str) -> Result<Interface, String> {
+        crate::json::parse_interface_json(json)
+    }
+
+    /// TypeScript declarations for the interface, see [`crate::typescript`]
+    pub fn to_typescript_dts(&self) -> String {
+        crate::typescript::interface_to_dts(self)
+    }
+
+    /// Check that the wasm module `wasm` satisfies the interface
+    #[cfg(feature = "validation")]
+    pub fn validate_module(&self, wasm: &[u8]) -> Result<(), crate::validate::WasmValidationError> {
+        crate::validate::validate_wasm_and_report_errors(wasm, self)
+    }
+
+    /// Returns true if the interface has the import `namespace` `name`
+    pub fn contains_import(&self, namespace: &str, name: &str) -> bool {
+        self.imports
+            .contains_key(&Import::format_key(namespace, name))
+    }
+
+    /// Returns true if the interface has a func or global export `name`
+    pub fn contains_export(&self, name: &str) -> bool {
+        self.get_export(name).is_some()
+    }
+
+    /// Get the import `namespace` `name`
+    pub fn get_import(&self, namespace: &str, name: &str) -> Option<&Import> {
+        self.imports.get(&Import::format_key(namespace, name))
+    }
+
+    /// Get the export `name`. When a func and a global are both exported as `name`, this is
+    /// the func; use [`Interface::get_export_of_kind`] to get the global.
+    pub fn get_export(&self, name: &str) -> Option<&Export> {
+        self.get_export_of_kind(ExportKind::Func, name)
+            .or_else(|| self.get_export_of_kind(ExportKind::Global, name))
+    }
+
+    /// Get the export of kind `kind` named `name`
+    pub fn get_export_of_kind(&self, kind: ExportKind, name: &str) -> Option<&Export> {
+        self.exports.get(&Export::format_key(kind, name))
+    }
+
+    /// Remove the import `namespace` `name`, returning it if it was present
+    pub fn remove_import(&mut self, namespace: &str, name: &str) -> Option<Import> {
+        self.imports.remove(&Import::format_key(namespace, name))
+    }
+
+    /// Remove the export `name`, returning it if it was present. Like
+    /// [`Interface::get_export`], a func is removed before a global with the same name.
+    pub fn remove_export(&mut self, name: &str) -> Option<Export> {
+        self.exports
+            .remove(&Export::format_key(ExportKind::Func, name))
+            .or_else(|| {
+                self.exports
+                    .remove(&Export::format_key(ExportKind::Global, name))
+            })
+    }
+
+    /// The number of imports
+    pub fn import_count(&self) -> usize {
+        self.imports.len()
+    }
+
+    /// The number of exports
+    pub fn export_count(&self) -> usize {
+        self.exports.len()
+    }
+
+    /// Iterate over the imports, in no particular order
+    pub fn imports_iter(&self) -> impl Iterator<Item = &Import> {
+        self.imports.values()
+    }
+
+    /// Iterate over the exports, in no particular order
+    pub fn exports_iter(&self) -> impl Iterator<Item = &Export> {
+        self.exports.values()
+    }
+
+    /// Group the imports by namespace. Within each namespace the imports are sorted by name,
+    /// so the grouping is the same every time.
+    pub fn imports_by_namespace(&self) -> HashMap<String, Vec<&Import>> {
+        let mut namespaces: HashMap<String, Vec<&Import>> = HashMap::new();
+        for ((namespace, _), import) in self.imports.iter() {
+            namespaces
+                .entry(namespace.clone())
+                .or_default()
+                .push(import);
+        }
+        for imports in namespaces.values_mut() {
+            imports.sort_by_key(|import| import.get_key());
+        }
+        namespaces
+    }
+
+    /// How often each type occurs in the params and results of the funcs and as the type of the
+    /// globals, over both imports and exports. Types that don't occur are left out.
+    pub fn type_usage(&self) -> HashMap<WasmType, usize> {
+        let mut usage = HashMap::new();
+        let import_types = self.imports.values().flat_map(|import| match import {
+            Import::Func { params, result, .. } => params.iter().chain(result.iter()).collect(),
+            Import::Global { var_type, .. } => vec![var_type],
+        });
+        let export_types = self.exports.values().flat_map(|export| match export {
+            Export::Func { params, result, .. } => params.iter().chain(result.iter()).collect(),
+            Export::Global { var_type, .. } => vec![var_type],
+        });
+        for wasm_type in import_types.chain(export_types) {
+            *usage.entry(wasm_type.clone()).or_insert(0) += 1;
+        }
+        usage
+    }
+
+    /// Which WASI snapshot the interface's imports target
+    pub fn wasi_version(&self) -> WasiVersion {
+        let unstable = self
+            .imports
+            .keys()
+            .any(|(namespace, _)| namespace == WASI_UNSTABLE_NAMESPACE);
+        let snapshot_preview1 = self
+            .imports
+            .keys()
+            .any(|(namespace, _)| namespace == WASI_SNAPSHOT_PREVIEW1_NAMESPACE);
+        match (unstable, snapshot_preview1) {
+            (false, false) => WasiVersion::NotWasi,
+            (true, false) => WasiVersion::Unstable,
+            (false, true) => WasiVersion::SnapshotPreview1,
+            (true, true) => WasiVersion::Mixed,
+        }
+    }
+
+    /// Get a copy of the interface without the imports from the WASI namespaces, leaving the
+    /// imports that the host has to provide itself
+    pub fn without_wasi(&self) -> Interface {
+        self.filter(
+            |import| {
+                let (namespace, _) = import.get_key();
+                !WASI_NAMESPACES.contains(&namespace.as_str())
+            },
+            |_| true,
+        )
+    }
+
+    /// Get a copy of the interface with the exports named in `renames` renamed. The renames are
+    /// applied together, so two exports can swap names; renames of missing exports are ignored.
+    /// A rename applies to both a func and a global with that name. A start function that is
+    /// renamed follows its export.
+    pub fn rename_exports(
+        &self,
+        renames: &HashMap<String, String>,
+    ) -> Result<Interface, RenameError> {
+        let mut exports = HashMap::new();
+        for export in self.exports.values() {
+            let export = match renames.get(export.name()) {
+                Some(name) => export.renamed(name),
+                None => export.clone(),
+            };
+            let key = export.get_key();
+            if exports.insert(key.clone(), export).is_some() {
+                return Err(RenameError::ExportCollision { name: key.1 });
+            }
+        }
+        Ok(Interface {
+            name: self.name.clone(),
+            imports: self.imports.clone(),
+            exports,
+            start: self
+                .start
+                .as_ref()
+                .map(|start| renames.get(start).unwrap_or(start).clone()),
+        })
+    }
+
+    /// Get a copy of the interface with the imports keyed by `(namespace, name)` in `renames`
+    /// moved to their new namespace and name, like [`Interface::rename_exports`]
+    pub fn rename_imports(
+        &self,
+        renames: &HashMap<(String, String), (String, String)>,
+    ) -> Result<Interface, RenameError> {
+        let mut imports = HashMap::new();
+        for import in self.imports.values() {
+            let import = match renames.get(&import.get_key()) {
+                Some((namespace, name)) => import.renamed(namespace, name),
+                None => import.clone(),
+            };
+            let key = import.get_key();
+            if imports.insert(key.clone(), import).is_some() {
+                let (namespace, name) = key;
+                return Err(RenameError::ImportCollision { namespace, name });
+            }
+        }
+        Ok(Interface {
+            imports,
+            ..self.clone()
+        })
+    }
+
+    /// Get a copy of the interface that only has the imports and exports the predicates keep
+    pub fn filter<F, G>(&self, keep_import: F, keep_export: G) -> Interface
+    where
+        F: Fn(&Import) -> bool,
+        G: Fn(&Export) -> bool,
+    {
+        Interface {
+            name: self.name.clone(),
+            imports: self
+                .imports
+                .values()
+                .filter(|import| keep_import(import))
+                .map(|import| (import.get_key(), import.clone()))
+                .collect(),
+            exports: self
+                .exports
+                .values()
+                .filter(|export| keep_export(export))
+                .map(|export| (export.get_key(), export.clone()))
+                .collect(),
+            start: self.start.clone(),
+        }
+    }
+
+    /// Get a copy of the interface in canonical form: surrounding whitespace is trimmed from
+    /// the name and every import and export is keyed by its own definition.
+    pub fn normalize(&self) -> Interface {
+        Interface {
+            name: self.name.as_ref().map(|name| name.trim().to_string()),
+            imports: self
+                .imports
+                .values()
+                .map(|import| (import.get_key(), import.clone()))
+                .collect(),
+            exports: self
+                .exports
+                .values()
+                .map(|export| (export.get_key(), export.clone()))
+                .collect(),
+            start: self.start.clone(),
+        }
+    }
+
+    /// Check that the interface is internally consistent: every import and export is stored
+    /// under its own key, no namespace or name is empty, and nothing is defined twice.
+    ///
+    /// Interfaces parsed from text are always consistent; this catches mistakes when building
+    /// one by hand. Returns a description of every problem found, sorted.
+    pub fn validate_self(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        let mut seen_imports = HashSet::new();
+        for (key, import) in self.imports.iter() {
+            let (namespace, name) = import.get_key();
+            if *key != (namespace.clone(), name.clone()) {
+                errors.push(format!(
+                    "The import \"{}\" \"{}\" is stored under the key (\"{}\", \"{}\")",
+                    namespace, name, key.0, key.1
+                ));
+            }
+            if namespace.is_empty() {
+                errors.push(format!("The import \"{}\" has an empty namespace", name));
+            }
+            if name.is_empty() {
+                errors.push(format!(
+                    "An import from \"{}\" has an empty name",
+                    namespace
+                ));
+            }
+            if !seen_imports.insert((namespace.clone(), name.clone())) {
+                errors.push(format!(
+                    "The import \"{}\" \"{}\" is defined more than once",
+                    namespace, name
+                ));
+            }
+        }
+        let mut seen_exports = HashSet::new();
+        for (key, export) in self.exports.iter() {
+            let (kind, name) = export.get_key();
+            if *key != (kind, name.clone()) {
+                errors.push(format!(
+                    "The {} export \"{}\" is stored under the key ({}, \"{}\")",
+                    kind, name, key.0, key.1
+                ));
+            }
+            if name.is_empty() {
+                errors.push("An export has an empty name".to_string());
+            }
+            if !seen_exports.insert((kind, name.clone())) {
+                errors.push(format!(
+                    "The {} export \"{}\" is defined more than once",
+                    kind, name
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort();
+            Err(errors)
+        }
+    }
+
+    /// Whether both interfaces have exactly the same imports, exports and start function.
+    ///
+    /// Unlike `==`, the interfaces' names are ignored. Everything else is compared exactly: an
+    /// export is only equal to an export with the same name and signature. An interface only
+    /// records signatures, not which import a module re-exports, so an export that forwards an
+    /// import under a different name is just a different export.
+    pub fn structurally_equal(&self, other: &Interface) -> bool {
+        self.imports == other.imports && self.exports == other.exports && self.start == other.start
+    }
+
+    /// Compare this interface with a `newer` version of it, listing the imports and exports
+    /// that were added, removed or whose signature changed.
+    pub fn diff(&self, newer: &Interface) -> InterfaceDiff {
+        let mut diff = InterfaceDiff::default();
+        for (key, import) in self.imports.iter() {
+            match newer.imports.get(key) {
+                None => diff.removed_imports.push(import.clone()),
+                Some(newer_import) if newer_import != import => diff
+                    .changed_imports
+                    .push((import.clone(), newer_import.clone())),
+                Some(_) => {}
+            }
+        }
+        for (key, import) in newer.imports.iter() {
+            if !self.imports.contains_key(key) {
+                diff.added_imports.push(import.clone());
+            }
+        }
+        for (key, export) in self.exports.iter() {
+            match newer.exports.get(key) {
+                None => diff.removed_exports.push(export.clone()),
+                Some(newer_export) if newer_export != export => diff
+                    .changed_exports
+                    .push((export.clone(), newer_export.clone())),
+                Some(_) => {}
+            }
+        }
+        for (key, export) in newer.exports.iter() {
+            if !self.exports.contains_key(key) {
+                diff.added_exports.push(export.clone());
+            }
+        }
+
+        diff.added_imports.sort_by_key(Import::get_key);
+        diff.removed_imports.sort_by_key(Import::get_key);
+        diff.changed_imports
+            .sort_by_key(|(import, _)| import.get_key());
+        diff.added_exports.sort_by_key(Export::get_key);
+        diff.removed_exports.sort_by_key(Export::get_key);
+        diff.changed_exports
+            .sort_by_key(|(export, _)| export.get_key());
+        diff
+    }
+
+    /// A hash of the canonical form of the interface, so that interfaces can be deduplicated
+    /// by content regardless of the order their entries were added in.
+    ///
+    /// The hash is stable across runs and platforms.
+    pub fn canonical_hash(&self) -> u64 {
+        fnv1a(self.canonical_form().bytes())
+    }
+
+    /// The text the canonical hash is computed from: the normalized interface with its entries
+    /// sorted. Hash it with a cryptographic hash where a collision must not go unnoticed.
+    pub fn canonical_form(&self) -> String {
+        self.normalize().canonical_text()
+    }
+
+    /// Render the imports grouped by namespace and the exports, with the names in each group
+    /// aligned. With `color`, names are bold and types are dim.
+    pub fn pretty_print<W: std::io::Write>(&self, w: &mut W, color: bool) -> std::io::Result<()> {
+        let style = |text: &str, code: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+        let bold = |text: &str| style(text, "1");
+        let dim = |text: &str| style(text, "2");
+        let describe = |signature: Signature| match signature {
+            Signature::Func { .. } => format!("func {}", signature),
+            Signature::Global(_) => signature.to_string(),
+        };
+        let write_rows =
+            |w: &mut W, indent: &str, rows: Vec<(&str, String)>| -> std::io::Result<()> {
+                let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+                for (name, signature) in rows {
+                    writeln!(
+                        w,
+                        "{}{}{}  {}",
+                        indent,
+                        bold(name),
+                        " ".repeat(width - name.len()),
+                        dim(&signature)
+                    )?;
+                }
+                Ok(())
+            };
+
+        if let Some(ref name) = self.name {
+            writeln!(w, "Interface {}", bold(name))?;
+        }
+        if !self.imports.is_empty() {
+            writeln!(w, "Imports:")?;
+            let namespaces = self.imports_by_namespace();
+            let mut names: Vec<&String> = namespaces.keys().collect();
+            names.sort();
+            for namespace in names {
+                writeln!(w, "  {}", bold(namespace))?;
+                let rows = namespaces[namespace]
+                    .iter()
+                    .map(|import| {
+                        let name = match import {
+                            Import::Func { name, .. } | Import::Global { name, .. } => name,
+                        };
+                        (name.as_str(), describe(import_signature(import)))
+                    })
+                    .collect();
+                write_rows(w, "    ", rows)?;
+            }
+        }
+        if !self.exports.is_empty() {
+            writeln!(w, "Exports:")?;
+            let mut exports: Vec<&Export> = self.exports.values().collect();
+            exports.sort_by_key(|export| (export.name(), export.kind()));
+            let rows = exports
+                .into_iter()
+                .map(|export| (export.name(), describe(export_signature(export))))
+                .collect();
+            write_rows(w, "  ", rows)?;
+        }
+        if let Some(ref start) = self.start {
+            writeln!(w, "Start: {}", bold(start))?;
+        }
+        Ok(())
+    }
+
+    /// The interface in the text format, with imports and exports sorted by key
+    pub(crate) fn canonical_text(&self) -> String {
+        let mut imports: Vec<&Import> = self.imports.values().collect();
+        imports.sort_by_key(|import| import.get_key());
+        let mut exports: Vec<&Export> = self.exports.values().collect();
+        exports.sort_by_key(|export| export.get_key());
+
+        let mut out = String::from("(interface");
+        if let Some(ref name) = self.name {
+            out.push_str(&format!(" \"{}\"", name));
+        }
+        for import in imports {
+            out.push_str(&match import {
+                Import::Func {
+                    namespace,
+                    name,
+                    params,
+                    result,
+                } => format!(
+                    "\n  (func (import \"{}\" \"{}\") (param{}) (result{}))",
+                    namespace,
+                    name,
+                    format_types(params),
+                    format_types(result)
+                ),
+                Import::Global {
+                    namespace,
+                    name,
+                    var_type,
+                } => format!(
+                    "\n  (global (import \"{}\" \"{}\") (type {}))",
+                    namespace, name, var_type
+                ),
+            });
+        }
+        for export in exports {
+            out.push_str(&match export {
+                Export::Func {
+                    name,
+                    params,
+                    result,
+                } => format!(
+                    "\n  (func (export \"{}\") (param{}) (result{}))",
+                    name,
+                    format_types(params),
+                    format_types(result)
+                ),
+                Export::Global { name, var_type } => {
+                    format!("\n  (global (export \"{}\") (type {}))", name, var_type)
+                }
+            });
+        }
+        if let Some(ref start) = self.start {
+            out.push_str(&format!("\n  (assert_start \"{}\")", start));
+        }
+        out.push(')');
+        out
+    }
+
     pub fn create_interface_matcher(&self) -> InterfaceMatcher {
         let mut namespaces = HashSet::new();
         let mut namespace_imports: HashMap<String, HashSet<Import>> =
@@ -70,6 +717,380 @@ impl Interface {
     }
 }
 
+/// An import or an export of an interface, see `Interface::into_iter`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InterfaceItem {
+    Import(Import),
+    Export(Export),
+}
+
+/// Iterate over the imports sorted by namespace and name, followed by the exports sorted by
+/// name
+impl IntoIterator for Interface {
+    type Item = InterfaceItem;
+    type IntoIter = std::vec::IntoIter<InterfaceItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut imports: Vec<_> = self.imports.into_iter().collect();
+        imports.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut exports: Vec<_> = self.exports.into_iter().collect();
+        exports.sort_by(|a, b| a.0.cmp(&b.0));
+        imports
+            .into_iter()
+            .map(|(_, import)| InterfaceItem::Import(import))
+            .chain(
+                exports
+                    .into_iter()
+                    .map(|(_, export)| InterfaceItem::Export(export)),
+            )
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// Merge all the interfaces, or an empty interface if there are none
+impl std::iter::FromIterator<Interface> for Result<Interface, MergeError> {
+    fn from_iter<I: IntoIterator<Item = Interface>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        let first = iter.next().unwrap_or_default();
+        iter.try_fold(first, Interface::merge_into)
+    }
+}
+
+/// The imports of `modules` that nothing satisfies. An import is satisfied by the `host` if the
+/// host provides the same import, or by another module named after the import's namespace that
+/// exports a matching func or global.
+pub fn unsatisfied_imports(modules: &[Interface], host: &Interface) -> HashSet<Import> {
+    let mut unsatisfied = HashSet::new();
+    for (index, module) in modules.iter().enumerate() {
+        for ((namespace, name), import) in module.imports.iter() {
+            if host.get_import(namespace, name) == Some(import) {
+                continue;
+            }
+            let exported = modules.iter().enumerate().any(|(other_index, other)| {
+                other_index != index
+                    && other.name.as_deref() == Some(namespace.as_str())
+                    && other
+                        .get_export_of_kind(import.export_kind(), name)
+                        .map_or(false, |export| {
+                            import_signature(import) == export_signature(export)
+                        })
+            });
+            if !exported {
+                unsatisfied.insert(import.clone());
+            }
+        }
+    }
+    unsatisfied
+}
+
+/// The import namespace of the first WASI snapshot
+pub const WASI_UNSTABLE_NAMESPACE: &str = "wasi_unstable";
+/// The import namespace of WASI `snapshot_preview1`
+pub const WASI_SNAPSHOT_PREVIEW1_NAMESPACE: &str = "wasi_snapshot_preview1";
+/// Every known WASI import namespace
+pub const WASI_NAMESPACES: &[&str] = &[WASI_UNSTABLE_NAMESPACE, WASI_SNAPSHOT_PREVIEW1_NAMESPACE];
+
+/// The WASI snapshot a module targets, see [`Interface::wasi_version`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WasiVersion {
+    /// Only imports from `wasi_unstable`
+    Unstable,
+    /// Only imports from `wasi_snapshot_preview1`
+    SnapshotPreview1,
+    /// Imports from both `wasi_unstable` and `wasi_snapshot_preview1`
+    Mixed,
+    /// No WASI imports
+    NotWasi,
+}
+
+/// Two interfaces define the same import, export or start function differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    ImportConflict {
+        existing: Box<Import>,
+        conflicting: Box<Import>,
+    },
+    ExportConflict {
+        existing: Box<Export>,
+        conflicting: Box<Export>,
+    },
+    /// The interfaces require different exports to be the start function
+    StartConflict {
+        existing: String,
+        conflicting: String,
+    },
+    /// Both interfaces import from `namespace`, see [`Interface::merge_disjoint_namespaces`]
+    SharedNamespace { namespace: String },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (expected, found, mismatch) = match self {
+            MergeError::ImportConflict {
+                existing,
+                conflicting,
+            } => {
+                let (namespace, name) = existing.get_key();
+                write!(
+                    f,
+                    "Conflict detected: the import \"{}\" \"{}\"",
+                    namespace, name
+                )?;
+                (
+                    existing.normalized_signature(),
+                    conflicting.normalized_signature(),
+                    Signature::mismatch(
+                        &import_signature(existing),
+                        &import_signature(conflicting),
+                    ),
+                )
+            }
+            MergeError::ExportConflict {
+                existing,
+                conflicting,
+            } => {
+                write!(f, "Conflict detected: the export \"{}\"", existing.name())?;
+                (
+                    existing.normalized_signature(),
+                    conflicting.normalized_signature(),
+                    Signature::mismatch(
+                        &export_signature(existing),
+                        &export_signature(conflicting),
+                    ),
+                )
+            }
+            MergeError::StartConflict {
+                existing,
+                conflicting,
+            } => {
+                return write!(
+                    f,
+                    "Conflict detected: the start function must be the export \"{}\", but was also required to be the export \"{}\"",
+                    existing, conflicting
+                );
+            }
+            MergeError::SharedNamespace { namespace } => {
+                return write!(
+                    f,
+                    "Conflict detected: both interfaces import from the namespace \"{}\"",
+                    namespace
+                );
+            }
+        };
+        write!(
+            f,
+            " was found with different definitions: expected {} but found {}. {}",
+            expected, found, mismatch
+        )
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// Renaming imports or exports would give two of them the same key, see
+/// [`Interface::rename_exports`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    ImportCollision { namespace: String, name: String },
+    ExportCollision { name: String },
+}
+
+impl std::fmt::Display for RenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenameError::ImportCollision { namespace, name } => write!(
+                f,
+                "Renaming the imports would leave two imports of \"{}\" \"{}\"",
+                namespace, name
+            ),
+            RenameError::ExportCollision { name } => write!(
+                f,
+                "Renaming the exports would leave two exports named \"{}\"",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenameError {}
+
+/// How an interface changed between two versions, see [`Interface::diff`]. Every list is sorted
+/// by key.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InterfaceDiff {
+    pub added_imports: Vec<Import>,
+    pub removed_imports: Vec<Import>,
+    /// The old and new definitions of imports whose signature changed
+    pub changed_imports: Vec<(Import, Import)>,
+    pub added_exports: Vec<Export>,
+    pub removed_exports: Vec<Export>,
+    /// The old and new definitions of exports whose signature changed
+    pub changed_exports: Vec<(Export, Export)>,
+}
+
+impl InterfaceDiff {
+    /// Whether the two interfaces have the same imports and exports
+    pub fn is_empty(&self) -> bool {
+        self.added_imports.is_empty()
+            && self.removed_imports.is_empty()
+            && self.changed_imports.is_empty()
+            && self.added_exports.is_empty()
+            && self.removed_exports.is_empty()
+            && self.changed_exports.is_empty()
+    }
+}
+
+/// One line per change, e.g. `~ export "run": (i32)->() became (i64)->(). The params differ.`
+impl std::fmt::Display for InterfaceDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for import in self.added_imports.iter() {
+            writeln!(f, "+ {}", import)?;
+        }
+        for import in self.removed_imports.iter() {
+            writeln!(f, "- {}", import)?;
+        }
+        for (old, new) in self.changed_imports.iter() {
+            let (old_signature, new_signature) = (import_signature(old), import_signature(new));
+            writeln!(
+                f,
+                "~ {} became {}. {}",
+                old,
+                new.normalized_signature(),
+                Signature::mismatch(&old_signature, &new_signature)
+            )?;
+        }
+        for export in self.added_exports.iter() {
+            writeln!(f, "+ {}", export)?;
+        }
+        for export in self.removed_exports.iter() {
+            writeln!(f, "- {}", export)?;
+        }
+        for (old, new) in self.changed_exports.iter() {
+            let (old_signature, new_signature) = (export_signature(old), export_signature(new));
+            writeln!(
+                f,
+                "~ {} became {}. {}",
+                old,
+                new.normalized_signature(),
+                Signature::mismatch(&old_signature, &new_signature)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The type of an import or export, used to describe merge conflicts and changes and to link
+/// imports to exports
+#[derive(PartialEq)]
+pub(crate) enum Signature<'a> {
+    Func {
+        params: &'a [WasmType],
+        result: &'a [WasmType],
+    },
+    Global(&'a WasmType),
+}
+
+impl<'a> Signature<'a> {
+    /// Describe how `found` differs from `expected`
+    fn mismatch(expected: &Signature, found: &Signature) -> String {
+        match (expected, found) {
+            (
+                Signature::Func { params, result },
+                Signature::Func {
+                    params: found_params,
+                    result: found_result,
+                },
+            ) => {
+                let count = |kind: &str, expected: &[WasmType], found: &[WasmType]| {
+                    if expected.len() == found.len() {
+                        String::new()
+                    } else {
+                        format!(
+                            " (expected {} {}, found {})",
+                            expected.len(),
+                            kind,
+                            found.len()
+                        )
+                    }
+                };
+                match (params != found_params, result != found_result) {
+                    (true, false) => format!(
+                        "The params differ{}.",
+                        count("params", params, found_params)
+                    ),
+                    (false, _) => format!(
+                        "The results differ{}.",
+                        count("results", result, found_result)
+                    ),
+                    (true, true) => format!(
+                        "Both the params{} and the results{} differ.",
+                        count("params", params, found_params),
+                        count("results", result, found_result)
+                    ),
+                }
+            }
+            (Signature::Global(_), Signature::Global(_)) => "The global types differ.".to_string(),
+            (Signature::Func { .. }, Signature::Global(_)) => {
+                "Expected a func but found a global.".to_string()
+            }
+            (Signature::Global(_), Signature::Func { .. }) => {
+                "Expected a global but found a func.".to_string()
+            }
+        }
+    }
+}
+
+impl<'a> std::fmt::Display for Signature<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let list = |types: &[WasmType]| {
+            types
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Signature::Func { params, result } => {
+                write!(f, "({})->({})", list(params), list(result))
+            }
+            Signature::Global(var_type) => write!(f, "global {}", var_type),
+        }
+    }
+}
+
+pub(crate) fn import_signature(import: &Import) -> Signature<'_> {
+    match import {
+        Import::Func { params, result, .. } => Signature::Func { params, result },
+        Import::Global { var_type, .. } => Signature::Global(var_type),
+    }
+}
+
+pub(crate) fn export_signature(export: &Export) -> Signature<'_> {
+    match export {
+        Export::Func { params, result, .. } => Signature::Func { params, result },
+        Export::Global { var_type, .. } => Signature::Global(var_type),
+    }
+}
+
+/// The 64 bit FNV-1a hash of `bytes`, which is stable across runs and platforms
+pub(crate) fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Format a list of types with a leading space before each one
+fn format_types(types: &[WasmType]) -> String {
+    types.iter().map(|t| format!(" {}", t)).collect()
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Import {
     Func {
@@ -101,6 +1122,96 @@ impl Import {
             } => Self::format_key(&namespace, &name),
         }
     }
+
+    /// The kind of export that can satisfy this import
+    fn export_kind(&self) -> ExportKind {
+        match self {
+            Import::Func { .. } => ExportKind::Func,
+            Import::Global { .. } => ExportKind::Global,
+        }
+    }
+
+    /// The same import under another namespace and name
+    fn renamed(&self, namespace: &str, name: &str) -> Import {
+        let mut import = self.clone();
+        match import {
+            Import::Func {
+                namespace: ref mut ns,
+                name: ref mut n,
+                ..
+            }
+            | Import::Global {
+                namespace: ref mut ns,
+                name: ref mut n,
+                ..
+            } => {
+                *ns = namespace.to_string();
+                *n = name.to_string();
+            }
+        }
+        import
+    }
+
+    /// The canonical form of the import's type, e.g. `(i32, i64)->(i32)` for a func or
+    /// `global i32` for a global
+    pub fn normalized_signature(&self) -> String {
+        import_signature(self).to_string()
+    }
+
+    /// Merge two func imports if their params are equal and one result list is a prefix of the
+    /// other. See [`Interface::merge_compatible`].
+    pub fn merge_compatible(&self, other: &Import) -> Option<Import> {
+        if self == other {
+            return Some(self.clone());
+        }
+        match (self, other) {
+            (
+                Import::Func {
+                    namespace,
+                    name,
+                    params,
+                    result,
+                },
+                Import::Func {
+                    namespace: other_namespace,
+                    name: other_name,
+                    params: other_params,
+                    result: other_result,
+                },
+            ) if namespace == other_namespace && name == other_name && params == other_params => {
+                let (shorter, longer) = if result.len() <= other_result.len() {
+                    (result, other_result)
+                } else {
+                    (other_result, result)
+                };
+                if longer.starts_with(shorter) {
+                    Some(Import::Func {
+                        namespace: namespace.clone(),
+                        name: name.clone(),
+                        params: params.clone(),
+                        result: longer.clone(),
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The key and signature of the import, e.g. `import "env" "log": (i32)->()`
+impl std::fmt::Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (namespace, name) = self.get_key();
+        write!(
+            f,
+            "import \"{}\" \"{}\": {}",
+            namespace,
+            name,
+            self.normalized_signature()
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -116,22 +1227,87 @@ pub enum Export {
     },
 }
 
+/// Whether an export is a func or a global. Exports are keyed by their kind as well as their
+/// name, so a func and a global with the same name are different exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ExportKind {
+    Func,
+    Global,
+}
+
+impl std::fmt::Display for ExportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExportKind::Func => write!(f, "func"),
+            ExportKind::Global => write!(f, "global"),
+        }
+    }
+}
+
 impl Export {
-    pub fn format_key(name: &str) -> String {
-        name.to_string()
+    pub fn format_key(kind: ExportKind, name: &str) -> (ExportKind, String) {
+        (kind, name.to_string())
     }
 
     /// Get the key used to look this export up in the Interface's export hashmap
-    pub fn get_key(&self) -> String {
+    pub fn get_key(&self) -> (ExportKind, String) {
+        Self::format_key(self.kind(), self.name())
+    }
+
+    /// The name the export is exported as
+    pub fn name(&self) -> &str {
+        match self {
+            Export::Func { name, .. } | Export::Global { name, .. } => name,
+        }
+    }
+
+    /// Whether the export is a func or a global
+    pub fn kind(&self) -> ExportKind {
         match self {
-            Export::Func { name, .. } => Self::format_key(&name),
-            Export::Global { name, .. } => Self::format_key(&name),
+            Export::Func { .. } => ExportKind::Func,
+            Export::Global { .. } => ExportKind::Global,
+        }
+    }
+
+    /// The same export under another name
+    fn renamed(&self, name: &str) -> Export {
+        let mut export = self.clone();
+        match export {
+            Export::Func {
+                name: ref mut n, ..
+            }
+            | Export::Global {
+                name: ref mut n, ..
+            } => {
+                *n = name.to_string();
+            }
         }
+        export
+    }
+
+    /// The canonical form of the export's type, see [`Import::normalized_signature`]
+    pub fn normalized_signature(&self) -> String {
+        export_signature(self).to_string()
+    }
+}
+
+/// The key and signature of the export, e.g. `export "run": (i32)->(i32)`
+impl std::fmt::Display for Export {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "export \"{}\": {}",
+            self.name(),
+            self.normalized_signature()
+        )
     }
 }
 
 /// Primitive wasm type
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+///
+/// Types are ordered canonically: `i32 < i64 < f32 < f64`. Any new types must be added after
+/// the existing ones so that the order stays stable.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum WasmType {
     I32,
     I64,
@@ -154,9 +1330,45 @@ impl std::fmt::Display for WasmType {
     }
 }
 
+impl std::str::FromStr for WasmType {
+    type Err = ParseWasmTypeError;
+
+    /// Parse the name of a type as it is written by `Display`, e.g. `i32`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(WasmType::I32),
+            "i64" => Ok(WasmType::I64),
+            "f32" => Ok(WasmType::F32),
+            "f64" => Ok(WasmType::F64),
+            _ => Err(ParseWasmTypeError(s.to_string())),
+        }
+    }
+}
+
+/// The string given to `WasmType::from_str` is not the name of a type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWasmTypeError(pub String);
+
+impl std::fmt::Display for ParseWasmTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown wasm type \"{}\", expected one of i32, i64, f32, f64",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseWasmTypeError {}
+
 #[cfg(test)]
 mod test {
     use crate::parser;
+    use crate::{
+        unsatisfied_imports, Export, ExportKind, Import, Interface, InterfaceItem, MergeError,
+        ParseWasmTypeError, RenameError, WasiVersion, WasmType,
+    };
+    use std::collections::{HashMap, HashSet};
 
     #[test]
     fn merging_works() {
@@ -192,4 +1404,979 @@ mod test {
         assert!(interface5.merge(interface5.clone()).is_ok());
         assert!(interface5.merge(interface6.clone()).is_err());
     }
+
+    #[test]
+    fn conflicts_describe_the_signature_difference() {
+        let interface1 = parser::parse_interface(
+            r#"(interface (func (import "env" "times_two") (param i64) (result i64)))"#,
+        )
+        .unwrap();
+        let interface2 = parser::parse_interface(
+            r#"(interface (func (import "env" "times_two") (param i64 i64) (result i64)))"#,
+        )
+        .unwrap();
+
+        let error = interface1.merge(interface2.clone()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Conflict detected: the import \"env\" \"times_two\" was found with different \
+             definitions: expected (i64)->(i64) but found (i64, i64)->(i64). \
+             The params differ (expected 1 params, found 2)."
+        );
+        match error {
+            MergeError::ImportConflict {
+                existing,
+                conflicting,
+            } => {
+                assert_eq!(&*existing, interface1.imports.values().next().unwrap());
+                assert_eq!(&*conflicting, interface2.imports.values().next().unwrap());
+            }
+            _ => panic!("expected an import conflict"),
+        }
+    }
+
+    #[test]
+    fn compatible_merging_accepts_result_prefixes() {
+        let parse = |src: &str| parser::parse_interface(src).unwrap();
+        let no_result = parse(r#"(interface (func (import "env" "read") (param i32)))"#);
+        let one_result =
+            parse(r#"(interface (func (import "env" "read") (param i32) (result i32)))"#);
+        let two_results =
+            parse(r#"(interface (func (import "env" "read") (param i32) (result i32 i64)))"#);
+        let reordered =
+            parse(r#"(interface (func (import "env" "read") (param i32) (result i64 i32)))"#);
+        let other_params =
+            parse(r#"(interface (func (import "env" "read") (param i64) (result i32)))"#);
+
+        let merged = no_result.merge_compatible(two_results.clone()).unwrap();
+        assert_eq!(merged, two_results);
+        let merged = two_results.merge_compatible(one_result.clone()).unwrap();
+        assert_eq!(merged, two_results);
+        assert!(one_result.merge(two_results.clone()).is_err());
+
+        assert!(one_result.merge_compatible(reordered.clone()).is_err());
+        assert!(one_result.merge_compatible(other_params).is_err());
+
+        let export_a = parse(r#"(interface (func (export "run") (param) (result)))"#);
+        let export_b = parse(r#"(interface (func (export "run") (param) (result i32)))"#);
+        assert!(export_a.merge_compatible(export_b).is_err());
+    }
+
+    #[test]
+    fn disjoint_merging_rejects_shared_namespaces() {
+        let parse = |src: &str| parser::parse_interface(src).unwrap();
+        let env = parse(r#"(interface (func (import "env" "plus_one") (param i32) (result i32)))"#);
+        let same_import =
+            parse(r#"(interface (func (import "env" "plus_one") (param i32) (result i32)))"#);
+        let other_import = parse(r#"(interface (func (import "env" "times_two") (param i64)))"#);
+        let wasi = parse(
+            r#"(interface
+  (func (import "wasi_unstable" "proc_exit") (param i32))
+  (func (export "_start") (param) (result)))"#,
+        );
+
+        let merged = env.merge_disjoint_namespaces(wasi.clone()).unwrap();
+        assert_eq!(merged, env.merge(wasi).unwrap());
+
+        for overlapping in [same_import, other_import] {
+            let error = env.merge_disjoint_namespaces(overlapping).unwrap_err();
+            assert_eq!(
+                error,
+                MergeError::SharedNamespace {
+                    namespace: "env".to_string()
+                }
+            );
+            assert_eq!(
+                error.to_string(),
+                "Conflict detected: both interfaces import from the namespace \"env\""
+            );
+        }
+    }
+
+    #[test]
+    fn renaming_exports_and_imports() {
+        let interface = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "log") (param i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (export "run") (param i32) (result i32))
+  (func (export "init"))
+  (global (export "counter") (type i64))
+  (assert_start "init"))"#,
+        )
+        .unwrap();
+        let renames = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect()
+        };
+
+        let renamed = interface
+            .rename_exports(&renames(&[
+                ("run", "counter"),
+                ("counter", "run"),
+                ("init", "_start"),
+                ("missing", "ignored"),
+            ]))
+            .unwrap();
+        assert_eq!(
+            renamed.get_export("counter"),
+            Some(&Export::Func {
+                name: "counter".to_string(),
+                params: vec![WasmType::I32],
+                result: vec![WasmType::I32],
+            })
+        );
+        assert_eq!(
+            renamed.get_export("run"),
+            Some(&Export::Global {
+                name: "run".to_string(),
+                var_type: WasmType::I64,
+            })
+        );
+        assert!(renamed.contains_export("_start"));
+        assert!(!renamed.contains_export("init"));
+        assert_eq!(renamed.start.as_deref(), Some("_start"));
+        assert_eq!(renamed.imports, interface.imports);
+
+        let error = interface
+            .rename_exports(&renames(&[("init", "run")]))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            RenameError::ExportCollision {
+                name: "run".to_string()
+            }
+        );
+        // a func and a global can share a name
+        let renamed = interface
+            .rename_exports(&renames(&[("run", "counter")]))
+            .unwrap();
+        assert_eq!(renamed.export_count(), interface.export_count());
+
+        let key = |namespace: &str, name: &str| (namespace.to_string(), name.to_string());
+        let mut import_renames = HashMap::new();
+        import_renames.insert(key("env", "log"), key("host", "log"));
+        let renamed = interface.rename_imports(&import_renames).unwrap();
+        assert!(renamed.contains_import("host", "log"));
+        assert!(!renamed.contains_import("env", "log"));
+        assert!(renamed.contains_import("env", "memory_base"));
+        assert_eq!(renamed.exports, interface.exports);
+
+        import_renames.insert(key("env", "log"), key("env", "memory_base"));
+        let error = interface.rename_imports(&import_renames).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Renaming the imports would leave two imports of \"env\" \"memory_base\""
+        );
+    }
+
+    #[test]
+    fn removing_imports_and_exports() {
+        let mut interface = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "plus_one") (param i32) (result i32))
+  (global (export "counter") (type i64)))"#,
+        )
+        .unwrap();
+
+        assert_eq!(interface.remove_import("env", "times_two"), None);
+        assert_eq!(interface.remove_import("wasi", "plus_one"), None);
+        assert_eq!(
+            interface.remove_import("env", "plus_one"),
+            Some(Import::Func {
+                namespace: "env".to_string(),
+                name: "plus_one".to_string(),
+                params: vec![WasmType::I32],
+                result: vec![WasmType::I32],
+            })
+        );
+        assert!(interface.imports.is_empty());
+        assert_eq!(interface.remove_import("env", "plus_one"), None);
+
+        assert_eq!(interface.remove_export("missing"), None);
+        assert_eq!(
+            interface.remove_export("counter"),
+            Some(Export::Global {
+                name: "counter".to_string(),
+                var_type: WasmType::I64,
+            })
+        );
+        assert!(interface.exports.is_empty());
+    }
+
+    #[test]
+    fn canonical_hash_ignores_insertion_order() {
+        let imports = [
+            Import::Func {
+                namespace: "env".to_string(),
+                name: "plus_one".to_string(),
+                params: vec![WasmType::I32],
+                result: vec![WasmType::I32],
+            },
+            Import::Global {
+                namespace: "env".to_string(),
+                name: "memory_base".to_string(),
+                var_type: WasmType::I32,
+            },
+            Import::Func {
+                namespace: "wasi".to_string(),
+                name: "fd_write".to_string(),
+                params: vec![WasmType::I32, WasmType::I32],
+                result: vec![],
+            },
+        ];
+        let exports = [
+            Export::Func {
+                name: "_start".to_string(),
+                params: vec![],
+                result: vec![],
+            },
+            Export::Global {
+                name: "counter".to_string(),
+                var_type: WasmType::I64,
+            },
+        ];
+
+        let mut forwards = Interface {
+            name: Some("calc".to_string()),
+            ..Interface::default()
+        };
+        for import in imports.iter() {
+            forwards.imports.insert(import.get_key(), import.clone());
+        }
+        for export in exports.iter() {
+            forwards.exports.insert(export.get_key(), export.clone());
+        }
+        let mut backwards = Interface {
+            name: Some(" calc\n".to_string()),
+            ..Interface::default()
+        };
+        for import in imports.iter().rev() {
+            backwards.imports.insert(import.get_key(), import.clone());
+        }
+        for export in exports.iter().rev() {
+            backwards.exports.insert(export.get_key(), export.clone());
+        }
+
+        assert_eq!(forwards.canonical_hash(), backwards.canonical_hash());
+        assert_eq!(forwards.canonical_form(), backwards.canonical_form());
+        assert_eq!(forwards.normalize(), backwards.normalize());
+
+        backwards.remove_export("counter");
+        assert_ne!(forwards.canonical_hash(), backwards.canonical_hash());
+    }
+
+    #[test]
+    fn wasm_types_sort_canonically() {
+        let mut types = vec![
+            WasmType::F64,
+            WasmType::I32,
+            WasmType::F32,
+            WasmType::I64,
+            WasmType::I32,
+        ];
+        types.sort();
+        assert_eq!(
+            types,
+            vec![
+                WasmType::I32,
+                WasmType::I32,
+                WasmType::I64,
+                WasmType::F32,
+                WasmType::F64
+            ]
+        );
+    }
+
+    #[test]
+    fn grouping_imports_by_namespace() {
+        let interface = parser::parse_interface(
+            r#"(interface
+  (func (import "wasi" "fd_write") (param i32 i32 i32 i32) (result i32))
+  (func (import "env" "plus_one") (param i32) (result i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (import "wasi" "args_get") (param i32 i32) (result i32)))"#,
+        )
+        .unwrap();
+
+        let grouped = interface.imports_by_namespace();
+        assert_eq!(grouped.len(), 2);
+        let names = |namespace: &str| -> Vec<String> {
+            grouped[namespace]
+                .iter()
+                .map(|import| import.get_key().1)
+                .collect()
+        };
+        assert_eq!(names("env"), vec!["memory_base", "plus_one"]);
+        assert_eq!(names("wasi"), vec!["args_get", "fd_write"]);
+    }
+
+    #[test]
+    fn filter_keeps_only_funcs() {
+        let interface = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "plus_one") (param i32) (result i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (export "main") (param) (result))
+  (global (export "counter") (type i64)))"#,
+        )
+        .unwrap();
+
+        let funcs = interface.filter(
+            |import| matches!(import, Import::Func { .. }),
+            |export| matches!(export, Export::Func { .. }),
+        );
+        assert_eq!(funcs.name, interface.name);
+        assert_eq!(funcs.imports.len(), 1);
+        assert!(funcs
+            .imports
+            .contains_key(&("env".to_string(), "plus_one".to_string())));
+        assert_eq!(funcs.exports.len(), 1);
+        assert!(funcs
+            .exports
+            .contains_key(&(ExportKind::Func, "main".to_string())));
+        // the original is left untouched
+        assert_eq!(interface.imports.len(), 2);
+        assert_eq!(interface.exports.len(), 2);
+    }
+
+    #[test]
+    fn looking_up_imports_and_exports() {
+        let interface = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "plus_one") (param i32) (result i32))
+  (global (export "counter") (type i64)))"#,
+        )
+        .unwrap();
+
+        assert!(interface.contains_import("env", "plus_one"));
+        assert!(!interface.contains_import("env", "counter"));
+        assert!(!interface.contains_import("wasi", "plus_one"));
+        assert!(interface.contains_export("counter"));
+        assert!(!interface.contains_export("plus_one"));
+
+        assert_eq!(
+            interface.get_import("env", "plus_one"),
+            Some(&Import::Func {
+                namespace: "env".to_string(),
+                name: "plus_one".to_string(),
+                params: vec![WasmType::I32],
+                result: vec![WasmType::I32],
+            })
+        );
+        assert_eq!(interface.get_import("env", "missing"), None);
+        assert_eq!(
+            interface.get_export("counter"),
+            Some(&Export::Global {
+                name: "counter".to_string(),
+                var_type: WasmType::I64,
+            })
+        );
+        assert_eq!(interface.get_export("missing"), None);
+    }
+
+    #[test]
+    fn validate_self_finds_corrupt_maps() {
+        let valid = parser::parse_interface(
+            r#"(interface "test"
+  (func (import "env" "do_panic") (param i32 i64))
+  (global (import "env" "length") (type i32))
+  (func (export "run") (param i32 i64) (result i32)))"#,
+        )
+        .unwrap();
+        assert_eq!(valid.validate_self(), Ok(()));
+        assert_eq!(Interface::default().validate_self(), Ok(()));
+
+        let length = Import::Global {
+            namespace: "env".to_string(),
+            name: "length".to_string(),
+            var_type: WasmType::I32,
+        };
+        let mut imports = HashMap::new();
+        imports.insert(("env".to_string(), "length".to_string()), length.clone());
+        imports.insert(("env".to_string(), "size".to_string()), length);
+        let mut exports = HashMap::new();
+        exports.insert(
+            (ExportKind::Func, "start".to_string()),
+            Export::Func {
+                name: "".to_string(),
+                params: vec![],
+                result: vec![],
+            },
+        );
+        let corrupt = Interface {
+            name: None,
+            imports,
+            exports,
+            start: None,
+        };
+
+        assert_eq!(
+            corrupt.validate_self(),
+            Err(vec![
+                "An export has an empty name".to_string(),
+                "The func export \"\" is stored under the key (func, \"start\")".to_string(),
+                "The import \"env\" \"length\" is defined more than once".to_string(),
+                "The import \"env\" \"length\" is stored under the key (\"env\", \"size\")"
+                    .to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn funcs_and_globals_with_the_same_name_coexist() {
+        let func =
+            parser::parse_interface(r#"(interface (func (export "x") (param i32) (result i32)))"#)
+                .unwrap();
+        let global =
+            parser::parse_interface(r#"(interface (global (export "x") (type i64)))"#).unwrap();
+
+        let merged = func.merge(global.clone()).unwrap();
+        assert_eq!(merged.export_count(), 2);
+        assert_eq!(merged.get_export("x"), func.get_export("x"));
+        assert_eq!(
+            merged.get_export_of_kind(ExportKind::Global, "x"),
+            global.get_export("x")
+        );
+        assert_eq!(merged.validate_self(), Ok(()));
+        assert_eq!(merged.merge(global).unwrap(), merged);
+        // exports of the same kind and name still conflict
+        let other_func =
+            parser::parse_interface(r#"(interface (func (export "x") (param i64)))"#).unwrap();
+        assert!(matches!(
+            merged.merge(other_func),
+            Err(MergeError::ExportConflict { .. })
+        ));
+
+        let both = parser::parse_interface(
+            r#"(interface
+  (func (export "x") (param i32) (result i32))
+  (global (export "x") (type i64)))"#,
+        )
+        .unwrap();
+        assert_eq!(both, merged);
+    }
+
+    #[test]
+    fn diff_categorizes_changes() {
+        let old = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "abort") (param i32))
+  (func (import "env" "log") (param i32 i32))
+  (func (export "run") (param i32) (result i32)))"#,
+        )
+        .unwrap();
+        let new = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "log") (param i32 i32))
+  (func (export "run") (param i64) (result i32))
+  (global (export "VERSION") (type i32)))"#,
+        )
+        .unwrap();
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.removed_imports,
+            vec![Import::Func {
+                namespace: "env".to_string(),
+                name: "abort".to_string(),
+                params: vec![WasmType::I32],
+                result: vec![],
+            }]
+        );
+        assert_eq!(
+            diff.added_exports,
+            vec![Export::Global {
+                name: "VERSION".to_string(),
+                var_type: WasmType::I32,
+            }]
+        );
+        assert_eq!(diff.changed_exports.len(), 1);
+        assert!(diff.added_imports.is_empty());
+        assert!(diff.changed_imports.is_empty());
+        assert!(diff.removed_exports.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            r#"- import "env" "abort": (i32)->()
++ export "VERSION": global i32
+~ export "run": (i32)->(i32) became (i64)->(i32). The params differ.
+"#
+        );
+
+        // the diff is symmetric
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.added_imports, diff.removed_imports);
+        assert_eq!(reverse.removed_exports, diff.added_exports);
+        assert!(old.diff(&old).is_empty());
+        assert_eq!(old.diff(&old).to_string(), "No changes\n");
+    }
+
+    #[test]
+    fn merging_start_assertions() {
+        let start = |name: &str| {
+            parser::parse_interface(&format!(
+                r#"(interface (func (export "{}")) (assert_start "{}"))"#,
+                name, name
+            ))
+            .unwrap()
+        };
+        let no_start = parser::parse_interface(r#"(interface (func (export "init")))"#).unwrap();
+
+        let merged = start("init").merge(start("init")).unwrap();
+        assert_eq!(merged.start.as_deref(), Some("init"));
+        let merged = no_start.merge(start("init")).unwrap();
+        assert_eq!(merged.start.as_deref(), Some("init"));
+        let merged = start("init").merge(no_start).unwrap();
+        assert_eq!(merged.start.as_deref(), Some("init"));
+
+        let error = start("init").merge(start("main")).unwrap_err();
+        assert_eq!(
+            error,
+            MergeError::StartConflict {
+                existing: "init".to_string(),
+                conflicting: "main".to_string(),
+            }
+        );
+        assert!(error.to_string().contains("\"main\""), "{}", error);
+    }
+
+    #[test]
+    fn merge_into_reuses_the_base() {
+        let interfaces: Vec<Interface> = (0..100)
+            .map(|i| {
+                parser::parse_interface(&format!(
+                    r#"(interface "merged"
+  (func (import "env" "log") (param i32 i32))
+  (func (import "env" "import_{}") (param i32))
+  (func (export "export_{}") (result i32)))"#,
+                    i, i
+                ))
+                .unwrap()
+            })
+            .collect();
+
+        let pointers = |interface: &Interface| {
+            let import_name = match interface.get_import("env", "import_0") {
+                Some(Import::Func { name, .. }) => name.as_ptr(),
+                _ => panic!("import_0 is missing"),
+            };
+            (interface.name.as_ref().unwrap().as_ptr(), import_name)
+        };
+        let mut merged = interfaces[0].clone();
+        let base = pointers(&merged);
+        for other in interfaces[1..].iter().cloned() {
+            merged = merged.merge_into(other).unwrap();
+            // the base's strings are moved into the result, never cloned
+            assert_eq!(pointers(&merged), base);
+        }
+
+        let cloning = interfaces[1..]
+            .iter()
+            .try_fold(interfaces[0].clone(), |base, other| {
+                base.merge(other.clone())
+            })
+            .unwrap();
+        assert_eq!(merged, cloning);
+        assert_eq!(merged.imports.len(), 101);
+        assert_eq!(merged.exports.len(), 100);
+
+        let collected: Result<Interface, MergeError> = interfaces.into_iter().collect();
+        assert_eq!(collected, Ok(cloning));
+
+        let empty: Result<Interface, MergeError> = std::iter::empty().collect();
+        assert_eq!(empty, Ok(Interface::default()));
+
+        let conflicting = vec![
+            parser::parse_interface(r#"(interface (func (export "run") (param i32)))"#).unwrap(),
+            parser::parse_interface(r#"(interface (func (export "run") (param i64)))"#).unwrap(),
+        ];
+        let result: Result<Interface, MergeError> = conflicting.into_iter().collect();
+        assert!(matches!(result, Err(MergeError::ExportConflict { .. })));
+    }
+
+    #[test]
+    fn allowlisted_conflicts_keep_the_base() {
+        let base = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "log") (param i32))
+  (func (import "env" "now") (result i64))
+  (func (export "run") (param i32) (result i32)))"#,
+        )
+        .unwrap();
+        let other = parser::parse_interface(
+            r#"(interface
+  (func (import "env" "log") (param i64))
+  (func (import "env" "now") (result i64))
+  (func (export "run") (result i32))
+  (func (export "stop")))"#,
+        )
+        .unwrap();
+        let allow: HashSet<String> = ["env.log", "run"].iter().map(|s| s.to_string()).collect();
+
+        let merged = base.merge_with_allowlist(other.clone(), &allow).unwrap();
+        assert_eq!(
+            merged.get_import("env", "log"),
+            base.get_import("env", "log")
+        );
+        assert_eq!(merged.get_export("run"), base.get_export("run"));
+        assert!(merged.contains_export("stop"));
+        assert!(merged.contains_import("env", "now"));
+
+        let allow: HashSet<String> = ["env.log"].iter().map(|s| s.to_string()).collect();
+        match base.merge_with_allowlist(other, &allow) {
+            Err(MergeError::ExportConflict { existing, .. }) => {
+                assert_eq!(existing.name(), "run")
+            }
+            result => panic!("expected an export conflict, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn classifying_the_wasi_version() {
+        let wasi_version = |src: &str| parser::parse_interface(src).unwrap().wasi_version();
+
+        assert_eq!(
+            wasi_version(
+                r#"(interface
+  (func (import "wasi_snapshot_preview1" "fd_write") (param i32 i32 i32 i32) (result i32))
+  (func (import "env" "log") (param i32)))"#
+            ),
+            WasiVersion::SnapshotPreview1
+        );
+        assert_eq!(
+            wasi_version(
+                r#"(interface
+  (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32)))"#
+            ),
+            WasiVersion::Unstable
+        );
+        assert_eq!(
+            wasi_version(
+                r#"(interface
+  (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32))
+  (func (import "wasi_snapshot_preview1" "proc_exit") (param i32)))"#
+            ),
+            WasiVersion::Mixed
+        );
+        assert_eq!(
+            wasi_version(r#"(interface (func (import "env" "log") (param i32)))"#),
+            WasiVersion::NotWasi
+        );
+    }
+
+    #[test]
+    fn removing_wasi_imports() {
+        let interface = parser::parse_interface(
+            r#"(interface "logger"
+  (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32))
+  (func (import "wasi_snapshot_preview1" "proc_exit") (param i32))
+  (func (import "env" "log") (param i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (export "_start")))"#,
+        )
+        .unwrap();
+
+        let host = interface.without_wasi();
+        let mut imports: Vec<_> = host.imports.keys().cloned().collect();
+        imports.sort();
+        assert_eq!(
+            imports,
+            vec![
+                ("env".to_string(), "log".to_string()),
+                ("env".to_string(), "memory_base".to_string()),
+            ]
+        );
+        assert_eq!(host.exports, interface.exports);
+        assert_eq!(host.name, interface.name);
+        assert_eq!(host.wasi_version(), WasiVersion::NotWasi);
+    }
+
+    #[test]
+    fn pretty_printing_aligns_names() {
+        let interface = parser::parse_interface(
+            r#"(interface "logger"
+  (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32))
+  (func (import "env" "log") (param i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (export "run") (param i32) (result i32))
+  (func (export "initialize"))
+  (assert_start "initialize"))"#,
+        )
+        .unwrap();
+
+        let mut out = vec![];
+        interface.pretty_print(&mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            r#"Interface logger
+Imports:
+  env
+    log          func (i32)->()
+    memory_base  global i32
+  wasi_unstable
+    fd_write  func (i32, i32, i32, i32)->(i32)
+Exports:
+  initialize  func ()->()
+  run         func (i32)->(i32)
+Start: initialize
+"#
+        );
+        assert!(!out.contains('\x1b'));
+
+        let mut colored = vec![];
+        interface.pretty_print(&mut colored, true).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+        assert!(colored.contains("\x1b[1mrun\x1b[0m"));
+        assert!(colored.contains("\x1b[2mfunc (i32)->(i32)\x1b[0m"));
+    }
+
+    #[test]
+    fn structural_equality_ignores_only_the_name() {
+        let parse = |src: &str| parser::parse_interface(src).unwrap();
+        let interface = parse(
+            r#"(interface "logger"
+  (func (import "env" "log") (param i32))
+  (func (export "log") (param i32)))"#,
+        );
+
+        let reordered = parse(
+            r#"(interface "renamed"
+  (func (export "log") (param i32))
+  (func (import "env" "log") (param i32)))"#,
+        );
+        assert!(interface.structurally_equal(&reordered));
+        assert_ne!(interface, reordered);
+
+        // re-exporting the import under another name is a different export
+        let aliased = parse(
+            r#"(interface "logger"
+  (func (import "env" "log") (param i32))
+  (func (export "write_log") (param i32)))"#,
+        );
+        assert!(!interface.structurally_equal(&aliased));
+
+        let with_start = parse(
+            r#"(interface "logger"
+  (func (import "env" "log") (param i32))
+  (func (export "log") (param i32))
+  (assert_start "log"))"#,
+        );
+        assert!(!interface.structurally_equal(&with_start));
+    }
+
+    #[test]
+    fn parsing_wasm_types() {
+        for wasm_type in &[WasmType::I32, WasmType::I64, WasmType::F32, WasmType::F64] {
+            assert_eq!(
+                wasm_type.to_string().parse::<WasmType>().as_ref(),
+                Ok(wasm_type)
+            );
+        }
+        assert_eq!("i32".parse(), Ok(WasmType::I32));
+        assert_eq!("f64".parse(), Ok(WasmType::F64));
+
+        let error = "u32".parse::<WasmType>().unwrap_err();
+        assert_eq!(error, ParseWasmTypeError("u32".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Unknown wasm type \"u32\", expected one of i32, i64, f32, f64"
+        );
+        assert!(" i32".parse::<WasmType>().is_err());
+    }
+
+    #[test]
+    fn linking_modules_against_each_other_and_the_host() {
+        let host = parser::parse_interface(
+            r#"(interface "host"
+                (func (import "env" "log") (param i32))
+                (global (import "env" "memory_base") (type i32)))"#,
+        )
+        .unwrap();
+        let math = parser::parse_interface(
+            r#"(interface "math"
+                (func (import "strings" "len") (param i32) (result i32))
+                (func (import "env" "log") (param i32))
+                (func (export "add") (param i32 i32) (result i32))
+                (global (export "precision") (type f64)))"#,
+        )
+        .unwrap();
+        let strings = parser::parse_interface(
+            r#"(interface "strings"
+                (func (import "math" "add") (param i32 i32) (result i32))
+                (global (import "math" "precision") (type f64))
+                (global (import "env" "memory_base") (type i32))
+                (func (export "len") (param i32) (result i32)))"#,
+        )
+        .unwrap();
+        assert!(unsatisfied_imports(&[math.clone(), strings.clone()], &host).is_empty());
+
+        let app = parser::parse_interface(
+            r#"(interface "app"
+                (func (import "math" "add") (param i64 i64) (result i64))
+                (func (import "math" "sub") (param i32 i32) (result i32))
+                (func (import "env" "log") (param i32))
+                (func (import "env" "exit") (param i32)))"#,
+        )
+        .unwrap();
+        let unsatisfied = unsatisfied_imports(&[math.clone(), strings, app], &host);
+        let mut keys: Vec<(String, String)> = unsatisfied.iter().map(Import::get_key).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                Import::format_key("env", "exit"),
+                Import::format_key("math", "add"),
+                Import::format_key("math", "sub"),
+            ]
+        );
+
+        // a module doesn't satisfy its own imports
+        let unsatisfied = unsatisfied_imports(&[math], &host);
+        assert_eq!(
+            unsatisfied
+                .into_iter()
+                .map(|i| i.get_key())
+                .collect::<Vec<_>>(),
+            vec![Import::format_key("strings", "len")]
+        );
+    }
+
+    #[test]
+    fn counting_and_iterating_imports_and_exports() {
+        let interface = parser::parse_interface(
+            r#"(interface "sample"
+                (func (import "env" "log") (param i32))
+                (global (import "env" "base") (type i32))
+                (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32))
+                (func (export "run") (param) (result i32)))"#,
+        )
+        .unwrap();
+        assert_eq!(interface.import_count(), 3);
+        assert_eq!(interface.export_count(), 1);
+        assert_eq!(Interface::default().import_count(), 0);
+
+        let mut namespaces: Vec<&str> = interface
+            .imports_iter()
+            .map(|import| match import {
+                Import::Func { namespace, .. } | Import::Global { namespace, .. } => {
+                    namespace.as_str()
+                }
+            })
+            .collect();
+        namespaces.sort();
+        assert_eq!(namespaces, vec!["env", "env", "wasi_unstable"]);
+        assert_eq!(
+            interface.exports_iter().collect::<Vec<_>>(),
+            vec![interface.get_export("run").unwrap()]
+        );
+
+        let keys: Vec<String> = interface
+            .into_iter()
+            .map(|item| match item {
+                InterfaceItem::Import(import) => {
+                    let (namespace, name) = import.get_key();
+                    format!("import {}.{}", namespace, name)
+                }
+                InterfaceItem::Export(export) => format!("export {}", export.name()),
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "import env.base",
+                "import env.log",
+                "import wasi_unstable.fd_write",
+                "export run",
+            ]
+        );
+    }
+
+    #[test]
+    fn normalized_signatures() {
+        let interface = parser::parse_interface(
+            r#"(interface
+                (func (import "env" "log") (param i32))
+                (global (import "env" "base") (type i64))
+                (func (import "wasi_unstable" "fd_write") (param i32 i32 i32 i32) (result i32))
+                (func (export "now") (result f64)))"#,
+        )
+        .unwrap();
+        let import = |namespace, name| interface.get_import(namespace, name).unwrap();
+        assert_eq!(import("env", "log").normalized_signature(), "(i32)->()");
+        assert_eq!(import("env", "base").normalized_signature(), "global i64");
+        assert_eq!(
+            import("wasi_unstable", "fd_write").normalized_signature(),
+            "(i32, i32, i32, i32)->(i32)"
+        );
+        let now = interface.get_export("now").unwrap();
+        assert_eq!(now.normalized_signature(), "()->(f64)");
+        assert_eq!(now.to_string(), "export \"now\": ()->(f64)");
+        assert_eq!(
+            import("env", "log").to_string(),
+            "import \"env\" \"log\": (i32)->()"
+        );
+    }
+
+    #[test]
+    fn merging_an_identical_interface_skips_the_per_entry_comparisons() {
+        let interface = parser::parse_interface(
+            r#"(interface
+                (func (import "env" "log") (param i32))
+                (global (import "env" "base") (type i64))
+                (func (export "run") (result i32)))"#,
+        )
+        .unwrap();
+        assert_eq!(interface.merge(interface.clone()).unwrap(), interface);
+
+        let comparisons = std::cell::Cell::new(0);
+        let counting = |base: &Import, other: &Import| {
+            comparisons.set(comparisons.get() + 1);
+            if base == other {
+                Some(base.clone())
+            } else {
+                None
+            }
+        };
+        let merged = interface
+            .clone()
+            .merge_into_with(interface.clone(), counting)
+            .unwrap();
+        assert_eq!(merged, interface);
+        assert_eq!(comparisons.get(), 0);
+
+        // anything else compares every shared import
+        let mut extended = interface.clone();
+        extended.exports.extend(
+            parser::parse_interface(r#"(interface (func (export "stop")))"#)
+                .unwrap()
+                .exports,
+        );
+        let merged = interface
+            .clone()
+            .merge_into_with(extended.clone(), counting)
+            .unwrap();
+        assert_eq!(merged, extended);
+        assert_eq!(comparisons.get(), 2);
+    }
+
+    #[test]
+    fn type_usage_counts_params_results_and_globals() {
+        let interface = parser::parse_interface(
+            r#"(interface
+                (func (import "env" "log") (param i32 i32))
+                (global (import "env" "base") (type i64))
+                (func (export "mix") (param i32 f64) (result i64 f64))
+                (global (export "scale") (type f64)))"#,
+        )
+        .unwrap();
+        let expected: HashMap<WasmType, usize> =
+            vec![(WasmType::I32, 3), (WasmType::I64, 2), (WasmType::F64, 3)]
+                .into_iter()
+                .collect();
+        assert_eq!(interface.type_usage(), expected);
+        assert!(Interface::default().type_usage().is_empty());
+    }
 }
@@ -0,0 +1,201 @@
+//! Interfaces written as JSON, for teams that find it easier to author than the text format.
+//!
+//! An interface is an object with optional `name`, `imports`, `exports` and `start` fields.
+//! Every import and export has a `kind` of `func` or `global`; funcs list their `params` and
+//! `results` and globals their `type`, using the type names `i32`, `i64`, `f32` and `f64`:
+//!
+//! ```json
+//! {
+//!   "name": "logger",
+//!   "imports": [
+//!     { "kind": "func", "namespace": "env", "name": "log", "params": ["i32", "i32"] },
+//!     { "kind": "global", "namespace": "env", "name": "memory_base", "type": "i32" }
+//!   ],
+//!   "exports": [
+//!     { "kind": "func", "name": "run", "params": ["i32"], "results": ["i32"] },
+//!     { "kind": "global", "name": "VERSION", "type": "i32" }
+//!   ],
+//!   "start": "run"
+//! }
+//! ```
+//!
+//! Missing `params` and `results` are empty.
+
+use crate::{Export, Import, Interface, WasmType};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InterfaceJson {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    imports: Vec<ImportJson>,
+    #[serde(default)]
+    exports: Vec<ExportJson>,
+    #[serde(default)]
+    start: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum ImportJson {
+    Func {
+        namespace: String,
+        name: String,
+        #[serde(default)]
+        params: Vec<String>,
+        #[serde(default)]
+        results: Vec<String>,
+    },
+    Global {
+        namespace: String,
+        name: String,
+        #[serde(rename = "type")]
+        var_type: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum ExportJson {
+    Func {
+        name: String,
+        #[serde(default)]
+        params: Vec<String>,
+        #[serde(default)]
+        results: Vec<String>,
+    },
+    Global {
+        name: String,
+        #[serde(rename = "type")]
+        var_type: String,
+    },
+}
+
+/// Read an interface in the JSON format described in the module documentation
+pub fn parse_interface_json(json: &str) -> Result<Interface, String> {
+    let parsed: InterfaceJson = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut interface = Interface {
+        name: parsed.name,
+        start: parsed.start,
+        ..Interface::default()
+    };
+    for import in parsed.imports {
+        let import = match import {
+            ImportJson::Func {
+                namespace,
+                name,
+                params,
+                results,
+            } => Import::Func {
+                params: parse_types(&params, &name)?,
+                result: parse_types(&results, &name)?,
+                namespace,
+                name,
+            },
+            ImportJson::Global {
+                namespace,
+                name,
+                var_type,
+            } => Import::Global {
+                var_type: parse_type(&var_type, &name)?,
+                namespace,
+                name,
+            },
+        };
+        if let Some(dup) = interface.imports.insert(import.get_key(), import) {
+            return Err(format!("Duplicate import found {:?}", dup));
+        }
+    }
+    for export in parsed.exports {
+        let export = match export {
+            ExportJson::Func {
+                name,
+                params,
+                results,
+            } => Export::Func {
+                params: parse_types(&params, &name)?,
+                result: parse_types(&results, &name)?,
+                name,
+            },
+            ExportJson::Global { name, var_type } => Export::Global {
+                var_type: parse_type(&var_type, &name)?,
+                name,
+            },
+        };
+        if let Some(dup) = interface.exports.insert(export.get_key(), export) {
+            return Err(format!("Duplicate export found {:?}", dup));
+        }
+    }
+    Ok(interface)
+}
+
+fn parse_types(types: &[String], item: &str) -> Result<Vec<WasmType>, String> {
+    types.iter().map(|ty| parse_type(ty, item)).collect()
+}
+
+fn parse_type(ty: &str, item: &str) -> Result<WasmType, String> {
+    ty.parse()
+        .map_err(|e| format!("Invalid type in \"{}\": {}", item, e))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::parse_interface;
+    use crate::Interface;
+
+    #[test]
+    fn json_interfaces_match_the_text_format() {
+        let json = r#"{
+  "name": "logger",
+  "imports": [
+    { "kind": "func", "namespace": "env", "name": "log", "params": ["i32", "i32"] },
+    { "kind": "global", "namespace": "env", "name": "memory_base", "type": "i32" }
+  ],
+  "exports": [
+    { "kind": "func", "name": "run", "params": ["i32"], "results": ["i64"] },
+    { "kind": "global", "name": "run", "type": "f64" }
+  ],
+  "start": "run"
+}"#;
+        let expected = parse_interface(
+            r#"(interface "logger"
+  (func (import "env" "log") (param i32 i32))
+  (global (import "env" "memory_base") (type i32))
+  (func (export "run") (param i32) (result i64))
+  (global (export "run") (type f64))
+  (assert_start "run"))"#,
+        )
+        .unwrap();
+        assert_eq!(Interface::from_interface_json(json).unwrap(), expected);
+        assert_eq!(
+            Interface::from_interface_json("{}").unwrap(),
+            Interface::default()
+        );
+    }
+
+    #[test]
+    fn invalid_json_interfaces_are_rejected() {
+        let error = Interface::from_interface_json(
+            r#"{"exports": [{"kind": "func", "name": "run", "params": ["i128"]}]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "Invalid type in \"run\": Unknown wasm type \"i128\", expected one of i32, i64, f32, f64"
+        );
+
+        let error = Interface::from_interface_json(
+            r#"{"imports": [
+  {"kind": "global", "namespace": "env", "name": "x", "type": "i32"},
+  {"kind": "global", "namespace": "env", "name": "x", "type": "i64"}
+]}"#,
+        )
+        .unwrap_err();
+        assert!(error.starts_with("Duplicate import found"), "{}", error);
+
+        let error = Interface::from_interface_json(r#"{"exprts": []}"#).unwrap_err();
+        assert!(error.contains("unknown field `exprts`"), "{}", error);
+    }
+}
@@ -4,9 +4,14 @@
 //! wasm interfaces ensure wasm modules conform to a specific shape
 //! they do this by asserting on the imports and exports of the module.
 
+#[cfg(feature = "cache")]
+pub mod cache;
 pub mod interface;
 pub mod interface_matcher;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod parser;
+pub mod typescript;
 #[cfg(feature = "validation")]
 pub mod validate;
 
@@ -2,7 +2,7 @@
 //!
 //! The grammar of the text format is:
 //! interface = "(" interface name? interface-entry* ")"
-//! interface-entry = func | global
+//! interface-entry = func | global | assert-start
 //!
 //! func = import-fn | export-fn
 //! global = import-global | export-global
@@ -15,6 +15,8 @@
 //! export-global = "(" "global" export-id type-decl ")"
 //! export-id = "(" export name ")"
 //!
+//! assert-start = "(" "assert_start" name ")"
+//!
 //! param-list = "(" param type* ")"
 //! result-list = "(" result type* ")"
 //! type-decl = "(" "type" type ")"
@@ -23,6 +25,8 @@
 //! identifier = any character that's not a whitespace character or an open or close parenthesis
 //! type = "i32" | "i64" | "f32" | "f64"
 //!
+//! a word in a param or result list that isn't a type is reported with its line and column
+//!
 //! + means 1 or more
 //! * means 0 or more
 //! ? means 0 or 1
@@ -38,45 +42,105 @@ use nom::{
     bytes::complete::{escaped, is_not, tag},
     character::complete::{char, multispace0, multispace1, one_of},
     combinator::*,
-    error::context,
+    error::{context, ErrorKind},
     multi::many0,
-    sequence::{delimited, preceded, tuple},
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 
 use crate::interface::*;
+use std::path::{Path, PathBuf};
+
+/// Why an interface file could not be parsed
+#[derive(Debug)]
+pub enum ParseInterfaceFileError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+impl std::fmt::Display for ParseInterfaceFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseInterfaceFileError::Io { path, error } => {
+                write!(f, "Could not read \"{}\": {}", path.display(), error)
+            }
+            ParseInterfaceFileError::Parse { path, error } => {
+                write!(f, "Could not parse \"{}\": {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseInterfaceFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseInterfaceFileError::Io { error, .. } => Some(error),
+            ParseInterfaceFileError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Read and parse the interface in the file at `path`
+pub fn parse_interface_file<P: AsRef<Path>>(path: P) -> Result<Interface, ParseInterfaceFileError> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).map_err(|error| ParseInterfaceFileError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse_interface(&contents).map_err(|error| ParseInterfaceFileError::Parse {
+        path: path.to_path_buf(),
+        error,
+    })
+}
 
 /// Some example input:
 /// (interface "example_interface"
 ///     (func (import "ns" "name") (param f64 i32) (result f64 i32))
 ///     (func (export "name") (param f64 i32) (result f64 i32))
-///     (global (import "ns" "name") (type f64)))
+///     (global (import "ns" "name") (type f64))
+///     (assert_start "name"))
 pub fn parse_interface(mut input: &str) -> Result<Interface, String> {
+    let source = input;
     let mut interface = Interface::default();
     let interface_inner = preceded(
         tag("interface"),
         tuple((
             opt(preceded(space_comments, identifier)),
-            many0(parse_func_or_global),
+            many0(parse_entry),
         )),
     );
     let interface_parser = preceded(space_comments, s_exp(interface_inner));
 
-    if let Result::Ok((inp, (sig_id, out))) = interface_parser(input) {
+    let parsed = match interface_parser(input) {
+        Err(nom::Err::Failure((rest, _))) => return Err(unknown_type_error(source, rest)),
+        parsed => parsed,
+    };
+    if let Result::Ok((inp, (sig_id, out))) = parsed {
         interface.name = sig_id.map(|s_id| s_id.to_string());
 
         for entry in out.into_iter() {
             match entry {
-                Either::Left(import) => {
+                Entry::Import(import) => {
                     if let Some(dup) = interface.imports.insert(import.get_key(), import) {
                         return Err(format!("Duplicate import found {:?}", dup));
                     }
                 }
-                Either::Right(export) => {
+                Entry::Export(export) => {
                     if let Some(dup) = interface.exports.insert(export.get_key(), export) {
                         return Err(format!("Duplicate export found {:?}", dup));
                     }
                 }
+                Entry::Start(start) => {
+                    if let Some(dup) = interface.start.replace(start) {
+                        return Err(format!("Duplicate start assertion found {:?}", dup));
+                    }
+                }
             }
         }
         input = inp;
@@ -92,6 +156,24 @@ pub fn parse_interface(mut input: &str) -> Result<Interface, String> {
     }
 }
 
+/// Describe the unknown type at the start of `rest`, with its position in `source`
+fn unknown_type_error(source: &str, rest: &str) -> String {
+    let offset = source.len() - rest.len();
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = source[..offset].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+    let token = is_not::<_, _, (&str, ErrorKind)>(TOKEN_DELIMITERS)(rest)
+        .map(|(_, token)| token)
+        .unwrap_or(rest);
+    format!(
+        "Unknown type \"{}\" at line {}, column {}, expected one of i32, i64, f32, f64",
+        token, line, column
+    )
+}
+
+/// The characters that end a keyword or type
+const TOKEN_DELIMITERS: &str = " \t\r\n();";
+
 fn parse_comment(input: &str) -> IResult<&str, ()> {
     map(
         preceded(multispace0, preceded(char(';'), many0(is_not("\n")))),
@@ -138,6 +220,21 @@ fn wasm_type(input: &str) -> IResult<&str, WasmType> {
     alt((i32_tag, i64_tag, f32_tag, f64_tag))(input)
 }
 
+/// Parses a `keyword` that isn't just the start of a longer word
+fn keyword<'a>(keyword: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(tag(keyword), not(is_not(TOKEN_DELIMITERS)))
+}
+
+/// Parses a type in a param or result list. Any other word is a failure, so that the error
+/// points at the unknown type rather than the enclosing list.
+fn type_keyword(input: &str) -> IResult<&str, WasmType> {
+    let (rest, token) = is_not(TOKEN_DELIMITERS)(input)?;
+    match wasm_type(token) {
+        Ok(("", wasm_type)) => Ok((rest, wasm_type)),
+        _ => Err(nom::Err::Failure((input, ErrorKind::Tag))),
+    }
+}
+
 /// Parses an S-expression
 fn s_exp<'a, O1, F>(inner: F) -> impl Fn(&'a str) -> IResult<&'a str, O1>
 where
@@ -150,16 +247,46 @@ where
     )
 }
 
+/// An entry in the body of an interface
+enum Entry {
+    Import(Import),
+    Export(Export),
+    Start(String),
+}
+
+fn parse_entry(input: &str) -> IResult<&str, Entry> {
+    let func_or_global = map(parse_func_or_global, |entry| match entry {
+        Either::Left(import) => Entry::Import(import),
+        Either::Right(export) => Entry::Export(export),
+    });
+    let start = map(preceded(space_comments, assert_start), |name| {
+        Entry::Start(name.to_string())
+    });
+    alt((func_or_global, start))(input)
+}
+
 fn parse_func_or_global(input: &str) -> IResult<&str, Either<Import, Export>> {
     preceded(space_comments, alt((func, global)))(input)
 }
 
+/// (assert_start "name")
+fn assert_start(input: &str) -> IResult<&str, &str> {
+    let assert_start_inner = preceded(tag("assert_start"), preceded(space_comments, identifier));
+    s_exp(assert_start_inner)(input)
+}
+
 /// (func (import "ns" "name") (param f64 i32) (result f64 i32))
 /// (func (export "name") (param f64 i32) (result f64 i32))
 fn func(input: &str) -> IResult<&str, Either<Import, Export>> {
-    let param_list_inner = preceded(tag("param"), many0(preceded(space_comments, wasm_type)));
+    let param_list_inner = preceded(
+        keyword("param"),
+        many0(preceded(space_comments, type_keyword)),
+    );
     let param_list = opt(s_exp(param_list_inner));
-    let result_list_inner = preceded(tag("result"), many0(preceded(space_comments, wasm_type)));
+    let result_list_inner = preceded(
+        keyword("result"),
+        many0(preceded(space_comments, type_keyword)),
+    );
     let result_list = opt(s_exp(result_list_inner));
     let import_id_inner = preceded(
         tag("import"),
@@ -489,13 +616,14 @@ mod test {
         let export_map = exports
             .into_iter()
             .map(|entry| (entry.get_key(), entry))
-            .collect::<HashMap<String, Export>>();
+            .collect::<HashMap<_, Export>>();
         assert_eq!(
             parse_res,
             Interface {
                 name: None,
                 imports: import_map,
                 exports: export_map,
+                start: None,
             }
         );
     }
@@ -558,13 +686,14 @@ mod test {
         let export_map = exports
             .into_iter()
             .map(|entry| (entry.get_key(), entry))
-            .collect::<HashMap<String, Export>>();
+            .collect::<HashMap<_, Export>>();
         assert_eq!(
             parse_res,
             Interface {
                 name: Some("interface_name".to_string()),
                 imports: import_map,
                 exports: export_map,
+                start: None,
             }
         );
     }
@@ -593,4 +722,83 @@ mod test {
 
         assert!(parse_res.is_ok());
     }
+
+    #[test]
+    fn parse_interface_from_file() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let interface = parse_interface_file(fixtures.join("example.interface")).unwrap();
+        assert_eq!(interface.name.as_deref(), Some("example"));
+        assert!(interface.contains_import("env", "plus_one"));
+        assert!(interface.contains_import("env", "counter"));
+        assert!(interface.contains_export("run"));
+
+        let missing = fixtures.join("missing.interface");
+        let error = parse_interface_file(&missing).unwrap_err();
+        match error {
+            ParseInterfaceFileError::Io {
+                ref path,
+                ref error,
+            } => {
+                assert_eq!(path, &missing);
+                assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+            }
+            _ => panic!("expected an IO error, found {}", error),
+        }
+        assert!(error.to_string().contains("missing.interface"));
+    }
+
+    #[test]
+    fn parse_start_assertion() {
+        let interface = parse_interface(
+            r#"(interface
+  (func (export "init"))
+  ; the module must run "init" when it is instantiated
+  (assert_start "init"))"#,
+        )
+        .unwrap();
+        assert_eq!(interface.start.as_deref(), Some("init"));
+
+        let duplicate =
+            parse_interface(r#"(interface (assert_start "init") (assert_start "main"))"#);
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn unknown_types_are_reported_with_their_position() {
+        let error = parse_interface(
+            r#"(interface
+  (func (import "env" "log") (param i32 i33) (result))
+  (func (export "run")))"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "Unknown type \"i33\" at line 2, column 41, expected one of i32, i64, f32, f64"
+        );
+
+        let error =
+            parse_interface(r#"(interface (func (export "run") (result i32x)))"#).unwrap_err();
+        assert!(
+            error.starts_with("Unknown type \"i32x\" at line 1, column 41"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn empty_type_lists_are_allowed() {
+        let interface = parse_interface(
+            r#"(interface (func (export "run") (param) (result)) (func (export "init") (param )))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            interface.get_export("run"),
+            Some(&Export::Func {
+                name: "run".to_string(),
+                params: vec![],
+                result: vec![],
+            })
+        );
+        assert!(interface.contains_export("init"));
+    }
 }
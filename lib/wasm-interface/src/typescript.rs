@@ -0,0 +1,239 @@
+//! TypeScript declarations for the exports and imports of an interface, for JS and TS code that
+//! instantiates a module implementing it.
+//!
+//! `i32`, `f32` and `f64` are `number`s and `i64` is a `bigint`, as in the JS API for wasm. A
+//! func with no results returns `void` and one with several returns a tuple. Wasm doesn't name
+//! params, so they are called `p0`, `p1` and so on:
+//!
+//! ```ts
+//! export declare function add(p0: number, p1: number): number;
+//! export declare const counter: WebAssembly.Global;
+//!
+//! export interface Imports {
+//!   env: {
+//!     log(p0: number): void;
+//!   };
+//! }
+//! ```
+//!
+//! Exports whose names can't be declared in JS, like `fd-write` or `delete`, are listed in a
+//! comment instead.
+
+use crate::{Export, Import, Interface, WasmType};
+use std::collections::BTreeMap;
+
+/// Words that can't be the name of a declared function or const
+const RESERVED_WORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Render the `.d.ts` declarations for `interface`, with exports and imports sorted by name
+pub fn interface_to_dts(interface: &Interface) -> String {
+    let mut out = String::new();
+    if let Some(ref name) = interface.name {
+        out.push_str(&format!("// Declarations for the interface \"{}\"\n", name));
+    }
+
+    let mut exports: Vec<&Export> = interface.exports_iter().collect();
+    exports.sort_by_key(|export| export.get_key());
+    for export in &exports {
+        if !is_identifier(export.name()) || RESERVED_WORDS.contains(&export.name()) {
+            out.push_str(&format!(
+                "// export \"{}\" can't be declared: {}\n",
+                export.name(),
+                member(export.name(), &export_type(export))
+            ));
+            continue;
+        }
+        match export {
+            Export::Func { name, .. } => out.push_str(&format!(
+                "export declare function {}{};\n",
+                name,
+                export_type(export)
+            )),
+            Export::Global { name, .. } => out.push_str(&format!(
+                "export declare const {}{};\n",
+                name,
+                export_type(export)
+            )),
+        }
+    }
+
+    let mut namespaces: BTreeMap<&str, Vec<&Import>> = BTreeMap::new();
+    for import in interface.imports_iter() {
+        let namespace = match import {
+            Import::Func { namespace, .. } | Import::Global { namespace, .. } => namespace,
+        };
+        namespaces.entry(namespace).or_default().push(import);
+    }
+    if !namespaces.is_empty() {
+        if !exports.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("export interface Imports {\n");
+        for (namespace, mut imports) in namespaces {
+            imports.sort_by_key(|import| import.get_key());
+            out.push_str(&format!("  {}: {{\n", property_name(namespace)));
+            for import in imports {
+                let (name, signature) = match import {
+                    Import::Func {
+                        name,
+                        params,
+                        result,
+                        ..
+                    } => (name, func_type(params, result)),
+                    Import::Global { name, .. } => (name, global_type()),
+                };
+                out.push_str(&format!("    {};\n", member(name, &signature)));
+            }
+            out.push_str("  };\n");
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// The TypeScript type of a value of `wasm_type`
+fn ts_type(wasm_type: &WasmType) -> &'static str {
+    match wasm_type {
+        WasmType::I32 | WasmType::F32 | WasmType::F64 => "number",
+        WasmType::I64 => "bigint",
+    }
+}
+
+/// The params and return type of a func, e.g. `(p0: number): bigint`
+fn func_type(params: &[WasmType], result: &[WasmType]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| format!("p{}: {}", i, ts_type(param)))
+        .collect();
+    let result = match result {
+        [] => "void".to_string(),
+        [single] => ts_type(single).to_string(),
+        several => format!(
+            "[{}]",
+            several.iter().map(ts_type).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    format!("({}): {}", params.join(", "), result)
+}
+
+/// The type annotation of a global, which the JS API wraps in a `WebAssembly.Global`
+fn global_type() -> String {
+    ": WebAssembly.Global".to_string()
+}
+
+fn export_type(export: &Export) -> String {
+    match export {
+        Export::Func { params, result, .. } => func_type(params, result),
+        Export::Global { .. } => global_type(),
+    }
+}
+
+/// A member of an object type, e.g. `log(p0: number): void` or `"fd-write"(p0: number): void`
+fn member(name: &str, signature: &str) -> String {
+    format!("{}{}", property_name(name), signature)
+}
+
+/// `name` as a property name, quoted unless it is an identifier
+fn property_name(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' || first == '$' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::parse_interface;
+
+    #[test]
+    fn exports_and_imports_are_declared() {
+        let interface = parse_interface(
+            r#"(interface "math"
+                (func (import "env" "log") (param i32))
+                (func (import "env" "fd-write") (param i32 i64) (result i32))
+                (global (import "env" "base") (type i32))
+                (func (export "add") (param i32 f64) (result f64))
+                (func (export "split") (param i64) (result i32 i64))
+                (func (export "delete") (param i32))
+                (global (export "counter") (type i64)))"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            interface.to_typescript_dts(),
+            r#"// Declarations for the interface "math"
+export declare function add(p0: number, p1: number): number;
+// export "delete" can't be declared: delete(p0: number): void
+export declare function split(p0: bigint): [number, bigint];
+export declare const counter: WebAssembly.Global;
+
+export interface Imports {
+  env: {
+    base: WebAssembly.Global;
+    "fd-write"(p0: number, p1: bigint): number;
+    log(p0: number): void;
+  };
+}
+"#
+        );
+    }
+}
@@ -4,7 +4,7 @@
 //! are correct, as well as that all exports that the interface expects are exported
 //! by the module and that their types are correct.
 
-use crate::{Export, Import, Interface, WasmType};
+use crate::{Export, ExportKind, Import, Interface, WasmType};
 use std::collections::HashMap;
 use wasmparser::{ExternalKind, FuncType, GlobalType, ImportSectionEntryType};
 
@@ -12,104 +12,217 @@ pub fn validate_wasm_and_report_errors(
     wasm: &[u8],
     interface: &Interface,
 ) -> Result<(), WasmValidationError> {
-    use wasmparser::WasmDecoder;
-
+    let sections = read_sections(wasm)?;
     let mut errors: Vec<String> = vec![];
-    let mut import_fns: HashMap<(String, String), u32> = HashMap::new();
-    let mut export_fns: HashMap<String, u32> = HashMap::new();
-    let mut export_globals: HashMap<String, u32> = HashMap::new();
-    let mut type_defs: Vec<FuncType> = vec![];
-    let mut global_types: Vec<GlobalType> = vec![];
-    let mut fn_sigs: Vec<u32> = vec![];
 
+    for ((module, field), content_type) in &sections.import_globals {
+        let global_type = wasmparser_type_into_wasm_type(*content_type).map_err(|err| {
+            WasmValidationError::UnsupportedType {
+                error: format!(
+                    "Invalid type found in import \"{}\" \"{}\": {}",
+                    module, field, err
+                ),
+            }
+        })?;
+        if let Some(val) = interface.imports.get(&Import::format_key(module, field)) {
+            if let Import::Global { var_type, .. } = val {
+                if *var_type != global_type {
+                    errors.push(format!(
+                        "Invalid type on Global \"{}\". Expected {} found {}",
+                        field, var_type, global_type
+                    ));
+                }
+            } else {
+                errors.push(format!(
+                    "Invalid import type. Expected Global, found {:?}",
+                    val
+                ));
+            }
+        } else {
+            errors.push(format!(
+                "Global import \"{}\" not found in the specified interface",
+                field
+            ));
+        }
+    }
+
+    let import_fns: HashMap<(String, String), u32> = sections.import_fns.into_iter().collect();
+    let export_fns: HashMap<String, u32> = sections.export_fns.into_iter().collect();
+    let export_globals: HashMap<String, u32> = sections.export_globals.into_iter().collect();
+    let type_defs = &sections.type_defs;
+    let fn_sigs = &sections.fn_sigs;
+    validate_imports(&import_fns, type_defs, interface, &mut errors);
+    validate_export_fns(&export_fns, type_defs, fn_sigs, interface, &mut errors);
+    validate_export_globals(
+        &export_globals,
+        &sections.global_types,
+        interface,
+        &mut errors,
+    );
+    validate_start(sections.start_fn, &export_fns, interface, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(WasmValidationError::InterfaceViolated { errors: errors })
+    }
+}
+
+/// The entries of a module's sections that interfaces care about
+#[derive(Default)]
+struct ModuleSections {
+    import_fns: Vec<((String, String), u32)>,
+    import_globals: Vec<((String, String), wasmparser::Type)>,
+    export_fns: Vec<(String, u32)>,
+    export_globals: Vec<(String, u32)>,
+    type_defs: Vec<FuncType>,
+    /// The types of the imported globals followed by the module's own, by global index
+    global_types: Vec<GlobalType>,
+    /// The type index of each func, imported funcs first
+    fn_sigs: Vec<u32>,
+    start_fn: Option<u32>,
+}
+
+/// Parse and validate `wasm`, collecting the entries of its sections
+fn read_sections(wasm: &[u8]) -> Result<ModuleSections, WasmValidationError> {
+    use wasmparser::WasmDecoder;
+
+    let mut sections = ModuleSections::default();
     let mut parser = wasmparser::ValidatingParser::new(wasm, None);
     loop {
-        let state = parser.read();
-        match state {
+        match parser.read() {
             wasmparser::ParserState::EndWasm => break,
             wasmparser::ParserState::Error(e) => {
                 return Err(WasmValidationError::InvalidWasm {
                     error: format!("{}", e),
-                }
-                .into());
+                });
             }
-            wasmparser::ParserState::ImportSectionEntry {
-                module,
-                field,
-                ref ty,
-            } => match ty {
+            wasmparser::ParserState::ImportSectionEntry { module, field, ty } => match ty {
                 ImportSectionEntryType::Function(idx) => {
-                    import_fns.insert(Import::format_key(module, field), *idx);
-                    fn_sigs.push(*idx);
+                    sections
+                        .import_fns
+                        .push((Import::format_key(module, field), *idx));
+                    sections.fn_sigs.push(*idx);
                 }
-                ImportSectionEntryType::Global(GlobalType { content_type, .. }) => {
-                    let global_type =
-                        wasmparser_type_into_wasm_type(*content_type).map_err(|err| {
-                            WasmValidationError::UnsupportedType {
-                                error: format!(
-                                    "Invalid type found in import \"{}\" \"{}\": {}",
-                                    module, field, err
-                                ),
-                            }
-                        })?;
-                    if let Some(val) = interface.imports.get(&Import::format_key(module, field)) {
-                        if let Import::Global { var_type, .. } = val {
-                            if *var_type != global_type {
-                                errors.push(format!(
-                                    "Invalid type on Global \"{}\". Expected {} found {}",
-                                    field, var_type, global_type
-                                ));
-                            }
-                        } else {
-                            errors.push(format!(
-                                "Invalid import type. Expected Global, found {:?}",
-                                val
-                            ));
-                        }
-                    } else {
-                        errors.push(format!(
-                            "Global import \"{}\" not found in the specified interface",
-                            field
-                        ));
-                    }
+                ImportSectionEntryType::Global(gt) => {
+                    sections
+                        .import_globals
+                        .push((Import::format_key(module, field), gt.content_type));
+                    sections.global_types.push(*gt);
                 }
-                _ => (),
+                _ => {}
             },
-            wasmparser::ParserState::ExportSectionEntry {
-                field,
-                index,
-                ref kind,
-            } => match kind {
-                ExternalKind::Function => {
-                    export_fns.insert(Export::format_key(field), *index);
-                }
-                ExternalKind::Global => {
-                    export_globals.insert(Export::format_key(field), *index);
-                }
-                _ => (),
+            wasmparser::ParserState::ExportSectionEntry { field, index, kind } => match kind {
+                ExternalKind::Function => sections.export_fns.push((field.to_string(), *index)),
+                ExternalKind::Global => sections.export_globals.push((field.to_string(), *index)),
+                _ => {}
             },
-            wasmparser::ParserState::BeginGlobalSectionEntry(gt) => {
-                global_types.push(gt.clone());
-            }
-            wasmparser::ParserState::TypeSectionEntry(ft) => {
-                type_defs.push(ft.clone());
-            }
-            wasmparser::ParserState::FunctionSectionEntry(n) => {
-                fn_sigs.push(*n);
-            }
+            wasmparser::ParserState::BeginGlobalSectionEntry(gt) => sections.global_types.push(*gt),
+            wasmparser::ParserState::TypeSectionEntry(ft) => sections.type_defs.push(ft.clone()),
+            wasmparser::ParserState::FunctionSectionEntry(n) => sections.fn_sigs.push(*n),
+            wasmparser::ParserState::StartSectionEntry(n) => sections.start_fn = Some(*n),
             _ => {}
         }
     }
+    Ok(sections)
+}
 
-    validate_imports(&import_fns, &type_defs, interface, &mut errors);
-    validate_export_fns(&export_fns, &type_defs, &fn_sigs, interface, &mut errors);
-    validate_export_globals(&export_globals, &global_types, interface, &mut errors);
+/// Get the signatures of the functions a wasm module exports
+pub fn exported_functions(wasm: &[u8]) -> Result<Vec<Export>, WasmValidationError> {
+    let sections = read_sections(wasm)?;
+    let convert = |name: &str, types: &[wasmparser::Type]| {
+        types
+            .iter()
+            .cloned()
+            .map(wasmparser_type_into_wasm_type)
+            .collect::<Result<Vec<WasmType>, String>>()
+            .map_err(|e| WasmValidationError::UnsupportedType {
+                error: format!("Invalid type in exported func \"{}\": {}", name, e),
+            })
+    };
+    let (type_defs, fn_sigs) = (&sections.type_defs, &sections.fn_sigs);
+    sections
+        .export_fns
+        .into_iter()
+        .map(|(name, index)| {
+            // the parser has already checked that the indices are in bounds
+            let type_sig = &type_defs[fn_sigs[index as usize] as usize];
+            Ok(Export::Func {
+                params: convert(&name, &type_sig.params)?,
+                result: convert(&name, &type_sig.returns)?,
+                name,
+            })
+        })
+        .collect()
+}
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(WasmValidationError::InterfaceViolated { errors: errors })
+/// Derive the interface a wasm module implies: everything it imports and exports, and its
+/// start function if that is exported
+pub fn interface_from_wasm(wasm: &[u8]) -> Result<Interface, WasmValidationError> {
+    let sections = read_sections(wasm)?;
+    let mut interface = Interface::default();
+    for ((namespace, name), content_type) in sections.import_globals {
+        let var_type = wasmparser_type_into_wasm_type(content_type).map_err(|e| {
+            WasmValidationError::UnsupportedType {
+                error: format!(
+                    "Invalid type in imported global \"{}\" \"{}\": {}",
+                    namespace, name, e
+                ),
+            }
+        })?;
+        let import = Import::Global {
+            namespace,
+            name,
+            var_type,
+        };
+        interface.imports.insert(import.get_key(), import);
+    }
+
+    let convert = |kind: &str, name: &str, types: &[wasmparser::Type]| {
+        types
+            .iter()
+            .cloned()
+            .map(wasmparser_type_into_wasm_type)
+            .collect::<Result<Vec<WasmType>, String>>()
+            .map_err(|e| WasmValidationError::UnsupportedType {
+                error: format!("Invalid type in {} func \"{}\": {}", kind, name, e),
+            })
+    };
+    // the parser has already checked that the indices are in bounds
+    let type_defs = &sections.type_defs;
+    for ((namespace, name), index) in sections.import_fns {
+        let type_sig = &type_defs[index as usize];
+        let import = Import::Func {
+            params: convert("imported", &name, &type_sig.params)?,
+            result: convert("imported", &name, &type_sig.returns)?,
+            namespace,
+            name,
+        };
+        interface.imports.insert(import.get_key(), import);
+    }
+    for (name, index) in sections.export_fns {
+        if sections.start_fn == Some(index) {
+            interface.start = Some(name.clone());
+        }
+        let type_sig = &type_defs[sections.fn_sigs[index as usize] as usize];
+        let export = Export::Func {
+            params: convert("exported", &name, &type_sig.params)?,
+            result: convert("exported", &name, &type_sig.returns)?,
+            name,
+        };
+        interface.exports.insert(export.get_key(), export);
+    }
+    for (name, index) in sections.export_globals {
+        let content_type = sections.global_types[index as usize].content_type;
+        let var_type = wasmparser_type_into_wasm_type(content_type).map_err(|e| {
+            WasmValidationError::UnsupportedType {
+                error: format!("Invalid type in exported global \"{}\": {}", name, e),
+            }
+        })?;
+        let export = Export::Global { name, var_type };
+        interface.exports.insert(export.get_key(), export);
     }
+    Ok(interface)
 }
 
 /// Validates the import functions, checking the name and type against the given
@@ -205,7 +318,7 @@ fn validate_export_fns(
     errors: &mut Vec<String>,
 ) {
     'export_loop: for (key, val) in export_fns.iter() {
-        if let Some(interface_def) = interface.exports.get(key) {
+        if let Some(interface_def) = interface.get_export_of_kind(ExportKind::Func, key) {
             let type_sig = if let Some(type_idx) = fn_sigs.get(*val as usize) {
                 if let Some(v) = type_defs.get(*type_idx as usize) {
                     v
@@ -290,7 +403,7 @@ fn validate_export_globals(
     errors: &mut Vec<String>,
 ) {
     for (key, val) in export_globals.iter() {
-        if let Some(interface_def) = interface.exports.get(key) {
+        if let Some(interface_def) = interface.get_export_of_kind(ExportKind::Global, key) {
             if let Export::Global { var_type, .. } = interface_def {
                 if global_types.get(*val as usize).is_none() {
                     errors.push(format!(
@@ -315,6 +428,34 @@ fn validate_export_globals(
     }
 }
 
+/// Validates that the module's start function is the export the `Interface` asserts it is
+fn validate_start(
+    start_fn: Option<u32>,
+    export_fns: &HashMap<String, u32>,
+    interface: &Interface,
+    errors: &mut Vec<String>,
+) {
+    let name = match interface.start {
+        Some(ref name) => name,
+        None => return,
+    };
+    match (start_fn, export_fns.get(name)) {
+        (None, _) => errors.push(format!(
+            "The module has no start function, but the interface requires it to be the export \"{}\"",
+            name
+        )),
+        (Some(_), None) => errors.push(format!(
+            "The start function must be the export \"{}\", but the module does not export a func with that name",
+            name
+        )),
+        (Some(start), Some(index)) if start != *index => errors.push(format!(
+            "The start function must be the export \"{}\", but it is a different function",
+            name
+        )),
+        _ => {}
+    }
+}
+
 /// Converts Wasmparser's type enum into wasm-interface's type enum
 /// wasmparser's enum contains things which are invalid in many situations
 ///
@@ -458,6 +599,81 @@ mod validation_tests {
 
         assert!(result.is_err(), "missing a required export is an error");
     }
+
+    #[test]
+    fn start_assertions() {
+        const WAT: &str = r#"(module
+(func $init (export "init"))
+(func $main (export "main"))
+(start $init)
+)"#;
+        let wasm = wat::parse_str(WAT).unwrap();
+
+        let interface_src = r#"
+(interface
+(assert_start "init"))"#;
+        let interface = parser::parse_interface(interface_src).unwrap();
+
+        let result = validate_wasm_and_report_errors(&wasm[..], &interface);
+
+        assert!(result.is_ok());
+
+        // Now require a different export to be the start function
+        let interface_src = r#"
+(interface
+(assert_start "main"))"#;
+        let interface = parser::parse_interface(interface_src).unwrap();
+
+        let result = validate_wasm_and_report_errors(&wasm[..], &interface);
+
+        assert!(
+            result.is_err(),
+            "a start function that is a different export is an error"
+        );
+
+        // Now try a module without a start function
+        let wasm = wat::parse_str(r#"(module (func (export "init")))"#).unwrap();
+        let interface_src = r#"
+(interface
+(assert_start "init"))"#;
+        let interface = parser::parse_interface(interface_src).unwrap();
+
+        let result = validate_wasm_and_report_errors(&wasm[..], &interface);
+
+        assert!(result.is_err(), "a missing start function is an error");
+    }
+
+    #[test]
+    #[cfg(feature = "wat")]
+    fn interface_from_wat() {
+        let interface = Interface::from_wat(
+            r#"(module
+(import "env" "log" (func $log (param i32 i32)))
+(import "env" "memory_base" (global i32))
+(global (export "counter") (mut i64) (i64.const 0))
+(func $init (export "init"))
+(func (export "add") (param i32 i32) (result i32)
+  (i32.add (local.get 0) (local.get 1)))
+(start $init)
+)"#,
+        )
+        .unwrap();
+
+        let expected = parser::parse_interface(
+            r#"(interface
+(func (import "env" "log") (param i32 i32))
+(global (import "env" "memory_base") (type i32))
+(global (export "counter") (type i64))
+(func (export "init"))
+(func (export "add") (param i32 i32) (result i32))
+(assert_start "init"))"#,
+        )
+        .unwrap();
+        assert_eq!(interface, expected);
+
+        let error = Interface::from_wat("(module (func (export \"f\") (param i33)))").unwrap_err();
+        assert!(error.contains("i33"), "{}", error);
+    }
 }
 
 #[derive(Debug)]
@@ -466,3 +682,17 @@ pub enum WasmValidationError {
     InterfaceViolated { errors: Vec<String> },
     UnsupportedType { error: String },
 }
+
+impl std::fmt::Display for WasmValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WasmValidationError::InvalidWasm { error } => write!(f, "Invalid wasm: {}", error),
+            WasmValidationError::InterfaceViolated { errors } => {
+                write!(f, "The interface is violated: {}", errors.join(", "))
+            }
+            WasmValidationError::UnsupportedType { error } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for WasmValidationError {}
@@ -0,0 +1,147 @@
+//! A library interface to wapm's core operations, for tools that want to embed wapm.
+//!
+//! None of these functions print to the terminal or exit the process; failures are returned
+//! to the caller.
+
+use std::path::Path;
+
+#[cfg(feature = "full")]
+pub use crate::commands::SearchResult;
+pub use crate::data::manifest::{FeatureSelection, Manifest, ManifestError};
+pub use crate::dataflow::installed_packages::{Error as InstallError, Install};
+pub use crate::dataflow::resolved_packages::{resolve_each, Error as ResolveError, Resolve};
+pub use crate::dataflow::update_plan::UpdatePlan;
+pub use crate::dataflow::{
+    Error as UpdateError, PackageKey, UpdateOptions, WapmPackageKey, WapmPackageRange,
+};
+pub use crate::keys::WapmPackageSignature;
+pub use crate::progress::{set_progress_sink, ProgressSink};
+#[cfg(not(target_os = "wasi"))]
+pub use crate::registry::SearchStream;
+
+use crate::dataflow;
+use crate::dataflow::installed_packages::RegistryInstaller;
+use crate::dataflow::resolved_packages::RegistryResolver;
+
+/// Parse the `wapm.toml` manifest in `directory`.
+pub fn parse_manifest<P: AsRef<Path>>(directory: P) -> Result<Manifest, ManifestError> {
+    Manifest::find_in_directory(directory)
+}
+
+/// Resolve `packages` (pairs of name and exact version) against the registry and report the
+/// changes installing them into `directory` would make, without downloading anything.
+pub fn resolve<P: AsRef<Path>>(
+    directory: P,
+    packages: &[(&str, &str)],
+) -> Result<UpdatePlan, UpdateError> {
+    dataflow::plan_update(
+        packages.to_vec(),
+        vec![],
+        directory,
+        &UpdateOptions::default(),
+    )
+}
+
+/// Install `packages` (pairs of name and exact version) from the registry into `directory`,
+/// updating its lockfile and manifest. Returns whether anything changed.
+pub fn install<P: AsRef<Path>>(
+    directory: P,
+    packages: &[(&str, &str)],
+) -> Result<bool, UpdateError> {
+    install_with::<RegistryResolver, RegistryInstaller, P>(directory, packages)
+}
+
+/// Like `install`, but with a custom resolver and installer, e.g. for a mirror of the registry.
+pub fn install_with<Resolver, Installer, P>(
+    directory: P,
+    packages: &[(&str, &str)],
+) -> Result<bool, UpdateError>
+where
+    Resolver: for<'a> Resolve<'a>,
+    Installer: for<'a> Install<'a>,
+    P: AsRef<Path>,
+{
+    dataflow::update_with::<Resolver, Installer, P>(
+        packages.to_vec(),
+        vec![],
+        directory,
+        UpdateOptions::default(),
+    )
+}
+
+/// Search the registry for package versions matching `query`.
+#[cfg(feature = "full")]
+pub fn search(query: &str) -> anyhow::Result<Vec<SearchResult>> {
+    crate::commands::search_packages(query)
+}
+
+/// Like [`search`], but yield each result as soon as it has been read from the registry's
+/// response.
+#[cfg(all(feature = "full", not(target_os = "wasi")))]
+pub fn search_stream(query: &str) -> anyhow::Result<SearchStream> {
+    crate::registry::RegistryClient::from_config()?.search_stream(query)
+}
+
+/// Bundle and publish the package in `directory`. With `dry_run` the package is validated and
+/// bundled but not uploaded.
+#[cfg(feature = "full")]
+pub fn publish<P: AsRef<Path>>(directory: P, dry_run: bool) -> anyhow::Result<()> {
+    crate::commands::publish_package(
+        directory.as_ref(),
+        &crate::commands::PublishOptions {
+            dry_run,
+            ..crate::commands::PublishOptions::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{RecordingSink, TestInstaller, TestResolver};
+    use std::fs;
+
+    #[test]
+    fn install_through_library_api() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+
+        let changed =
+            install_with::<TestResolver, TestInstaller, _>(tmp_dir.path(), &[("hello", "1.0.0")])
+                .unwrap();
+        assert!(changed);
+
+        let lockfile = fs::read_to_string(tmp_dir.path().join("wapm.lock")).unwrap();
+        assert!(lockfile.contains("_/hello"));
+        assert!(tmp_dir
+            .path()
+            .join("wapm_packages/_/hello@1.0.0/hello.wasm")
+            .exists());
+
+        // installing the same package again is a no-op
+        let changed =
+            install_with::<TestResolver, TestInstaller, _>(tmp_dir.path(), &[("hello", "1.0.0")])
+                .unwrap();
+        assert!(!changed);
+    }
+
+    #[test]
+    fn installs_report_progress_to_the_sink() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let sink = std::rc::Rc::new(RecordingSink::default());
+        let previous = set_progress_sink(Some(sink.clone()));
+
+        install_with::<TestResolver, TestInstaller, _>(tmp_dir.path(), &[("hello", "1.0.0")])
+            .unwrap();
+        set_progress_sink(previous);
+
+        assert_eq!(
+            *sink.events.borrow(),
+            vec![
+                "resolving _/hello@1.0.0",
+                "resolved _/hello@1.0.0",
+                "download _/hello@1.0.0 from url",
+                "installed _/hello@1.0.0",
+            ]
+        );
+    }
+}
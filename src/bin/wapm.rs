@@ -1,6 +1,7 @@
 #![cfg_attr(target_os = "wasi", allow(unused_variables))]
 use std::{env, path};
 use structopt::{clap::AppSettings, StructOpt};
+use wapm_cli::exit_code::ErrorKind;
 #[cfg(feature = "update-notifications")]
 use wapm_cli::update_notifier;
 #[allow(unused_imports)]
@@ -8,10 +9,35 @@ use wapm_cli::{commands, logging};
 
 #[derive(StructOpt, Debug)]
 #[structopt(global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::ColorAuto, AppSettings::ColoredHelp])]
+struct Wapm {
+    /// The number of seconds to wait for each registry request (default: 30)
+    #[structopt(long = "registry-timeout", global = true)]
+    registry_timeout: Option<u64>,
+    /// The user agent to send to the registry (default: `wapm-cli/<version>`)
+    #[structopt(long = "user-agent", global = true)]
+    user_agent: Option<String>,
+    /// Fetch package metadata from the registry instead of using the cached metadata
+    #[structopt(long = "refresh", global = true)]
+    refresh: bool,
+    /// Don't print anything when the command succeeds, only report failures
+    #[structopt(short = "q", long = "quiet", global = true)]
+    quiet: bool,
+    /// Print more about what the command does
+    #[structopt(short = "v", long = "verbose", global = true)]
+    verbose: bool,
+    /// Fail instead of using the default registry when no other registry is configured
+    #[structopt(long = "no-default-registry", global = true)]
+    no_default_registry: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
 enum Command {
     #[structopt(name = "whoami")]
     /// Prints the current user (if authed) in the stdout
-    WhoAmI,
+    WhoAmI(commands::WhoAmIOpt),
 
     #[structopt(name = "login")]
     /// Logins into wapm, saving the token locally for future commands
@@ -46,15 +72,19 @@ enum Command {
     /// Search packages
     Search(commands::SearchOpt),
 
-    #[cfg(feature = "package")]
-    #[structopt(name = "package", aliases = r#"&["p", "pkg"]"#)]
-    /// Create a wasm package with bundled assets
+    #[cfg(feature = "full")]
+    #[structopt(name = "package", aliases = &["p", "pkg", "pack"])]
+    /// Bundle the package into the tarball that `wapm publish` would upload
     Package(commands::PackageOpt),
 
     /// Check if a directory or tar.gz is a valid wapm package
     #[structopt(name = "validate")]
     Validate(commands::ValidateOpt),
 
+    /// Check the manifest for the problems `wapm publish` would reject, without publishing
+    #[structopt(name = "validate-manifest")]
+    ValidateManifest(commands::ValidateManifestOpt),
+
     #[structopt(name = "completions")]
     /// Generate autocompletion scripts for your shell
     Completions(commands::CompletionOpt),
@@ -96,6 +126,26 @@ enum Command {
     /// Remove packages from the manifest
     Remove(commands::RemoveOpt),
 
+    #[structopt(name = "why")]
+    /// Show the dependency paths that pull a package into the project
+    Why(commands::WhyOpt),
+
+    #[structopt(name = "contract")]
+    /// Show, validate and merge wasm interfaces
+    Contract(commands::ContractOpt),
+
+    #[structopt(name = "cache")]
+    /// Manage the caches wapm keeps in the wasmer directory
+    Cache(commands::CacheOpt),
+
+    #[structopt(name = "clean")]
+    /// Remove the installed packages and built tarballs of the project, and optionally its lockfile
+    Clean(commands::CleanOpt),
+
+    #[structopt(name = "export")]
+    /// Download the locked dependencies into a directory for offline installs
+    Export(commands::ExportOpt),
+
     #[cfg(feature = "full")]
     /// Execute a command, installing it temporarily if necessary
     Execute(commands::ExecuteOpt),
@@ -140,20 +190,49 @@ fn main() {
         .to_string_lossy();
 
     #[cfg(feature = "full")]
-    let args = if prog_name == "wax" {
-        Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(1).collect(),
-        ))
+    let wapm = if prog_name == "wax" {
+        Wapm {
+            registry_timeout: None,
+            user_agent: None,
+            refresh: false,
+            quiet: false,
+            verbose: false,
+            no_default_registry: false,
+            command: Command::Execute(commands::ExecuteOpt::ExecArgs(
+                env::args().skip(1).collect(),
+            )),
+        }
     } else if maybe_subcommand_name == Some("execute".to_string()) {
-        Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(2).collect(),
-        ))
+        Wapm {
+            registry_timeout: None,
+            user_agent: None,
+            refresh: false,
+            quiet: false,
+            verbose: false,
+            no_default_registry: false,
+            command: Command::Execute(commands::ExecuteOpt::ExecArgs(
+                env::args().skip(2).collect(),
+            )),
+        }
     } else {
-        Command::from_args()
+        parse_args()
     };
 
     #[cfg(not(feature = "full"))]
-    let args = Command::from_args();
+    let wapm = parse_args();
+
+    wapm_cli::util::set_command_line_settings(wapm_cli::util::CommandLineSettings {
+        registry_timeout: wapm.registry_timeout,
+        user_agent: wapm.user_agent,
+        refresh_metadata: wapm.refresh,
+        quiet: wapm.quiet,
+        verbose: wapm.verbose,
+        no_default_registry: wapm.no_default_registry,
+    });
+    wapm_cli::progress::set_progress_sink(Some(std::rc::Rc::new(
+        wapm_cli::progress::ConsoleProgress,
+    )));
+    let args = wapm.command;
 
     #[cfg(feature = "update-notifications")]
     // Only show the async check on certain commands
@@ -173,13 +252,18 @@ fn main() {
     };
 
     let result = match args {
-        Command::WhoAmI => commands::whoami(),
+        Command::WhoAmI(whoami_options) => commands::whoami(whoami_options),
         Command::Login(login_options) => commands::login(login_options),
         Command::Logout => commands::logout(),
         Command::Config(config_options) => commands::config(config_options),
         Command::Install(install_options) => commands::install(install_options),
         Command::Add(add_options) => commands::add(add_options),
         Command::Remove(remove_options) => commands::remove(remove_options),
+        Command::Why(why_options) => commands::why(why_options),
+        Command::Contract(contract_options) => commands::contract(contract_options),
+        Command::Export(export_options) => commands::export(export_options),
+        Command::Cache(cache_options) => commands::cache(cache_options),
+        Command::Clean(clean_options) => commands::clean(clean_options),
         #[cfg(feature = "full")]
         Command::Publish(publish_options) => commands::publish(publish_options),
         Command::Run(run_options) => commands::run(run_options),
@@ -187,9 +271,12 @@ fn main() {
         Command::Execute(execute_options) => commands::execute(execute_options),
         #[cfg(feature = "full")]
         Command::Search(search_options) => commands::search(search_options),
-        #[cfg(feature = "package")]
+        #[cfg(feature = "full")]
         Command::Package(package_options) => commands::package(package_options),
         Command::Validate(validate_options) => commands::validate(validate_options),
+        Command::ValidateManifest(validate_manifest_options) => {
+            commands::validate_manifest(validate_manifest_options)
+        }
         Command::Init(init_options) => commands::init(init_options),
         #[cfg(feature = "full")]
         Command::List(list_options) => commands::list(list_options),
@@ -197,7 +284,7 @@ fn main() {
         #[cfg(feature = "packagesigning")]
         Command::Keys(key_options) => commands::keys(key_options),
         Command::Completions(completion_options) => {
-            Command::clap().gen_completions_to(
+            Wapm::clap().gen_completions_to(
                 "wapm",
                 completion_options.shell,
                 &mut ::std::io::stdout(),
@@ -233,11 +320,24 @@ fn main() {
         }
     }
 
-    if result.is_err() {
+    if let Err(e) = result {
         #[cfg(feature = "telemetry")]
         {
             drop(_guard);
         };
-        std::process::exit(-1);
+        std::process::exit(ErrorKind::of(&e).exit_code());
+    }
+}
+
+/// Parse the command line. Invalid arguments exit with the usage error code; `--help` and
+/// `--version` exit successfully.
+fn parse_args() -> Wapm {
+    match Wapm::from_iter_safe(env::args()) {
+        Ok(command) => command,
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(ErrorKind::Usage.exit_code());
+        }
     }
 }
@@ -1,8 +1,10 @@
 //! Code pertaining to the `add` subcommand: it adds dependencies to
 //! the manifest without installing
 
-use crate::graphql::execute_query;
-use graphql_client::*;
+use crate::dataflow::resolved_packages::{RegistryResolver, Resolve};
+use crate::dataflow::{normalize_global_namespace_package_name, PackageKey};
+use crate::registry::RegistryClient;
+use semver::{Version, VersionReq};
 use thiserror::Error;
 
 use crate::data::manifest::Manifest;
@@ -11,17 +13,14 @@ use structopt::StructOpt;
 /// Options for the `add` subcommand
 #[derive(StructOpt, Debug)]
 pub struct AddOpt {
+    /// Packages to add, as `name`, `name@version` or `name@<range>` (e.g. `foo/bar@^1.2`)
     packages: Vec<String>,
+    /// Expose the command of the package under this name instead of its own, e.g. when two
+    /// packages have a command with the same name
+    #[structopt(long = "alias")]
+    alias: Option<String>,
 }
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/get_package_version.graphql",
-    response_derives = "Debug"
-)]
-struct GetPackageVersionQuery;
-
 #[derive(Debug, Error)]
 enum AddError {
     #[error("There were problems adding packages")]
@@ -30,6 +29,8 @@ enum AddError {
     NoManifest,
     #[error("No packages listed to add")]
     ArgumentsRequired,
+    #[error("--alias can only be used when adding a single package")]
+    AliasRequiresOnePackage,
 }
 
 /// Run the add command
@@ -43,7 +44,11 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
     if options.packages.is_empty() {
         return Err(AddError::ArgumentsRequired.into());
     }
+    if options.alias.is_some() && options.packages.len() != 1 {
+        return Err(AddError::AliasRequiresOnePackage.into());
+    }
 
+    let client = RegistryClient::from_config()?;
     for (package_name, maybe_version) in options.packages.into_iter().map(|package_str| {
         if package_str.contains('@') {
             let mut p = package_str.split('@');
@@ -54,15 +59,47 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
             (package_str, None)
         }
     }) {
-        let q = GetPackageVersionQuery::build_query(get_package_version_query::Variables {
-            name: package_name.clone(),
-            version: maybe_version.clone(),
-        });
-        let response: get_package_version_query::ResponseData = execute_query(&q)?;
+        // a version range is recorded as is, once a published version matches it
+        let version_req = maybe_version
+            .as_deref()
+            .filter(|version| Version::parse(version).is_err())
+            .and_then(|version| VersionReq::parse(version).ok());
+        if let Some(version_req) = version_req {
+            let name = normalize_global_namespace_package_name(package_name.as_str().into());
+            let key = PackageKey::new_registry_package_range(name, version_req.clone());
+            match RegistryResolver::sync_packages(vec![key])?
+                .into_iter()
+                .next()
+            {
+                Some((resolved, _)) => {
+                    info!(
+                        "Adding {}@{} (resolves to {})",
+                        &package_name, &version_req, &resolved.version
+                    );
+                    if let Some(alias) = options.alias.clone() {
+                        manifest.set_command_alias(package_name.clone(), alias);
+                    }
+                    manifest.add_dependency(package_name, version_req.to_string());
+                }
+                None => {
+                    error = true;
+                    error!(
+                        "No version of package \"{}\" matches \"{}\"",
+                        &package_name, &version_req
+                    );
+                }
+            }
+            continue;
+        }
 
-        if let Some(pv) = response.package_version {
-            info!("Adding {}@{}", &package_name, &pv.version);
-            manifest.add_dependency(package_name, pv.version);
+        let resolved = client.resolve_package(&package_name, maybe_version.as_deref())?;
+
+        if let Some(resolved) = resolved {
+            info!("Adding {}@{}", &resolved.name, &resolved.version);
+            if let Some(alias) = options.alias.clone() {
+                manifest.set_command_alias(resolved.name.clone(), alias);
+            }
+            manifest.add_dependency(resolved.name, resolved.version);
         } else {
             error = true;
             if let Some(ver) = maybe_version {
@@ -86,6 +123,9 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
 #[cfg(feature = "integration_tests")]
 impl AddOpt {
     pub fn new(packages: Vec<String>) -> Self {
-        AddOpt { packages }
+        AddOpt {
+            packages,
+            alias: None,
+        }
     }
 }
@@ -19,7 +19,7 @@ pub enum BinError {
 
 pub fn bin(options: BinOpt) -> anyhow::Result<()> {
     let mut root_dir = match options.global {
-        true => Config::get_globals_directory()?,
+        true => Config::get_global_directory()?,
         false => Config::get_current_dir()?,
     };
     root_dir.push(PACKAGES_DIR_NAME);
@@ -0,0 +1,76 @@
+//! The `cache` subcommand manages the caches wapm keeps in the wasmer directory
+use crate::config::Config;
+use crate::util;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum CacheOpt {
+    #[structopt(name = "clean")]
+    /// Remove the precompiled modules, the packages downloaded by `wapm run`, and the cached
+    /// registry metadata and search results
+    Clean,
+}
+
+pub fn cache(options: CacheOpt) -> anyhow::Result<()> {
+    match options {
+        CacheOpt::Clean => {
+            let paths = vec![
+                Config::get_compiled_directory()?,
+                Config::get_ephemeral_directory()?,
+                Config::get_metadata_cache_file_path()?,
+                Config::get_search_cache_file_path()?,
+            ];
+            let removed = clean(&paths)?;
+            util::print_success(&format!("Removed {} cache(s)", removed));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the files and directories in `paths` that exist, and count them
+pub(super) fn clean(paths: &[PathBuf]) -> io::Result<usize> {
+    let mut removed = 0;
+    for path in paths {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            fs::remove_file(path)?;
+        } else {
+            continue;
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::clean;
+    use std::fs;
+
+    #[test]
+    fn clean_removes_cache_files_and_directories() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let compiled = tmp_dir.path().join("compiled");
+        fs::create_dir_all(&compiled).unwrap();
+        fs::write(compiled.join("module.wasmu"), b"compiled").unwrap();
+        let metadata = tmp_dir.path().join("metadata.json");
+        fs::write(&metadata, b"{}").unwrap();
+        let config = tmp_dir.path().join("wapm.toml");
+        fs::write(&config, b"").unwrap();
+
+        let paths = vec![
+            compiled.clone(),
+            metadata.clone(),
+            tmp_dir.path().join("missing"),
+        ];
+        assert_eq!(clean(&paths).unwrap(), 2);
+        assert!(!compiled.exists());
+        assert!(!metadata.exists());
+        assert!(config.exists());
+        assert_eq!(clean(&paths).unwrap(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! The `clean` subcommand removes what installing and packaging leave in a project, keeping the
+//! manifest and the sources
+use crate::config::Config;
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest::{Manifest, PACKAGES_DIR_NAME};
+use crate::util;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct CleanOpt {
+    /// Remove the lockfile too
+    #[structopt(long = "lockfile")]
+    lockfile: bool,
+    /// List what would be removed without removing anything
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+pub fn clean(options: CleanOpt) -> anyhow::Result<()> {
+    let directory = Config::get_current_dir()?;
+    let paths = clean_directory(&directory, &options)?;
+    if options.dry_run {
+        for path in paths.iter() {
+            println!("Would remove {}", path.display());
+        }
+    } else {
+        util::print_success(&format!("Removed {} path(s)", paths.len()));
+    }
+    Ok(())
+}
+
+/// Remove the paths listed by [`paths_to_clean`] from `directory`, unless `options.dry_run` is
+/// set, and return them
+fn clean_directory(directory: &Path, options: &CleanOpt) -> anyhow::Result<Vec<PathBuf>> {
+    let paths = paths_to_clean(directory, options.lockfile)?;
+    if !options.dry_run {
+        super::cache::clean(&paths)?;
+    }
+    Ok(paths)
+}
+
+/// The installed packages in `directory`, the tarballs `wapm package` built there for the
+/// project's package and, if `lockfile` is set, the lockfile. Only paths that exist are listed.
+fn paths_to_clean(directory: &Path, lockfile: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![directory.join(PACKAGES_DIR_NAME)];
+    if lockfile {
+        paths.push(directory.join(LOCKFILE_NAME));
+    }
+    paths.retain(|path| path.exists());
+
+    if let Ok(manifest) = Manifest::find_in_directory(directory) {
+        let prefix = format!("{}-", manifest.package.name.replace('/', "-"));
+        let mut tarballs = vec![];
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            let is_tarball = path
+                .file_name()
+                .and_then(|name| {
+                    name.to_str()?
+                        .strip_prefix(&prefix)?
+                        .strip_suffix(".tar.gz")
+                })
+                .map_or(false, |version| semver::Version::parse(version).is_ok());
+            if is_tarball && path.is_file() {
+                tarballs.push(path);
+            }
+        }
+        tarballs.sort();
+        paths.extend(tarballs);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{clean_directory, paths_to_clean, CleanOpt};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn clean_removes_packages_tarballs_and_the_lockfile() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        let manifest = "[package]\nname = \"ns/app\"\nversion = \"1.1.0\"\ndescription = \"app\"\n";
+        fs::write(dir.join("wapm.toml"), manifest).unwrap();
+        fs::write(dir.join("app.wasm"), b"\0asm").unwrap();
+        fs::write(dir.join("wapm.lock"), b"").unwrap();
+        fs::create_dir_all(dir.join("wapm_packages/_/hello@1.0.0")).unwrap();
+        for tarball in &[
+            "ns-app-1.0.0.tar.gz",
+            "ns-app-1.1.0.tar.gz",
+            "ns-app-extra-1.0.0.tar.gz",
+            "vendor.tar.gz",
+        ] {
+            fs::write(dir.join(tarball), b"tarball").unwrap();
+        }
+
+        let paths = paths_to_clean(dir, false).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("wapm_packages"),
+                dir.join("ns-app-1.0.0.tar.gz"),
+                dir.join("ns-app-1.1.0.tar.gz"),
+            ]
+        );
+        let with_lockfile = paths_to_clean(dir, true).unwrap();
+        assert_eq!(with_lockfile.len(), 4);
+        assert!(with_lockfile.contains(&dir.join("wapm.lock")));
+
+        let dry_run = CleanOpt {
+            lockfile: true,
+            dry_run: true,
+        };
+        assert_eq!(clean_directory(dir, &dry_run).unwrap(), with_lockfile);
+        for path in with_lockfile.iter() {
+            assert!(path.exists(), "{}", path.display());
+        }
+
+        let options = CleanOpt {
+            lockfile: true,
+            dry_run: false,
+        };
+        assert_eq!(clean_directory(dir, &options).unwrap(), with_lockfile);
+        for path in with_lockfile.iter() {
+            assert!(!path.exists(), "{}", path.display());
+        }
+        for kept in &[
+            "wapm.toml",
+            "app.wasm",
+            "ns-app-extra-1.0.0.tar.gz",
+            "vendor.tar.gz",
+        ] {
+            assert!(dir.join(kept).exists(), "{}", kept);
+        }
+        assert_eq!(paths_to_clean(dir, true).unwrap(), Vec::<PathBuf>::new());
+    }
+}
@@ -1,4 +1,8 @@
-use crate::config::{get, set, Config};
+use crate::config::{get, save_edited_config, set, Config};
+use rpassword_wasi as rpassword;
+use std::fs;
+use std::io::BufRead;
+use std::process::Command;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -10,6 +14,37 @@ pub enum ConfigOpt {
     #[structopt(name = "get")]
     /// Gets a key
     Get(ConfigKey),
+
+    #[structopt(name = "edit")]
+    /// Opens the config file in $EDITOR
+    Edit,
+
+    #[structopt(name = "token")]
+    /// Manages the token saved for the active registry
+    Token(TokenOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub enum TokenOpt {
+    #[structopt(name = "show")]
+    /// Prints the saved token, masked unless `--reveal` is given
+    Show {
+        /// Print the whole token
+        #[structopt(long = "reveal")]
+        reveal: bool,
+    },
+
+    #[structopt(name = "set")]
+    /// Saves a token, replacing the current one. Without an argument the token is read from
+    /// stdin, or prompted for without echoing it, so that it doesn't end up in the shell history
+    Set {
+        #[structopt(parse(from_str))]
+        token: Option<String>,
+    },
+
+    #[structopt(name = "clear")]
+    /// Removes the saved token
+    Clear,
 }
 
 #[derive(StructOpt, Debug)]
@@ -36,7 +71,129 @@ pub fn config(config_opt: ConfigOpt) -> anyhow::Result<()> {
             println!("{}", value);
             Ok(())
         }
+        ConfigOpt::Edit => edit(&config),
+        ConfigOpt::Token(token_opt) => token(&mut config, token_opt),
+    }
+}
+
+fn token(config: &mut Config, token_opt: TokenOpt) -> anyhow::Result<()> {
+    match token_opt {
+        TokenOpt::Show { reveal } => println!("{}", show_token(config, reveal)),
+        TokenOpt::Set { token } => {
+            let token = match token {
+                Some(token) => token,
+                None if stdin_is_terminal() => rpassword::prompt_password("Token: ")?,
+                None => read_token(std::io::stdin().lock())?,
+            };
+            set_token(config, token);
+            config.save()?;
+            println!("Token for {} saved", config.registry.url);
+        }
+        TokenOpt::Clear => {
+            clear_token(config);
+            config.save()?;
+            println!("Token for {} cleared", config.registry.url);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn stdin_is_terminal() -> bool {
+    atty::is(atty::Stream::Stdin)
+}
+
+#[cfg(target_os = "wasi")]
+fn stdin_is_terminal() -> bool {
+    true
+}
+
+/// The token saved for the active registry, with all but its last 4 characters masked unless
+/// `reveal` is set
+fn show_token(config: &Config, reveal: bool) -> String {
+    let token = match config.registry.token {
+        Some(ref token) => token,
+        None => return format!("No token saved for {}", config.registry.url),
+    };
+    if reveal {
+        return token.clone();
+    }
+    let chars: Vec<char> = token.chars().collect();
+    let shown = if chars.len() > 8 { 4 } else { 0 };
+    let (masked, visible) = chars.split_at(chars.len() - shown);
+    "*".repeat(masked.len()) + &visible.iter().collect::<String>()
+}
+
+/// Read a token piped to stdin: the first line, without surrounding whitespace
+fn read_token<R: BufRead>(mut input: R) -> anyhow::Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let token = line.trim();
+    if token.is_empty() {
+        bail!("No token was given on stdin");
     }
+    Ok(token.to_string())
+}
+
+/// Save `token` for the active registry
+fn set_token(config: &mut Config, token: String) {
+    config.registry.token = Some(token);
+}
+
+fn clear_token(config: &mut Config) {
+    config.registry.token = None;
+}
+
+/// The editor to open the config with, from `$VISUAL` or `$EDITOR`
+fn get_editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
+}
+
+/// Edit a copy of the config, only replacing the real one if the result is valid
+fn edit(config: &Config) -> anyhow::Result<()> {
+    let config_path = Config::get_file_location()?;
+    let contents = match fs::read_to_string(&config_path) {
+        Ok(contents) => contents,
+        Err(_) => toml::to_string(config)?,
+    };
+    let tmp_dir = tempfile::TempDir::new()?;
+    let edit_path = tmp_dir.path().join("wapm.toml");
+    fs::write(&edit_path, &contents)?;
+
+    let editor = get_editor();
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args.next().unwrap_or_default();
+    let status = Command::new(program)
+        .args(editor_args)
+        .arg(&edit_path)
+        .status()
+        .map_err(|e| anyhow!("Could not start editor `{}`: {}", editor, e))?;
+    if !status.success() {
+        bail!(
+            "Editor `{}` exited with {}, config not saved",
+            editor,
+            status
+        );
+    }
+
+    let edited = fs::read_to_string(&edit_path)?;
+    if edited == contents {
+        println!("Config unchanged");
+        return Ok(());
+    }
+    save_edited_config(&config_path, &edited)?;
+    println!("Config saved to {}", config_path.display());
+    Ok(())
 }
 
 /// Extras for testing
@@ -50,3 +207,43 @@ impl ConfigOpt {
         ConfigOpt::Get(ConfigKey { key })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{clear_token, read_token, set_token, show_token};
+    use crate::config::Config;
+
+    #[test]
+    fn tokens_round_trip_through_the_config() {
+        let mut config = Config::default();
+        set_token(&mut config, "0123456789abcdef".to_string());
+
+        let saved: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(show_token(&saved, false), "************cdef");
+        assert_eq!(show_token(&saved, true), "0123456789abcdef");
+
+        set_token(&mut config, "short".to_string());
+        assert_eq!(show_token(&config, false), "*****");
+    }
+
+    #[test]
+    fn clearing_removes_the_token() {
+        let mut config = Config::default();
+        set_token(&mut config, "0123456789abcdef".to_string());
+        clear_token(&mut config);
+
+        let saved: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.registry.token, None);
+        assert_eq!(
+            show_token(&saved, true),
+            format!("No token saved for {}", saved.registry.url)
+        );
+    }
+
+    #[test]
+    fn piped_tokens_are_read_from_the_first_line() {
+        let token = read_token(&b"  0123456789abcdef\nignored\n"[..]).unwrap();
+        assert_eq!(token, "0123456789abcdef");
+        assert!(read_token(&b"\n"[..]).is_err());
+    }
+}
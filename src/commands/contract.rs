@@ -0,0 +1,114 @@
+//! The `contract` subcommand inspects, checks and combines wasm interfaces
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use wasmer_wasm_interface::{parser, Interface};
+
+#[derive(StructOpt, Debug)]
+pub enum ContractOpt {
+    #[structopt(name = "show")]
+    /// Print the interface of a wasm module, a `.wat` file or an interface file
+    Show {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    #[structopt(name = "validate")]
+    /// Check that a wasm module satisfies an interface
+    Validate {
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// The interface file the module has to satisfy
+        #[structopt(long = "against", parse(from_os_str))]
+        contract: PathBuf,
+    },
+    #[structopt(name = "merge")]
+    /// Merge two interface files and print the result
+    Merge {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
+}
+
+pub fn contract(options: ContractOpt) -> anyhow::Result<()> {
+    let color = should_color();
+    match options {
+        ContractOpt::Show { file } => print!("{}", show(&file, color)?),
+        ContractOpt::Validate { wasm, contract } => println!("{}", validate(&wasm, &contract)?),
+        ContractOpt::Merge { a, b } => print!("{}", merge(&a, &b, color)?),
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn should_color() -> bool {
+    crate::util::wapm_should_print_color() && atty::is(atty::Stream::Stdout)
+}
+
+#[cfg(target_os = "wasi")]
+fn should_color() -> bool {
+    false
+}
+
+/// Read an interface from a wasm module, a `.wat` file or an interface file
+pub fn load(path: &Path) -> anyhow::Result<Interface> {
+    let contents =
+        fs::read(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    if contents.starts_with(b"\0asm") {
+        return Interface::from_wasm(&contents)
+            .map_err(|e| anyhow!("Could not read the interface of {}: {}", path.display(), e));
+    }
+    let text = String::from_utf8(contents).map_err(|_| {
+        anyhow!(
+            "{} is neither a wasm module nor a text file",
+            path.display()
+        )
+    })?;
+    if path
+        .extension()
+        .map_or(false, |extension| extension == "wat")
+    {
+        Interface::from_wat(&text)
+            .map_err(|e| anyhow!("Could not read the interface of {}: {}", path.display(), e))
+    } else {
+        parser::parse_interface(&text)
+            .map_err(|e| anyhow!("Could not parse the interface {}: {}", path.display(), e))
+    }
+}
+
+fn pretty(interface: &Interface, color: bool) -> anyhow::Result<String> {
+    let mut out = vec![];
+    interface.pretty_print(&mut out, color)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// The `show` subcommand
+pub fn show(file: &Path, color: bool) -> anyhow::Result<String> {
+    pretty(&load(file)?, color)
+}
+
+/// The `validate` subcommand
+pub fn validate(wasm: &Path, contract: &Path) -> anyhow::Result<String> {
+    let interface = load(contract)?;
+    let module = fs::read(wasm).map_err(|e| anyhow!("Could not read {}: {}", wasm.display(), e))?;
+    interface.validate_module(&module).map_err(|e| {
+        anyhow!(
+            "{} does not satisfy {}: {}",
+            wasm.display(),
+            contract.display(),
+            e
+        )
+    })?;
+    Ok(format!(
+        "{} satisfies {}",
+        wasm.display(),
+        contract.display()
+    ))
+}
+
+/// The `merge` subcommand
+pub fn merge(a: &Path, b: &Path, color: bool) -> anyhow::Result<String> {
+    let merged = load(a)?.merge_into(load(b)?)?;
+    pretty(&merged, color)
+}
@@ -1,6 +1,7 @@
 //! Module for wax, executes a module immediately
 
 //use crate::constants::RFC3339_FORMAT_STRING_WITH_TIMEZONE;
+use crate::commands::run::RunSettings;
 use crate::config;
 use crate::data::wax_index;
 use crate::dataflow::find_command_result::FindCommandResult;
@@ -9,7 +10,7 @@ use crate::dataflow::lockfile_packages::{LockfilePackages, LockfileResult};
 use crate::dataflow::merged_lockfile_packages::MergedLockfilePackages;
 use crate::dataflow::resolved_packages::ResolvedPackages;
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
-use crate::dataflow::WapmPackageKey;
+use crate::dataflow::{Target, WapmPackageKey};
 use crate::graphql::{execute_query, DateTime};
 //use crate::keys;
 use crate::util;
@@ -83,6 +84,10 @@ struct ExecuteOptInner {
     /// Arguments that the command will get.
     #[structopt(multiple = true, parse(from_os_str), last(true), value_name = "ARGS")]
     args: Vec<OsString>,
+
+    /// Set when the help text was requested instead of running a command.
+    #[structopt(skip)]
+    print_help: bool,
 }
 
 impl ExecuteOptInner {
@@ -180,8 +185,8 @@ fn transform_args(arg_stream: &[String]) -> Result<ExecuteOptInner, ExecuteArgPa
                 idx += 1;
             }
             "help" | "--help" | "-h" => {
-                ExecuteOptInner::print_help_text();
-                std::process::exit(0);
+                out.print_help = true;
+                return Ok(out);
             }
             misc => {
                 if misc.contains('=') {
@@ -222,6 +227,10 @@ fn transform_args(arg_stream: &[String]) -> Result<ExecuteOptInner, ExecuteArgPa
 
 pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
     let mut opt = transform_args(opt.args())?;
+    if opt.print_help {
+        ExecuteOptInner::print_help_text();
+        return Ok(());
+    }
     if !opt.no_default_preopen {
         opt.pre_opened_directories.push(".".into());
     }
@@ -292,9 +301,12 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
                 manifest_dir,
                 command_name,
                 &module_name,
-                &opt.pre_opened_directories,
-                &opt.args,
                 prehashed_cache_key,
+                &RunSettings {
+                    pre_opened_directories: opt.pre_opened_directories.clone(),
+                    args: opt.args.clone(),
+                    ..RunSettings::default()
+                },
             )?;
             return Ok(());
         }
@@ -478,15 +490,16 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
             resolved_packages,
             !opt.verify_signature,
         )?;
-        let added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
-            .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
+        let added_lockfile_data =
+            LockfilePackages::from_installed_packages(&installed_packages, &Target::host())
+                .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
 
         let retained_lockfile_packages =
             RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
         let final_lockfile_data =
             MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
         final_lockfile_data
-            .generate_lockfile(&install_loc)
+            .generate_lockfile(&install_loc, true)
             .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
 
         debug!("Wax package installed to {}", install_loc.to_string_lossy());
@@ -544,9 +557,12 @@ fn run(
                 manifest_dir,
                 command_name,
                 &module_name,
-                pre_opened_directories,
-                args,
                 prehashed_cache_key,
+                &RunSettings {
+                    pre_opened_directories: pre_opened_directories.to_vec(),
+                    args: args.to_vec(),
+                    ..RunSettings::default()
+                },
             );
         }
         FindCommandResult::Error(e) => return Err(e),
@@ -0,0 +1,313 @@
+//! Vendor the locked dependencies of a project into a directory, for offline installs with
+//! `wapm install --from <dir>`
+use crate::data::lock::lockfile::Lockfile;
+use crate::dataflow::installed_packages::download_archive;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::util::{create_package_dir, fully_qualified_package_display_name};
+use flate2::read::GzDecoder;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use tar::Archive;
+
+/// The name of the index written next to the exported archives
+pub const EXPORT_INDEX_FILE_NAME: &str = "wapm-export.json";
+
+#[derive(StructOpt, Debug)]
+pub struct ExportOpt {
+    /// The directory to download the package archives into
+    #[structopt(long = "out", parse(from_os_str))]
+    out: PathBuf,
+}
+
+/// The index of an export directory
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ExportIndex {
+    pub packages: Vec<ExportedPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ExportedPackage {
+    pub name: String,
+    pub version: String,
+    /// The archive's file name in the export directory
+    pub file: String,
+    /// Where the archive was downloaded from
+    pub download_url: String,
+    /// The hex encoded SHA-256 hash of the archive
+    pub sha256: String,
+}
+
+pub fn export(options: ExportOpt) -> anyhow::Result<()> {
+    let cwd = crate::config::Config::get_current_dir()?;
+    let lockfile = find_lockfile(&cwd)?;
+    let index = export_lockfile(&lockfile, &options.out, download_archive)?;
+    info!(
+        "Exported {} packages to {}",
+        index.packages.len(),
+        options.out.display()
+    );
+    Ok(())
+}
+
+/// The lockfile of the project in `directory`, which has to exist
+pub fn find_lockfile(directory: &Path) -> anyhow::Result<Lockfile> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => Ok(lockfile),
+        LockfileResult::NoLockfile => Err(anyhow!(
+            "No lockfile found in {}. Run `wapm install` first.",
+            directory.display()
+        )),
+        LockfileResult::LockfileError(e) => Err(e.into()),
+    }
+}
+
+/// The name, version and download URL of every registry package in `lockfile`
+fn locked_registry_packages(
+    lockfile: &Lockfile,
+) -> impl Iterator<Item = (&String, &Version, &String)> {
+    lockfile.modules.iter().flat_map(|(name, versions)| {
+        versions.iter().filter_map(move |(version, modules)| {
+            // every module of a package is downloaded in the same archive
+            match modules.values().next() {
+                Some(module) if module.resolved != "local" => {
+                    Some((name, version, &module.resolved))
+                }
+                _ => None,
+            }
+        })
+    })
+}
+
+/// Download the archive of every registry package in `lockfile` into `out` with `download`, and
+/// write the index of the archives next to them.
+fn export_lockfile<F>(
+    lockfile: &Lockfile,
+    out: &Path,
+    mut download: F,
+) -> anyhow::Result<ExportIndex>
+where
+    F: FnMut(&str, &Path) -> io::Result<()>,
+{
+    fs::create_dir_all(out)?;
+    let mut index = ExportIndex::default();
+    for (name, version, download_url) in locked_registry_packages(lockfile) {
+        let file = format!("{}-{}.tar.gz", name.replace('/', "-"), version);
+        let path = out.join(&file);
+        download(download_url, &path)
+            .map_err(|e| anyhow!("Could not download {}@{}: {}", name, version, e))?;
+        index.packages.push(ExportedPackage {
+            name: name.clone(),
+            version: version.to_string(),
+            file,
+            download_url: download_url.clone(),
+            sha256: format!("{:x}", Sha256::digest(&fs::read(&path)?)),
+        });
+    }
+    crate::util::write_file_atomically(
+        &out.join(EXPORT_INDEX_FILE_NAME),
+        serde_json::to_string_pretty(&index)?.as_bytes(),
+    )?;
+    Ok(index)
+}
+
+/// Install the registry packages in `lockfile` into the project in `directory` from the export
+/// directory `from`, checking each archive against the hash in the index. Packages that are
+/// already installed are kept. Returns how many packages were installed.
+pub fn install_from_export(
+    lockfile: &Lockfile,
+    directory: &Path,
+    from: &Path,
+) -> anyhow::Result<usize> {
+    let index_path = from.join(EXPORT_INDEX_FILE_NAME);
+    let index: ExportIndex = fs::read_to_string(&index_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        .map_err(|e| anyhow!("Could not read {}: {}", index_path.display(), e))?;
+    let mut installed = 0;
+    for (name, version, _) in locked_registry_packages(lockfile) {
+        let exported = index
+            .packages
+            .iter()
+            .find(|package| &package.name == name && package.version == version.to_string())
+            .ok_or_else(|| {
+                anyhow!(
+                    "{}@{} is not in the export in {}",
+                    name,
+                    version,
+                    from.display()
+                )
+            })?;
+        let (namespace, package_name) = crate::util::get_package_namespace_and_name(name)?;
+        let package_name = fully_qualified_package_display_name(package_name, version);
+        let package_dir = directory
+            .join(crate::data::manifest::PACKAGES_DIR_NAME)
+            .join(namespace)
+            .join(&package_name);
+        if package_dir.exists() {
+            continue;
+        }
+        let archive = fs::read(from.join(&exported.file))?;
+        if format!("{:x}", Sha256::digest(&archive)) != exported.sha256 {
+            return Err(anyhow!(
+                "The archive of {}@{} in {} does not match the hash in the index",
+                name,
+                version,
+                from.display()
+            ));
+        }
+        let package_dir = create_package_dir(directory, namespace, &package_name)?;
+        Archive::new(GzDecoder::new(&archive[..])).unpack(&package_dir)?;
+        installed += 1;
+    }
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use semver::Version;
+    use std::collections::BTreeMap;
+
+    fn module(name: &str, resolved: &str) -> LockfileModule {
+        LockfileModule {
+            name: name.to_string(),
+            resolved: resolved.to_string(),
+            ..LockfileModule::default()
+        }
+    }
+
+    fn lockfile(packages: &[(&str, &str, &str)]) -> Lockfile {
+        let mut modules = BTreeMap::new();
+        for (name, version, resolved) in packages {
+            let mut package_modules = BTreeMap::new();
+            package_modules.insert("main".to_string(), module("main", resolved));
+            package_modules.insert("extra".to_string(), module("extra", resolved));
+            modules
+                .entry(name.to_string())
+                .or_insert_with(BTreeMap::new)
+                .insert(Version::parse(version).unwrap(), package_modules);
+        }
+        Lockfile {
+            modules,
+            commands: BTreeMap::new(),
+            features: None,
+        }
+    }
+
+    #[test]
+    fn one_archive_per_locked_dependency_and_an_index() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let out = tmp_dir.path().join("vendor");
+        let lockfile = lockfile(&[
+            ("ns/app", "1.0.0", "https://registry/app-1.0.0.tar.gz"),
+            ("ns/json", "1.2.0", "https://registry/json-1.2.0.tar.gz"),
+            ("ns/json", "2.0.0", "https://registry/json-2.0.0.tar.gz"),
+            ("local/project", "0.1.0", "local"),
+        ]);
+
+        let mut downloads = vec![];
+        let index = export_lockfile(&lockfile, &out, |url, path| {
+            downloads.push(url.to_string());
+            fs::write(path, url)
+        })
+        .unwrap();
+
+        assert_eq!(
+            downloads,
+            vec![
+                "https://registry/app-1.0.0.tar.gz",
+                "https://registry/json-1.2.0.tar.gz",
+                "https://registry/json-2.0.0.tar.gz",
+            ]
+        );
+        let mut files: Vec<String> = fs::read_dir(&out)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "ns-app-1.0.0.tar.gz",
+                "ns-json-1.2.0.tar.gz",
+                "ns-json-2.0.0.tar.gz",
+                EXPORT_INDEX_FILE_NAME,
+            ]
+        );
+
+        let written: ExportIndex =
+            serde_json::from_str(&fs::read_to_string(out.join(EXPORT_INDEX_FILE_NAME)).unwrap())
+                .unwrap();
+        assert_eq!(written, index);
+        assert_eq!(written.packages[1].name, "ns/json");
+        assert_eq!(written.packages[1].file, "ns-json-1.2.0.tar.gz");
+        assert_eq!(
+            written.packages[1].sha256,
+            format!(
+                "{:x}",
+                Sha256::digest(b"https://registry/json-1.2.0.tar.gz")
+            )
+        );
+    }
+
+    /// A package archive with a manifest that names `url`
+    fn archive(url: &str) -> Vec<u8> {
+        let manifest = format!("[package]\nname = \"{}\"\n", url);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        builder
+            .append_data(&mut header, "wapm.toml", manifest.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn exported_packages_are_installed_from_the_export() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let out = tmp_dir.path().join("vendor");
+        let project = tmp_dir.path().join("project");
+        let lockfile = lockfile(&[
+            ("ns/app", "1.0.0", "https://registry/app-1.0.0.tar.gz"),
+            ("ns/json", "1.2.0", "https://registry/json-1.2.0.tar.gz"),
+            ("local/project", "0.1.0", "local"),
+        ]);
+        export_lockfile(&lockfile, &out, |url, path| fs::write(path, archive(url))).unwrap();
+
+        let installed = install_from_export(&lockfile, &project, &out).unwrap();
+        assert_eq!(installed, 2);
+        let manifest =
+            fs::read_to_string(project.join("wapm_packages/ns/json@1.2.0/wapm.toml")).unwrap();
+        assert!(manifest.contains("json-1.2.0"), "{}", manifest);
+        assert!(project
+            .join("wapm_packages/ns/app@1.0.0/wapm.toml")
+            .exists());
+        // installed packages are kept
+        assert_eq!(install_from_export(&lockfile, &project, &out).unwrap(), 0);
+
+        // archives that changed since the export are refused
+        fs::remove_dir_all(project.join("wapm_packages/ns/app@1.0.0")).unwrap();
+        fs::write(out.join("ns-app-1.0.0.tar.gz"), archive("tampered")).unwrap();
+        let error = install_from_export(&lockfile, &project, &out).unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+
+        // so are lockfiles with packages that weren't exported
+        let newer = self::lockfile(&[("ns/json", "2.0.0", "https://registry/json-2.0.0.tar.gz")]);
+        let error = install_from_export(&newer, &project, &out).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("ns/json@2.0.0 is not in the export"),
+            "{}",
+            error
+        );
+    }
+}
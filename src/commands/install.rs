@@ -1,20 +1,29 @@
 //! Code pertaining to the `install` subcommand
 
+use crate::commands::export::{find_lockfile, install_from_export};
 use crate::graphql::execute_query;
 
 use graphql_client::*;
 
-use crate::config::Config;
+use crate::config::{Config, GlobalConfigError};
+use crate::data::manifest::{FeatureSelection, Manifest, PACKAGES_DIR_NAME};
 use crate::dataflow;
+use crate::dataflow::bin_script::{save_lockfile_bin_scripts, BIN_DIR_NAME};
+use crate::dataflow::installed_packages::RegistryInstaller;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::resolved_packages::{OfflineResolver, RegistryResolver};
+use crate::dataflow::update_plan::UpdatePlan;
+use crate::dataflow::{Target, UpdateOptions};
 use crate::util;
 use std::borrow::Cow;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use thiserror::Error;
 
 /// Options for the `install` subcommand
 #[derive(StructOpt, Debug)]
 pub struct InstallOpt {
+    /// Packages to install, as `name`, `name@version` or `name@<range>` (e.g. `foo/bar@^1.2`)
     packages: Vec<String>,
     /// Install the package(s) globally
     #[structopt(short = "g", long = "global")]
@@ -22,10 +31,68 @@ pub struct InstallOpt {
     /// Agree to all prompts. Useful for non-interactive uses. (WARNING: this may cause undesired behavior)
     #[structopt(long = "force-yes", short = "y")]
     force_yes: bool,
+    /// Resolve the packages and print the changes that would be made, without downloading
+    /// anything or writing to the lockfile
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+    /// Fail if the lockfile would need to be changed
+    #[structopt(long = "locked")]
+    locked: bool,
+    /// Like `--locked`, but also fail if any package would need to be downloaded
+    #[structopt(long = "frozen")]
+    frozen: bool,
+    /// Install the packages without creating entry points for their commands
+    #[structopt(long = "no-commands")]
+    no_commands: bool,
+    /// Only create the entry points for the commands of the packages already in the lockfile,
+    /// without installing anything
+    #[structopt(long = "commands-only", conflicts_with_all = &["packages", "no-commands"])]
+    commands_only: bool,
+    /// Expose the command of the package under this name instead of its own, e.g. when two
+    /// packages have a command with the same name. The alias is recorded in the manifest
+    #[structopt(long = "alias", conflicts_with_all = &["global", "commands-only"])]
+    alias: Option<String>,
+    /// Install the variants of modules built for this operating system, e.g. `linux`.
+    /// Defaults to the host's
+    #[structopt(long = "platform")]
+    platform: Option<String>,
+    /// Install the variants of modules built for this CPU architecture, e.g. `x86_64`.
+    /// Defaults to the host's
+    #[structopt(long = "arch")]
+    arch: Option<String>,
+    /// Only install the dependencies of the manifest, without adding the modules and commands
+    /// of the local package to the lockfile
+    #[structopt(long = "deps-only", conflicts_with_all = &["packages", "global", "commands-only"])]
+    deps_only: bool,
+    /// Fail if a chain of dependencies is longer than this, counting the project's own
+    /// dependencies as depth 1. Unlimited by default
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+    /// Fail instead of warning when an updated dependency no longer provides an export, or
+    /// provides it with another signature
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Install the optional dependencies of these features of the manifest, separated by commas
+    #[structopt(long = "features", use_delimiter = true)]
+    features: Vec<String>,
+    /// Install the optional dependencies of every feature of the manifest
+    #[structopt(long = "all-features")]
+    all_features: bool,
+    /// Don't enable the manifest's `default` feature
+    #[structopt(long = "no-default-features")]
+    no_default_features: bool,
+    /// Install the locked dependencies from a directory written by `wapm export` instead of
+    /// downloading them. The lockfile must be up to date
+    #[structopt(
+        long = "from",
+        parse(from_os_str),
+        conflicts_with_all = &["packages", "global", "commands-only", "dry-run"]
+    )]
+    from: Option<PathBuf>,
 }
 
 #[derive(Debug, Error)]
-enum InstallError {
+pub(crate) enum InstallError {
     #[error("Package not found in the registry: {name}")]
     PackageNotFound { name: String },
 
@@ -48,6 +115,24 @@ enum InstallError {
     InvalidPackageIdentifier { name: String },
     #[error("Must supply package names to install command when using --global/-g flag.")]
     MustSupplyPackagesWithGlobalFlag,
+
+    #[error("The lockfile needs to be updated but {flag} was passed. Changes:\n{changes}")]
+    LockfileWouldChange { flag: &'static str, changes: String },
+
+    #[error("Packages need to be downloaded but --frozen was passed: {packages}")]
+    FrozenRequiresDownload { packages: String },
+
+    #[error("Package {name} must be given an exact version when using --frozen")]
+    FrozenRequiresVersion { name: String },
+
+    #[error("Could not create the command entry points. {0}")]
+    CannotCreateCommands(String),
+
+    #[error("--alias can only be used when installing a single package")]
+    AliasRequiresOnePackage,
+
+    #[error("Could not find a manifest to record the alias in, try running `wapm init`")]
+    AliasRequiresManifest,
 }
 
 #[derive(GraphQLQuery)]
@@ -78,17 +163,81 @@ pub fn install(options: InstallOpt) -> anyhow::Result<()> {
         "this function should only be called once!"
     );
 
+    let mut update_options = UpdateOptions {
+        create_commands: !options.no_commands,
+        target: target(options.platform.as_deref(), options.arch.as_deref()),
+        include_local_package: !options.deps_only,
+        max_depth: options.max_depth,
+        strict_exports: options.strict,
+        features: feature_selection(&options),
+        ..UpdateOptions::default()
+    };
+
+    if options.alias.is_some() && options.packages.len() != 1 {
+        return Err(InstallError::AliasRequiresOnePackage.into());
+    }
+
+    if let Some(ref from) = options.from {
+        let lockfile = find_lockfile(&current_directory)?;
+        let installed = install_from_export(&lockfile, &current_directory, from)?;
+        // every locked package is installed now, so nothing is resolved or downloaded
+        check_lockfile_is_current(vec![], &current_directory, &update_options, false, true)?;
+        dataflow::update_with::<OfflineResolver, RegistryInstaller, _>(
+            vec![],
+            vec![],
+            &current_directory,
+            update_options,
+        )
+        .map_err(InstallError::FailureInstallingPackages)?;
+        util::print_success(&format!(
+            "{} packages installed to wapm_packages from {}",
+            installed,
+            from.display()
+        ));
+        return Ok(());
+    }
+
+    if options.commands_only {
+        let directory = install_directory(
+            options.global,
+            &current_directory,
+            Config::get_global_install_directory,
+        )?;
+        create_command_entry_points(&directory)?;
+        util::print_success("Command entry points created!");
+        return Ok(());
+    }
+
     match (options.global, options.packages.is_empty()) {
         (global_flag::GLOBAL_INSTALL, package_args::NO_PACKAGES) => {
             // install all global packages - unacceptable use case
             return Err(InstallError::MustSupplyPackagesWithGlobalFlag.into());
         }
         (global_flag::LOCAL_INSTALL, package_args::NO_PACKAGES) => {
+            if options.dry_run {
+                let plan =
+                    dataflow::plan_update(vec![], vec![], &current_directory, &update_options)
+                        .map_err(InstallError::FailureInstallingPackages)?;
+                print!("{}", render_update_plan(&plan));
+                return Ok(());
+            }
+            check_lockfile_is_current(
+                vec![],
+                &current_directory,
+                &update_options,
+                options.locked,
+                options.frozen,
+            )?;
             // install all packages locally
             let added_packages = vec![];
-            dataflow::update(added_packages, vec![], &current_directory)
-                .map_err(|err| InstallError::FailureInstallingPackages(err))?;
-            println!("Packages installed to wapm_packages!");
+            dataflow::update_with::<RegistryResolver, RegistryInstaller, _>(
+                added_packages,
+                vec![],
+                &current_directory,
+                update_options,
+            )
+            .map_err(InstallError::FailureInstallingPackages)?;
+            util::print_success("Packages installed to wapm_packages!");
         }
         (_, package_args::SOME_PACKAGES) => {
             let mut packages = vec![];
@@ -99,6 +248,12 @@ pub fn install(options: InstallOpt) -> anyhow::Result<()> {
                     [package_name, package_version] => {
                         packages.push((package_name.to_string(), package_version.to_string()));
                     }
+                    [name] if options.frozen => {
+                        return Err(InstallError::FrozenRequiresVersion {
+                            name: name.to_string(),
+                        }
+                        .into());
+                    }
                     [name] => {
                         let q = GetPackageQuery::build_query(get_package_query::Variables {
                             name: name.to_string(),
@@ -130,32 +285,614 @@ pub fn install(options: InstallOpt) -> anyhow::Result<()> {
                 .map(|(s1, s2)| (s1.as_str(), s2.as_str()))
                 .collect();
 
-            // the install directory will determine which wapm.lock we are updating. For now, we
-            // look in the local directory, or the global install directory
-            let install_directory: Cow<Path> = match options.global {
-                true => {
-                    let folder = Config::get_globals_directory()?;
-                    Cow::Owned(folder)
-                }
-                false => Cow::Borrowed(&current_directory),
-            };
+            let install_directory = install_directory(
+                options.global,
+                &current_directory,
+                Config::get_global_install_directory,
+            )?;
+            if options.dry_run {
+                let plan = dataflow::plan_update(
+                    installed_packages,
+                    vec![],
+                    install_directory,
+                    &update_options,
+                )
+                .map_err(InstallError::CannotRegenLockFile)?;
+                print!("{}", render_update_plan(&plan));
+                return Ok(());
+            }
+            check_lockfile_is_current(
+                installed_packages.clone(),
+                &install_directory,
+                &update_options,
+                options.locked,
+                options.frozen,
+            )?;
             std::fs::create_dir_all(install_directory.clone())
                 .map_err(|err| InstallError::CannotCreateInstallDirectory(err))?;
+            // the alias is only saved once the install succeeded, but the install has to
+            // apply it so that the command doesn't collide
+            if let Some(ref alias) = options.alias {
+                Manifest::find_in_directory(&install_directory)
+                    .map_err(|_| InstallError::AliasRequiresManifest)?;
+                update_options
+                    .command_aliases
+                    .insert(packages[0].0.clone(), alias.clone());
+            }
 
-            let changes_applied =
-                dataflow::update(installed_packages.clone(), vec![], install_directory)
-                    .map_err(|err| InstallError::CannotRegenLockFile(err))?;
+            let changes_applied = dataflow::update_with::<RegistryResolver, RegistryInstaller, _>(
+                installed_packages.clone(),
+                vec![],
+                &install_directory,
+                update_options,
+            )
+            .map_err(InstallError::CannotRegenLockFile)?;
+            if let Some(alias) = options.alias {
+                record_alias(&install_directory, &packages[0].0, alias)?;
+            }
 
             if changes_applied {
                 if options.global {
-                    println!("Global package installed successfully!");
+                    util::print_success("Global package installed successfully!");
+                    if !options.no_commands {
+                        let bin_directory =
+                            install_directory.join(PACKAGES_DIR_NAME).join(BIN_DIR_NAME);
+                        util::print_success(&format!(
+                            "Its commands are in {}, add it to your PATH to run them directly.",
+                            bin_directory.display()
+                        ));
+                    }
                 } else {
-                    println!("Package installed successfully to wapm_packages!");
+                    util::print_success("Package installed successfully to wapm_packages!");
                 }
             } else {
-                println!("No packages to install")
+                util::print_success("No packages to install")
             }
         }
     }
     Ok(())
 }
+
+/// The directory whose wapm.lock an install updates: the global install directory returned by
+/// `global_directory` with `--global`, otherwise the current directory. Global packages are
+/// recorded in the global lockfile and their commands are placed in its `wapm_packages/.bin`.
+fn install_directory<F>(
+    global: bool,
+    current_directory: &Path,
+    global_directory: F,
+) -> anyhow::Result<Cow<'_, Path>>
+where
+    F: FnOnce() -> Result<PathBuf, GlobalConfigError>,
+{
+    Ok(match global {
+        true => Cow::Owned(global_directory()?),
+        false => Cow::Borrowed(current_directory),
+    })
+}
+
+/// The target to install module variants for: the host, with the platform and architecture
+/// overridden by `--platform` and `--arch`
+fn target(platform: Option<&str>, arch: Option<&str>) -> Target {
+    let host = Target::host();
+    Target {
+        platform: platform.map_or(host.platform, str::to_string),
+        arch: arch.map_or(host.arch, str::to_string),
+    }
+}
+
+/// Record in the manifest in `directory` that the command of `package_name` is exposed as
+/// `alias`.
+fn record_alias(directory: &Path, package_name: &str, alias: String) -> anyhow::Result<()> {
+    let mut manifest =
+        Manifest::find_in_directory(directory).map_err(|_| InstallError::AliasRequiresManifest)?;
+    manifest.set_command_alias(package_name.to_string(), alias);
+    manifest.save()
+}
+
+/// Create the entry points for the commands in the lockfile in `directory`.
+fn create_command_entry_points(directory: &Path) -> anyhow::Result<()> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => save_lockfile_bin_scripts(directory, &lockfile)
+            .map_err(|e| InstallError::CannotCreateCommands(e.to_string()).into()),
+        LockfileResult::NoLockfile => Err(InstallError::CannotCreateCommands(
+            "There is no lockfile, run `wapm install` first.".to_string(),
+        )
+        .into()),
+        LockfileResult::LockfileError(e) => {
+            Err(InstallError::CannotCreateCommands(e.to_string()).into())
+        }
+    }
+}
+
+/// The features selected with `--features`, `--all-features` and `--no-default-features`, if
+/// any of them is given. Otherwise the features recorded in the lockfile are kept.
+fn feature_selection(options: &InstallOpt) -> Option<FeatureSelection> {
+    if options.features.is_empty() && !options.all_features && !options.no_default_features {
+        return None;
+    }
+    Some(FeatureSelection {
+        features: options.features.clone(),
+        all_features: options.all_features,
+        default_features: !options.no_default_features,
+    })
+}
+
+/// Enforce `--locked` and `--frozen`: resolving the packages must not change the lockfile, and
+/// with `--frozen` nothing may be fetched from the registry.
+fn check_lockfile_is_current(
+    added_packages: Vec<(&str, &str)>,
+    directory: &Path,
+    options: &UpdateOptions,
+    locked: bool,
+    frozen: bool,
+) -> anyhow::Result<()> {
+    if frozen {
+        let plan = dataflow::plan_update_with::<OfflineResolver, _>(
+            added_packages,
+            vec![],
+            directory,
+            options,
+        )
+        .map_err(InstallError::CannotRegenLockFile)?;
+        check_plan(&plan, "--frozen", true)?;
+    } else if locked {
+        let plan = dataflow::plan_update(added_packages, vec![], directory, options)
+            .map_err(InstallError::CannotRegenLockFile)?;
+        check_plan(&plan, "--locked", false)?;
+    }
+    Ok(())
+}
+
+fn check_plan(plan: &UpdatePlan, flag: &'static str, frozen: bool) -> Result<(), InstallError> {
+    if !plan.added.is_empty() || !plan.removed.is_empty() {
+        let changes = plan
+            .added
+            .iter()
+            .map(|key| format!("  + {}@{}", key.name, key.version))
+            .chain(
+                plan.removed
+                    .iter()
+                    .map(|key| format!("  - {}@{}", key.name, key.version)),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(InstallError::LockfileWouldChange { flag, changes });
+    }
+    if frozen && !plan.to_download.is_empty() {
+        let packages = plan
+            .to_download
+            .iter()
+            .map(|key| format!("{}@{}", key.name, key.version))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(InstallError::FrozenRequiresDownload { packages });
+    }
+    Ok(())
+}
+
+/// Describe the packages an install would download and the resulting lockfile changes.
+fn render_update_plan(plan: &UpdatePlan) -> String {
+    if plan.is_empty() {
+        return "No packages to install\n".to_string();
+    }
+    let mut out = String::new();
+    if !plan.to_download.is_empty() {
+        out.push_str("Packages that would be downloaded:\n");
+        for key in plan.to_download.iter() {
+            out.push_str(&format!("  {}@{}\n", key.name, key.version));
+        }
+    }
+    out.push_str("Lockfile changes:\n");
+    for key in plan.added.iter() {
+        out.push_str(&format!("  + {}@{}\n", key.name, key.version));
+    }
+    for key in plan.removed.iter() {
+        out.push_str(&format!("  - {}@{}\n", key.name, key.version));
+    }
+    out.push_str("Dry run: nothing was downloaded or written.\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_plan, create_command_entry_points, install_directory, render_update_plan, target,
+        InstallError,
+    };
+    use crate::data::manifest::{FeatureSelection, PACKAGES_DIR_NAME};
+    use crate::dataflow;
+    use crate::dataflow::bin_script::BIN_DIR_NAME;
+    use crate::dataflow::lockfile_packages::LockfileResult;
+    use crate::dataflow::resolved_packages::OfflineResolver;
+    use crate::dataflow::update_plan::UpdatePlan;
+    use crate::dataflow::{Target, UpdateOptions, WapmPackageKey};
+    use crate::fixtures::{ExportsInstaller, TestInstaller, TestResolver, VariantInstaller};
+    use std::fs;
+
+    #[test]
+    fn install_selects_the_module_variant_for_the_target() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let options = UpdateOptions {
+            target: target(Some("windows"), Some("x86_64")),
+            ..UpdateOptions::default()
+        };
+        dataflow::update_with::<TestResolver, VariantInstaller, _>(
+            vec![("hello", "1.0.0")],
+            vec![],
+            tmp_dir.path(),
+            options,
+        )
+        .unwrap();
+
+        let lockfile = match LockfileResult::find_in_directory(tmp_dir.path()) {
+            LockfileResult::Lockfile(lockfile) => lockfile,
+            otherwise => panic!("expected a lockfile: {:?}", otherwise),
+        };
+        let module = &lockfile.modules["_/hello"][&semver::Version::new(1, 0, 0)]["hello"];
+        assert_eq!(module.source, "hello-windows-x86_64.wasm");
+        assert_eq!(module.target.as_deref(), Some("windows-x86_64"));
+
+        let host = target(None, None);
+        assert_eq!(host, Target::host());
+        assert_eq!(target(Some("linux"), None).arch, host.arch);
+    }
+
+    #[test]
+    fn aliases_in_the_options_are_applied_but_not_saved() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = "[package]\nname = \"_/app\"\nversion = \"1.0.0\"\ndescription = \"app\"\n";
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest).unwrap();
+        dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![("_/hello", "1.0.0")],
+            vec![],
+            tmp_dir.path(),
+            UpdateOptions::default(),
+        )
+        .unwrap();
+
+        let mut options = UpdateOptions::default();
+        options
+            .command_aliases
+            .insert("_/greeter".to_string(), "greet".to_string());
+        dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![("_/greeter", "1.0.0")],
+            vec![],
+            tmp_dir.path(),
+            options,
+        )
+        .unwrap();
+
+        let lockfile = match LockfileResult::find_in_directory(tmp_dir.path()) {
+            LockfileResult::Lockfile(lockfile) => lockfile,
+            otherwise => panic!("expected a lockfile: {:?}", otherwise),
+        };
+        assert_eq!(
+            lockfile.get_command("greet").unwrap().package_name,
+            "_/greeter"
+        );
+        assert_eq!(
+            lockfile.get_command("hello").unwrap().package_name,
+            "_/hello"
+        );
+        let saved = fs::read_to_string(tmp_dir.path().join("wapm.toml")).unwrap();
+        assert!(!saved.contains("\"greet\""), "{}", saved);
+    }
+
+    #[test]
+    fn install_fails_without_a_module_variant_for_the_target() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let options = UpdateOptions {
+            target: target(Some("macos"), Some("aarch64")),
+            ..UpdateOptions::default()
+        };
+        let error = dataflow::update_with::<TestResolver, VariantInstaller, _>(
+            vec![("hello", "1.0.0")],
+            vec![],
+            tmp_dir.path(),
+            options,
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Package _/hello has no variant of the module \"hello\" for macos-aarch64"
+            ),
+            "{}",
+            error
+        );
+        assert!(!tmp_dir.path().join("wapm.lock").exists());
+    }
+
+    #[test]
+    fn render_dry_run_plan() {
+        let foo = WapmPackageKey {
+            name: "_/foo".into(),
+            version: semver::Version::new(1, 0, 0),
+        };
+        let old_foo = WapmPackageKey {
+            name: "_/foo".into(),
+            version: semver::Version::new(0, 9, 0),
+        };
+        let plan = UpdatePlan {
+            to_download: vec![foo.clone()],
+            added: vec![foo],
+            removed: vec![old_foo],
+        };
+        assert_eq!(
+            render_update_plan(&plan),
+            "Packages that would be downloaded:\n  _/foo@1.0.0\nLockfile changes:\n  + _/foo@1.0.0\n  - _/foo@0.9.0\nDry run: nothing was downloaded or written.\n"
+        );
+        assert_eq!(
+            render_update_plan(&UpdatePlan::default()),
+            "No packages to install\n"
+        );
+    }
+
+    #[test]
+    fn locked_fails_when_manifest_dependency_is_not_in_lockfile() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = r#"
+[package]
+name = "locked-test"
+version = "1.0.0"
+description = "test package"
+"#;
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest).unwrap();
+        dataflow::update(vec![], vec![], tmp_dir.path()).unwrap();
+
+        let plan = dataflow::plan_update_with::<OfflineResolver, _>(
+            vec![],
+            vec![],
+            tmp_dir.path(),
+            &UpdateOptions::default(),
+        )
+        .unwrap();
+        assert!(check_plan(&plan, "--locked", false).is_ok());
+
+        let manifest = format!("{}\n[dependencies]\n\"_/foo\" = \"1.0.0\"\n", manifest);
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest).unwrap();
+        let lockfile_before = fs::read_to_string(tmp_dir.path().join("wapm.lock")).unwrap();
+
+        let plan = dataflow::plan_update_with::<OfflineResolver, _>(
+            vec![],
+            vec![],
+            tmp_dir.path(),
+            &UpdateOptions::default(),
+        )
+        .unwrap();
+        match check_plan(&plan, "--locked", false) {
+            Err(InstallError::LockfileWouldChange { changes, .. }) => {
+                assert_eq!(changes, "  + _/foo@1.0.0")
+            }
+            otherwise => panic!("expected the lockfile to be out of date: {:?}", otherwise),
+        }
+        let lockfile_after = fs::read_to_string(tmp_dir.path().join("wapm.lock")).unwrap();
+        assert_eq!(lockfile_before, lockfile_after);
+    }
+
+    #[test]
+    fn no_commands_leaves_the_bin_directory_untouched() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let bin_dir = tmp_dir.path().join(PACKAGES_DIR_NAME).join(BIN_DIR_NAME);
+        let options = UpdateOptions {
+            create_commands: false,
+            ..UpdateOptions::default()
+        };
+
+        dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![("hello", "1.0.0")],
+            vec![],
+            tmp_dir.path(),
+            options,
+        )
+        .unwrap();
+        assert!(tmp_dir
+            .path()
+            .join("wapm_packages/_/hello@1.0.0/hello.wasm")
+            .exists());
+        assert!(!bin_dir.exists());
+
+        // the entry points can be created afterwards with `--commands-only`
+        create_command_entry_points(tmp_dir.path()).unwrap();
+        assert_eq!(fs::read_dir(&bin_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn deps_only_installs_dependencies_without_the_local_package() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = r#"
+[package]
+name = "_/app"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "app"
+source = "app.wasm"
+
+[[command]]
+name = "app"
+module = "app"
+
+[dependencies]
+"_/hello" = "1.0.0"
+"#;
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest).unwrap();
+        let options = UpdateOptions {
+            include_local_package: false,
+            ..UpdateOptions::default()
+        };
+
+        dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![],
+            vec![],
+            tmp_dir.path(),
+            options,
+        )
+        .unwrap();
+
+        assert!(tmp_dir
+            .path()
+            .join("wapm_packages/_/hello@1.0.0/hello.wasm")
+            .exists());
+        let lockfile = match LockfileResult::find_in_directory(tmp_dir.path()) {
+            LockfileResult::Lockfile(lockfile) => lockfile,
+            otherwise => panic!("expected a lockfile: {:?}", otherwise),
+        };
+        assert_eq!(lockfile.modules.keys().collect::<Vec<_>>(), vec!["_/hello"]);
+        assert_eq!(lockfile.commands.keys().collect::<Vec<_>>(), vec!["hello"]);
+        let bin_dir = tmp_dir.path().join(PACKAGES_DIR_NAME).join(BIN_DIR_NAME);
+        assert_eq!(fs::read_dir(&bin_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn global_commands_are_placed_in_the_global_bin_directory() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let global_dir = tmp_dir.path().join("global");
+        let project_dir = tmp_dir.path().join("project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let directory = install_directory(true, &project_dir, || Ok(global_dir.clone())).unwrap();
+        assert_eq!(directory, global_dir);
+        fs::create_dir_all(&directory).unwrap();
+        dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![("hello", "1.0.0")],
+            vec![],
+            &directory,
+            UpdateOptions::default(),
+        )
+        .unwrap();
+
+        let global_bin = global_dir.join(PACKAGES_DIR_NAME).join(BIN_DIR_NAME);
+        assert_eq!(fs::read_dir(&global_bin).unwrap().count(), 1);
+        let global_lockfile = fs::read_to_string(global_dir.join("wapm.lock")).unwrap();
+        assert!(
+            global_lockfile.contains("[commands.hello]"),
+            "{}",
+            global_lockfile
+        );
+        assert!(!project_dir.join(PACKAGES_DIR_NAME).exists());
+        assert!(!project_dir.join("wapm.lock").exists());
+    }
+
+    #[test]
+    fn optional_dependencies_are_installed_with_their_feature() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = |default_features: &str| {
+            format!(
+                "[package]\nname = \"_/app\"\nversion = \"0.1.0\"\ndescription = \"test package\"\n\n[dependencies]\n\"_/math\" = \"1.0.0\"\n\"_/json\" = \"1.0.0\"\n\n[features]\ndefault = [{}]\njson = [\"_/json\"]\n",
+                default_features
+            )
+        };
+        let installed = |features: Option<FeatureSelection>| {
+            dataflow::update_with::<TestResolver, ExportsInstaller, _>(
+                vec![],
+                vec![],
+                tmp_dir.path(),
+                UpdateOptions {
+                    features,
+                    include_local_package: false,
+                    ..UpdateOptions::default()
+                },
+            )
+            .unwrap();
+            let lockfile = match LockfileResult::find_in_directory(tmp_dir.path()) {
+                LockfileResult::Lockfile(lockfile) => lockfile,
+                otherwise => panic!("expected a lockfile: {:?}", otherwise),
+            };
+            let mut packages: Vec<String> = lockfile.modules.keys().cloned().collect();
+            packages.sort();
+            packages
+        };
+
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest("")).unwrap();
+        assert_eq!(installed(None), vec!["_/math"]);
+        let json = FeatureSelection {
+            features: vec!["json".to_string()],
+            ..FeatureSelection::default()
+        };
+        assert_eq!(installed(Some(json)), vec!["_/json", "_/math"]);
+        // updates that don't select features, like `wapm uninstall`, keep the recorded ones
+        assert_eq!(installed(None), vec!["_/json", "_/math"]);
+        assert_eq!(installed(Some(FeatureSelection::default())), vec!["_/math"]);
+        assert_eq!(installed(None), vec!["_/math"]);
+        let all_features = FeatureSelection {
+            all_features: true,
+            ..FeatureSelection::default()
+        };
+        assert_eq!(installed(Some(all_features)), vec!["_/json", "_/math"]);
+
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest("\"json\"")).unwrap();
+        assert_eq!(
+            installed(Some(FeatureSelection::default())),
+            vec!["_/json", "_/math"]
+        );
+        let no_default_features = FeatureSelection {
+            default_features: false,
+            ..FeatureSelection::default()
+        };
+        assert_eq!(installed(Some(no_default_features)), vec!["_/math"]);
+        assert_eq!(installed(None), vec!["_/math"]);
+
+        let unknown = FeatureSelection {
+            features: vec!["yaml".to_string()],
+            ..FeatureSelection::default()
+        };
+        let error = dataflow::update_with::<TestResolver, ExportsInstaller, _>(
+            vec![],
+            vec![],
+            tmp_dir.path(),
+            UpdateOptions {
+                features: Some(unknown),
+                ..UpdateOptions::default()
+            },
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("The manifest has no feature \"yaml\"."),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn updates_that_remove_exports_warn_or_fail_with_strict() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = |version: &str| {
+            format!(
+                "[package]\nname = \"_/app\"\nversion = \"0.1.0\"\ndescription = \"test package\"\n\n[dependencies]\n\"_/math\" = \"{}\"\n",
+                version
+            )
+        };
+        let update = |strict_exports: bool| {
+            dataflow::update_with::<TestResolver, ExportsInstaller, _>(
+                vec![],
+                vec![],
+                tmp_dir.path(),
+                UpdateOptions {
+                    strict_exports,
+                    ..UpdateOptions::default()
+                },
+            )
+        };
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest("1.0.0")).unwrap();
+        update(true).unwrap();
+
+        fs::write(tmp_dir.path().join("wapm.toml"), manifest("2.0.0")).unwrap();
+        match update(true) {
+            Err(dataflow::Error::IncompatibleExports(problems)) => assert_eq!(
+                problems,
+                "_/math 1.0.0 → 2.0.0: module \"math\" no longer provides export \"add\": (i32, i32)->(i32)"
+            ),
+            otherwise => panic!("expected the removed export to fail: {:?}", otherwise),
+        }
+        let lockfile = fs::read_to_string(tmp_dir.path().join("wapm.lock")).unwrap();
+        assert!(lockfile.contains("1.0.0"), "{}", lockfile);
+
+        // without `--strict` the update goes ahead with a warning
+        assert!(update(false).unwrap());
+        let lockfile = fs::read_to_string(tmp_dir.path().join("wapm.lock")).unwrap();
+        assert!(lockfile.contains("2.0.0"), "{}", lockfile);
+    }
+}
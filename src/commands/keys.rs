@@ -8,7 +8,7 @@ use crate::util;
 use graphql_client::*;
 use prettytable::{format, Table};
 use rusqlite::Connection;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -32,6 +32,10 @@ pub enum KeyOpt {
     #[structopt(name = "generate")]
     /// Generate a keypair for use with package signing
     Generate(Generate),
+
+    #[structopt(name = "export")]
+    /// Print a personal public key, e.g. to share it or register it elsewhere
+    Export(Export),
 }
 
 /// Print the keys wapm knows about in a table
@@ -80,6 +84,17 @@ pub struct Generate {
     force: bool,
 }
 
+/// Prints a personal public key in minisign's format
+#[derive(StructOpt, Debug)]
+pub struct Export {
+    /// The identifier of the public key. Defaults to the active key
+    public_key_id: Option<String>,
+
+    #[structopt(long = "public")]
+    /// Export the public key. Private keys are never exported
+    public: bool,
+}
+
 /// Import a public key from somewhere else
 #[derive(StructOpt, Debug)]
 pub struct Import {
@@ -191,43 +206,10 @@ pub fn keys(options: KeyOpt) -> anyhow::Result<()> {
             import_public_key(&mut key_db, &pk_id, &pkv, user_name)?;
         }
         KeyOpt::Generate(Generate { key_path, force }) => {
-            let private_key_path = key_path.join("minisign.key");
-            let public_key_path = key_path.join("minisign.pub");
-
-            if !key_path.exists() {
-                return Err(anyhow!(
-                    "Path {} does not exist!",
-                    &key_path.as_os_str().to_string_lossy()
-                ));
-            }
-            if !force {
-                if private_key_path.exists() {
-                    return Err(anyhow!(
-                        "Private key file, {}, exists",
-                        &private_key_path.as_os_str().to_string_lossy()
-                    ));
-                }
-
-                if public_key_path.exists() {
-                    return Err(anyhow!(
-                        "Public key file, {}, exists",
-                        &public_key_path.as_os_str().to_string_lossy()
-                    ));
-                }
-            }
-
-            let private_key_file = std::fs::File::create(&private_key_path)?;
-            let public_key_file = std::fs::File::create(&public_key_path)?;
-
             info!("Generating key pair!");
-
-            let keypair = minisign::KeyPair::generate_and_write_encrypted_keypair(
-                public_key_file,
-                private_key_file,
-                None,
-                // None causes minisign to prompt for the password
-                None,
-            )?;
+            // no password makes minisign prompt for it
+            let (public_key_path, private_key_path, keypair) =
+                generate_key_pair(&key_path, force, None)?;
 
             info!(
                 "Key pair successfully generated! Public key is: {}",
@@ -242,11 +224,81 @@ pub fn keys(options: KeyOpt) -> anyhow::Result<()> {
                 private_key_path.to_string_lossy().to_string(),
             )?;
         }
+        KeyOpt::Export(Export {
+            public_key_id,
+            public,
+        }) => {
+            if !public {
+                return Err(anyhow!(
+                    "Only public keys can be exported, pass `--public` to export one"
+                ));
+            }
+            let key = match public_key_id {
+                Some(public_key_id) => get_personal_keys_from_database(&key_db)?
+                    .into_iter()
+                    .find(|key| key.public_key_id == public_key_id)
+                    .ok_or_else(|| anyhow!("No personal key with the id {:?}", public_key_id))?,
+                None => get_active_personal_key(&key_db)?,
+            };
+            print!("{}", export_public_key(&key)?);
+        }
     }
 
     Ok(())
 }
 
+/// Write a new key pair to `minisign.pub` and `minisign.key` in `key_path`, encrypting the
+/// private key with `password`. Existing keys are only overwritten if `force` is set. Returns
+/// the paths of the public and private keys and the key pair.
+fn generate_key_pair(
+    key_path: &Path,
+    force: bool,
+    password: Option<String>,
+) -> anyhow::Result<(PathBuf, PathBuf, minisign::KeyPair)> {
+    let private_key_path = key_path.join("minisign.key");
+    let public_key_path = key_path.join("minisign.pub");
+
+    if !key_path.exists() {
+        return Err(anyhow!(
+            "Path {} does not exist!",
+            &key_path.as_os_str().to_string_lossy()
+        ));
+    }
+    if !force {
+        if private_key_path.exists() {
+            return Err(anyhow!(
+                "Private key file, {}, exists",
+                &private_key_path.as_os_str().to_string_lossy()
+            ));
+        }
+
+        if public_key_path.exists() {
+            return Err(anyhow!(
+                "Public key file, {}, exists",
+                &public_key_path.as_os_str().to_string_lossy()
+            ));
+        }
+    }
+
+    let private_key_file = std::fs::File::create(&private_key_path)?;
+    let public_key_file = std::fs::File::create(&public_key_path)?;
+
+    let keypair = minisign::KeyPair::generate_and_write_encrypted_keypair(
+        public_key_file,
+        private_key_file,
+        None,
+        password,
+    )?;
+    Ok((public_key_path, private_key_path, keypair))
+}
+
+/// The public key of `key` in minisign's format, as it's written to `minisign.pub`
+fn export_public_key(key: &PersonalKey) -> anyhow::Result<String> {
+    Ok(minisign::PublicKey::from_base64(&key.public_key_value)?
+        .to_box()?
+        .into_string())
+}
+
 pub fn create_personal_key_table(keys: Vec<PersonalKey>) -> anyhow::Result<String> {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
@@ -283,3 +335,71 @@ pub fn create_wapm_public_key_table(keys: Vec<WapmPublicKey>) -> anyhow::Result<
     }
     Ok(format!("{}", table))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn generated_key_pairs_sign_and_verify() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let key_path: &Path = tmp_dir.as_ref();
+        let (public_key_path, private_key_path, keypair) =
+            generate_key_pair(key_path, false, Some("password".to_string())).unwrap();
+
+        let secret_key =
+            minisign::SecretKey::from_file(&private_key_path, Some("password".to_string()))
+                .unwrap();
+        let public_key = minisign::PublicKey::from_file(&public_key_path).unwrap();
+        assert_eq!(public_key.to_base64(), keypair.pk.to_base64());
+        let data = b"package contents";
+        let signature =
+            minisign::sign(Some(&public_key), &secret_key, &data[..], false, None, None).unwrap();
+        minisign::verify(
+            &public_key,
+            &signature,
+            std::io::Cursor::new(&data[..]),
+            true,
+            false,
+        )
+        .unwrap();
+
+        assert!(generate_key_pair(key_path, false, Some("password".to_string())).is_err());
+        assert!(
+            minisign::SecretKey::from_file(&private_key_path, Some("wrong".to_string())).is_err()
+        );
+    }
+
+    #[test]
+    fn registered_keys_are_listed_and_exported() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let key_path: &Path = tmp_dir.as_ref();
+        let (public_key_path, private_key_path, _) =
+            generate_key_pair(key_path, false, Some("password".to_string())).unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
+        database::apply_migrations(&mut conn).unwrap();
+
+        let (public_key_id, public_key_value, tx) = add_personal_key_pair_to_database(
+            &mut conn,
+            public_key_path.to_string_lossy().to_string(),
+            private_key_path.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        let keys = get_personal_keys_from_database(&conn).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys[0].active);
+        assert_eq!(keys[0].public_key_id, public_key_id);
+        assert_eq!(keys[0].public_key_value, public_key_value);
+        let active = get_active_personal_key(&conn).unwrap();
+        assert_eq!(active.public_key_id, public_key_id);
+
+        let exported = export_public_key(&active).unwrap();
+        assert_eq!(
+            normalize_public_key(exported).unwrap(),
+            (public_key_id, public_key_value)
+        );
+    }
+}
@@ -2,11 +2,15 @@
 
 mod add;
 mod bin;
+mod cache;
+mod clean;
 mod completions;
 mod config;
+pub(crate) mod contract;
 mod execute;
+mod export;
 mod init;
-mod install;
+pub(crate) mod install;
 #[cfg(feature = "full")]
 mod keys;
 #[cfg(feature = "full")]
@@ -14,20 +18,28 @@ mod list;
 mod login;
 mod logout;
 #[cfg(feature = "full")]
+mod package;
+#[cfg(feature = "full")]
 mod publish;
 mod remove;
-mod run;
+pub(crate) mod run;
 #[cfg(feature = "full")]
 mod search;
 mod uninstall;
 mod validate;
+mod validate_manifest;
 mod whoami;
+mod why;
 
 pub use self::add::{add, AddOpt};
 pub use self::bin::{bin, BinOpt};
+pub use self::cache::{cache, CacheOpt};
+pub use self::clean::{clean, CleanOpt};
 pub use self::completions::CompletionOpt;
 pub use self::config::{config, ConfigOpt};
+pub use self::contract::{contract, ContractOpt};
 pub use self::execute::{execute, ExecuteOpt};
+pub use self::export::{export, ExportOpt};
 pub use self::init::{init, InitOpt};
 pub use self::install::{install, InstallOpt};
 #[cfg(feature = "full")]
@@ -37,11 +49,15 @@ pub use self::list::{list, ListOpt};
 pub use self::login::{login, LoginOpt};
 pub use self::logout::logout;
 #[cfg(feature = "full")]
-pub use self::publish::{publish, PublishOpt};
+pub use self::package::{package, PackageOpt};
+#[cfg(feature = "full")]
+pub use self::publish::{publish, publish_package, PublishOpt, PublishOptions, SizeLimits};
 pub use self::remove::{remove, RemoveOpt};
 pub use self::run::{run, RunOpt};
 #[cfg(feature = "full")]
-pub use self::search::{search, SearchOpt};
+pub use self::search::{search, search_packages, SearchOpt, SearchResult};
 pub use self::uninstall::{uninstall, UninstallOpt};
 pub use self::validate::{validate, ValidateOpt};
-pub use self::whoami::whoami;
+pub use self::validate_manifest::{validate_manifest, ValidateManifestOpt};
+pub use self::whoami::{whoami, WhoAmIOpt};
+pub use self::why::{why, WhyOpt};
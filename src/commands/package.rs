@@ -0,0 +1,545 @@
+//! The package command builds the tarball that `wapm publish` uploads, without uploading it.
+use crate::data::ignore::IgnorePatterns;
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME, PACKAGES_DIR_NAME};
+
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use structopt::StructOpt;
+use tar::Builder;
+use thiserror::Error;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(StructOpt, Debug)]
+pub struct PackageOpt {
+    /// Where to write the tarball. Defaults to `<name>-<version>.tar.gz` in the current directory
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Print the files in the tarball and their SHA-256 hashes
+    #[structopt(long = "manifest")]
+    manifest: bool,
+    /// Bundle the source files matching the manifest's `source` patterns under `src/`
+    #[structopt(long = "include-source")]
+    include_source: bool,
+    /// Leave the README out of the package, even if the manifest names one
+    #[structopt(long = "no-readme")]
+    no_readme: bool,
+}
+
+/// The bundled contents of a package, exactly as they are uploaded to the registry
+#[derive(Debug)]
+pub struct PackageArchive {
+    pub manifest: Manifest,
+    pub readme: Option<String>,
+    pub license_file: Option<String>,
+    /// The gzipped tarball
+    pub data: Vec<u8>,
+    /// The files in the tarball, in the order they were added
+    pub files: Vec<PackagedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackagedFile {
+    /// The path of the file in the tarball
+    pub path: String,
+    /// The hex encoded SHA-256 hash of the file's contents
+    pub sha256: String,
+    /// The uncompressed size of the file in bytes
+    pub size: u64,
+}
+
+impl PackageArchive {
+    /// The default file name for the tarball, e.g. `namespace-name-1.0.0.tar.gz`
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}-{}.tar.gz",
+            self.manifest.package.name.replace('/', "-"),
+            self.manifest.package.version
+        )
+    }
+}
+
+pub fn package(package_opts: PackageOpt) -> anyhow::Result<()> {
+    let cwd = crate::config::Config::get_current_dir()?;
+    let (archive, output) = write_package(
+        &cwd,
+        package_opts.output.as_deref(),
+        package_opts.include_source,
+        !package_opts.no_readme,
+    )?;
+
+    if package_opts.manifest {
+        for file in archive.files.iter() {
+            println!("{}  {}", file.sha256, file.path);
+        }
+    }
+    info!("Wrote package to {}", output.display());
+    Ok(())
+}
+
+/// Build the package in `cwd` and write the tarball to `output`, or to the default file name in
+/// `cwd`. Returns the package and where it was written.
+pub fn write_package(
+    cwd: &Path,
+    output: Option<&Path>,
+    include_source: bool,
+    include_readme: bool,
+) -> anyhow::Result<(PackageArchive, PathBuf)> {
+    let archive = build_package(cwd, include_source, include_readme)?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| cwd.join(archive.file_name()));
+    fs::write(&output, &archive.data)?;
+    Ok((archive, output))
+}
+
+fn normalize_path(cwd: &Path, path: &Path) -> PathBuf {
+    let mut out = PathBuf::from(cwd);
+    let mut components = path.components();
+    if path.is_absolute() {
+        warn!(
+            "Interpreting absolute path {} as a relative path",
+            path.to_string_lossy()
+        );
+        components.next();
+    }
+    for comp in components {
+        out.push(comp);
+    }
+    out
+}
+
+/// Bundle the manifest, modules, bindings, readme, license and package filesystem of the
+/// package in `cwd` into a gzipped tarball. With `include_source`, the files matching the
+/// manifest's `source` patterns are bundled under `src/` too. Unless `include_readme` is unset,
+/// a README found next to the manifest is bundled and the bundled manifest's `readme` field
+/// points at it.
+pub fn build_package(
+    cwd: &Path,
+    include_source: bool,
+    include_readme: bool,
+) -> anyhow::Result<PackageArchive> {
+    let mut builder = Builder::new(Vec::new());
+    let cwd = cwd.to_path_buf();
+
+    let mut manifest = Manifest::find_in_directory(&cwd)?;
+    if !include_readme {
+        manifest.package.readme = None;
+    }
+
+    let manifest_path_buf = cwd.join(MANIFEST_FILE_NAME);
+    let manifest_contents = with_readme_field(
+        &fs::read_to_string(&manifest_path_buf)?,
+        manifest.package.readme.as_deref(),
+    )?;
+    let mut header = reproducible_header(tar::EntryType::Regular);
+    header.set_size(manifest_contents.len() as u64);
+    builder.append_data(
+        &mut header,
+        MANIFEST_FILE_NAME,
+        manifest_contents.as_bytes(),
+    )?;
+    let package = &manifest.package;
+    let modules = manifest.module.as_ref().ok_or(PackageError::NoModule)?;
+
+    // paths in the manifest are relative to `cwd` and are kept relative in the archive
+    let in_archive = |path: &Path| normalize_path(Path::new(""), path);
+
+    let readme = package.readme.as_ref().and_then(|readme_path| {
+        let name = in_archive(readme_path);
+        if let Err(e) = append_file(&mut builder, &cwd.join(&name), &name) {
+            warn!(
+                "Could not add the readme \"{}\" to the package: {}",
+                name.display(),
+                e
+            );
+        }
+        fs::read_to_string(cwd.join(name)).ok()
+    });
+    let license_file = package.license_file.as_ref().and_then(|license_file_path| {
+        let name = in_archive(license_file_path);
+        if let Err(e) = append_file(&mut builder, &cwd.join(&name), &name) {
+            warn!(
+                "Could not add the license file \"{}\" to the package: {}",
+                name.display(),
+                e
+            );
+        }
+        fs::read_to_string(cwd.join(name)).ok()
+    });
+
+    for module in modules {
+        let mut names = vec![in_archive(&module.source)];
+        if let Some(bindings) = &module.bindings {
+            names.extend(
+                bindings
+                    .referenced_files(&cwd)
+                    .iter()
+                    .map(|p| in_archive(p)),
+            );
+        }
+        for name in names {
+            cwd.join(&name)
+                .metadata()
+                .map_err(|_| PackageError::SourceMustBeFile(module.name.clone()))?;
+            append_file(&mut builder, &cwd.join(&name), &name)
+                .map_err(|_| PackageError::ErrorBuildingPackage(module.name.clone()))?;
+        }
+    }
+
+    // bundle the package filesystem, leaving out anything the `.wapmignore` matches
+    let ignore = IgnorePatterns::from_directory(&cwd)?;
+    let mut fs_entries: Vec<(String, PathBuf)> = manifest
+        .fs
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    fs_entries.sort();
+    for (_alias, path) in fs_entries.iter() {
+        let normalized_path = normalize_path(&cwd, path);
+        let path_metadata = normalized_path.metadata().map_err(|_| {
+            PackageError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
+        })?;
+        if path_metadata.is_dir() {
+            append_dir_without_ignored(&mut builder, path, &normalized_path, &ignore)
+        } else {
+            return Err(PackageError::PackageFileSystemEntryMustBeDirectory(
+                path.to_string_lossy().to_string(),
+            )
+            .into());
+        }
+        .map_err(|_| {
+            PackageError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
+        })?;
+    }
+
+    if include_source {
+        let patterns = package.source.as_deref().unwrap_or_default();
+        if patterns.is_empty() {
+            warn!("`--include-source` was given, but the manifest has no `source` patterns");
+        }
+        let source = IgnorePatterns::parse(&patterns.join("\n"));
+        append_source_files(&mut builder, &cwd, Path::new(""), &source, &ignore)?;
+    }
+
+    let tar_archive_data = builder.into_inner()?;
+    let files = hash_files(&tar_archive_data)?;
+    let mut gz_enc = GzEncoder::new(Vec::new(), Compression::default());
+    gz_enc.write_all(&tar_archive_data)?;
+    let data = gz_enc.finish()?;
+
+    Ok(PackageArchive {
+        manifest,
+        readme,
+        license_file,
+        data,
+        files,
+    })
+}
+
+/// The manifest `contents` with its `readme` field set to `readme`, or removed if it's `None`.
+/// A manifest whose field already matches is returned unchanged.
+fn with_readme_field(contents: &str, readme: Option<&Path>) -> anyhow::Result<String> {
+    let mut value: toml::Value = toml::from_str(contents)?;
+    let package = match value.get_mut("package").and_then(toml::Value::as_table_mut) {
+        Some(package) => package,
+        None => return Ok(contents.to_string()),
+    };
+    let declared = package.get("readme").and_then(toml::Value::as_str);
+    if declared.map(Path::new) == readme {
+        return Ok(contents.to_string());
+    }
+    match readme {
+        Some(readme) => package.insert(
+            "readme".to_string(),
+            toml::Value::String(readme.to_string_lossy().to_string()),
+        ),
+        None => package.remove("readme"),
+    };
+    Ok(toml::to_string(&value)?)
+}
+
+/// Append the file at `src_path` to the tarball as `name`. The header only depends on the
+/// file's contents and whether it's executable, so that building the same package twice
+/// produces the same bytes.
+fn append_file<W: Write>(
+    builder: &mut Builder<W>,
+    src_path: &Path,
+    name: &Path,
+) -> std::io::Result<()> {
+    let contents = fs::read(src_path)?;
+    let mut header = reproducible_header(tar::EntryType::Regular);
+    header.set_size(contents.len() as u64);
+    if is_executable(&fs::metadata(src_path)?) {
+        header.set_mode(0o755);
+    }
+    builder.append_data(&mut header, name, contents.as_slice())
+}
+
+/// Append a directory entry to the tarball, like `append_file`
+fn append_dir<W: Write>(builder: &mut Builder<W>, name: &Path) -> std::io::Result<()> {
+    let mut header = reproducible_header(tar::EntryType::Directory);
+    header.set_mode(0o755);
+    header.set_size(0);
+    builder.append_data(&mut header, name, std::io::empty())
+}
+
+/// A header without timestamps or owners, with the permissions normalized to `0o644`
+fn reproducible_header(entry_type: tar::EntryType) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Hash the regular files in an uncompressed tarball
+fn hash_files(tar_archive_data: &[u8]) -> std::io::Result<Vec<PackagedFile>> {
+    let mut archive = tar::Archive::new(tar_archive_data);
+    let mut files = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        files.push(PackagedFile {
+            path,
+            sha256: format!("{:x}", Sha256::digest(&contents)),
+            size: contents.len() as u64,
+        });
+    }
+    Ok(files)
+}
+
+/// Like `Builder::append_dir_all`, but skips the entries `ignore` matches. `path` is the
+/// directory's path in the archive, relative to the package root.
+fn append_dir_without_ignored<W: Write>(
+    builder: &mut Builder<W>,
+    path: &Path,
+    src_path: &Path,
+    ignore: &IgnorePatterns,
+) -> std::io::Result<()> {
+    if ignore.is_ignored(path, true) {
+        return Ok(());
+    }
+    append_dir(builder, path)?;
+    let mut entries = fs::read_dir(src_path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let entry_path = path.join(entry.file_name());
+        if entry.path().is_dir() {
+            append_dir_without_ignored(builder, &entry_path, &entry.path(), ignore)?;
+        } else if !ignore.is_ignored(&entry_path, false) {
+            append_file(builder, &entry.path(), &entry_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Append the files in `directory`, relative to the package root `cwd`, that match `source`
+/// under `src/`. Dependencies and the entries `ignore` matches are skipped.
+fn append_source_files<W: Write>(
+    builder: &mut Builder<W>,
+    cwd: &Path,
+    directory: &Path,
+    source: &IgnorePatterns,
+    ignore: &IgnorePatterns,
+) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(cwd.join(directory))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = directory.join(entry.file_name());
+        let is_dir = entry.path().is_dir();
+        if path == Path::new(PACKAGES_DIR_NAME) || ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            append_source_files(builder, cwd, &path, source, ignore)?;
+        } else if source.is_ignored(&path, false) {
+            append_file(builder, &entry.path(), &Path::new("src").join(&path))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum PackageError {
+    #[error("Cannot publish without a module.")]
+    NoModule,
+    #[error("Module \"{0}\" must have a source that is a file.")]
+    SourceMustBeFile(String),
+    #[error("Error building package when parsing module \"{0}\".")]
+    ErrorBuildingPackage(String),
+    #[error(
+        "Path \"{0}\", specified in the manifest as part of the package file system does not exist.",
+    )]
+    MissingManifestFsPath(String),
+    #[error("When processing the package filesystem, found path \"{0}\" which is not a directory")]
+    PackageFileSystemEntryMustBeDirectory(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wapmignore_patterns_are_left_out_of_the_package() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let static_dir = tmp_dir.path().join("static");
+        fs::create_dir_all(static_dir.join("cache")).unwrap();
+        for file in &["index.html", "debug.log", "keep.log", "cache/data.bin"] {
+            fs::write(static_dir.join(file), "contents").unwrap();
+        }
+        let ignore = IgnorePatterns::parse("*.log\n!keep.log\ncache/\n");
+
+        let mut builder = Builder::new(Vec::new());
+        append_dir_without_ignored(&mut builder, Path::new("static"), &static_dir, &ignore)
+            .unwrap();
+        let data = builder.into_inner().unwrap();
+        let mut archive = tar::Archive::new(data.as_slice());
+        let paths: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec!["static", "static/index.html", "static/keep.log"]
+        );
+    }
+
+    #[test]
+    fn source_files_are_only_bundled_with_include_source() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join(MANIFEST_FILE_NAME),
+            r#"
+[package]
+name = "test/sourced"
+version = "0.1.0"
+description = "test package"
+source = ["src/**/*.rs", "Cargo.toml"]
+
+[[module]]
+name = "main"
+source = "main.wasm"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(cwd.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(cwd.join("notes.txt"), "not source").unwrap();
+        fs::create_dir_all(cwd.join("src").join("bin")).unwrap();
+        fs::write(cwd.join("src").join("lib.rs"), "").unwrap();
+        fs::write(cwd.join("src").join("bin").join("main.rs"), "").unwrap();
+        fs::write(cwd.join("src").join("README.md"), "").unwrap();
+        fs::create_dir_all(cwd.join(PACKAGES_DIR_NAME).join("src")).unwrap();
+        fs::write(cwd.join(PACKAGES_DIR_NAME).join("src").join("dep.rs"), "").unwrap();
+
+        let paths = |archive: &PackageArchive| -> Vec<String> {
+            archive.files.iter().map(|f| f.path.clone()).collect()
+        };
+        let without_source = build_package(cwd, false, true).unwrap();
+        assert_eq!(paths(&without_source), vec!["wapm.toml", "main.wasm"]);
+
+        let with_source = build_package(cwd, true, true).unwrap();
+        assert_eq!(
+            paths(&with_source),
+            vec![
+                "wapm.toml",
+                "main.wasm",
+                "src/Cargo.toml",
+                "src/src/bin/main.rs",
+                "src/src/lib.rs",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn building_twice_produces_identical_bytes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join(MANIFEST_FILE_NAME),
+            r#"
+[package]
+name = "test/reproducible"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+
+[fs]
+"b" = "b"
+"a" = "a"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        for dir in &["a", "b"] {
+            fs::create_dir(cwd.join(dir)).unwrap();
+            fs::write(cwd.join(dir).join("z.txt"), "z").unwrap();
+            fs::write(cwd.join(dir).join("y.txt"), "y").unwrap();
+        }
+        let first = build_package(cwd, false, true).unwrap();
+
+        // touching the files or changing their permissions doesn't change the archive
+        let file = fs::File::options()
+            .write(true)
+            .open(cwd.join("a").join("z.txt"))
+            .unwrap();
+        file.set_modified(
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000),
+        )
+        .unwrap();
+        fs::set_permissions(
+            cwd.join("b").join("y.txt"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        let second = build_package(cwd, false, true).unwrap();
+        assert_eq!(first.data, second.data);
+        let paths: Vec<&str> = second.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "wapm.toml",
+                "main.wasm",
+                "a/y.txt",
+                "a/z.txt",
+                "b/y.txt",
+                "b/z.txt"
+            ]
+        );
+
+        // only the executable bit of a file is kept
+        fs::set_permissions(cwd.join("main.wasm"), fs::Permissions::from_mode(0o700)).unwrap();
+        assert_ne!(build_package(cwd, false, true).unwrap().data, first.data);
+    }
+}
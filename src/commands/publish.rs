@@ -1,21 +1,20 @@
 //! The publish command uploads the package specified in the Manifest (`wapm.toml`)
 //! to the wapm registry.
-use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::commands::package::{self, PackageArchive};
+use crate::data::manifest::Manifest;
+use crate::data::workspace::find_workspace_members;
 use crate::database;
-use crate::graphql::execute_query_modifier;
 use crate::keys;
-use crate::util::create_temp_dir;
+use crate::progress;
+use crate::registry::{publish_package_mutation, RegistryClient};
+use crate::util::{self, create_temp_dir};
 use crate::validate;
 
-use flate2::{write::GzEncoder, Compression};
-use graphql_client::*;
 use rpassword_wasi as rpassword;
 use structopt::StructOpt;
-use tar::Builder;
 use thiserror::Error;
 
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
 #[derive(StructOpt, Debug)]
@@ -23,116 +22,328 @@ pub struct PublishOpt {
     /// Run the publish logic without sending anything to the registry server
     #[structopt(long = "dry-run")]
     dry_run: bool,
+    /// Publish without checking the modules are valid wasm and satisfy their interfaces
+    #[structopt(long = "skip-validation")]
+    skip_validation: bool,
+    /// Read the registry token from this file instead of the config, like `WAPM_TOKEN_FILE`
+    #[structopt(long = "token-file", parse(from_os_str))]
+    token_file: Option<PathBuf>,
+    /// Warn when the compressed tarball is larger than this many bytes
+    #[structopt(long = "max-size", default_value = "52428800")]
+    max_size: u64,
+    /// Warn when the package contains more than this many files
+    #[structopt(long = "max-files", default_value = "1000")]
+    max_files: usize,
+    /// Fail instead of warning when the package exceeds `--max-size` or `--max-files`
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Send at most this many requests per second to the registry, counting the uploads of all
+    /// workspace members together. Requests the registry rate limits are retried after its
+    /// `Retry-After` delay either way
+    #[structopt(long = "rate-limit", parse(try_from_str = parse_rate_limit))]
+    rate_limit: Option<f64>,
+    /// The directory of the package to publish. Defaults to the current directory
+    #[structopt(parse(from_os_str))]
+    directory: Option<PathBuf>,
+    /// The directory of the package to publish, like the positional argument
+    #[structopt(long = "path", parse(from_os_str), conflicts_with = "directory")]
+    path: Option<PathBuf>,
+    /// Bundle the source files matching the manifest's `source` patterns under `src/`
+    #[structopt(long = "include-source")]
+    include_source: bool,
+    /// Publish without a README, even if the manifest names one
+    #[structopt(long = "no-readme")]
+    no_readme: bool,
+    /// Publish every member of the workspace instead of the package itself
+    #[structopt(long = "workspace")]
+    workspace: bool,
+    /// Upload at most this many workspace members at a time
+    #[structopt(long = "jobs", default_value = "4")]
+    jobs: usize,
 }
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/publish_package.graphql",
-    response_derives = "Debug"
-)]
-struct PublishPackageMutation;
-
-fn normalize_path(cwd: &Path, path: &Path) -> PathBuf {
-    let mut out = PathBuf::from(cwd);
-    let mut components = path.components();
-    if path.is_absolute() {
-        warn!(
-            "Interpreting absolute path {} as a relative path",
-            path.to_string_lossy()
-        );
-        components.next();
+impl PublishOpt {
+    /// The root of the package to publish, with relative paths resolved from `cwd`
+    fn package_dir(&self, cwd: &Path) -> PathBuf {
+        match self.path.as_ref().or(self.directory.as_ref()) {
+            Some(directory) => cwd.join(directory),
+            None => cwd.to_path_buf(),
+        }
+    }
+}
+
+fn parse_rate_limit(s: &str) -> Result<f64, PublishError> {
+    s.parse()
+        .ok()
+        .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| PublishError::InvalidRateLimit(s.to_string()))
+}
+
+/// How big a package can be before publish warns about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// The size of the compressed tarball in bytes
+    pub max_size: u64,
+    pub max_files: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            max_size: 50 * 1024 * 1024,
+            max_files: 1000,
+        }
     }
-    for comp in components {
-        out.push(comp);
+}
+
+/// How [`publish_package`] publishes a package. The default validates the package and
+/// publishes it with its README.
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// Do everything but the upload
+    pub dry_run: bool,
+    pub skip_validation: bool,
+    /// A package exceeding these is only published with a warning, unless `strict` is set
+    pub limits: SizeLimits,
+    pub strict: bool,
+    /// Caps the requests per second sent to the registry
+    pub rate_limit: Option<f64>,
+    /// Bundle the package's source files
+    pub include_source: bool,
+    pub include_readme: bool,
+    /// Publish the members of the workspace instead of the package
+    pub workspace: bool,
+    /// How many workspace members are uploaded at a time
+    pub jobs: usize,
+    /// Authenticate with the token in this file instead of the configured one
+    pub token_file: Option<PathBuf>,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        PublishOptions {
+            dry_run: false,
+            skip_validation: false,
+            limits: SizeLimits::default(),
+            strict: false,
+            rate_limit: None,
+            include_source: false,
+            include_readme: true,
+            workspace: false,
+            jobs: 4,
+            token_file: None,
+        }
     }
-    out
 }
 
+/// How many of the largest files to list when a package is too large
+const LARGEST_FILES_SHOWN: usize = 5;
+
 pub fn publish(publish_opts: PublishOpt) -> anyhow::Result<()> {
-    let mut builder = Builder::new(Vec::new());
     let cwd = crate::config::Config::get_current_dir()?;
+    let package_dir = publish_opts.package_dir(&cwd);
+    let options = PublishOptions {
+        dry_run: publish_opts.dry_run,
+        skip_validation: publish_opts.skip_validation,
+        limits: SizeLimits {
+            max_size: publish_opts.max_size,
+            max_files: publish_opts.max_files,
+        },
+        strict: publish_opts.strict,
+        rate_limit: publish_opts.rate_limit,
+        include_source: publish_opts.include_source,
+        include_readme: !publish_opts.no_readme,
+        workspace: publish_opts.workspace,
+        jobs: publish_opts.jobs,
+        token_file: publish_opts.token_file,
+    };
+    publish_package(&package_dir, &options)
+}
 
-    validate::validate_directory(cwd.clone())?;
+/// Check the package in `cwd` before it's published, unless `skip_validation` is set
+fn validate_package(cwd: &Path, skip_validation: bool) -> anyhow::Result<()> {
+    if skip_validation {
+        warn!("Skipping validation of the package");
+        return Ok(());
+    }
+    validate::validate_directory(cwd.to_path_buf())
+}
 
-    let manifest = Manifest::find_in_directory(&cwd)?;
+/// Warn if the package exceeds `limits`, or fail if `strict` is set
+fn check_package_size(
+    archive: &PackageArchive,
+    limits: SizeLimits,
+    strict: bool,
+) -> Result<(), PublishError> {
+    match describe_oversized_package(archive, limits) {
+        Some(problem) if strict => Err(PublishError::PackageTooLarge(problem)),
+        Some(problem) => {
+            warn!("{}", problem);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
 
-    let manifest_path_buf = cwd.join(MANIFEST_FILE_NAME);
-    builder.append_path_with_name(&manifest_path_buf, MANIFEST_FILE_NAME)?;
-    let package = &manifest.package;
-    let modules = manifest.module.as_ref().ok_or(PublishError::NoModule)?;
-    let manifest_string = toml::to_string(&manifest)?;
+/// Describe how the package exceeds `limits` and list its largest files, or `None` if it's
+/// within them
+fn describe_oversized_package(archive: &PackageArchive, limits: SizeLimits) -> Option<String> {
+    let size = archive.data.len() as u64;
+    let mut problems = vec![];
+    if size > limits.max_size {
+        problems.push(format!(
+            "The package is {}, more than the limit of {}.",
+            format_size(size),
+            format_size(limits.max_size)
+        ));
+    }
+    if archive.files.len() > limits.max_files {
+        problems.push(format!(
+            "The package has {} files, more than the limit of {}.",
+            archive.files.len(),
+            limits.max_files
+        ));
+    }
+    if problems.is_empty() {
+        return None;
+    }
 
-    let readme = package.readme.as_ref().and_then(|readme_path| {
-        let normalized_path = normalize_path(&manifest.base_directory_path, &readme_path);
-        if let Err(_) = builder.append_path(&normalized_path) {
-            // Maybe do something here
+    let mut largest: Vec<&package::PackagedFile> = archive.files.iter().collect();
+    largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    problems.push("The largest files are:".to_string());
+    for file in largest.into_iter().take(LARGEST_FILES_SHOWN) {
+        problems.push(format!("  {} ({})", file.path, format_size(file.size)));
+    }
+    Some(problems.join("\n"))
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Validate, bundle, sign and upload the package in `cwd`, or the members of its workspace
+pub fn publish_package(cwd: &Path, options: &PublishOptions) -> anyhow::Result<()> {
+    let directories = if options.workspace {
+        let manifest = Manifest::find_in_directory(cwd)?;
+        if manifest.workspace.is_none() {
+            return Err(PublishError::NotAWorkspace.into());
         }
-        fs::read_to_string(normalized_path).ok()
-    });
-    let license_file = package.license_file.as_ref().and_then(|license_file_path| {
-        let normalized_path = normalize_path(&manifest.base_directory_path, &license_file_path);
-        if let Err(_) = builder.append_path(&normalized_path) {
-            // Maybe do something here
+        find_workspace_members(&manifest)?
+            .into_iter()
+            .map(|member| member.directory)
+            .collect()
+    } else {
+        vec![cwd.to_path_buf()]
+    };
+
+    let archive_dir = create_temp_dir()?;
+    let packages = directories
+        .iter()
+        .enumerate()
+        .map(|(i, directory)| {
+            prepare_package(
+                directory,
+                options,
+                &archive_dir.as_ref().join(i.to_string()),
+            )
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if options.dry_run {
+        for package in packages.iter() {
+            util::print_success(&format!(
+                "Successfully published package `{}@{}`",
+                package.name, package.version
+            ));
         }
-        fs::read_to_string(normalized_path).ok()
-    });
-
-    for module in modules {
-        let normalized_path = normalize_path(&manifest.base_directory_path, &module.source);
-        normalized_path
-            .metadata()
-            .map_err(|_| PublishError::SourceMustBeFile(module.name.clone()))?;
-        builder
-            .append_path(normalized_path)
-            .map_err(|_| PublishError::ErrorBuildingPackage(module.name.clone()))?;
-
-        if let Some(bindings) = &module.bindings {
-            for path in bindings.referenced_files(&manifest.base_directory_path) {
-                let normalized_path = normalize_path(&manifest.base_directory_path, &path);
-                normalized_path
-                    .metadata()
-                    .map_err(|_| PublishError::SourceMustBeFile(module.name.clone()))?;
-                builder
-                    .append_path(normalized_path)
-                    .map_err(|_| PublishError::ErrorBuildingPackage(module.name.clone()))?;
+        info!(
+            "Publish succeeded, but package was not published because it was run in dry-run mode"
+        );
+        return Ok(());
+    }
+
+    let mut client = RegistryClient::from_config_with_token_file(options.token_file.as_deref())?;
+    if let Some(rate_limit) = options.rate_limit {
+        client = client.with_rate_limit(rate_limit);
+    }
+    let names: Vec<_> = packages
+        .iter()
+        .map(|package| (package.name.clone(), package.version.clone()))
+        .collect();
+    let uploads = packages
+        .into_iter()
+        .map(|package| (package.variables, package.archive_path))
+        .collect();
+    let mut failures = vec![];
+    for ((name, version), published) in names
+        .into_iter()
+        .zip(client.publish_all(uploads, options.jobs))
+    {
+        match published {
+            Ok(_) => {
+                progress::report(|sink| sink.on_published(&name, &version));
+                util::print_success(&format!(
+                    "Successfully published package `{}@{}`",
+                    name, version
+                ));
+            }
+            Err(e) => {
+                #[cfg(feature = "telemetry")]
+                sentry::integrations::anyhow::capture_anyhow(&e);
+                failures.push((name, version, e));
             }
         }
     }
+    match failures.len() {
+        0 => Ok(()),
+        1 => Err(failures.pop().unwrap().2),
+        _ => Err(PublishError::UploadsFailed(
+            failures
+                .into_iter()
+                .map(|(name, version, e)| format!("  {}@{}: {}", name, version, e))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+        .into()),
+    }
+}
 
-    // bundle the package filesystem
-    for (_alias, path) in manifest.fs.unwrap_or_default().iter() {
-        let normalized_path = normalize_path(&cwd, &path);
-        let path_metadata = normalized_path.metadata().map_err(|_| {
-            PublishError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
-        })?;
-        if path_metadata.is_dir() {
-            builder.append_dir_all(path, &normalized_path)
-        } else {
-            return Err(PublishError::PackageFileSystemEntryMustBeDirectory(
-                path.to_string_lossy().to_string(),
-            )
-            .into());
-        }
-        .map_err(|_| {
-            PublishError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
-        })?;
+/// A package that has been validated, bundled and signed, ready to be uploaded
+struct PreparedPackage {
+    name: String,
+    version: semver::Version,
+    variables: publish_package_mutation::Variables,
+    archive_path: PathBuf,
+}
+
+/// Validate, bundle and sign the package in `cwd`, writing its tarball to `archive_dir`
+fn prepare_package(
+    cwd: &Path,
+    options: &PublishOptions,
+    archive_dir: &Path,
+) -> anyhow::Result<PreparedPackage> {
+    validate_package(cwd, options.skip_validation)?;
+
+    let archive = package::build_package(cwd, options.include_source, options.include_readme)?;
+    check_package_size(&archive, options.limits, options.strict)?;
+    if let Some(warning) = describe_missing_readme(&archive, options.include_readme) {
+        warn!("{}", warning);
     }
+    let manifest_string = toml::to_string(&archive.manifest)?;
+    let package = &archive.manifest.package;
 
-    builder.finish().ok();
-    let tar_archive_data = builder.into_inner().map_err(|_|
-                                                        // TODO:
-                                                        PublishError::NoModule)?;
     let archive_name = "package.tar.gz".to_string();
-    let archive_dir = create_temp_dir()?;
-    let archive_dir_path: &std::path::Path = archive_dir.as_ref();
-    fs::create_dir(archive_dir_path.join("wapm_package"))?;
-    let archive_path = archive_dir_path.join("wapm_package").join(&archive_name);
-    let mut compressed_archive = fs::File::create(&archive_path).unwrap();
-    let mut gz_enc = GzEncoder::new(&mut compressed_archive, Compression::default());
-
-    gz_enc.write_all(&tar_archive_data).unwrap();
-    let _compressed_archive = gz_enc.finish().unwrap();
+    let archive_path = write_archive(&archive, archive_dir, &archive_name)?;
     let mut compressed_archive_reader = fs::File::open(&archive_path)?;
 
     let maybe_signature_data = match sign_compressed_archive(&mut compressed_archive_reader)? {
@@ -156,59 +367,67 @@ pub fn publish(publish_opts: PublishOpt) -> anyhow::Result<()> {
         }
     };
 
-    let q = PublishPackageMutation::build_query(publish_package_mutation::Variables {
+    let variables = publish_package_mutation::Variables {
         name: package.name.to_string(),
         version: package.version.to_string(),
         description: package.description.clone(),
         manifest: manifest_string,
         license: package.license.clone(),
-        license_file,
-        readme,
+        license_file: archive.license_file.clone(),
+        readme: archive.readme.clone(),
         repository: package.repository.clone(),
         homepage: package.homepage.clone(),
-        file_name: Some(archive_name.clone()),
+        file_name: Some(archive_name),
         signature: maybe_signature_data,
-    });
+    };
     assert!(archive_path.exists());
     assert!(archive_path.is_file());
-    if !publish_opts.dry_run {
-        let _response: publish_package_mutation::ResponseData =
-            execute_query_modifier(&q, |f| f.file(archive_name, archive_path).unwrap()).map_err(
-                |e| {
-                    #[cfg(feature = "telemetry")]
-                    sentry::integrations::anyhow::capture_anyhow(&e);
-                    e
-                },
-            )?;
-    }
-
-    println!(
-        "Successfully published package `{}@{}`",
-        package.name, package.version
-    );
+    Ok(PreparedPackage {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        variables,
+        archive_path,
+    })
+}
 
-    if publish_opts.dry_run {
-        info!(
-            "Publish succeeded, but package was not published because it was run in dry-run mode"
-        );
+/// Why the registry won't be able to show a README for the package, if it won't. Nothing is
+/// said when the README was left out on purpose.
+fn describe_missing_readme(archive: &PackageArchive, include_readme: bool) -> Option<String> {
+    if !include_readme || archive.readme.is_some() {
+        return None;
     }
-    Ok(())
+    let problem = match &archive.manifest.package.readme {
+        Some(readme) => format!("The README \"{}\" could not be read", readme.display()),
+        None => "No README was found".to_string(),
+    };
+    Some(format!(
+        "{}, so the registry will show the package without one. Add a README.md next to the manifest, or pass `--no-readme` to publish without one on purpose.",
+        problem
+    ))
+}
+
+/// Write the tarball that gets uploaded to `directory`, returning its path
+fn write_archive(
+    archive: &PackageArchive,
+    directory: &Path,
+    archive_name: &str,
+) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(directory.join("wapm_package"))?;
+    let archive_path = directory.join("wapm_package").join(archive_name);
+    fs::write(&archive_path, &archive.data)?;
+    Ok(archive_path)
 }
 
 #[derive(Debug, Error)]
-enum PublishError {
-    #[error("Cannot publish without a module.")]
-    NoModule,
-    #[error("Module \"{0}\" must have a source that is a file.")]
-    SourceMustBeFile(String),
-    #[error("Error building package when parsing module \"{0}\".")]
-    ErrorBuildingPackage(String),
-    #[error(
-        "Path \"{0}\", specified in the manifest as part of the package file system does not exist.",
-    )]
-    MissingManifestFsPath(String),
-    #[error("When processing the package filesystem, found path \"{0}\" which is not a directory")]
-    PackageFileSystemEntryMustBeDirectory(String),
+pub enum PublishError {
+    #[error("{0}\nRemove files from the package or publish without `--strict`.")]
+    PackageTooLarge(String),
+    #[error("Invalid rate limit \"{0}\", expected a positive number of requests per second")]
+    InvalidRateLimit(String),
+    #[error("The `--workspace` flag can only be used in a workspace.")]
+    NotAWorkspace,
+    #[error("Some packages could not be published:\n{0}")]
+    UploadsFailed(String),
 }
 
 #[derive(Debug)]
@@ -267,3 +486,331 @@ pub fn sign_compressed_archive(
         .to_string()),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::manifest::Manifest;
+
+    #[test]
+    fn package_command_writes_the_tarball_publish_uploads() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join("wapm.toml"),
+            r#"
+[package]
+name = "test/packaged"
+version = "0.1.0"
+description = "test package"
+readme = "README.md"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+
+[fs]
+"static" = "static"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(cwd.join("README.md"), "# packaged").unwrap();
+        fs::create_dir(cwd.join("static")).unwrap();
+        fs::write(cwd.join("static").join("index.html"), "<html/>").unwrap();
+
+        let (packaged, package_path) = package::write_package(cwd, None, false, true).unwrap();
+        assert_eq!(package_path, cwd.join("test-packaged-0.1.0.tar.gz"));
+        let paths: Vec<&str> = packaged.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["wapm.toml", "README.md", "main.wasm", "static/index.html"]
+        );
+
+        let upload_dir = tempfile::TempDir::new().unwrap();
+        let upload = package::build_package(cwd, false, true).unwrap();
+        let upload_path = write_archive(&upload, upload_dir.path(), "package.tar.gz").unwrap();
+        assert_eq!(
+            fs::read(package_path).unwrap(),
+            fs::read(upload_path).unwrap()
+        );
+    }
+
+    /// `(module (func (export "add") (param i32 i32) (result i32) ...))`
+    const ADD_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f,
+        0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00,
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+    ];
+
+    #[test]
+    fn modules_must_satisfy_their_interface_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join("wapm.toml"),
+            r#"
+[package]
+name = "test/adder"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "adder"
+source = "add.wasm"
+interface-file = "adder.interface"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("add.wasm"), ADD_MODULE).unwrap();
+
+        fs::write(
+            cwd.join("adder.interface"),
+            r#"(interface (func (export "add") (param i32 i32) (result i32)))"#,
+        )
+        .unwrap();
+        validate_package(cwd, false).unwrap();
+
+        fs::write(
+            cwd.join("adder.interface"),
+            r#"(interface (func (export "add") (param i64 i64) (result i64)))"#,
+        )
+        .unwrap();
+        let error = validate_package(cwd, false).unwrap_err().to_string();
+        assert!(
+            error.contains("does not satisfy the interface") && error.contains("expected i64"),
+            "{}",
+            error
+        );
+        validate_package(cwd, true).unwrap();
+    }
+
+    #[test]
+    fn oversized_packages_warn_or_fail_with_strict() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join("wapm.toml"),
+            r#"
+[package]
+name = "test/large"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+
+[fs]
+"assets" = "assets"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::create_dir(cwd.join("assets")).unwrap();
+        fs::write(cwd.join("assets").join("video.bin"), vec![7; 4096]).unwrap();
+        fs::write(cwd.join("assets").join("small.txt"), "hello").unwrap();
+        let archive = package::build_package(cwd, false, true).unwrap();
+
+        check_package_size(&archive, SizeLimits::default(), true).unwrap();
+        assert_eq!(
+            describe_oversized_package(&archive, SizeLimits::default()),
+            None
+        );
+
+        let limits = SizeLimits {
+            max_size: 16,
+            max_files: 2,
+        };
+        let problem = describe_oversized_package(&archive, limits).unwrap();
+        assert!(
+            problem.contains("more than the limit of 16 bytes"),
+            "{}",
+            problem
+        );
+        assert!(problem.contains("has 4 files"), "{}", problem);
+        let largest = problem.lines().nth(3).unwrap();
+        assert_eq!(largest, "  assets/video.bin (4.0 KiB)");
+
+        check_package_size(&archive, limits, false).unwrap();
+        let error = check_package_size(&archive, limits, true).unwrap_err();
+        assert!(error.to_string().contains("assets/video.bin"), "{}", error);
+
+        let opts =
+            PublishOpt::from_iter_safe(&["publish", "--strict", "--max-files", "2"]).unwrap();
+        assert!(opts.strict);
+        assert_eq!(opts.max_files, 2);
+        assert_eq!(opts.max_size, SizeLimits::default().max_size);
+    }
+
+    #[test]
+    fn rate_limit_must_be_positive() {
+        let opts = PublishOpt::from_iter_safe(&["publish", "--rate-limit", "2.5"]).unwrap();
+        assert_eq!(opts.rate_limit, Some(2.5));
+        for invalid in &["0", "-1", "fast"] {
+            let err =
+                PublishOpt::from_iter_safe(&["publish", &format!("--rate-limit={}", invalid)])
+                    .unwrap_err();
+            assert!(
+                err.message.contains("Invalid rate limit"),
+                "{}",
+                err.message
+            );
+        }
+    }
+
+    #[test]
+    fn publish_packages_the_project_at_path() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        let build_dir = cwd.join("build");
+        fs::create_dir_all(build_dir.join("assets")).unwrap();
+        fs::write(
+            build_dir.join("wapm.toml"),
+            r#"
+[package]
+name = "test/built"
+version = "0.2.0"
+description = "test package"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+
+[fs]
+"assets" = "assets"
+"#,
+        )
+        .unwrap();
+        fs::write(build_dir.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(build_dir.join("assets").join("index.html"), "<html/>").unwrap();
+
+        let opts = PublishOpt::from_iter_safe(&["publish", "--path", "build"]).unwrap();
+        let package_dir = opts.package_dir(cwd);
+        assert_eq!(package_dir, build_dir);
+        validate_package(&package_dir, false).unwrap();
+        let archive = package::build_package(&package_dir, false, true).unwrap();
+        assert_eq!(archive.manifest.package.name, "test/built");
+        let paths: Vec<&str> = archive.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["wapm.toml", "main.wasm", "assets/index.html"]);
+
+        let opts = PublishOpt::from_iter_safe(&["publish", "./build"]).unwrap();
+        assert_eq!(opts.package_dir(cwd), cwd.join("./build"));
+        let opts = PublishOpt::from_iter_safe(&["publish"]).unwrap();
+        assert_eq!(opts.package_dir(cwd), cwd);
+        assert!(PublishOpt::from_iter_safe(&["publish", "build", "--path", "build"]).is_err());
+    }
+
+    #[test]
+    fn workspace_publishes_need_a_workspace() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            tmp_dir.path().join("wapm.toml"),
+            "[package]\nname = \"test/single\"\nversion = \"0.1.0\"\ndescription = \"test package\"\n",
+        )
+        .unwrap();
+        let options = PublishOptions {
+            dry_run: true,
+            workspace: true,
+            ..PublishOptions::default()
+        };
+        let error = publish_package(tmp_dir.path(), &options).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PublishError>(),
+            Some(PublishError::NotAWorkspace)
+        ));
+
+        let opts = PublishOpt::from_iter_safe(&["publish", "--workspace", "--jobs", "2"]).unwrap();
+        assert!(opts.workspace);
+        assert_eq!(opts.jobs, 2);
+    }
+
+    /// The contents of the file at `path` in the gzipped tarball `data`
+    fn read_packaged_file(data: &[u8], path: &str) -> Option<String> {
+        use std::io::Read;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+        let mut entry = archive
+            .entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|entry| entry.path().unwrap().to_str() == Some(path))?;
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        Some(contents)
+    }
+
+    #[test]
+    fn readmes_are_detected_and_referenced_by_the_bundled_manifest() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join("wapm.toml"),
+            r#"
+[package]
+name = "test/readme"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(cwd.join("README.md"), "# readme").unwrap();
+
+        let archive = package::build_package(cwd, false, true).unwrap();
+        let paths: Vec<&str> = archive.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["wapm.toml", "README.md", "main.wasm"]);
+        assert_eq!(archive.readme.as_deref(), Some("# readme"));
+        let bundled: Manifest =
+            toml::from_str(&read_packaged_file(&archive.data, "wapm.toml").unwrap()).unwrap();
+        assert_eq!(
+            bundled.package.readme.as_deref(),
+            Some(Path::new("README.md"))
+        );
+        assert_eq!(describe_missing_readme(&archive, true), None);
+
+        let archive = package::build_package(cwd, false, false).unwrap();
+        let paths: Vec<&str> = archive.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["wapm.toml", "main.wasm"]);
+        assert_eq!(
+            read_packaged_file(&archive.data, "wapm.toml").unwrap(),
+            fs::read_to_string(cwd.join("wapm.toml")).unwrap()
+        );
+        assert_eq!(describe_missing_readme(&archive, false), None);
+        assert!(
+            PublishOpt::from_iter_safe(&["publish", "--no-readme"])
+                .unwrap()
+                .no_readme
+        );
+    }
+
+    #[test]
+    fn publishing_without_a_readme_warns() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cwd = tmp_dir.path();
+        fs::write(
+            cwd.join("wapm.toml"),
+            r#"
+[package]
+name = "test/no-readme"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+"#,
+        )
+        .unwrap();
+        fs::write(cwd.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+
+        let archive = package::build_package(cwd, false, true).unwrap();
+        assert_eq!(archive.readme, None);
+        let warning = describe_missing_readme(&archive, true).unwrap();
+        assert!(warning.starts_with("No README was found"), "{}", warning);
+        assert!(warning.contains("--no-readme"), "{}", warning);
+    }
+}
@@ -1,12 +1,22 @@
-use crate::config::Config;
 use crate::constants::DEFAULT_RUNTIME;
 use crate::data::lock::is_lockfile_out_of_date;
+use crate::data::manifest::Manifest;
+use crate::data::workspace::{find_workspace_members, WorkspaceMember};
 use crate::dataflow;
+use crate::dataflow::dependency_graph::qualify;
 use crate::dataflow::find_command_result;
-use crate::dataflow::find_command_result::get_command_from_anywhere;
+use crate::dataflow::find_command_result::{get_command, FindCommandResult};
+use crate::dataflow::installed_packages::{Install, RegistryInstaller};
+use crate::dataflow::lockfile_packages::LockfileResult;
 use crate::dataflow::manifest_packages::ManifestResult;
-use crate::util::get_runtime_with_args;
+use crate::dataflow::resolved_packages::{RegistryResolver, Resolve};
+use crate::dataflow::UpdateOptions;
+use crate::util::{get_runtime_with_args, glob_matches, split_runtime_and_args};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::ffi::OsString;
+#[cfg(not(target_os = "wasi"))]
+use std::fs::File;
 use std::path::{Path, PathBuf};
 #[cfg(not(target_os = "wasi"))]
 use std::process::Command;
@@ -14,30 +24,407 @@ use structopt::StructOpt;
 use thiserror::Error;
 #[cfg(target_os = "wasi")]
 use wasm_bus_process::prelude::Command;
+use wasmer_wasm_interface::{validate, Export, WasmType};
 
+/// Commands are looked up in the local package first, then in the globally installed packages.
+///
+/// A command given as `namespace/package:command` (or `namespace/package@version:command`) is
+/// run from a copy of the package in wapm's cache, without adding it to the current project.
 #[derive(StructOpt, Debug)]
 pub struct RunOpt {
-    /// Command name
-    command: String,
+    /// Command name, `alias:command` for a command of a dependency listed under an alias, or
+    /// `namespace/package[@version]:command` to run a command of a package that isn't installed.
+    /// Defaults to the package's `default-command`, or its only command
+    command: Option<String>,
+    /// Only look for the command in the globally installed packages
+    #[structopt(short = "g", long = "global", conflicts_with = "package")]
+    global: bool,
     /// WASI pre-opened directory
     #[structopt(long = "dir", multiple = true, group = "wasi")]
     pre_opened_directories: Vec<String>,
+    /// Only look for the command in the workspace members whose package name matches this
+    /// pattern (`*` and `?` wildcards are supported)
+    #[structopt(long = "package")]
+    package: Option<String>,
+    #[structopt(flatten)]
+    stdio: GuestStdio,
+    #[structopt(flatten)]
+    env: GuestEnv,
+    #[structopt(flatten)]
+    capabilities: GuestCapabilities,
+    /// Call this exported function instead of the command's entrypoint. The arguments are
+    /// checked against the function's param types and the runtime prints its results
+    #[structopt(long = "invoke")]
+    invoke: Option<String>,
+    /// Read the arguments for `--invoke` from this file, a JSON array with one number per param
+    #[structopt(
+        long = "args-file",
+        parse(from_os_str),
+        requires = "invoke",
+        conflicts_with = "args"
+    )]
+    args_file: Option<PathBuf>,
+    /// Compile the module ahead of time with the runtime and cache the artifact, so that later
+    /// runs of the same module with the same backend skip compiling it
+    #[structopt(long = "precompile")]
+    precompile: bool,
     /// Application arguments
     #[structopt(multiple = true, parse(from_os_str))]
     args: Vec<OsString>,
 }
 
+impl RunOpt {
+    /// The application arguments, read from `--args-file` if it was given
+    fn guest_args(&self) -> Result<Vec<OsString>, RunError> {
+        match &self.args_file {
+            Some(path) => read_args_file(path),
+            None => Ok(self.args.clone()),
+        }
+    }
+}
+
+/// How [`do_run`] runs a command. `wapm run` builds these from its flags, other commands start
+/// from the default and only set the directories and arguments.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RunSettings {
+    pub(crate) pre_opened_directories: Vec<String>,
+    pub(crate) args: Vec<OsString>,
+    pub(crate) stdio: GuestStdio,
+    pub(crate) env: GuestEnv,
+    pub(crate) capabilities: GuestCapabilities,
+    pub(crate) invoke: Option<String>,
+    pub(crate) precompile: bool,
+    /// The runtime and its args to run with instead of `WAPM_RUNTIME` or the default runtime
+    pub(crate) runtime: Option<String>,
+}
+
+impl RunSettings {
+    fn from_run_opt(run_options: &RunOpt) -> Result<Self, RunError> {
+        Ok(RunSettings {
+            pre_opened_directories: run_options.pre_opened_directories.clone(),
+            args: run_options.guest_args()?,
+            stdio: run_options.stdio.clone(),
+            env: run_options.env.clone(),
+            capabilities: run_options.capabilities.clone(),
+            invoke: run_options.invoke.clone(),
+            precompile: run_options.precompile,
+            runtime: None,
+        })
+    }
+}
+
+/// Read a JSON array of arguments. Its elements are checked against the param types by
+/// [`check_invoke_args`], like arguments given on the command line.
+fn read_args_file(path: &Path) -> Result<Vec<OsString>, RunError> {
+    let invalid =
+        |reason: String| RunError::InvalidArgsFile(path.to_string_lossy().to_string(), reason);
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| match value {
+            serde_json::Value::Number(number) => Ok(OsString::from(number.to_string())),
+            serde_json::Value::String(string) => Ok(OsString::from(string)),
+            other => Err(invalid(format!(
+                "element {} `{}` is not a number",
+                i + 1,
+                other
+            ))),
+        })
+        .collect()
+}
+
+/// Where the guest's standard streams come from and go to. Streams without a path are
+/// inherited from wapm.
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct GuestStdio {
+    /// Feed this file to the guest as its stdin
+    #[structopt(long = "stdin", parse(from_os_str))]
+    stdin: Option<PathBuf>,
+    /// Write the guest's stdout to this file
+    #[structopt(long = "stdout", parse(from_os_str))]
+    stdout: Option<PathBuf>,
+    /// Write the guest's stderr to this file
+    #[structopt(long = "stderr", parse(from_os_str))]
+    stderr: Option<PathBuf>,
+}
+
+impl GuestStdio {
+    #[cfg(target_os = "wasi")]
+    fn is_inherited(&self) -> bool {
+        self.stdin.is_none() && self.stdout.is_none() && self.stderr.is_none()
+    }
+
+    /// Open the redirection files and attach them to `cmd`.
+    #[cfg(not(target_os = "wasi"))]
+    fn apply(&self, cmd: &mut Command) -> Result<(), RunError> {
+        let open_error = |path: &Path, e: std::io::Error| {
+            RunError::CannotOpenStdio(path.to_string_lossy().to_string(), e.to_string())
+        };
+        if let Some(path) = &self.stdin {
+            let file = File::open(path).map_err(|e| open_error(path, e))?;
+            cmd.stdin(file);
+        }
+        if let Some(path) = &self.stdout {
+            let file = File::create(path).map_err(|e| open_error(path, e))?;
+            cmd.stdout(file);
+        }
+        if let Some(path) = &self.stderr {
+            let file = File::create(path).map_err(|e| open_error(path, e))?;
+            cmd.stderr(file);
+        }
+        Ok(())
+    }
+}
+
+/// The environment variables set in the guest
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct GuestEnv {
+    /// Set an environment variable in the guest, as `KEY=VALUE`. Takes precedence over
+    /// `--env-file`
+    #[structopt(long = "env", number_of_values = 1)]
+    env: Vec<String>,
+    /// Read environment variables for the guest from this file, one `KEY=VALUE` per line.
+    /// Blank lines and lines starting with `#` are ignored
+    #[structopt(long = "env-file", parse(from_os_str))]
+    env_file: Option<PathBuf>,
+}
+
+impl GuestEnv {
+    /// The variables from the env file overridden by the `--env` flags, sorted by key
+    fn variables(&self) -> Result<BTreeMap<String, String>, RunError> {
+        let mut variables = BTreeMap::new();
+        if let Some(path) = &self.env_file {
+            let contents = std::fs::read_to_string(path).map_err(|e| {
+                RunError::CannotReadEnvFile(path.to_string_lossy().to_string(), e.to_string())
+            })?;
+            for (i, line) in contents.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (key, value) = parse_env_var(line).ok_or_else(|| {
+                    RunError::InvalidEnvFileLine(
+                        path.to_string_lossy().to_string(),
+                        i + 1,
+                        line.to_string(),
+                    )
+                })?;
+                variables.insert(key, value);
+            }
+        }
+        for var in self.env.iter() {
+            let (key, value) =
+                parse_env_var(var).ok_or_else(|| RunError::InvalidEnvVar(var.to_string()))?;
+            variables.insert(key, value);
+        }
+        Ok(variables)
+    }
+
+    /// The runtime flags that set the variables
+    fn flags(&self) -> Result<Vec<OsString>, RunError> {
+        Ok(self
+            .variables()?
+            .into_iter()
+            .map(|(key, value)| OsString::from(format!("--env={}={}", key, value)))
+            .collect())
+    }
+}
+
+/// The host capabilities the guest is given. The `--allow-*` flags add to what the guest gets
+/// anyway, unless `--sandbox` is set: then the guest gets nothing else.
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct GuestCapabilities {
+    /// Deny the guest every capability that isn't given with an `--allow-*` flag. Directories
+    /// of `--dir` and of the manifest's `fs` section are only pre-opened within the allowed
+    /// directories, and the `--env` variables are only set with `--allow-env`
+    #[structopt(long = "sandbox")]
+    sandbox: bool,
+    /// Let the guest use the network
+    #[structopt(long = "allow-net")]
+    allow_net: bool,
+    /// Set the variables of `--env` and `--env-file` in a sandboxed guest
+    #[structopt(long = "allow-env")]
+    allow_env: bool,
+    /// Pre-open this directory for the guest to read. Rejected for now: the runtime can't
+    /// pre-open directories read-only, use `--allow-write` instead
+    #[structopt(long = "allow-read", number_of_values = 1, parse(from_os_str))]
+    allow_read: Vec<PathBuf>,
+    /// Pre-open this directory for the guest to read and write
+    #[structopt(long = "allow-write", number_of_values = 1, parse(from_os_str))]
+    allow_write: Vec<PathBuf>,
+}
+
+impl GuestCapabilities {
+    /// Fail on the grants that the runtime can't enforce
+    fn check_supported(&self) -> Result<(), RunError> {
+        match self.allow_read.first() {
+            Some(path) => Err(RunError::ReadOnlyDirNotSupported(
+                path.to_string_lossy().to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the guest may be given the host directory `path`. Both it and the allowed
+    /// directories are resolved first, so that `..` and symlinks can't leave the allowed
+    /// directories. Directories that don't exist are never allowed in the sandbox.
+    fn allows_path(&self, path: &Path) -> bool {
+        if !self.sandbox {
+            return true;
+        }
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        self.allow_write
+            .iter()
+            .filter_map(|allowed| allowed.canonicalize().ok())
+            .any(|allowed| path.starts_with(allowed))
+    }
+
+    /// The runtime flags that pre-open the allowed paths, the directories of `--dir` and the
+    /// `(guest path, host path)` pairs of `mapped_dirs` that the guest may be given
+    fn dir_flags(&self, dirs: &[String], mapped_dirs: &[(String, PathBuf)]) -> Vec<OsString> {
+        let mut flags = vec![];
+        let requested = dirs.iter().map(PathBuf::from);
+        for dir in self.allow_write.iter().cloned().chain(requested) {
+            let flag = OsString::from(format!("--dir={}", dir.to_string_lossy()));
+            if !self.allows_path(&dir) {
+                warn!(
+                    "Not pre-opening {} for the sandboxed guest, allow it with --allow-write",
+                    dir.display()
+                );
+            } else if !flags.contains(&flag) {
+                flags.push(flag);
+            }
+        }
+        for (guest_path, host_path) in mapped_dirs {
+            if self.allows_path(host_path) {
+                flags.push(OsString::from(format!(
+                    "--mapdir={}:{}",
+                    guest_path,
+                    host_path.to_string_lossy(),
+                )));
+            } else {
+                warn!(
+                    "Not mapping {} to {} for the sandboxed guest, allow it with --allow-write",
+                    host_path.display(),
+                    guest_path
+                );
+            }
+        }
+        flags
+    }
+
+    /// The `env_flags` that a sandboxed guest may be given
+    fn env_flags(&self, env_flags: Vec<OsString>) -> Vec<OsString> {
+        if self.sandbox && !self.allow_env && !env_flags.is_empty() {
+            warn!("Not setting environment variables in the sandboxed guest, allow it with --allow-env");
+            return vec![];
+        }
+        env_flags
+    }
+
+    /// The runtime flags that give the guest network access
+    fn net_flags(&self) -> Vec<OsString> {
+        if self.allow_net {
+            vec![OsString::from("--net")]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Split `KEY=VALUE`, removing quotes around the value
+fn parse_env_var(var: &str) -> Option<(String, String)> {
+    let (key, value) = var.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    let value = value.trim();
+    let value = ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value);
+    Some((key.to_string(), value.to_string()))
+}
+
 pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
-    let command_name = run_options.command.as_str();
-    let args = &run_options.args;
     let current_dir = crate::config::Config::get_current_dir()?;
+    let command_name = match run_options.command {
+        Some(ref command_name) => command_name.clone(),
+        None => default_command(&current_dir)?,
+    };
+    let command_name = command_name.as_str();
+    let settings = RunSettings::from_run_opt(&run_options)?;
+
+    if let Some(ephemeral) = EphemeralCommand::parse(command_name) {
+        let cache_dir = crate::config::Config::get_ephemeral_directory()?;
+        return run_ephemeral::<RegistryResolver, RegistryInstaller>(
+            &cache_dir, &ephemeral, &settings,
+        );
+    }
 
     // always update the local lockfile if the manifest has changed
-    match is_lockfile_out_of_date(&current_dir) {
-        Ok(false) => {}
-        _ => dataflow::update(vec![], vec![], &current_dir)
-            .map(|_| ())
-            .map_err(|e| RunError::CannotRegenLockfile(command_name.to_string(), e))?,
+    if !run_options.global {
+        match is_lockfile_out_of_date(&current_dir) {
+            Ok(false) => {}
+            _ => dataflow::update(vec![], vec![], &current_dir)
+                .map(|_| ())
+                .map_err(|e| RunError::CannotRegenLockfile(command_name.to_string(), e))?,
+        }
+    }
+
+    let command_name = resolve_aliased_command(&current_dir, command_name)?.unwrap_or(command_name);
+
+    let workspace_manifest = match ManifestResult::find_in_directory(&current_dir) {
+        ManifestResult::Manifest(manifest)
+            if manifest.workspace.is_some() && !run_options.global =>
+        {
+            Some(manifest)
+        }
+        _ => None,
+    };
+    match (workspace_manifest, &run_options.package) {
+        (Some(manifest), package_filter) => {
+            let found = find_command_in_workspace(
+                &manifest,
+                command_name,
+                package_filter.as_ref().map(String::as_str),
+            )?;
+            if let Some((
+                member,
+                FindCommandResult::CommandFound {
+                    source,
+                    manifest_dir,
+                    module_name,
+                    prehashed_cache_key,
+                    ..
+                },
+            )) = found
+            {
+                info!(
+                    "Running command `{}` from workspace member `{}`",
+                    command_name, member.name
+                );
+                let manifest_dir = member.directory.join(manifest_dir);
+                return do_run(
+                    member.directory,
+                    source,
+                    manifest_dir,
+                    command_name,
+                    &module_name,
+                    prehashed_cache_key,
+                    &settings,
+                );
+            }
+        }
+        (None, Some(_)) => return Err(RunError::NotAWorkspace.into()),
+        (None, None) => {}
     }
 
     let find_command_result::Command {
@@ -46,8 +433,9 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
         args: _,
         module_name,
         is_global,
+        directory: run_dir,
         prehashed_cache_key,
-    } = match get_command_from_anywhere(command_name) {
+    } = match get_command(command_name, run_options.global) {
         Err(find_command_result::Error::CommandNotFound(command)) => {
             let package_info = find_command_result::PackageInfoFromCommand::get(command)?;
             return Err(anyhow!("Command {} not found, but package {} version {} has this command. You can install it with `wapm install {}@{}`",
@@ -61,12 +449,22 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
         otherwise => otherwise?,
     };
 
-    let run_dir = if is_global {
-        Config::get_globals_directory().unwrap()
-    } else {
-        current_dir.clone()
-    };
-
+    if is_global {
+        debug!(
+            "Running global command `{}` from {}",
+            command_name,
+            run_dir.display()
+        );
+    }
+    if !is_global && crate::util::is_verbose() {
+        if let Some(note) = find_command_result::describe_shadowing(
+            &run_dir,
+            crate::config::Config::get_global_directories,
+            command_name,
+        ) {
+            info!("{}", note);
+        }
+    }
     let manifest_dir = run_dir.join(manifest_dir);
 
     do_run(
@@ -75,10 +473,203 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
         manifest_dir,
         command_name,
         &module_name,
-        &run_options.pre_opened_directories,
-        &args,
         prehashed_cache_key,
+        &settings,
+    )
+}
+
+/// A command of a package that isn't installed in the project, `namespace/package:command` or
+/// `namespace/package@version:command`
+#[derive(Debug, PartialEq)]
+struct EphemeralCommand<'a> {
+    package: &'a str,
+    /// A version or version range, `*` when none was given
+    version: &'a str,
+    command: &'a str,
+}
+
+impl<'a> EphemeralCommand<'a> {
+    fn parse(command: &'a str) -> Option<Self> {
+        let (package, command) = command.split_once(':')?;
+        let (package, version) = package.split_once('@').unwrap_or((package, "*"));
+        let (namespace, name) = package.split_once('/')?;
+        if namespace.is_empty() || name.is_empty() || command.is_empty() || version.is_empty() {
+            return None;
+        }
+        Some(EphemeralCommand {
+            package,
+            version,
+            command,
+        })
+    }
+}
+
+/// Install the package of `ephemeral` into its own directory under `cache_dir` and run the
+/// command from there. The current project's manifest and lockfile are left alone, and a
+/// package that is already in the cache is reused.
+fn run_ephemeral<Resolver, Installer>(
+    cache_dir: &Path,
+    ephemeral: &EphemeralCommand,
+    settings: &RunSettings,
+) -> anyhow::Result<()>
+where
+    Resolver: for<'a> Resolve<'a>,
+    Installer: for<'a> Install<'a>,
+{
+    let directory = cache_dir.join(ephemeral.package);
+    std::fs::create_dir_all(&directory)?;
+    dataflow::update_with::<Resolver, Installer, _>(
+        vec![(ephemeral.package, ephemeral.version)],
+        vec![],
+        &directory,
+        UpdateOptions {
+            create_commands: false,
+            ..UpdateOptions::default()
+        },
     )
+    .map_err(|e| RunError::CannotRegenLockfile(ephemeral.command.to_string(), e))?;
+
+    match FindCommandResult::find_command_in_directory(&directory, ephemeral.command) {
+        FindCommandResult::CommandFound {
+            source,
+            manifest_dir,
+            module_name,
+            prehashed_cache_key,
+            ..
+        } => {
+            debug!(
+                "Running command `{}` of {} from {}",
+                ephemeral.command,
+                ephemeral.package,
+                directory.display()
+            );
+            do_run(
+                directory,
+                source,
+                manifest_dir,
+                ephemeral.command,
+                &module_name,
+                prehashed_cache_key,
+                settings,
+            )
+        }
+        FindCommandResult::CommandNotFound(_) => Err(RunError::CommandNotInPackage(
+            ephemeral.command.to_string(),
+            ephemeral.package.to_string(),
+        )
+        .into()),
+        FindCommandResult::Error(e) => Err(e),
+    }
+}
+
+/// Resolve `alias:command`, where `alias` is a dependency listed under an alias in the manifest
+/// in `directory`, to the name of the command in the lockfile. Returns `None` for other commands.
+fn resolve_aliased_command<'a>(
+    directory: &Path,
+    command: &'a str,
+) -> Result<Option<&'a str>, RunError> {
+    let (alias, command) = match command.split_once(':') {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+    let package = match ManifestResult::find_in_directory(directory) {
+        ManifestResult::Manifest(manifest) => match manifest.dependency_alias(alias) {
+            Some(package) => qualify(package),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let provided_by_package = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile
+            .get_command(command)
+            .map_or(false, |lockfile_command| {
+                lockfile_command.package_name == package
+            }),
+        _ => false,
+    };
+    if provided_by_package {
+        Ok(Some(command))
+    } else {
+        Err(RunError::AliasedCommandNotFound(
+            command.to_string(),
+            alias.to_string(),
+            package,
+        ))
+    }
+}
+
+/// The command to run when none is named: the manifest's `default-command`, or its only command
+fn default_command(directory: &Path) -> Result<String, RunError> {
+    let manifest = match ManifestResult::find_in_directory(directory) {
+        ManifestResult::Manifest(manifest) => manifest,
+        _ => return Err(RunError::NoCommandGiven),
+    };
+    manifest.default_command().ok_or_else(|| {
+        let commands = manifest.command_names();
+        if commands.is_empty() {
+            RunError::NoCommandGiven
+        } else {
+            RunError::AmbiguousDefaultCommand(commands.join(", "))
+        }
+    })
+}
+
+/// Search the members of the workspace declared by `manifest` for `command_name`, keeping only
+/// the members whose package name matches `package_filter`.
+///
+/// Returns the member providing the command, or an error if several members provide it.
+fn find_command_in_workspace(
+    manifest: &Manifest,
+    command_name: &str,
+    package_filter: Option<&str>,
+) -> anyhow::Result<Option<(WorkspaceMember, FindCommandResult)>> {
+    let members: Vec<WorkspaceMember> = find_workspace_members(manifest)?
+        .into_iter()
+        .filter(|member| {
+            package_filter
+                .map(|pattern| glob_matches(pattern, &member.name))
+                .unwrap_or(true)
+        })
+        .collect();
+    if let Some(pattern) = package_filter {
+        if members.is_empty() {
+            return Err(RunError::NoMatchingWorkspaceMember(pattern.to_string()).into());
+        }
+    }
+
+    let mut found = vec![];
+    for member in members {
+        // the member lockfiles are kept up to date just like the current directory's
+        match is_lockfile_out_of_date(&member.directory) {
+            Ok(false) => {}
+            _ => dataflow::update(vec![], vec![], &member.directory)
+                .map(|_| ())
+                .map_err(|e| RunError::CannotRegenLockfile(command_name.to_string(), e))?,
+        }
+        match FindCommandResult::find_command_in_directory(&member.directory, command_name) {
+            result @ FindCommandResult::CommandFound { .. } => found.push((member, result)),
+            FindCommandResult::CommandNotFound(_) => {}
+            FindCommandResult::Error(e) => return Err(e),
+        }
+    }
+
+    match found.len() {
+        0 if package_filter.is_some() => Err(RunError::CommandNotFoundInWorkspaceMembers(
+            command_name.to_string(),
+            package_filter.unwrap_or_default().to_string(),
+        )
+        .into()),
+        0 | 1 => Ok(found.pop()),
+        _ => Err(RunError::AmbiguousWorkspaceCommand(
+            command_name.to_string(),
+            found
+                .iter()
+                .map(|(member, _)| member.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+        .into()),
+    }
 }
 
 pub(crate) fn do_run(
@@ -87,10 +678,20 @@ pub(crate) fn do_run(
     manifest_dir: PathBuf,
     command_name: &str,
     module_name: &str,
-    pre_opened_directories: &[String],
-    args: &[OsString],
     prehashed_cache_key: Option<String>,
+    settings: &RunSettings,
 ) -> anyhow::Result<()> {
+    let RunSettings {
+        ref pre_opened_directories,
+        ref args,
+        ref stdio,
+        ref env,
+        ref capabilities,
+        precompile,
+        ..
+    } = *settings;
+    let invoke = settings.invoke.as_deref();
+    capabilities.check_supported()?;
     debug!(
         "Running module located at {:?}",
         &run_dir.join(&source_path_buf)
@@ -104,7 +705,17 @@ pub(crate) fn do_run(
         )
     })?;
 
-    let wasmer_extra_flags: Option<Vec<OsString>> =
+    let invoke_args;
+    let args = match invoke {
+        Some(_) if cfg!(target_os = "wasi") => return Err(RunError::InvokeNotSupported.into()),
+        Some(export) => {
+            invoke_args = check_invoke_args(&run_dir.join(&source_path_buf), export, args)?;
+            &invoke_args[..]
+        }
+        None => args,
+    };
+
+    let mut wasmer_extra_flags: Option<Vec<OsString>> =
         match ManifestResult::find_in_directory(&manifest_dir) {
             ManifestResult::Manifest(manifest) => {
                 manifest
@@ -120,12 +731,26 @@ pub(crate) fn do_run(
             }
             _ => None,
         };
+    if let Some(export) = invoke {
+        wasmer_extra_flags
+            .get_or_insert_with(Vec::new)
+            .push(OsString::from(format!("--invoke={}", export)));
+    }
+    let env_flags = capabilities.env_flags(env.flags()?);
+    if !env_flags.is_empty() {
+        wasmer_extra_flags
+            .get_or_insert_with(Vec::new)
+            .extend(env_flags);
+    }
 
-    let mut wasi_preopened_dir_flags: Vec<OsString> = pre_opened_directories
-        .iter()
-        .map(|entry| OsString::from(format!("--dir={}", entry)))
-        .collect();
+    let net_flags = capabilities.net_flags();
+    if !net_flags.is_empty() {
+        wasmer_extra_flags
+            .get_or_insert_with(Vec::new)
+            .extend(net_flags);
+    }
 
+    let mut mapped_dirs = vec![];
     let mut disable_command_rename = false;
 
     match ManifestResult::find_in_directory(&manifest_dir) {
@@ -135,18 +760,35 @@ pub(crate) fn do_run(
             if let Some(ref fs) = manifest.fs {
                 // todo: normalize (rm `:` and newline, etc) these paths if we haven't yet
                 for (guest_path, host_path) in fs.iter() {
-                    wasi_preopened_dir_flags.push(OsString::from(format!(
-                        "--mapdir={}:{}",
-                        guest_path,
-                        manifest_dir.join(host_path).to_string_lossy(),
-                    )));
+                    mapped_dirs.push((guest_path.clone(), manifest_dir.join(host_path)));
                 }
             }
         }
         _ => (),
     }
+    let wasi_preopened_dir_flags = capabilities.dir_flags(pre_opened_directories, &mapped_dirs);
 
-    let (runtime, runtime_args) = get_runtime_with_args();
+    let (runtime, runtime_args) = match &settings.runtime {
+        Some(runtime) => split_runtime_and_args(runtime.clone()),
+        None => get_runtime_with_args(),
+    };
+
+    let source_path_buf = match precompile {
+        true if cfg!(target_os = "wasi") => {
+            warn!("Precompiling modules is not supported on this platform, ignoring it");
+            source_path_buf
+        }
+        true => precompiled_module(
+            &crate::config::Config::get_compiled_directory()?,
+            &run_dir.join(&source_path_buf),
+            &runtime_version(&runtime),
+            runtime_backend(&runtime_args),
+            |module, artifact| {
+                compile_with_runtime(&runtime, backend_flag(&runtime_args), module, artifact)
+            },
+        )?,
+        false => source_path_buf,
+    };
 
     let mut cmd;
     if cfg!(target_os = "wasi") {
@@ -187,6 +829,17 @@ pub(crate) fn do_run(
         cmd.args(&command_vec);
     };
 
+    #[cfg(not(target_os = "wasi"))]
+    stdio.apply(&mut cmd)?;
+    #[cfg(target_os = "wasi")]
+    if !stdio.is_inherited() {
+        warn!("Redirecting the guest's stdio is not supported on this platform, ignoring it");
+    }
+    #[cfg(target_os = "wasi")]
+    if !env.env.is_empty() || env.env_file.is_some() {
+        warn!("Setting the guest's environment is not supported on this platform, ignoring it");
+    }
+
     let mut child = cmd
         .spawn()
         .map_err(|e| -> RunError { RunError::ProcessFailed(runtime, format!("{:?}", e)) })?;
@@ -195,6 +848,177 @@ pub(crate) fn do_run(
     Ok(())
 }
 
+/// The arg that selects the compiler backend in the runtime's args, e.g. `--backend=llvm` or
+/// `--llvm`
+fn backend_flag(runtime_args: &[String]) -> Option<&str> {
+    runtime_args.iter().map(String::as_str).find(|arg| {
+        arg.starts_with("--backend=") || matches!(*arg, "--llvm" | "--cranelift" | "--singlepass")
+    })
+}
+
+/// The compiler backend selected in the runtime's args, e.g. `llvm` for
+/// `wasmer --backend=llvm`, or `default`
+fn runtime_backend(runtime_args: &[String]) -> &str {
+    match backend_flag(runtime_args) {
+        Some(flag) => flag.strip_prefix("--backend=").unwrap_or(&flag[2..]),
+        None => "default",
+    }
+}
+
+/// What `<runtime> --version` prints, which tells apart the runtimes whose artifacts can't be
+/// loaded by each other. Empty if the runtime couldn't be asked.
+#[cfg(not(target_os = "wasi"))]
+fn runtime_version(runtime: &str) -> String {
+    match Command::new(runtime).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn runtime_version(_runtime: &str) -> String {
+    String::new()
+}
+
+/// The artifact of the module at `module_path` compiled with `backend` by the runtime that
+/// reported `runtime_version`, from `cache_dir`. Modules that aren't cached yet are compiled
+/// with `compile`, which is given the module and the path to write the artifact to.
+fn precompiled_module<F>(
+    cache_dir: &Path,
+    module_path: &Path,
+    runtime_version: &str,
+    backend: &str,
+    compile: F,
+) -> Result<PathBuf, RunError>
+where
+    F: FnOnce(&Path, &Path) -> Result<(), String>,
+{
+    let compile_error =
+        |e: String| RunError::CannotPrecompile(module_path.to_string_lossy().to_string(), e);
+    if runtime_version.is_empty() {
+        return Err(compile_error(
+            "the runtime did not report its version".to_string(),
+        ));
+    }
+    let wasm = std::fs::read(module_path).map_err(|e| compile_error(e.to_string()))?;
+    let runtime_hash = format!("{:x}", Sha256::digest(runtime_version.as_bytes()));
+    let artifact = cache_dir.join(format!(
+        "{:x}-{}-{}.wasmu",
+        Sha256::digest(&wasm),
+        backend,
+        &runtime_hash[..16]
+    ));
+    if artifact.exists() {
+        debug!("Using the precompiled module at {}", artifact.display());
+        return Ok(artifact);
+    }
+    std::fs::create_dir_all(cache_dir).map_err(|e| compile_error(e.to_string()))?;
+    // compile next to the artifact so that an interrupted compilation isn't reused
+    let partial = artifact.with_extension("partial");
+    compile(module_path, &partial).map_err(compile_error)?;
+    std::fs::rename(&partial, &artifact).map_err(|e| compile_error(e.to_string()))?;
+    Ok(artifact)
+}
+
+/// Compile `module` into `artifact` with `<runtime> compile`, passing on the flag that selects
+/// the backend. The other runtime args are meant for running the module.
+#[cfg(not(target_os = "wasi"))]
+fn compile_with_runtime(
+    runtime: &str,
+    backend_flag: Option<&str>,
+    module: &Path,
+    artifact: &Path,
+) -> Result<(), String> {
+    let status = Command::new(runtime)
+        .arg("compile")
+        .args(backend_flag)
+        .arg(module)
+        .arg("-o")
+        .arg(artifact)
+        .status()
+        .map_err(|e| format!("could not start {}: {}", runtime, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{} compile` failed ({})", runtime, status))
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn compile_with_runtime(
+    _runtime: &str,
+    _backend_flag: Option<&str>,
+    _module: &Path,
+    _artifact: &Path,
+) -> Result<(), String> {
+    Err("precompiling is not supported on this platform".to_string())
+}
+
+/// Check that `args` can be passed to the function `export` of the module at `module_path`,
+/// returning them normalized for the runtime.
+fn check_invoke_args(
+    module_path: &Path,
+    export: &str,
+    args: &[OsString],
+) -> Result<Vec<OsString>, RunError> {
+    let wasm = std::fs::read(module_path).map_err(|e| {
+        RunError::CannotInspectModule(module_path.to_string_lossy().to_string(), e.to_string())
+    })?;
+    let exports = validate::exported_functions(&wasm).map_err(|e| {
+        RunError::CannotInspectModule(
+            module_path.to_string_lossy().to_string(),
+            format!("{:?}", e),
+        )
+    })?;
+    let params = exports
+        .into_iter()
+        .find_map(|function| match function {
+            Export::Func { name, params, .. } if name == export => Some(params),
+            _ => None,
+        })
+        .ok_or_else(|| RunError::ExportNotFound(export.to_string()))?;
+    let expected = format!(
+        "({})",
+        params
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let mismatch = |reason: String| RunError::InvalidInvokeArgs {
+        export: export.to_string(),
+        expected: expected.clone(),
+        reason,
+    };
+    if args.len() != params.len() {
+        return Err(mismatch(format!("{} were given", args.len())));
+    }
+    params
+        .iter()
+        .zip(args)
+        .enumerate()
+        .map(|(i, (ty, arg))| {
+            let arg = arg.to_string_lossy();
+            let parsed = match ty {
+                WasmType::I32 => arg.parse::<i32>().map(|v| v.to_string()).ok(),
+                WasmType::I64 => arg.parse::<i64>().map(|v| v.to_string()).ok(),
+                WasmType::F32 => arg.parse::<f32>().map(|v| v.to_string()).ok(),
+                WasmType::F64 => arg.parse::<f64>().map(|v| v.to_string()).ok(),
+            };
+            parsed.map(OsString::from).ok_or_else(|| {
+                mismatch(format!(
+                    "argument {} \"{}\" is not a valid {}",
+                    i + 1,
+                    arg,
+                    ty
+                ))
+            })
+        })
+        .collect()
+}
+
 fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
     args: &[OsString],
     wasmer_extra_flags: Option<Vec<OsString>>,
@@ -233,12 +1057,21 @@ fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
 
 #[cfg(test)]
 mod test {
-    use crate::commands::run::create_run_command;
+    use crate::commands::run::{
+        backend_flag, check_invoke_args, create_run_command, default_command,
+        find_command_in_workspace, precompiled_module, read_args_file, resolve_aliased_command,
+        run_ephemeral, runtime_backend, EphemeralCommand, GuestCapabilities, GuestEnv, GuestStdio,
+        RunError, RunOpt, RunSettings,
+    };
     use crate::data::manifest::PACKAGES_DIR_NAME;
+    use crate::dataflow::find_command_result::FindCommandResult;
+    use crate::dataflow::manifest_packages::ManifestResult;
+    use crate::dataflow::UpdateOptions;
+    use crate::fixtures::{TestInstaller, TestResolver};
     use crate::util::create_temp_dir;
     use std::ffi::OsString;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn create_run_command_vec() {
@@ -266,6 +1099,538 @@ mod test {
             create_run_command(&args, None, vec![], &dir, wasm_relative_path, None, None).unwrap();
         assert_eq!(expected_command, actual_command);
     }
+
+    fn write_member(directory: &Path, name: &str, command: &str) {
+        fs::create_dir_all(directory).unwrap();
+        fs::write(directory.join("hello.wasm"), b"\0asm").unwrap();
+        let manifest = format!(
+            r#"
+[package]
+name = "{}"
+version = "1.0.0"
+description = "workspace member"
+
+[[module]]
+name = "hello"
+source = "hello.wasm"
+abi = "wasi"
+
+[[command]]
+name = "{}"
+module = "hello"
+"#,
+            name, command
+        );
+        fs::write(directory.join("wapm.toml"), manifest).unwrap();
+    }
+
+    #[test]
+    fn find_command_in_second_workspace_member() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let root: &Path = tmp_dir.as_ref();
+        fs::write(
+            root.join("wapm.toml"),
+            r#"
+[package]
+name = "root"
+version = "1.0.0"
+description = "workspace root"
+
+[workspace]
+members = ["members/*"]
+"#,
+        )
+        .unwrap();
+        write_member(&root.join("members").join("first"), "first", "greet");
+        write_member(&root.join("members").join("second"), "second", "hello");
+        let manifest = match ManifestResult::find_in_directory(root) {
+            ManifestResult::Manifest(m) => m,
+            _ => panic!("workspace manifest should be valid"),
+        };
+
+        let (member, result) = find_command_in_workspace(&manifest, "hello", None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(member.name, "second");
+        assert_eq!(member.directory, root.join("members").join("second"));
+        match result {
+            FindCommandResult::CommandFound { module_name, .. } => {
+                assert_eq!(module_name, "hello")
+            }
+            _ => panic!("command should have been found"),
+        }
+
+        let (member, _) = find_command_in_workspace(&manifest, "hello", Some("sec*"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(member.name, "second");
+        assert!(find_command_in_workspace(&manifest, "hello", Some("first")).is_err());
+        assert!(find_command_in_workspace(&manifest, "hello", Some("third")).is_err());
+        assert!(find_command_in_workspace(&manifest, "missing", None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn single_commands_run_by_default() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        write_member(dir, "single", "hello");
+        assert_eq!(default_command(dir).unwrap(), "hello");
+
+        let manifest = fs::read_to_string(dir.join("wapm.toml")).unwrap();
+        fs::write(
+            dir.join("wapm.toml"),
+            manifest + "\n[[command]]\nname = \"goodbye\"\nmodule = \"hello\"\n",
+        )
+        .unwrap();
+        match default_command(dir) {
+            Err(RunError::AmbiguousDefaultCommand(commands)) => {
+                assert_eq!(commands, "hello, goodbye")
+            }
+            other => panic!("expected an ambiguous command error, got {:?}", other),
+        }
+
+        let manifest = fs::read_to_string(dir.join("wapm.toml")).unwrap();
+        fs::write(
+            dir.join("wapm.toml"),
+            manifest.replace(
+                "description = \"workspace member\"",
+                "description = \"workspace member\"\ndefault-command = \"goodbye\"",
+            ),
+        )
+        .unwrap();
+        assert_eq!(default_command(dir).unwrap(), "goodbye");
+
+        let empty = create_temp_dir().unwrap();
+        assert!(matches!(
+            default_command(empty.as_ref()),
+            Err(RunError::NoCommandGiven)
+        ));
+    }
+
+    /// `(module (func (export "add") (param i32 i32) (result i32) ...))`
+    const ADD_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // type: (i32, i32) -> i32
+        0x03, 0x02, 0x01, 0x00, // function 0 has type 0
+        0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00, // export "add"
+        // code: local.get 0; local.get 1; i32.add
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+    ];
+
+    #[test]
+    fn invoke_args_are_checked_against_the_export() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let module_path = tmp_dir.as_ref().join("add.wasm");
+        fs::write(&module_path, ADD_MODULE).unwrap();
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        let checked = check_invoke_args(&module_path, "add", &args(&["2", "+3"])).unwrap();
+        assert_eq!(checked, args(&["2", "3"]));
+
+        let error = check_invoke_args(&module_path, "add", &args(&["2", "3.5"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid arguments for \"add\", expected (i32, i32): argument 2 \"3.5\" is not a valid i32"
+        );
+        let error = check_invoke_args(&module_path, "add", &args(&["2"])).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("expected (i32, i32): 1 were given"));
+        assert!(check_invoke_args(&module_path, "sub", &args(&["2", "3"])).is_err());
+    }
+
+    #[test]
+    fn args_files_are_checked_against_the_export() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let module_path = dir.join("add.wasm");
+        fs::write(&module_path, ADD_MODULE).unwrap();
+        let args_file = |contents: &str| {
+            let path = dir.join("args.json");
+            fs::write(&path, contents).unwrap();
+            read_args_file(&path)
+        };
+
+        let args = args_file("[2, \"3\"]").unwrap();
+        let checked = check_invoke_args(&module_path, "add", &args).unwrap();
+        assert_eq!(checked, vec![OsString::from("2"), OsString::from("3")]);
+
+        let args = args_file("[2, 3, 4]").unwrap();
+        let error = check_invoke_args(&module_path, "add", &args).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("expected (i32, i32): 3 were given"));
+        let args = args_file("[2, 3.5]").unwrap();
+        let error = check_invoke_args(&module_path, "add", &args).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("argument 2 \"3.5\" is not a valid i32"));
+
+        let error = args_file("[2, true]").unwrap_err();
+        assert!(matches!(error, RunError::InvalidArgsFile(..)));
+        assert!(error
+            .to_string()
+            .ends_with("element 2 `true` is not a number"));
+        assert!(args_file("{\"a\": 1}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdin_file_reaches_the_guest() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        fs::write(dir.join("input.txt"), "hello from a file\n").unwrap();
+        let stdio = GuestStdio {
+            stdin: Some(dir.join("input.txt")),
+            stdout: Some(dir.join("output.txt")),
+            stderr: None,
+        };
+
+        // `cat` stands in for a guest that echoes its stdin
+        let mut cmd = std::process::Command::new("cat");
+        stdio.apply(&mut cmd).unwrap();
+        assert!(cmd.status().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(dir.join("output.txt")).unwrap(),
+            "hello from a file\n"
+        );
+    }
+
+    #[test]
+    fn sandboxed_guests_only_get_the_allowed_capabilities() {
+        use structopt::StructOpt;
+
+        let capabilities = |flags: &[&str]| {
+            GuestCapabilities::from_iter_safe(std::iter::once("run").chain(flags.iter().copied()))
+                .unwrap()
+        };
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let data = dir.join("data");
+        let pkg = dir.join("pkg");
+        fs::create_dir_all(&data).unwrap();
+        fs::create_dir_all(pkg.join("config")).unwrap();
+        let path = |path: &Path| path.to_string_lossy().to_string();
+        let dirs = vec![path(&data)];
+        // manifest directories are joined to the manifest's directory
+        let mapped_dirs = vec![("/config".to_string(), pkg.join("config"))];
+        let flags =
+            |flags: &[String]| -> Vec<OsString> { flags.iter().map(OsString::from).collect() };
+
+        // a sandboxed guest denied read can't open the files under `data`
+        let denied = capabilities(&["--sandbox"]);
+        assert!(denied.dir_flags(&dirs, &mapped_dirs).is_empty());
+        // allowing the directory lets it through
+        let allowed = capabilities(&["--sandbox", "--allow-write", &path(&data)]);
+        assert_eq!(
+            allowed.dir_flags(&dirs, &mapped_dirs),
+            flags(&[format!("--dir={}", path(&data))])
+        );
+        let allowed = capabilities(&["--sandbox", "--allow-write", &path(&pkg)]);
+        assert_eq!(
+            allowed.dir_flags(&dirs, &mapped_dirs),
+            flags(&[
+                format!("--dir={}", path(&pkg)),
+                format!("--mapdir=/config:{}", path(&pkg.join("config"))),
+            ])
+        );
+        // `..` and symlinks can't leave the allowed directory
+        let allowed = capabilities(&["--sandbox", "--allow-write", &path(&data)]);
+        assert!(!allowed.allows_path(&data.join("../pkg")));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&pkg, data.join("link")).unwrap();
+            assert!(!allowed.allows_path(&data.join("link")));
+        }
+        // relative allowed directories match absolute requested ones
+        let allowed = capabilities(&["--sandbox", "--allow-write", "src"]);
+        let cwd = std::env::current_dir().unwrap();
+        assert!(allowed.allows_path(&cwd.join("src/commands")));
+        assert!(!allowed.allows_path(&cwd.join("src/../lib")));
+        // without `--sandbox` everything that was asked for is pre-opened
+        assert_eq!(
+            capabilities(&[]).dir_flags(&dirs, &mapped_dirs),
+            flags(&[
+                format!("--dir={}", path(&data)),
+                format!("--mapdir=/config:{}", path(&pkg.join("config"))),
+            ])
+        );
+
+        let flags =
+            |flags: &[&str]| -> Vec<OsString> { flags.iter().map(OsString::from).collect() };
+
+        let env = flags(&["--env=KEY=value"]);
+        assert!(denied.env_flags(env.clone()).is_empty());
+        assert_eq!(
+            capabilities(&["--sandbox", "--allow-env"]).env_flags(env.clone()),
+            env
+        );
+        assert_eq!(capabilities(&[]).env_flags(env.clone()), env);
+
+        assert!(denied.net_flags().is_empty());
+        assert_eq!(
+            capabilities(&["--sandbox", "--allow-net"]).net_flags(),
+            flags(&["--net"])
+        );
+
+        // read-only grants are refused until the runtime can enforce them
+        assert!(capabilities(&["--sandbox", "--allow-write", "src"])
+            .check_supported()
+            .is_ok());
+        assert!(matches!(
+            capabilities(&["--sandbox", "--allow-read", "src"]).check_supported(),
+            Err(RunError::ReadOnlyDirNotSupported(path)) if path == "src"
+        ));
+    }
+
+    #[test]
+    fn a_second_precompiled_run_reuses_the_artifact() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let module_path = dir.join("guest.wasm");
+        fs::write(&module_path, b"\0asm guest").unwrap();
+        let cache_dir = dir.join("compiled");
+        let compiles = std::cell::Cell::new(0);
+        let compile = |module: &Path, artifact: &Path| {
+            compiles.set(compiles.get() + 1);
+            let wasm = fs::read(module).map_err(|e| e.to_string())?;
+            fs::write(artifact, [&b"compiled "[..], &wasm].concat()).map_err(|e| e.to_string())
+        };
+
+        let precompiled = |version: &str, backend: &str| {
+            precompiled_module(&cache_dir, &module_path, version, backend, compile)
+        };
+
+        let first = precompiled("wasmer 2.3.0", "default").unwrap();
+        let second = precompiled("wasmer 2.3.0", "default").unwrap();
+        assert_eq!(compiles.get(), 1);
+        assert_eq!(first, second);
+        assert_eq!(fs::read(&second).unwrap(), b"compiled \0asm guest");
+
+        // the artifacts of other backends and runtime versions are cached separately
+        let llvm = precompiled("wasmer 2.3.0", "llvm").unwrap();
+        let upgraded = precompiled("wasmer 3.0.0", "default").unwrap();
+        assert_eq!(compiles.get(), 3);
+        assert_ne!(llvm, first);
+        assert_ne!(upgraded, first);
+        assert!(precompiled("", "default").is_err());
+
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let runtime_args = args(&["--backend=llvm", "--enable-threads"]);
+        assert_eq!(runtime_backend(&runtime_args), "llvm");
+        assert_eq!(backend_flag(&runtime_args), Some("--backend=llvm"));
+        assert_eq!(runtime_backend(&args(&["--singlepass"])), "singlepass");
+        assert_eq!(runtime_backend(&[]), "default");
+        assert_eq!(backend_flag(&args(&["--enable-threads"])), None);
+    }
+
+    #[test]
+    fn env_file_variables_reach_the_guest() {
+        use structopt::StructOpt;
+
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let env_file = dir.join(".env");
+        fs::write(
+            &env_file,
+            "# settings for the guest\n\nGREETING=hello\nTARGET = \"world\"\nMODE=debug\n",
+        )
+        .unwrap();
+        let opts = RunOpt::from_iter_safe(&[
+            OsString::from("run"),
+            OsString::from("greet"),
+            OsString::from("--env-file"),
+            env_file.clone().into_os_string(),
+            OsString::from("--env"),
+            OsString::from("MODE=release"),
+        ])
+        .unwrap();
+
+        let env_flags = opts.env.flags().unwrap();
+        assert_eq!(
+            env_flags,
+            vec![
+                OsString::from("--env=GREETING=hello"),
+                OsString::from("--env=MODE=release"),
+                OsString::from("--env=TARGET=world"),
+            ]
+        );
+        let command = create_run_command(
+            &[],
+            Some(env_flags),
+            vec![],
+            PathBuf::from("dir"),
+            PathBuf::from("greet.wasm"),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(command[1], OsString::from("--env=GREETING=hello"));
+        assert_eq!(command.last(), Some(&OsString::from("--")));
+
+        fs::write(&env_file, "GREETING=hello\nnot a variable\n").unwrap();
+        let env = GuestEnv {
+            env: vec![],
+            env_file: Some(env_file),
+        };
+        let error = env.flags().unwrap_err();
+        assert!(matches!(
+            error,
+            RunError::InvalidEnvFileLine(_, 2, ref line) if line == "not a variable"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdout_and_stderr_are_redirected_to_files() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let stdio = GuestStdio {
+            stdin: None,
+            stdout: Some(dir.join("stdout.txt")),
+            stderr: Some(dir.join("stderr.txt")),
+        };
+
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", "echo out; echo err >&2"]);
+        stdio.apply(&mut cmd).unwrap();
+        assert!(cmd.status().unwrap().success());
+        assert_eq!(fs::read_to_string(dir.join("stdout.txt")).unwrap(), "out\n");
+        assert_eq!(fs::read_to_string(dir.join("stderr.txt")).unwrap(), "err\n");
+    }
+
+    #[test]
+    fn parsing_ephemeral_commands() {
+        assert_eq!(
+            EphemeralCommand::parse("foo/bar:cmd"),
+            Some(EphemeralCommand {
+                package: "foo/bar",
+                version: "*",
+                command: "cmd",
+            })
+        );
+        assert_eq!(
+            EphemeralCommand::parse("foo/bar@^1.2:cmd"),
+            Some(EphemeralCommand {
+                package: "foo/bar",
+                version: "^1.2",
+                command: "cmd",
+            })
+        );
+        assert_eq!(EphemeralCommand::parse("cmd"), None);
+        assert_eq!(EphemeralCommand::parse("bar:cmd"), None);
+        assert_eq!(EphemeralCommand::parse("foo/bar:"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ephemeral_runs_leave_the_project_untouched() {
+        use std::os::unix::fs::PermissionsExt;
+        use structopt::StructOpt;
+
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        let project_dir = dir.join("project");
+        let cache_dir = dir.join("cache");
+        write_member(&project_dir, "project", "build");
+        crate::dataflow::update(vec![], vec![], &project_dir).unwrap();
+        let manifest_before = fs::read_to_string(project_dir.join("wapm.toml")).unwrap();
+        let lockfile_before = fs::read_to_string(project_dir.join("wapm.lock")).unwrap();
+        let packages_dir_existed = project_dir.join(PACKAGES_DIR_NAME).exists();
+
+        // the runtime records what it was asked to run
+        let runtime = dir.join("runtime.sh");
+        let ran = dir.join("ran.txt");
+        fs::write(
+            &runtime,
+            format!("#!/bin/sh\necho \"$@\" > {}\n", ran.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&runtime, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let opts = RunOpt::from_iter_safe(&["run", "foo/hello@1.0.0:hello", "world"]).unwrap();
+        let settings = RunSettings {
+            runtime: Some(runtime.to_string_lossy().to_string()),
+            ..RunSettings::from_run_opt(&opts).unwrap()
+        };
+        let ephemeral = EphemeralCommand::parse("foo/hello@1.0.0:hello").unwrap();
+        run_ephemeral::<TestResolver, TestInstaller>(&cache_dir, &ephemeral, &settings).unwrap();
+
+        let ran = fs::read_to_string(&ran).unwrap();
+        assert!(
+            ran.contains("foo/hello/wapm_packages/foo/hello@1.0.0/hello.wasm"),
+            "{}",
+            ran
+        );
+        assert!(ran.trim_end().ends_with("world"), "{}", ran);
+        assert_eq!(
+            fs::read_to_string(project_dir.join("wapm.toml")).unwrap(),
+            manifest_before
+        );
+        assert_eq!(
+            fs::read_to_string(project_dir.join("wapm.lock")).unwrap(),
+            lockfile_before
+        );
+        assert_eq!(
+            project_dir.join(PACKAGES_DIR_NAME).exists(),
+            packages_dir_existed
+        );
+
+        let missing = EphemeralCommand::parse("foo/hello@1.0.0:goodbye").unwrap();
+        let error = run_ephemeral::<TestResolver, TestInstaller>(&cache_dir, &missing, &settings)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The package \"foo/hello\" has no command \"goodbye\"."
+        );
+    }
+
+    #[test]
+    fn run_targets_aliased_dependencies() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        fs::write(
+            dir.join("wapm.toml"),
+            r#"
+[package]
+name = "aliases"
+version = "1.0.0"
+description = "test package"
+
+[dependencies]
+mylib = { package = "foo/hello", version = "1.0.0" }
+"#,
+        )
+        .unwrap();
+        crate::dataflow::update_with::<TestResolver, TestInstaller, _>(
+            vec![],
+            vec![],
+            dir,
+            UpdateOptions::default(),
+        )
+        .unwrap();
+
+        let lockfile = fs::read_to_string(dir.join("wapm.lock")).unwrap();
+        assert!(lockfile.contains("foo/hello"), "{}", lockfile);
+        assert!(dir
+            .join("wapm_packages/foo/hello@1.0.0/hello.wasm")
+            .exists());
+
+        let command = resolve_aliased_command(dir, "mylib:hello").unwrap();
+        assert_eq!(command, Some("hello"));
+        assert!(matches!(
+            FindCommandResult::find_command_in_directory(dir, "hello"),
+            FindCommandResult::CommandFound { .. }
+        ));
+        assert_eq!(resolve_aliased_command(dir, "hello").unwrap(), None);
+        assert_eq!(resolve_aliased_command(dir, "other:hello").unwrap(), None);
+        assert!(matches!(
+            resolve_aliased_command(dir, "mylib:goodbye"),
+            Err(RunError::AliasedCommandNotFound(_, _, ref package)) if package == "foo/hello"
+        ));
+    }
 }
 
 #[derive(Debug, Error)]
@@ -278,4 +1643,54 @@ enum RunError {
     SourceForCommandNotFound(String, String, String),
     #[error("Failed to run {0}: {1}")]
     ProcessFailed(String, String),
+    #[error("The `--package` flag can only be used in a workspace.")]
+    NotAWorkspace,
+    #[error("No workspace member matches the package \"{0}\".")]
+    NoMatchingWorkspaceMember(String),
+    #[error("Command \"{0}\" was not found in the workspace members matching \"{1}\".")]
+    CommandNotFoundInWorkspaceMembers(String, String),
+    #[error(
+        "Command \"{0}\" is provided by multiple workspace members ({1}). Use `--package` to select one."
+    )]
+    AmbiguousWorkspaceCommand(String, String),
+    #[error("Could not open \"{0}\" for the guest's stdio: {1}")]
+    CannotOpenStdio(String, String),
+    #[error("Could not read the env file \"{0}\": {1}")]
+    CannotReadEnvFile(String, String),
+    #[error("Line {1} of the env file \"{0}\" is not `KEY=VALUE`: {2}")]
+    InvalidEnvFileLine(String, usize, String),
+    #[error("The environment variable \"{0}\" is not `KEY=VALUE`")]
+    InvalidEnvVar(String),
+    #[error("Could not read the exports of \"{0}\": {1}")]
+    CannotInspectModule(String, String),
+    #[error("The module does not export a function named \"{0}\".")]
+    ExportNotFound(String),
+    #[error("Invalid arguments for \"{export}\", expected {expected}: {reason}")]
+    InvalidInvokeArgs {
+        export: String,
+        expected: String,
+        reason: String,
+    },
+    #[error("Could not read the args file \"{0}\": {1}")]
+    InvalidArgsFile(String, String),
+    #[error(
+        "Can't give the guest read-only access to \"{0}\": the runtime can only pre-open directories read-write. Use `--allow-write` instead."
+    )]
+    ReadOnlyDirNotSupported(String),
+    #[error("`--invoke` is not supported on this platform.")]
+    InvokeNotSupported,
+    #[error(
+        "No command given and the current directory has no package with commands to default to."
+    )]
+    NoCommandGiven,
+    #[error(
+        "No command given and the package has several commands ({0}). Name one, or set `default-command` in the manifest."
+    )]
+    AmbiguousDefaultCommand(String),
+    #[error("The package \"{1}\" has no command \"{0}\".")]
+    CommandNotInPackage(String, String),
+    #[error("The package \"{2}\", depended on as \"{1}\", has no command \"{0}\".")]
+    AliasedCommandNotFound(String, String, String),
+    #[error("Could not precompile \"{0}\": {1}")]
+    CannotPrecompile(String, String),
 }
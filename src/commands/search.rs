@@ -1,12 +1,18 @@
 //! Code pertaining to the `search` subcommand, which queries the server about
 //! the specified package.
 
-use crate::graphql::execute_query;
-
-use graphql_client::*;
+use crate::config::Config;
+use crate::data::manifest::{Manifest, PACKAGES_DIR_NAME};
+use crate::data::search_cache::{SearchCache, SEARCH_CACHE_TTL_SECS};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::registry::RegistryClient;
+pub use crate::registry::SearchResult;
 
 use prettytable::format;
 use prettytable::Table;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
 
 /// Options for the `search` subcommand
@@ -14,27 +20,147 @@ use structopt::StructOpt;
 pub struct SearchOpt {
     #[structopt(parse(from_str))]
     query: String,
+    /// How to order the results: `relevance`, `downloads` or `newest`
+    #[structopt(long = "sort", default_value = "relevance")]
+    sort: SearchSort,
+    /// Query the registry even if the same search was made recently
+    #[structopt(long = "no-cache")]
+    no_cache: bool,
+    /// Search the packages installed in the current directory and globally instead of the
+    /// registry, matching their names, descriptions and commands
+    #[structopt(long = "installed", conflicts_with = "no-cache")]
+    installed: bool,
+}
+
+/// The order search results are shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSort {
+    /// The order the registry returned them in
+    Relevance,
+    /// The most downloaded packages first
+    Downloads,
+    /// The most recently published versions first
+    Newest,
+}
+
+impl std::str::FromStr for SearchSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relevance" => Ok(SearchSort::Relevance),
+            "downloads" => Ok(SearchSort::Downloads),
+            "newest" => Ok(SearchSort::Newest),
+            _ => Err(format!(
+                "Invalid sort order \"{}\", expected one of: relevance, downloads, newest",
+                s
+            )),
+        }
+    }
+}
+
+impl SearchSort {
+    /// Reorder `results`, which are in the order the registry returned them. Results that
+    /// compare equal keep that order.
+    pub fn sort(self, results: &mut [SearchResult]) {
+        match self {
+            SearchSort::Relevance => {}
+            SearchSort::Downloads => {
+                results.sort_by_key(|result| std::cmp::Reverse(result.downloads.unwrap_or(0)))
+            }
+            SearchSort::Newest => results.sort_by(|a, b| b.date.cmp(&a.date)),
+        }
+    }
 }
 
-type DateTime = String;
+/// Query the registry for package versions matching `query`
+pub fn search_packages(query: &str) -> anyhow::Result<Vec<SearchResult>> {
+    RegistryClient::from_config()?.search(query)
+}
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/search.graphql",
-    response_derives = "Debug"
-)]
-struct SearchQuery;
+/// Query the registry for `query`, reusing the results of the same search made in the last
+/// minute unless `no_cache` is set. Returns whether the results came from the cache.
+fn cached_search(query: &str, no_cache: bool) -> anyhow::Result<(Vec<SearchResult>, bool)> {
+    let cache_path = match Config::get_search_cache_file_path() {
+        Ok(cache_path) => cache_path,
+        Err(_) => return Ok((search_packages(query)?, false)),
+    };
+    let config = Config::from_file()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut cache = SearchCache::open(&cache_path, config.registry.get_url()?);
+    let searched = cache.search(
+        query,
+        now,
+        Duration::from_secs(SEARCH_CACHE_TTL_SECS),
+        no_cache,
+        search_packages,
+    )?;
+    if let Err(e) = cache.save(&cache_path) {
+        debug!("Could not save the search cache: {}", e);
+    }
+    Ok(searched)
+}
+
+/// Match `query` against the names, descriptions and commands of the packages installed in
+/// `directories`, ignoring case. Packages installed in several directories are listed once,
+/// sorted by name and version.
+pub fn search_installed(directories: &[PathBuf], query: &str) -> Vec<SearchResult> {
+    let query = query.to_lowercase();
+    let mut found = BTreeMap::new();
+    for directory in directories {
+        let lockfile = match LockfileResult::find_in_directory(directory) {
+            LockfileResult::Lockfile(lockfile) => lockfile,
+            _ => continue,
+        };
+        for (name, versions) in lockfile.modules.iter() {
+            for (version, modules) in versions.iter() {
+                let package_dir = match modules.values().next() {
+                    Some(module) if module.resolved == "local" => directory.clone(),
+                    Some(module) => directory.join(PACKAGES_DIR_NAME).join(&module.package_path),
+                    None => continue,
+                };
+                let description = Manifest::find_in_directory(&package_dir)
+                    .map(|manifest| manifest.package.description)
+                    .unwrap_or_default();
+                let matches = name.to_lowercase().contains(&query)
+                    || description.to_lowercase().contains(&query)
+                    || lockfile.commands.values().any(|command| {
+                        command.package_name == *name
+                            && command.package_version == *version
+                            && command.name.to_lowercase().contains(&query)
+                    });
+                if matches {
+                    found
+                        .entry((name.clone(), version.clone()))
+                        .or_insert(SearchResult {
+                            name: name.clone(),
+                            description,
+                            date: String::new(),
+                            version: version.to_string(),
+                            downloads: None,
+                        });
+                }
+            }
+        }
+    }
+    found.into_values().collect()
+}
 
 /// Run the search command
 pub fn search(options: SearchOpt) -> anyhow::Result<()> {
     let query = options.query;
-    let q = SearchQuery::build_query(search_query::Variables {
-        query: query.to_string(),
-    });
-    let response: search_query::ResponseData = execute_query(&q)?;
+    let (mut results, cached) = if options.installed {
+        let directories = vec![Config::get_current_dir()?, Config::get_globals_directory()?];
+        (search_installed(&directories, &query), false)
+    } else {
+        cached_search(&query, options.no_cache)?
+    };
+    options.sort.sort(&mut results);
 
-    if response.search.edges.len() == 0 {
+    if results.is_empty() {
         println!("No packages found for \"{}\"", query);
         return Ok(());
     }
@@ -43,21 +169,98 @@ pub fn search(options: SearchOpt) -> anyhow::Result<()> {
 
     // Add a row per time
     table.add_row(row!["NAME", "DESCRIPTION", "DATE", "VERSION"]);
-    for edge in response.search.edges.into_iter() {
-        let node = edge.unwrap().node;
-        match node {
-            Some(search_query::SearchQuerySearchEdgesNode::PackageVersion(version)) => {
-                table.add_row(row![
-                    version.package.display_name,
-                    version.description,
-                    version.created_at[..10],
-                    version.version
-                ]);
-            }
-            _ => {}
-        }
+    for result in results {
+        table.add_row(row![
+            result.name,
+            result.description,
+            result.date,
+            result.version
+        ]);
     }
     table.printstd();
+    if cached {
+        println!("(cached results, pass --no-cache to refresh)");
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataflow;
+    use crate::dataflow::UpdateOptions;
+    use crate::fixtures::{DescribedInstaller, TestResolver};
+
+    #[test]
+    fn installed_packages_are_searched_offline() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        dataflow::update_with::<TestResolver, DescribedInstaller, _>(
+            vec![("json", "1.0.0"), ("yaml", "2.1.0")],
+            vec![],
+            tmp_dir.path(),
+            UpdateOptions::default(),
+        )
+        .unwrap();
+        let directories = vec![tmp_dir.path().to_path_buf(), tmp_dir.path().join("missing")];
+        let names = |query: &str| {
+            search_installed(&directories, query)
+                .into_iter()
+                .map(|result| format!("{}@{}", result.name, result.version))
+                .collect::<Vec<_>>()
+        };
+
+        let results = search_installed(&directories, "JSON");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "_/json");
+        assert_eq!(results[0].description, "Tools for json");
+        assert_eq!(names("yaml-cli"), vec!["_/yaml@2.1.0"]);
+        assert_eq!(names("tools"), vec!["_/json@1.0.0", "_/yaml@2.1.0"]);
+        assert!(names("toml").is_empty());
+        assert!(
+            SearchOpt::from_iter_safe(&["search", "json", "--installed"])
+                .unwrap()
+                .installed
+        );
+    }
+
+    fn result(name: &str, date: &str, downloads: Option<i64>) -> SearchResult {
+        SearchResult {
+            name: name.to_string(),
+            description: String::new(),
+            date: date.to_string(),
+            version: "1.0.0".to_string(),
+            downloads,
+        }
+    }
+
+    #[test]
+    fn sorting_by_downloads_puts_the_most_downloaded_first() {
+        let results = vec![
+            result("relevant", "2021-01-01", Some(10)),
+            result("unknown", "2021-03-01", None),
+            result("popular", "2020-01-01", Some(500)),
+            result("middling", "2021-02-01", Some(50)),
+        ];
+        let names = |sort: &str| {
+            let mut results = results.clone();
+            let opts = SearchOpt::from_iter_safe(&["search", "query", "--sort", sort]).unwrap();
+            opts.sort.sort(&mut results);
+            results.into_iter().map(|r| r.name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names("downloads"),
+            vec!["popular", "middling", "relevant", "unknown"]
+        );
+        assert_eq!(
+            names("newest"),
+            vec!["unknown", "middling", "relevant", "popular"]
+        );
+        assert_eq!(
+            names("relevance"),
+            vec!["relevant", "unknown", "popular", "middling"]
+        );
+        assert!(SearchOpt::from_iter_safe(&["search", "query", "--sort", "stars"]).is_err());
+    }
+}
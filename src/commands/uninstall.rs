@@ -19,7 +19,7 @@ pub struct UninstallOpt {
 
 pub fn uninstall(options: UninstallOpt) -> anyhow::Result<()> {
     let dir = match options.global {
-        true => Config::get_globals_directory()?,
+        true => Config::get_global_directory()?,
         false => Config::get_current_dir()?,
     };
     let uninstalled_package_names = vec![options.package.as_str()];
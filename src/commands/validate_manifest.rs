@@ -0,0 +1,169 @@
+//! The `validate-manifest` subcommand checks a manifest the way `wapm publish` would, without
+//! packaging or publishing anything
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::util;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use toml::Value;
+
+#[derive(StructOpt, Debug)]
+pub struct ValidateManifestOpt {
+    /// The directory containing the manifest, defaults to the current directory
+    #[structopt(parse(from_os_str))]
+    directory: Option<PathBuf>,
+}
+
+/// The fields every manifest has to declare, with a placeholder used to keep checking the rest
+/// of a manifest that is missing one
+const REQUIRED_PACKAGE_FIELDS: &[(&str, &str)] =
+    &[("name", "_"), ("version", "0.0.0"), ("description", "")];
+
+pub fn validate_manifest(options: ValidateManifestOpt) -> anyhow::Result<()> {
+    let directory = match options.directory {
+        Some(directory) => directory,
+        None => crate::config::Config::get_current_dir()?,
+    };
+    let path = directory.join(MANIFEST_FILE_NAME);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    let problems = manifest_problems(&contents);
+    if problems.is_empty() {
+        util::print_success(&format!("{} is valid", path.display()));
+        return Ok(());
+    }
+    for problem in problems.iter() {
+        println!("error: {}", problem);
+    }
+    Err(anyhow!(
+        "{} has {} problem(s)",
+        path.display(),
+        problems.len()
+    ))
+}
+
+/// Every problem with the manifest in `contents`: missing required fields, an invalid version
+/// or license, and commands that refer to missing modules
+pub fn manifest_problems(contents: &str) -> Vec<String> {
+    let mut value: Value = match toml::from_str(contents) {
+        Ok(value) => value,
+        Err(e) => return vec![format!("Could not parse the manifest: {}", e)],
+    };
+    let package = match value.get_mut("package").and_then(Value::as_table_mut) {
+        Some(package) => package,
+        None => return vec!["The manifest has no [package] section".to_string()],
+    };
+
+    let mut problems = vec![];
+    for (field, placeholder) in REQUIRED_PACKAGE_FIELDS {
+        if !package.get(*field).map_or(false, Value::is_str) {
+            problems.push(format!("Missing required field `package.{}`", field));
+            package.insert(field.to_string(), Value::String(placeholder.to_string()));
+        }
+    }
+    if let Some(version) = package.get("version").and_then(Value::as_str) {
+        if let Err(e) = semver::Version::parse(version) {
+            problems.push(format!(
+                "`package.version` \"{}\" is not a semantic version: {}",
+                version, e
+            ));
+            package.insert("version".to_string(), Value::String("0.0.0".to_string()));
+        }
+    }
+    if let Some(license) = package.get("license").and_then(Value::as_str) {
+        if let Err(e) = util::validate_license(license) {
+            problems.push(format!("`package.license`: {}", e));
+        }
+    }
+
+    let manifest: Manifest = match value.try_into() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            problems.push(format!("Could not parse the manifest: {}", e));
+            return problems;
+        }
+    };
+    if let Err(e) = manifest.schema_compatibility() {
+        problems.push(e.to_string());
+    }
+    problems.extend(
+        manifest
+            .validation_errors()
+            .into_iter()
+            .map(|e| e.to_string()),
+    );
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::manifest_problems;
+
+    #[test]
+    fn a_valid_manifest_has_no_problems() {
+        let manifest = r#"
+[package]
+name = "_/hello"
+version = "1.0.0"
+description = "hello"
+license = "MIT OR Apache-2.0"
+
+[[module]]
+name = "hello"
+source = "hello.wasm"
+abi = "wasi"
+
+[[command]]
+name = "hello"
+module = "hello"
+"#;
+        assert_eq!(manifest_problems(manifest), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let manifest = r#"
+[package]
+name = "_/hello"
+version = "1.0"
+license = "NOT-A-LICENSE"
+
+[[module]]
+name = "hello"
+source = "hello.wasm"
+
+[[command]]
+name = "hello"
+module = "hello"
+
+[[command]]
+name = "goodbye"
+module = "goodbye"
+"#;
+        assert_eq!(
+            manifest_problems(manifest),
+            vec![
+                "Missing required field `package.description`".to_string(),
+                "`package.version` \"1.0\" is not a semantic version: unexpected end of input while parsing minor version number".to_string(),
+                "`package.license`: \"NOT-A-LICENSE\" is not a valid SPDX license".to_string(),
+                "missing ABI field on module hello used by command hello; an ABI of `wasi` or `emscripten` is required".to_string(),
+                "missing module goodbye in manifest used by command goodbye".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn unparseable_manifests_are_reported() {
+        assert_eq!(
+            manifest_problems("[dependencies]\n"),
+            vec!["The manifest has no [package] section".to_string()]
+        );
+        let problems = manifest_problems("[package\n");
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("Could not parse the manifest: "),
+            "{:?}",
+            problems
+        );
+    }
+}
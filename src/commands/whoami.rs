@@ -1,7 +1,18 @@
-use crate::util;
+use crate::registry::RegistryClient;
+use std::path::PathBuf;
+use structopt::StructOpt;
 
-pub fn whoami() -> anyhow::Result<()> {
-    let username = util::get_username()?.unwrap_or("(not logged in)".to_string());
+#[derive(StructOpt, Debug)]
+pub struct WhoAmIOpt {
+    /// Read the registry token from this file instead of the config, like `WAPM_TOKEN_FILE`
+    #[structopt(long = "token-file", parse(from_os_str))]
+    token_file: Option<PathBuf>,
+}
+
+pub fn whoami(options: WhoAmIOpt) -> anyhow::Result<()> {
+    let username = RegistryClient::from_config_with_token_file(options.token_file.as_deref())?
+        .whoami()?
+        .unwrap_or("(not logged in)".to_string());
     println!("{}", username);
     Ok(())
 }
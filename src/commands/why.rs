@@ -0,0 +1,146 @@
+//! Explain why a package is in the dependency tree
+use crate::data::manifest::Manifest;
+use crate::dataflow::dependency_graph::{qualify, DependencyGraph};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::PackageKey;
+use std::fmt::Write as _;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct WhyOpt {
+    /// The package to explain, e.g. `namespace/name`
+    package: String,
+}
+
+pub fn why(options: WhyOpt) -> anyhow::Result<()> {
+    let cwd = crate::config::Config::get_current_dir()?;
+    print!("{}", explain(&cwd, &options.package)?);
+    Ok(())
+}
+
+/// Describe every path from a dependency in the manifest in `directory` to `package`, using
+/// the packages in its lockfile
+fn explain(directory: &Path, package: &str) -> anyhow::Result<String> {
+    let manifest = Manifest::find_in_directory(directory)?;
+    let lockfile = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::NoLockfile => {
+            return Err(anyhow!(
+                "No lockfile found in {}. Run `wapm install` first.",
+                directory.display()
+            ))
+        }
+        LockfileResult::LockfileError(e) => return Err(e.into()),
+    };
+    let package_keys: Vec<PackageKey> = lockfile
+        .modules
+        .iter()
+        .flat_map(|(name, versions)| {
+            versions
+                .keys()
+                .map(move |version| PackageKey::new_registry_package(name, version.clone()))
+        })
+        .collect();
+    let graph = DependencyGraph::from_installed_packages(directory, Some(&manifest), &package_keys);
+
+    let package = qualify(package);
+    let paths = graph.paths_to(&package);
+    let mut out = String::new();
+    if paths.is_empty() {
+        writeln!(out, "{} is not in the dependency tree", package)?;
+        return Ok(out);
+    }
+    writeln!(out, "{} is required through:", package)?;
+    for path in paths {
+        writeln!(out, "  {}", path.join(" → "))?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::explain;
+    use crate::data::lock::lockfile::Lockfile;
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use semver::Version;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    /// Install `name` with a manifest depending on `dependencies`
+    fn install_package(directory: &Path, name: &str, dependencies: &[&str]) {
+        let (namespace, unqualified) = name.split_once('/').unwrap();
+        let package_dir = directory
+            .join("wapm_packages")
+            .join(namespace)
+            .join(format!("{}@1.0.0", unqualified));
+        fs::create_dir_all(&package_dir).unwrap();
+        let dependencies: String = dependencies
+            .iter()
+            .map(|dependency| format!("\"{}\" = \"1.0.0\"\n", dependency))
+            .collect();
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"1.0.0\"\ndescription = \"test package\"\n\n[dependencies]\n{}",
+            name, dependencies
+        );
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+    }
+
+    #[test]
+    fn every_path_to_the_package_is_printed() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let directory = tmp_dir.path();
+        fs::write(
+            directory.join("wapm.toml"),
+            r#"
+[package]
+name = "root"
+version = "0.1.0"
+description = "project"
+
+[dependencies]
+"ns/app" = "1.0.0"
+"ns/cli" = "1.0.0"
+"ns/json" = "1.0.0"
+"#,
+        )
+        .unwrap();
+        install_package(directory, "ns/app", &["ns/http"]);
+        install_package(directory, "ns/http", &["ns/json", "ns/tls"]);
+        install_package(directory, "ns/cli", &["ns/json"]);
+        install_package(directory, "ns/json", &[]);
+        install_package(directory, "ns/tls", &[]);
+
+        let mut modules = BTreeMap::new();
+        for name in &["ns/app", "ns/http", "ns/cli", "ns/json", "ns/tls"] {
+            let mut versions = BTreeMap::new();
+            versions.insert(Version::new(1, 0, 0), BTreeMap::new());
+            versions
+                .get_mut(&Version::new(1, 0, 0))
+                .unwrap()
+                .insert("main".to_string(), LockfileModule::default());
+            modules.insert(name.to_string(), versions);
+        }
+        Lockfile {
+            modules,
+            commands: BTreeMap::new(),
+            features: None,
+        }
+        .save(directory)
+        .unwrap();
+
+        assert_eq!(
+            explain(directory, "ns/json").unwrap(),
+            "ns/json is required through:\n  ns/app → ns/http → ns/json\n  ns/cli → ns/json\n  ns/json\n"
+        );
+        assert_eq!(
+            explain(directory, "ns/tls").unwrap(),
+            "ns/tls is required through:\n  ns/app → ns/http → ns/tls\n"
+        );
+        assert_eq!(
+            explain(directory, "ns/missing").unwrap(),
+            "ns/missing is not in the dependency tree\n"
+        );
+    }
+}
@@ -5,9 +5,11 @@
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::util::{command_line_settings, write_file_atomically, CommandLineSettings};
+
 pub static GLOBAL_CONFIG_FILE_NAME: &str = if cfg!(target_os = "wasi") {
     "/.private/wapm.toml"
 } else {
@@ -17,7 +19,25 @@ pub static GLOBAL_CONFIG_FILE_NAME: &str = if cfg!(target_os = "wasi") {
 pub static GLOBAL_CONFIG_FOLDER_NAME: &str = ".wasmer";
 pub static GLOBAL_WAX_INDEX_FILE_NAME: &str = ".wax_index.json";
 pub static GLOBAL_CONFIG_DATABASE_FILE_NAME: &str = "wapm.sqlite";
+pub static GLOBAL_METADATA_CACHE_FILE_NAME: &str = ".registry_metadata_cache.json";
+pub static GLOBAL_SEARCH_CACHE_FILE_NAME: &str = ".registry_search_cache.json";
+pub static GLOBAL_EPHEMERAL_DIR_NAME: &str = "ephemeral";
+pub static GLOBAL_COMPILED_DIR_NAME: &str = "compiled";
 pub static GLOBAL_CONFIG_FOLDER_ENV_VAR: &str = "WASMER_DIR";
+pub static GLOBAL_PACKAGES_DIR_ENV_VAR: &str = "WAPM_GLOBAL_DIR";
+pub static GLOBAL_OVERLAY_DIR_ENV_VAR: &str = "WAPM_GLOBAL_OVERLAY_DIR";
+pub static REGISTRY_TOKEN_ENV_VAR: &str = "WAPM_REGISTRY_TOKEN";
+pub static TOKEN_FILE_ENV_VAR: &str = "WAPM_TOKEN_FILE";
+pub static NETRC_ENV_VAR: &str = "NETRC";
+
+/// The public registry, used when no other registry is configured.
+pub const DEFAULT_REGISTRY_URL: &str = "https://registry.wapm.io";
+
+/// The number of seconds to wait for a registry request when no timeout is configured.
+pub const DEFAULT_REGISTRY_TIMEOUT_SECS: u64 = 30;
+
+/// The number of seconds cached package metadata is used for when no TTL is configured.
+pub const DEFAULT_METADATA_TTL_SECS: u64 = 5 * 60;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Config {
@@ -53,6 +73,33 @@ pub const fn wax_default_cooldown() -> i32 {
 pub struct Registry {
     pub url: String,
     pub token: Option<String>,
+    /// The number of seconds to wait for each request to the registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// The user agent sent with every request to the registry.
+    #[serde(
+        rename = "user-agent",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub user_agent: Option<String>,
+    /// The number of seconds to use cached package metadata for before fetching it again.
+    #[serde(
+        rename = "metadata-ttl",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub metadata_ttl: Option<u64>,
+    /// Refuse to use the default registry, so that a private registry has to be configured.
+    #[serde(
+        rename = "no-default",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub no_default: Option<bool>,
+    /// The global command line flags of this process, which take precedence over the config
+    #[serde(skip)]
+    pub command_line: CommandLineSettings,
 }
 
 #[cfg(feature = "telemetry")]
@@ -94,8 +141,13 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             registry: Registry {
-                url: "https://registry.wapm.io".to_string(),
+                url: DEFAULT_REGISTRY_URL.to_string(),
                 token: None,
+                timeout: None,
+                user_agent: None,
+                metadata_ttl: None,
+                no_default: None,
+                command_line: CommandLineSettings::default(),
             },
             #[cfg(feature = "telemetry")]
             telemetry: Telemetry::default(),
@@ -147,7 +199,7 @@ impl Config {
         )
     }
 
-    fn get_file_location() -> Result<PathBuf, GlobalConfigError> {
+    pub fn get_file_location() -> Result<PathBuf, GlobalConfigError> {
         Ok(Self::get_folder()?.join(GLOBAL_CONFIG_FILE_NAME))
     }
 
@@ -155,24 +207,45 @@ impl Config {
         Config::get_folder().map(|config_folder| config_folder.join(GLOBAL_WAX_INDEX_FILE_NAME))
     }
 
+    pub fn get_metadata_cache_file_path() -> Result<PathBuf, GlobalConfigError> {
+        Config::get_folder()
+            .map(|config_folder| config_folder.join(GLOBAL_METADATA_CACHE_FILE_NAME))
+    }
+
+    pub fn get_search_cache_file_path() -> Result<PathBuf, GlobalConfigError> {
+        Config::get_folder().map(|config_folder| config_folder.join(GLOBAL_SEARCH_CACHE_FILE_NAME))
+    }
+
+    /// The directory packages run with `wapm run namespace/package:command` are cached in
+    pub fn get_ephemeral_directory() -> Result<PathBuf, GlobalConfigError> {
+        Config::get_folder().map(|config_folder| config_folder.join(GLOBAL_EPHEMERAL_DIR_NAME))
+    }
+
+    /// The directory modules compiled with `wapm run --precompile` are cached in
+    pub fn get_compiled_directory() -> Result<PathBuf, GlobalConfigError> {
+        Config::get_folder().map(|config_folder| config_folder.join(GLOBAL_COMPILED_DIR_NAME))
+    }
+
     pub fn get_database_file_path() -> Result<PathBuf, GlobalConfigError> {
         Config::get_folder()
             .map(|config_folder| config_folder.join(GLOBAL_CONFIG_DATABASE_FILE_NAME))
     }
 
-    /// Load the config from a file
+    /// Load the config from a file, with the command line settings of this process applied
     #[cfg(not(feature = "integration_tests"))]
     pub fn from_file() -> Result<Self, GlobalConfigError> {
         let path = Self::get_file_location()?;
-        match File::open(&path) {
+        let mut config: Self = match File::open(&path) {
             Ok(mut file) => {
                 let mut config_toml = String::new();
                 file.read_to_string(&mut config_toml)
                     .map_err(|e| GlobalConfigError::Io(e))?;
-                toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))
+                toml::from_str(&config_toml).map_err(GlobalConfigError::Toml)?
             }
-            Err(_e) => Ok(Self::default()),
-        }
+            Err(_e) => Self::default(),
+        };
+        config.registry.command_line = command_line_settings();
+        Ok(config)
     }
 
     /// A mocked version of the standard function for integration tests
@@ -187,17 +260,59 @@ impl Config {
         })
     }
 
+    /// The directory that global packages are installed to: `WAPM_GLOBAL_DIR` if it is set,
+    /// otherwise `globals` in the config folder.
     pub fn get_globals_directory() -> Result<PathBuf, GlobalConfigError> {
+        if let Some(folder) = env::var_os(GLOBAL_PACKAGES_DIR_ENV_VAR).filter(|s| !s.is_empty()) {
+            return Ok(PathBuf::from(folder));
+        }
         Self::get_folder().map(|p| p.join("globals"))
     }
 
+    /// The per-user directory global packages are installed to when the globals directory is
+    /// read-only: `WAPM_GLOBAL_OVERLAY_DIR` if it is set, otherwise `wapm/globals` in the user's
+    /// local data directory.
+    pub fn get_globals_overlay_directory() -> Result<PathBuf, GlobalConfigError> {
+        if let Some(folder) = env::var_os(GLOBAL_OVERLAY_DIR_ENV_VAR).filter(|s| !s.is_empty()) {
+            return Ok(PathBuf::from(folder));
+        }
+        #[cfg(feature = "dirs")]
+        let data_dir = dirs::data_local_dir().ok_or(GlobalConfigError::CannotFindHomeDirectory)?;
+        #[cfg(not(feature = "dirs"))]
+        let data_dir = env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local").join("share"))
+            .ok_or(GlobalConfigError::CannotFindHomeDirectory)?;
+        Ok(data_dir.join("wapm").join("globals"))
+    }
+
+    /// The directories global packages are read from, in the order they are searched: the
+    /// overlay directory if it has been used, then the globals directory.
+    pub fn get_global_directories() -> Result<Vec<PathBuf>, GlobalConfigError> {
+        let primary = Self::get_globals_directory()?;
+        let overlay = Self::get_globals_overlay_directory()?;
+        Ok(global_directories(primary, overlay))
+    }
+
+    /// The directory global packages were last installed to, without checking whether it can be
+    /// written: the overlay directory if it has been used, otherwise the globals directory.
+    pub fn get_global_directory() -> Result<PathBuf, GlobalConfigError> {
+        Ok(Self::get_global_directories()?.remove(0))
+    }
+
+    /// The directory global packages are installed to: the globals directory, or the overlay
+    /// directory if the globals directory is read-only.
+    pub fn get_global_install_directory() -> Result<PathBuf, GlobalConfigError> {
+        let primary = Self::get_globals_directory()?;
+        let overlay = Self::get_globals_overlay_directory()?;
+        Ok(global_install_directory(primary, overlay, is_writable))
+    }
+
     /// Save the config to a file
     #[cfg(not(feature = "integration_tests"))]
     pub fn save(self: &Self) -> anyhow::Result<()> {
         let path = Self::get_file_location()?;
         let config_serialized = toml::to_string(&self)?;
-        let mut file = File::create(path)?;
-        file.write_all(config_serialized.as_bytes())?;
+        write_file_atomically(&path, config_serialized.as_bytes())?;
         Ok(())
     }
 
@@ -229,6 +344,220 @@ impl Registry {
             format!("{}/graphql", url)
         }
     }
+
+    /// The token to authenticate with. The file `token_file` given with `--token-file` takes
+    /// precedence, then `WAPM_REGISTRY_TOKEN`, then the file named by `WAPM_TOKEN_FILE`, then the
+    /// token saved in the config, then the password of the registry's host in `~/.netrc`.
+    pub fn get_token(&self, token_file: Option<&Path>) -> Result<Option<String>, ConfigError> {
+        let env_token_file = env::var_os(TOKEN_FILE_ENV_VAR).map(PathBuf::from);
+        self.get_token_with(
+            token_file,
+            env::var(REGISTRY_TOKEN_ENV_VAR).ok(),
+            env_token_file.as_deref(),
+        )
+    }
+
+    /// `get_token` with the variables of the environment passed in
+    fn get_token_with(
+        &self,
+        token_file: Option<&Path>,
+        env_token: Option<String>,
+        env_token_file: Option<&Path>,
+    ) -> Result<Option<String>, ConfigError> {
+        if let Some(path) = token_file {
+            return read_token_file(path).map(Some);
+        }
+        if let Some(token) = env_token {
+            return Ok(Some(token));
+        }
+        self.get_token_from(env_token_file, netrc_path().as_deref())
+    }
+
+    /// Fail if this is the default registry and `--no-default-registry` was passed or
+    /// `registry.no-default` is set, so private-only environments never fall back to it.
+    pub fn check_not_default(&self) -> Result<(), ConfigError> {
+        let forbidden = self.command_line.no_default_registry || self.no_default == Some(true);
+        if forbidden && self.url.trim_end_matches('/') == DEFAULT_REGISTRY_URL {
+            return Err(ConfigError::DefaultRegistryForbidden {
+                url: self.url.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The URL of the registry, for talking to it or to its caches. Fails like
+    /// `check_not_default` if that registry may not be used.
+    pub fn get_url(&self) -> Result<&str, ConfigError> {
+        self.check_not_default()?;
+        Ok(&self.url)
+    }
+
+    fn get_token_from(
+        &self,
+        token_file: Option<&Path>,
+        netrc: Option<&Path>,
+    ) -> Result<Option<String>, ConfigError> {
+        match token_file {
+            Some(path) => read_token_file(path).map(Some),
+            None => Ok(self
+                .token
+                .clone()
+                .or_else(|| netrc.and_then(|netrc| self.netrc_token(netrc)))),
+        }
+    }
+
+    /// The password of the registry's host in the netrc file at `path`
+    fn netrc_token(&self, path: &Path) -> Option<String> {
+        let host = url::Url::parse(&self.url).ok()?.host_str()?.to_string();
+        match std::fs::read_to_string(path) {
+            Ok(contents) => netrc_password(&contents, &host),
+            Err(e) => {
+                debug!("Could not read {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// How long to wait for each registry request. `--registry-timeout` takes precedence over the
+    /// timeout saved in the config.
+    pub fn get_timeout(&self) -> std::time::Duration {
+        let seconds = self
+            .command_line
+            .registry_timeout
+            .or(self.timeout)
+            .unwrap_or(DEFAULT_REGISTRY_TIMEOUT_SECS);
+        std::time::Duration::from_secs(seconds)
+    }
+
+    /// How long cached package metadata is used for before it is fetched again
+    pub fn get_metadata_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.metadata_ttl.unwrap_or(DEFAULT_METADATA_TTL_SECS))
+    }
+
+    /// The user agent to send to the registry. `--user-agent` takes precedence over the user
+    /// agent saved in the config, and the default is `wapm-cli/<version>` followed by the
+    /// platform.
+    pub fn get_user_agent(&self) -> String {
+        self.command_line
+            .user_agent
+            .clone()
+            .filter(|user_agent| !user_agent.is_empty())
+            .or_else(|| self.user_agent.clone())
+            .unwrap_or_else(default_user_agent)
+    }
+}
+
+/// `wapm-cli/<version> <platform> <distro>`
+pub fn default_user_agent() -> String {
+    format!(
+        "wapm-cli/{} {} {}",
+        crate::graphql::VERSION,
+        whoami::platform(),
+        crate::util::whoami_distro(),
+    )
+}
+
+/// Read a token from a file, e.g. a mounted secret. Surrounding whitespace is trimmed.
+pub fn read_token_file(path: &Path) -> Result<String, ConfigError> {
+    let token_file_error = |error: String| ConfigError::TokenFile {
+        path: path.to_string_lossy().to_string(),
+        error,
+    };
+    let contents = std::fs::read_to_string(path).map_err(|e| token_file_error(e.to_string()))?;
+    let token = contents.trim();
+    if token.is_empty() {
+        return Err(token_file_error("The file is empty".to_string()));
+    }
+    Ok(token.to_string())
+}
+
+/// The netrc file, `$NETRC` or `~/.netrc`
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(NETRC_ENV_VAR) {
+        return Some(PathBuf::from(path));
+    }
+    #[cfg(feature = "dirs")]
+    let home_dir = dirs::home_dir();
+    #[cfg(not(feature = "dirs"))]
+    let home_dir = env::var_os("HOME").map(PathBuf::from);
+    home_dir.map(|home_dir| home_dir.join(".netrc"))
+}
+
+/// The password of the `machine` entry for `host` in a netrc file, falling back to the
+/// `default` entry
+fn netrc_password(contents: &str, host: &str) -> Option<String> {
+    let mut tokens = contents.split_whitespace();
+    let mut in_entry = false;
+    let mut default_password = None;
+    let mut in_default = false;
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                in_entry = tokens.next() == Some(host);
+                in_default = false;
+            }
+            "default" => {
+                in_entry = false;
+                in_default = true;
+            }
+            "password" => {
+                let password = tokens.next()?;
+                if in_entry {
+                    return Some(password.to_string());
+                }
+                if in_default {
+                    default_password.get_or_insert_with(|| password.to_string());
+                }
+            }
+            "login" | "account" => {
+                tokens.next();
+            }
+            // macros run until the next blank line, which splitting on whitespace loses; the
+            // entries after a macro aren't looked at
+            "macdef" => break,
+            _ => {}
+        }
+    }
+    default_password
+}
+
+fn global_directories(primary: PathBuf, overlay: PathBuf) -> Vec<PathBuf> {
+    if overlay != primary && overlay.exists() {
+        vec![overlay, primary]
+    } else {
+        vec![primary]
+    }
+}
+
+fn global_install_directory<F>(primary: PathBuf, overlay: PathBuf, is_writable: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    if is_writable(&primary) {
+        primary
+    } else {
+        debug!(
+            "The global package directory {} is read-only, using {} instead",
+            primary.display(),
+            overlay.display()
+        );
+        overlay
+    }
+}
+
+/// Whether files can be created in `directory`, creating it if it doesn't exist
+fn is_writable(directory: &Path) -> bool {
+    if std::fs::create_dir_all(directory).is_err() {
+        return false;
+    }
+    let probe = directory.join(format!(".wapm-write-check-{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
 }
 
 #[derive(Debug, Error)]
@@ -251,6 +580,12 @@ pub enum ConfigError {
     KeyNotFound { key: String },
     #[error("Failed to parse value `{value}` for key `{key}`")]
     CanNotParse { value: String, key: String },
+    #[error("The edited config is invalid and was not saved. {error}{context}")]
+    InvalidEdit { error: String, context: String },
+    #[error("Could not read the token file \"{path}\": {error}")]
+    TokenFile { path: String, error: String },
+    #[error("Using the default registry ({url}) is not allowed. Select a registry with `wapm config set registry.url <url>`.")]
+    DefaultRegistryForbidden { url: String },
 }
 
 pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()> {
@@ -265,6 +600,29 @@ pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()
         "registry.token" => {
             config.registry.token = Some(value);
         }
+        "registry.user-agent" => {
+            config.registry.user_agent = if value.is_empty() { None } else { Some(value) };
+        }
+        "registry.timeout" => {
+            config.registry.timeout = if value.is_empty() {
+                None
+            } else {
+                Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?)
+            };
+        }
+        "registry.metadata-ttl" => {
+            config.registry.metadata_ttl = if value.is_empty() {
+                None
+            } else {
+                Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?)
+            };
+        }
         #[cfg(feature = "telemetry")]
         "telemetry.enabled" => {
             config.telemetry.enabled = value;
@@ -273,6 +631,16 @@ pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()
         "update-notifications.enabled" => {
             config.update_notifications.enabled = value;
         }
+        "registry.no-default" => {
+            config.registry.no_default = if value.is_empty() {
+                None
+            } else {
+                Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?)
+            };
+        }
         "proxy.url" => {
             config.proxy.url = if value.is_empty() { None } else { Some(value) };
         }
@@ -291,9 +659,32 @@ pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()
     Ok(())
 }
 
+/// Validate an edited config and write it to `path`. If `edited` isn't a valid config, the
+/// file at `path` is left untouched.
+pub fn save_edited_config(path: &Path, edited: &str) -> anyhow::Result<()> {
+    if let Err(e) = toml::from_str::<Config>(edited) {
+        let context = e
+            .line_col()
+            .and_then(|(line, _)| edited.lines().nth(line).map(|text| (line, text)))
+            .map(|(line, text)| format!("\n{:>4} | {}", line + 1, text))
+            .unwrap_or_default();
+        return Err(ConfigError::InvalidEdit {
+            error: e.to_string(),
+            context,
+        }
+        .into());
+    }
+    write_file_atomically(path, edited.as_bytes())?;
+    Ok(())
+}
+
 pub fn get(config: &mut Config, key: String) -> anyhow::Result<String> {
     let value = match key.as_ref() {
         "registry.url" => config.registry.url.clone(),
+        "registry.timeout" => format!("{}", config.registry.get_timeout().as_secs()),
+        "registry.user-agent" => config.registry.get_user_agent(),
+        "registry.metadata-ttl" => format!("{}", config.registry.get_metadata_ttl().as_secs()),
+        "registry.no-default" => format!("{}", config.registry.no_default.unwrap_or(false)),
         "registry.token" => {
             unimplemented!()
             // &(config.registry.token.as_ref().map_or("".to_string(), |n| n.to_string()).to_owned())
@@ -319,11 +710,23 @@ pub fn get(config: &mut Config, key: String) -> anyhow::Result<String> {
 
 #[cfg(test)]
 mod test {
-    use crate::config::{Config, GLOBAL_CONFIG_FILE_NAME, GLOBAL_CONFIG_FOLDER_ENV_VAR};
-    use crate::util::create_temp_dir;
+    use crate::config::{
+        global_directories, global_install_directory, save_edited_config, Config, Registry,
+        GLOBAL_CONFIG_FILE_NAME, GLOBAL_CONFIG_FOLDER_ENV_VAR,
+    };
+    use crate::util::{create_temp_dir, CommandLineSettings};
     use std::fs::*;
     use std::io::Write;
 
+    /// A registry at `url` with `saved-token` saved for it
+    fn registry_with_saved_token(url: &str) -> Registry {
+        Registry {
+            url: url.to_string(),
+            token: Some("saved-token".to_string()),
+            ..Config::default().registry
+        }
+    }
+
     #[test]
     fn get_config_and_wasmer_dir_does_not_exist() {
         // remove WASMER_DIR
@@ -371,4 +774,172 @@ mod test {
         let config_result = Config::from_file();
         assert!(config_result.is_ok(), "Config not found.");
     }
+
+    #[test]
+    fn invalid_edit_is_rejected() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let tmp_dir_path: &std::path::Path = tmp_dir.as_ref();
+        let config_path = tmp_dir_path.join(GLOBAL_CONFIG_FILE_NAME);
+        let original = toml::to_string(&Config::default()).unwrap();
+        write(&config_path, &original).unwrap();
+
+        let edited = original.replace("[registry]", "[registry");
+        let error = save_edited_config(&config_path, &edited).unwrap_err();
+        assert!(error.to_string().contains("| [registry"), "{}", error);
+        assert_eq!(read_to_string(&config_path).unwrap(), original);
+
+        let edited = original.replace("registry.wapm.io", "registry.wapm.dev");
+        save_edited_config(&config_path, &edited).unwrap();
+        assert_eq!(read_to_string(&config_path).unwrap(), edited);
+    }
+
+    #[test]
+    fn token_file_takes_precedence_over_saved_token() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let tmp_dir_path: &std::path::Path = tmp_dir.as_ref();
+        let token_path = tmp_dir_path.join("token");
+        write(&token_path, "  secret-token\n").unwrap();
+        let registry = registry_with_saved_token("https://registry.wapm.io");
+
+        let token = registry.get_token_from(Some(&token_path), None).unwrap();
+        assert_eq!(token.as_deref(), Some("secret-token"));
+        let token = registry.get_token_from(None, None).unwrap();
+        assert_eq!(token.as_deref(), Some("saved-token"));
+
+        // an explicit `--token-file` wins over `WAPM_REGISTRY_TOKEN`
+        let env_token = Some("env-token".to_string());
+        let token = registry
+            .get_token_with(Some(&token_path), env_token.clone(), None)
+            .unwrap();
+        assert_eq!(token.as_deref(), Some("secret-token"));
+        let token = registry
+            .get_token_with(None, env_token, Some(&token_path))
+            .unwrap();
+        assert_eq!(token.as_deref(), Some("env-token"));
+    }
+
+    #[test]
+    fn command_line_settings_take_precedence_and_are_not_saved() {
+        let mut config = Config::default();
+        config.registry.timeout = Some(10);
+        config.registry.user_agent = Some("saved-agent".to_string());
+        config.registry.command_line = CommandLineSettings {
+            registry_timeout: Some(3),
+            user_agent: Some("flag-agent".to_string()),
+            ..CommandLineSettings::default()
+        };
+        assert_eq!(config.registry.get_timeout().as_secs(), 3);
+        assert_eq!(config.registry.get_user_agent(), "flag-agent");
+
+        let saved: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.registry.command_line, CommandLineSettings::default());
+        assert_eq!(saved.registry.get_timeout().as_secs(), 10);
+        assert_eq!(saved.registry.get_user_agent(), "saved-agent");
+    }
+
+    #[test]
+    fn default_registry_is_refused_when_forbidden() {
+        let mut config = Config::default();
+        assert!(config.registry.check_not_default().is_ok());
+        config.registry.command_line.no_default_registry = true;
+        let error = config.registry.get_url().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Using the default registry (https://registry.wapm.io) is not allowed. Select a registry with `wapm config set registry.url <url>`."
+        );
+
+        config.registry.command_line.no_default_registry = false;
+        config.registry.no_default = Some(true);
+        assert!(config.registry.check_not_default().is_err());
+        config.registry.url = "https://registry.wapm.io/".to_string();
+        assert!(config.registry.check_not_default().is_err());
+        config.registry.url = "https://wapm.internal.example".to_string();
+        assert_eq!(
+            config.registry.get_url().unwrap(),
+            "https://wapm.internal.example"
+        );
+
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("no-default = true"), "{}", saved);
+    }
+
+    #[test]
+    fn netrc_passwords_are_used_without_a_saved_token() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let tmp_dir_path: &std::path::Path = tmp_dir.as_ref();
+        let netrc_path = tmp_dir_path.join(".netrc");
+        write(
+            &netrc_path,
+            "machine github.com login me password github-token\n\
+             machine registry.wapm.io\n  login me\n  password netrc-token\n\
+             default login anonymous password default-token\n",
+        )
+        .unwrap();
+        let mut registry = registry_with_saved_token("https://registry.wapm.io/graphql");
+
+        let token = registry.get_token_from(None, Some(&netrc_path)).unwrap();
+        assert_eq!(token.as_deref(), Some("saved-token"));
+        registry.token = None;
+        let token = registry.get_token_from(None, Some(&netrc_path)).unwrap();
+        assert_eq!(token.as_deref(), Some("netrc-token"));
+        registry.url = "https://registry.wapm.dev".to_string();
+        let token = registry.get_token_from(None, Some(&netrc_path)).unwrap();
+        assert_eq!(token.as_deref(), Some("default-token"));
+        let token = registry
+            .get_token_from(None, Some(&tmp_dir_path.join("missing")))
+            .unwrap();
+        assert_eq!(token, None);
+    }
+
+    #[test]
+    fn missing_token_file_is_an_error() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let tmp_dir_path: &std::path::Path = tmp_dir.as_ref();
+        let token_path = tmp_dir_path.join("missing");
+        let registry = registry_with_saved_token("https://registry.wapm.io");
+
+        let error = registry
+            .get_token_from(Some(&token_path), None)
+            .unwrap_err();
+        let message = error.to_string();
+        assert!(
+            message.starts_with("Could not read the token file"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains(&*token_path.to_string_lossy()),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn read_only_globals_directory_falls_back_to_the_overlay() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let tmp_dir_path: &std::path::Path = tmp_dir.as_ref();
+        let primary = tmp_dir_path.join("system");
+        let overlay = tmp_dir_path.join("user");
+        create_dir_all(&primary).unwrap();
+        let read_only = |directory: &std::path::Path| directory != primary;
+
+        // writes go to the overlay
+        let install_dir = global_install_directory(primary.clone(), overlay.clone(), read_only);
+        assert_eq!(install_dir, overlay);
+        assert_eq!(
+            global_install_directory(primary.clone(), overlay.clone(), |_| true),
+            primary
+        );
+
+        // reads only search the overlay once something has been installed there
+        assert_eq!(
+            global_directories(primary.clone(), overlay.clone()),
+            vec![primary.clone()]
+        );
+        create_dir_all(&overlay).unwrap();
+        assert_eq!(
+            global_directories(primary.clone(), overlay.clone()),
+            vec![overlay, primary]
+        );
+    }
 }
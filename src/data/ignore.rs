@@ -0,0 +1,158 @@
+//! The `.wapmignore` file lists gitignore-style patterns for files that should be left out of a
+//! published package.
+//!
+//! Each non-empty line that doesn't start with `#` is a pattern:
+//! - `*` and `?` match within a single path component, `**` matches any number of components
+//! - a pattern without a `/` matches a file or directory with that name at any depth, while a
+//!   pattern containing a `/` is relative to the package root
+//! - a trailing `/` only matches directories; everything inside an ignored directory is ignored
+//! - a leading `!` re-includes paths that an earlier pattern ignored
+//!
+//! As in git, the last pattern that matches a path decides whether it is ignored.
+
+use crate::util::glob_matches;
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+pub const IGNORE_FILE_NAME: &str = ".wapmignore";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Pattern {
+    components: Vec<String>,
+    negated: bool,
+    directory_only: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (directory_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let mut components: Vec<String> = pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(str::to_string)
+            .collect();
+        if components.is_empty() {
+            return None;
+        }
+        if !anchored {
+            components.insert(0, "**".to_string());
+        }
+        Some(Pattern {
+            components,
+            negated,
+            directory_only,
+        })
+    }
+
+    fn matches(&self, path: &[String], is_dir: bool) -> bool {
+        (is_dir || !self.directory_only) && components_match(&self.components, path)
+    }
+}
+
+fn components_match(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skipped| components_match(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((component, path)) => {
+                glob_matches(first, component) && components_match(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// The patterns from a `.wapmignore` file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IgnorePatterns {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnorePatterns {
+    pub fn parse(contents: &str) -> IgnorePatterns {
+        IgnorePatterns {
+            patterns: contents.lines().filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    /// Read the `.wapmignore` in `directory`. A missing file ignores nothing.
+    pub fn from_directory(directory: &Path) -> io::Result<IgnorePatterns> {
+        match fs::read_to_string(directory.join(IGNORE_FILE_NAME)) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `path`, relative to the package root, should be left out of the package.
+    /// `is_dir` says whether the path is a directory.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        // a path inside an ignored directory can't be re-included
+        (1..components.len()).any(|len| self.decide(&components[..len], true))
+            || self.decide(&components, is_dir)
+    }
+
+    fn decide(&self, path: &[String], is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map_or(false, |pattern| !pattern.negated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::ignore::IgnorePatterns;
+    use std::path::Path;
+
+    #[test]
+    fn negated_patterns_re_include_files() {
+        let patterns = IgnorePatterns::parse(
+            r#"
+# build artifacts
+*.log
+!keep.log
+/target/
+docs/**/*.tmp
+"#,
+        );
+        let ignored = |path: &str, is_dir: bool| patterns.is_ignored(Path::new(path), is_dir);
+
+        assert!(ignored("debug.log", false));
+        assert!(ignored("static/nested/debug.log", false));
+        assert!(!ignored("keep.log", false));
+        assert!(!ignored("static/keep.log", false));
+
+        assert!(ignored("target", true));
+        assert!(ignored("target/release/app.wasm", false));
+        assert!(!ignored("target", false));
+        assert!(!ignored("static/target", true));
+
+        assert!(ignored("docs/a.tmp", false));
+        assert!(ignored("docs/a/b/c.tmp", false));
+        assert!(!ignored("a.tmp", false));
+    }
+}
@@ -3,11 +3,11 @@ use crate::data::lock::lockfile_module::{
     LockfileModule, LockfileModuleV2, LockfileModuleV3, LockfileModuleV4,
 };
 use crate::data::lock::{LOCKFILE_HEADER, LOCKFILE_NAME};
+use crate::data::manifest::FeatureSelection;
+use crate::util::write_file_atomically;
 use semver::Version;
 use std::collections::BTreeMap;
-use std::fs::File;
 use std::io;
-use std::io::Write;
 use std::path::Path;
 use thiserror::Error;
 
@@ -40,6 +40,50 @@ pub type CommandMap = BTreeMap<String, LockfileCommand>;
 pub struct Lockfile {
     pub modules: ModuleMap, // PackageName -> VersionNumber -> ModuleName -> Module
     pub commands: CommandMap, // CommandName -> Command
+    /// The manifest features the lockfile was generated with, unless they were the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<LockfileFeatures>,
+}
+
+/// The features of the manifest that were selected when the lockfile was generated. Updates
+/// that don't select features themselves reuse them, so that they keep the same optional
+/// dependencies installed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LockfileFeatures {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enabled: Vec<String>,
+    #[serde(rename = "all-features", default, skip_serializing_if = "is_false")]
+    pub all_features: bool,
+    #[serde(
+        rename = "no-default-features",
+        default,
+        skip_serializing_if = "is_false"
+    )]
+    pub no_default_features: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl From<&FeatureSelection> for LockfileFeatures {
+    fn from(selection: &FeatureSelection) -> Self {
+        Self {
+            enabled: selection.features.clone(),
+            all_features: selection.all_features,
+            no_default_features: !selection.default_features,
+        }
+    }
+}
+
+impl From<LockfileFeatures> for FeatureSelection {
+    fn from(features: LockfileFeatures) -> Self {
+        Self {
+            features: features.enabled,
+            all_features: features.all_features,
+            default_features: !features.no_default_features,
+        }
+    }
 }
 
 pub type LockfileV4 = Lockfile;
@@ -52,8 +96,7 @@ impl<'a> Lockfile {
         let lockfile_string = toml::to_string(self)?;
         let lockfile_string = format!("{}\n{}", LOCKFILE_HEADER, lockfile_string);
         let lockfile_path = directory.as_ref().join(LOCKFILE_NAME);
-        let mut file = File::create(&lockfile_path)?;
-        file.write_all(lockfile_string.as_bytes())?;
+        write_file_atomically(&lockfile_path, lockfile_string.as_bytes())?;
         Ok(())
     }
 
@@ -50,6 +50,13 @@ pub struct LockfileModule {
     pub source: String,
     /// The hash of the wasm module cached here for faster startup time
     pub prehashed_module_key: Option<String>,
+    /// The target the module was installed for, if the package has a variant of it per target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The canonical hash of the module's interface, so that a dependency whose imports or
+    /// exports change without a version bump is caught
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_hash: Option<String>,
 }
 
 pub type LockfileModuleV4 = LockfileModule;
@@ -91,7 +98,9 @@ impl LockfileModule {
             resolved_source: format!("registry+{}", module.name),
             abi: module.abi.clone(),
             prehashed_module_key: util::get_hashed_module_key(&path.join(&source)),
+            contract_hash: util::get_contract_hash(&path.join(&source)),
             source,
+            target: None,
         };
         lockfile_module
     }
@@ -115,6 +124,8 @@ impl LockfileModule {
             abi: module.abi.clone(),
             source: module.source.to_string_lossy().to_string(),
             prehashed_module_key: util::get_hashed_module_key(&wasm_module_full_path),
+            target: None,
+            contract_hash: None,
         }
     }
 
@@ -141,8 +152,9 @@ impl LockfileModule {
         mut lockfile_dir: PathBuf,
         local_dep: bool,
     ) -> PathBuf {
-        if crate::config::Config::get_globals_directory().expect("Could not get globals direcotry")
-            == lockfile_dir
+        if crate::config::Config::get_global_directories()
+            .expect("Could not get globals direcotry")
+            .contains(&lockfile_dir)
             || local_dep
         {
             lockfile_dir.push(PACKAGES_DIR_NAME);
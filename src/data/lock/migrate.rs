@@ -160,6 +160,8 @@ pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> Lockf
                     },
                     package_path,
                     prehashed_module_key: module_data.prehashed_module_key,
+                    target: None,
+                    contract_hash: None,
                 };
                 name_map.insert(k3, module);
             }
@@ -170,6 +172,7 @@ pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> Lockf
     LockfileV4 {
         modules,
         commands: lockfile.commands,
+        features: None,
     }
 }
 
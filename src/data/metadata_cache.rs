@@ -0,0 +1,236 @@
+//! The metadata cache keeps the published versions of packages that were fetched from the
+//! registry, so that resolving the same packages again shortly after doesn't query the registry.
+//!
+//! Entries are used until they are older than the configured TTL, see `registry.metadata-ttl`.
+//! Passing `--refresh` fetches the metadata again regardless of its age, and so does asking for
+//! an exact version that isn't in the cache, as it may have been published since.
+
+use crate::keys::WapmPackageSignature;
+use semver::Version;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// A published version of a package, with its download URL and signature
+pub type PublishedVersion = (String, Version, String, Option<WapmPackageSignature>);
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MetadataCache {
+    /// The registry the metadata was fetched from
+    registry: String,
+    packages: HashMap<String, CachedPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedPackage {
+    /// Seconds since the unix epoch at which the versions were fetched
+    fetched_at: u64,
+    versions: Vec<CachedVersion>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedVersion {
+    version: String,
+    download_url: String,
+    signature: Option<CachedSignature>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedSignature {
+    public_key_id: String,
+    public_key: String,
+    signature_data: String,
+    date_created: i64,
+    revoked: bool,
+    owner: String,
+}
+
+impl MetadataCache {
+    /// Read the cache at `path`. A missing or unreadable cache, or one for a different registry,
+    /// is empty.
+    pub fn open(path: &Path, registry: &str) -> MetadataCache {
+        let cache = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<MetadataCache>(&contents).ok())
+            .filter(|cache| cache.registry == registry);
+        cache.unwrap_or_else(|| MetadataCache {
+            registry: registry.to_string(),
+            packages: HashMap::new(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string(self)?;
+        crate::util::write_file_atomically(path, contents.as_bytes())
+    }
+
+    /// The versions of `name`, if they were fetched less than `ttl` before `now`
+    fn fresh_versions(&self, name: &str, now: u64, ttl: Duration) -> Option<Vec<PublishedVersion>> {
+        let package = self.packages.get(name)?;
+        if now.saturating_sub(package.fetched_at) >= ttl.as_secs() {
+            return None;
+        }
+        package
+            .versions
+            .iter()
+            .map(|cached| {
+                let version = Version::parse(&cached.version).ok()?;
+                let signature = cached.signature.as_ref().map(|s| WapmPackageSignature {
+                    public_key_id: s.public_key_id.clone(),
+                    public_key: s.public_key.clone(),
+                    signature_data: s.signature_data.clone(),
+                    date_created: time::Timespec::new(s.date_created, 0),
+                    revoked: s.revoked,
+                    owner: s.owner.clone(),
+                });
+                Some((
+                    name.to_string(),
+                    version,
+                    cached.download_url.clone(),
+                    signature,
+                ))
+            })
+            .collect()
+    }
+
+    fn insert(&mut self, now: u64, published: &[PublishedVersion]) {
+        for (name, _, _, _) in published {
+            self.packages.insert(
+                name.clone(),
+                CachedPackage {
+                    fetched_at: now,
+                    versions: vec![],
+                },
+            );
+        }
+        for (name, version, download_url, signature) in published {
+            let signature = signature.as_ref().map(|s| CachedSignature {
+                public_key_id: s.public_key_id.clone(),
+                public_key: s.public_key.clone(),
+                signature_data: s.signature_data.clone(),
+                date_created: s.date_created.sec,
+                revoked: s.revoked,
+                owner: s.owner.clone(),
+            });
+            if let Some(package) = self.packages.get_mut(name) {
+                package.versions.push(CachedVersion {
+                    version: version.to_string(),
+                    download_url: download_url.clone(),
+                    signature,
+                });
+            }
+        }
+    }
+
+    /// The published versions of the packages in `names`. Packages that were fetched less than
+    /// `ttl` before `now` come from the cache unless `refresh` is set, the rest are fetched
+    /// with a single call to `fetch` and cached.
+    pub fn published_versions<F, E>(
+        &mut self,
+        names: Vec<String>,
+        now: u64,
+        ttl: Duration,
+        refresh: bool,
+        fetch: F,
+    ) -> Result<Vec<PublishedVersion>, E>
+    where
+        F: FnOnce(Vec<String>) -> Result<Vec<PublishedVersion>, E>,
+    {
+        let mut published = vec![];
+        let mut stale = vec![];
+        for name in names {
+            match self.fresh_versions(&name, now, ttl).filter(|_| !refresh) {
+                Some(versions) => published.extend(versions),
+                None => stale.push(name),
+            }
+        }
+        if !stale.is_empty() {
+            let fetched = fetch(stale)?;
+            self.insert(now, &fetched);
+            published.extend(fetched);
+        }
+        Ok(published)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::metadata_cache::{MetadataCache, PublishedVersion};
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn published(name: &str, version: &str) -> PublishedVersion {
+        (
+            name.to_string(),
+            semver::Version::parse(version).unwrap(),
+            format!("https://registry/{}-{}.tar.gz", name, version),
+            None,
+        )
+    }
+
+    #[test]
+    fn metadata_within_the_ttl_is_not_fetched_again() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("cache.json");
+        let ttl = Duration::from_secs(60);
+        let fetches = Cell::new(0);
+        let fetch = |names: Vec<String>| -> Result<Vec<PublishedVersion>, ()> {
+            fetches.set(fetches.get() + 1);
+            Ok(names.iter().map(|name| published(name, "1.0.0")).collect())
+        };
+
+        let mut cache = MetadataCache::open(&path, "https://registry");
+        let versions = cache
+            .published_versions(vec!["_/foo".to_string()], 1000, ttl, false, fetch)
+            .unwrap();
+        assert_eq!(versions, vec![published("_/foo", "1.0.0")]);
+        cache.save(&path).unwrap();
+
+        let mut cache = MetadataCache::open(&path, "https://registry");
+        let versions = cache
+            .published_versions(vec!["_/foo".to_string()], 1059, ttl, false, fetch)
+            .unwrap();
+        assert_eq!(versions, vec![published("_/foo", "1.0.0")]);
+        assert_eq!(fetches.get(), 1);
+
+        cache
+            .published_versions(vec!["_/foo".to_string()], 1060, ttl, false, fetch)
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+
+        let mut other_registry = MetadataCache::open(&path, "https://other-registry");
+        other_registry
+            .published_versions(vec!["_/foo".to_string()], 1000, ttl, false, fetch)
+            .unwrap();
+        assert_eq!(fetches.get(), 3);
+    }
+
+    #[test]
+    fn refresh_fetches_metadata_within_the_ttl() {
+        let ttl = Duration::from_secs(60);
+        let mut cache = MetadataCache::default();
+        cache
+            .published_versions(vec!["_/foo".to_string()], 1000, ttl, false, |_| {
+                Ok::<_, ()>(vec![published("_/foo", "1.0.0")])
+            })
+            .unwrap();
+
+        let versions = cache
+            .published_versions(vec!["_/foo".to_string()], 1001, ttl, true, |names| {
+                assert_eq!(names, vec!["_/foo".to_string()]);
+                Ok::<_, ()>(vec![
+                    published("_/foo", "1.0.0"),
+                    published("_/foo", "1.1.0"),
+                ])
+            })
+            .unwrap();
+        assert_eq!(versions.len(), 2);
+
+        let versions = cache
+            .published_versions(vec!["_/foo".to_string()], 1002, ttl, false, |_| Err(()))
+            .unwrap();
+        assert_eq!(versions[1], published("_/foo", "1.1.0"));
+    }
+}
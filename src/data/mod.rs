@@ -1,6 +1,10 @@
 //! The definitions of data types that wapm uses.  The Manifest and the Lockfile are
 //! collections of metadata, used for specifying a package and indexing dependencies
 //! respectively.
+pub mod ignore;
 pub mod lock;
 pub mod manifest;
+pub mod metadata_cache;
+pub mod search_cache;
 pub mod wax_index;
+pub mod workspace;
@@ -0,0 +1,155 @@
+//! The search cache keeps the results of recent `wapm search` queries, so that repeating a
+//! search shortly after doesn't query the registry again. Passing `--no-cache` bypasses it.
+
+use crate::registry::SearchResult;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// The number of seconds search results are reused for
+pub const SEARCH_CACHE_TTL_SECS: u64 = 60;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SearchCache {
+    /// The registry the results were fetched from
+    registry: String,
+    queries: HashMap<String, CachedSearch>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedSearch {
+    /// Seconds since the unix epoch at which the results were fetched
+    fetched_at: u64,
+    results: Vec<SearchResult>,
+}
+
+/// The key a query is cached under: trimmed, lowercase and with single spaces. The sort order
+/// is applied to the results afterwards, so it isn't part of the key.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl SearchCache {
+    /// Read the cache at `path`. A missing or unreadable cache, or one for a different registry,
+    /// is empty.
+    pub fn open(path: &Path, registry: &str) -> SearchCache {
+        let cache = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<SearchCache>(&contents).ok())
+            .filter(|cache| cache.registry == registry);
+        cache.unwrap_or_else(|| SearchCache {
+            registry: registry.to_string(),
+            queries: HashMap::new(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string(self)?;
+        crate::util::write_file_atomically(path, contents.as_bytes())
+    }
+
+    /// The results of `query`, and whether they came from the cache. Results fetched less than
+    /// `ttl` before `now` are reused unless `no_cache` is set, otherwise they are fetched with
+    /// `fetch` and cached.
+    pub fn search<F, E>(
+        &mut self,
+        query: &str,
+        now: u64,
+        ttl: Duration,
+        no_cache: bool,
+        fetch: F,
+    ) -> Result<(Vec<SearchResult>, bool), E>
+    where
+        F: FnOnce(&str) -> Result<Vec<SearchResult>, E>,
+    {
+        let key = normalize_query(query);
+        let fresh = |cached: &CachedSearch| now.saturating_sub(cached.fetched_at) < ttl.as_secs();
+        if !no_cache {
+            if let Some(cached) = self.queries.get(&key).filter(|cached| fresh(cached)) {
+                return Ok((cached.results.clone(), true));
+            }
+        }
+        let results = fetch(query)?;
+        // the cache is short-lived, don't keep expired queries around
+        self.queries.retain(|_, cached| fresh(cached));
+        self.queries.insert(
+            key,
+            CachedSearch {
+                fetched_at: now,
+                results: results.clone(),
+            },
+        );
+        Ok((results, false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::search_cache::{normalize_query, SearchCache};
+    use crate::registry::SearchResult;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn result(name: &str) -> SearchResult {
+        SearchResult {
+            name: name.to_string(),
+            description: String::new(),
+            date: "2021-01-01".to_string(),
+            version: "1.0.0".to_string(),
+            downloads: Some(1),
+        }
+    }
+
+    #[test]
+    fn repeated_queries_within_the_ttl_are_not_fetched_again() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("search.json");
+        let ttl = Duration::from_secs(60);
+        let fetches = Cell::new(0);
+        let fetch = |query: &str| -> Result<Vec<SearchResult>, ()> {
+            fetches.set(fetches.get() + 1);
+            Ok(vec![result(query.trim())])
+        };
+
+        let mut cache = SearchCache::open(&path, "https://registry");
+        let (results, cached) = cache.search("python", 1000, ttl, false, fetch).unwrap();
+        assert_eq!((results, cached), (vec![result("python")], false));
+        cache.save(&path).unwrap();
+
+        let mut cache = SearchCache::open(&path, "https://registry");
+        let (results, cached) = cache.search("  Python ", 1059, ttl, false, fetch).unwrap();
+        assert_eq!((results, cached), (vec![result("python")], true));
+        assert_eq!(fetches.get(), 1);
+
+        let (_, cached) = cache.search("python", 1060, ttl, false, fetch).unwrap();
+        assert!(!cached);
+        assert_eq!(fetches.get(), 2);
+        assert_eq!(normalize_query(" Hello   World"), "hello world");
+    }
+
+    #[test]
+    fn no_cache_fetches_the_results_again() {
+        let ttl = Duration::from_secs(60);
+        let mut cache = SearchCache::default();
+        let fetches = Cell::new(0);
+        let fetch = |_: &str| -> Result<Vec<SearchResult>, ()> {
+            fetches.set(fetches.get() + 1);
+            Ok(vec![result("python")])
+        };
+
+        cache.search("python", 1000, ttl, false, fetch).unwrap();
+        let (_, cached) = cache.search("python", 1001, ttl, true, fetch).unwrap();
+        assert!(!cached);
+        assert_eq!(fetches.get(), 2);
+
+        let (_, cached) = cache.search("python", 1002, ttl, false, fetch).unwrap();
+        assert!(cached);
+        assert_eq!(fetches.get(), 2);
+    }
+}
@@ -0,0 +1,93 @@
+//! A workspace is a manifest with a `[workspace]` section that lists other packages to be
+//! managed together with it.
+
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::util::glob_matches;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum WorkspaceError {
+    #[error("Could not read workspace member directory \"{0}\". {1}")]
+    IoError(String, String),
+    #[error("Workspace member \"{0}\" does not have a valid manifest. {1}")]
+    InvalidMember(String, String),
+}
+
+/// A package that is a member of a workspace.
+#[derive(Clone, Debug)]
+pub struct WorkspaceMember {
+    /// The name of the member's package
+    pub name: String,
+    /// The directory containing the member's manifest
+    pub directory: PathBuf,
+    pub manifest: Manifest,
+}
+
+/// Find the members of the workspace declared by `manifest`, in the order they are listed.
+/// Returns an empty list if the manifest does not declare a workspace.
+pub fn find_workspace_members(manifest: &Manifest) -> Result<Vec<WorkspaceMember>, WorkspaceError> {
+    let workspace = match manifest.workspace {
+        Some(ref workspace) => workspace,
+        None => return Ok(vec![]),
+    };
+    let mut members = vec![];
+    for member in workspace.members.iter() {
+        let is_pattern = member.contains(&['*', '?'][..]);
+        for directory in expand_member_path(&manifest.base_directory_path, member)? {
+            // directories that happen to match a wildcard aren't required to be packages
+            if is_pattern && !directory.join(MANIFEST_FILE_NAME).is_file() {
+                continue;
+            }
+            let manifest = match ManifestResult::find_in_directory(&directory) {
+                ManifestResult::Manifest(manifest) => manifest,
+                ManifestResult::NoManifest => {
+                    return Err(WorkspaceError::InvalidMember(
+                        member.clone(),
+                        format!("No manifest found in {}", directory.to_string_lossy()),
+                    ))
+                }
+                ManifestResult::ManifestError(e) => {
+                    return Err(WorkspaceError::InvalidMember(member.clone(), e.to_string()))
+                }
+            };
+            members.push(WorkspaceMember {
+                name: manifest.package.name.clone(),
+                directory,
+                manifest,
+            });
+        }
+    }
+    Ok(members)
+}
+
+/// Expand the wildcards in a member path, returning the matching directories sorted by name.
+fn expand_member_path(base: &Path, member: &str) -> Result<Vec<PathBuf>, WorkspaceError> {
+    let mut directories = vec![base.to_path_buf()];
+    for component in member.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        if !component.contains(&['*', '?'][..]) {
+            for directory in directories.iter_mut() {
+                directory.push(component);
+            }
+            continue;
+        }
+        let mut expanded = vec![];
+        for directory in directories {
+            let entries = fs::read_dir(&directory).map_err(|e| {
+                WorkspaceError::IoError(directory.to_string_lossy().to_string(), e.to_string())
+            })?;
+            for entry in entries.filter_map(Result::ok) {
+                let path = entry.path();
+                let matches = glob_matches(component, &entry.file_name().to_string_lossy());
+                if matches && path.is_dir() {
+                    expanded.push(path);
+                }
+            }
+        }
+        expanded.sort();
+        directories = expanded;
+    }
+    Ok(directories)
+}
@@ -1,12 +1,14 @@
 use crate::dataflow::{normalize_global_namespace, PackageKey};
-use semver::Version;
+use semver::{Version, VersionReq};
 use std::collections::HashSet;
 use thiserror::Error;
 
 #[derive(Clone, Debug, Error)]
 pub enum Error {
-    #[error("Package must have version that follows semantic versioning. {0}")]
-    SemVerError(String),
+    #[error(
+        "Package \"{0}\" must have a version or version range that follows semantic versioning, like 1.2.3 or ^1.2, but found \"{1}\"."
+    )]
+    SemVerError(String, String),
 }
 
 /// Holds packages that are added via the command line
@@ -16,16 +18,14 @@ pub struct AddedPackages<'a> {
 }
 
 impl<'a> AddedPackages<'a> {
-    /// Extract name and version, parse version as semver, construct registry key, and finally
-    /// normalize the global namespace if using the shorthand e.g. "_/pkg" == pkg
+    /// Extract name and version, parse version as semver or a semver range, construct registry
+    /// key, and finally normalize the global namespace if using the shorthand e.g. "_/pkg" == pkg
     pub fn new_from_str_pairs(added_packages: Vec<(&'a str, &'a str)>) -> Result<Self, Error> {
-        let added_packages = added_packages
-            .into_iter()
-            .map(Self::extract_name_and_version)
-            .collect::<Result<Vec<(&'a str, Version)>, Error>>()?;
         let packages = added_packages
             .into_iter()
-            .map(|(n, v)| PackageKey::new_registry_package(n, v))
+            .map(Self::extract_package_key)
+            .collect::<Result<Vec<PackageKey<'a>>, Error>>()?
+            .into_iter()
             .map(normalize_global_namespace)
             .collect();
 
@@ -39,6 +39,13 @@ impl<'a> AddedPackages<'a> {
         let added_packages = self.packages;
         let packages = added_packages
             .difference(&lockfile_packages_keys)
+            .filter(|key| match key {
+                // a range is already installed if any installed version matches it
+                PackageKey::WapmPackageRange(range) => {
+                    !lockfile_packages_keys.iter().any(|k| k.matches(range))
+                }
+                PackageKey::WapmPackage(_) => true,
+            })
             .cloned()
             .collect();
         Self { packages }
@@ -53,11 +60,15 @@ impl<'a> AddedPackages<'a> {
         Self { packages }
     }
 
-    /// parse the version as semver, or error
-    fn extract_name_and_version(pair: (&'a str, &'a str)) -> Result<(&'a str, Version), Error> {
-        Version::parse(pair.1)
-            .map(|version| (pair.0, version))
-            .map_err(|e| Error::SemVerError(e.to_string()))
+    /// parse the version as semver, then as a semver range, or error
+    fn extract_package_key((name, version): (&'a str, &'a str)) -> Result<PackageKey<'a>, Error> {
+        if let Ok(version) = Version::parse(version) {
+            Ok(PackageKey::new_registry_package(name, version))
+        } else if let Ok(version_req) = VersionReq::parse(version) {
+            Ok(PackageKey::new_registry_package_range(name, version_req))
+        } else {
+            Err(Error::SemVerError(name.to_string(), version.to_string()))
+        }
     }
 }
 
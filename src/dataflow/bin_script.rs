@@ -1,4 +1,5 @@
 #![cfg_attr(target_os = "wasi", allow(dead_code))]
+use crate::data::lock::lockfile::Lockfile;
 use crate::data::manifest::PACKAGES_DIR_NAME;
 use std::fs;
 use std::io::Write;
@@ -111,6 +112,29 @@ pub fn save_bin_script<P: AsRef<Path>>(
     save(data, directory, file_name)
 }
 
+/// Save the bin scripts for every command in `lockfile`.
+pub fn save_lockfile_bin_scripts<P: AsRef<Path>>(
+    directory: P,
+    lockfile: &Lockfile,
+) -> Result<(), Error> {
+    for command in lockfile.commands.values() {
+        if let Ok(module) = lockfile.get_module(
+            &command.package_name,
+            &command.package_version,
+            &command.module,
+        ) {
+            let module_path = format!("{}/{}", module.package_path, module.source);
+            save_bin_script(
+                directory.as_ref(),
+                command.name.clone(),
+                module.package_path.clone(),
+                module_path,
+            )?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(target_os = "wasi")]
 pub fn delete_bin_script<P: AsRef<Path>>(_directory: P, command_name: String) -> Result<(), Error> {
     let command_path = format!("/bin/{}", command_name);
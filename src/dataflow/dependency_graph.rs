@@ -0,0 +1,378 @@
+//! The dependencies between installed packages. Each package's dependencies are read from the
+//! manifest it was installed with.
+
+use crate::data::manifest::{Manifest, PACKAGES_DIR_NAME};
+use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::progress;
+use crate::util::{fully_qualified_package_display_name, get_package_namespace_and_name};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum Error {
+    #[error("Dependency cycle detected: {}", .0.join(" → "))]
+    Cycle(Vec<String>),
+    #[error("The dependency tree is deeper than the maximum depth of {max}: {}", .path.join(" → "))]
+    TooDeep { max: usize, path: Vec<String> },
+}
+
+/// A graph of package names, with an edge from every package to each of its dependencies.
+#[derive(Clone, Debug, Default)]
+pub struct DependencyGraph {
+    /// The dependencies of the project's own manifest
+    pub roots: BTreeSet<String>,
+    /// Package name -> names of the packages it depends on
+    pub dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    /// Build the graph for the packages installed in `directory`. `manifest` is the project's own
+    /// manifest, if there is one.
+    pub fn from_installed_packages<'a, 'b: 'a, I>(
+        directory: &Path,
+        manifest: Option<&Manifest>,
+        package_keys: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = &'a PackageKey<'b>>,
+    {
+        let mut graph = Self::default();
+        if let Some(manifest) = manifest {
+            for dependency in dependency_names(manifest) {
+                graph.roots.insert(dependency.clone());
+                graph.add_dependency(&qualify(&manifest.package.name), &dependency);
+            }
+        }
+        for key in package_keys {
+            let (name, version) = match key {
+                PackageKey::WapmPackage(WapmPackageKey { name, version }) => (name, version),
+                PackageKey::WapmPackageRange(_) => continue,
+            };
+            let (namespace, unqualified_name) = match get_package_namespace_and_name(name) {
+                Ok(split) => split,
+                Err(_) => continue,
+            };
+            let package_dir = directory.join(PACKAGES_DIR_NAME).join(namespace).join(
+                fully_qualified_package_display_name(unqualified_name, version),
+            );
+            // the local package and packages that aren't installed yet have no manifest here
+            if let Ok(package_manifest) = Manifest::find_in_directory(&package_dir) {
+                for dependency in dependency_names(&package_manifest) {
+                    graph.add_dependency(name, &dependency);
+                }
+            }
+        }
+        graph
+    }
+
+    pub fn add_dependency(&mut self, package: &str, dependency: &str) {
+        self.dependencies
+            .entry(package.to_string())
+            .or_default()
+            .insert(dependency.to_string());
+    }
+
+    /// Returns the first cycle found, as the path from a package back to itself.
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut finished = BTreeSet::new();
+        for start in self.dependencies.keys() {
+            if finished.contains(start) {
+                continue;
+            }
+            // depth first search, keeping the current path and each node's unvisited children
+            let mut path: Vec<&String> = vec![start];
+            let mut pending = vec![self.children(start)];
+            while let Some(children) = pending.last_mut() {
+                match children.next() {
+                    Some(child) => {
+                        if let Some(position) = path.iter().position(|p| *p == child) {
+                            let mut cycle: Vec<String> =
+                                path[position..].iter().map(|p| p.to_string()).collect();
+                            cycle.push(child.clone());
+                            return Some(cycle);
+                        }
+                        if !finished.contains(child) {
+                            path.push(child);
+                            pending.push(self.children(child));
+                        }
+                    }
+                    None => {
+                        pending.pop();
+                        if let Some(done) = path.pop() {
+                            finished.insert(done);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns an error naming every package in the loop if any package depends on itself.
+    pub fn detect_cycles(&self) -> Result<(), Error> {
+        match self.find_cycle() {
+            Some(cycle) => {
+                let error = Error::Cycle(cycle);
+                progress::report(|sink| sink.on_resolve_conflict(&error.to_string()));
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns an error naming the path if a chain of dependencies is longer than `max`. The
+    /// roots are at depth 1; without roots, every package is treated as one.
+    pub fn check_depth(&self, max: usize) -> Result<(), Error> {
+        let starts: Vec<&String> = if self.roots.is_empty() {
+            self.dependencies.keys().collect()
+        } else {
+            self.roots.iter().collect()
+        };
+        let mut longest = BTreeMap::new();
+        for start in starts {
+            self.find_longest_chains(start, &mut longest);
+            if longest[start].0 > max {
+                let mut path = vec![start.to_string()];
+                let mut next = longest[start].1;
+                while let Some(package) = next.filter(|_| path.len() <= max) {
+                    path.push(package.to_string());
+                    next = longest[package].1;
+                }
+                return Err(Error::TooDeep { max, path });
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the length of the longest chain of dependencies starting at `start` and at every
+    /// package below it, with the dependency each chain continues with. Packages that are
+    /// already in `longest` aren't walked again, so shared dependencies are only visited once.
+    fn find_longest_chains<'a>(
+        &'a self,
+        start: &'a String,
+        longest: &mut BTreeMap<&'a String, (usize, Option<&'a String>)>,
+    ) {
+        if longest.contains_key(start) {
+            return;
+        }
+        let mut path: Vec<&String> = vec![start];
+        let mut pending = vec![self.children(start)];
+        while let Some(children) = pending.last_mut() {
+            match children.next() {
+                // cycles are reported by `detect_cycles`
+                Some(child) if longest.contains_key(child) || path.contains(&child) => {}
+                Some(child) => {
+                    path.push(child);
+                    pending.push(self.children(child));
+                }
+                None => {
+                    pending.pop();
+                    if let Some(done) = path.pop() {
+                        let deepest = self
+                            .children(done)
+                            .filter_map(|child| {
+                                longest.get(child).map(|(depth, _)| (*depth, child))
+                            })
+                            .max_by_key(|(depth, _)| *depth);
+                        let chain = match deepest {
+                            Some((depth, child)) => (depth + 1, Some(child)),
+                            None => (1, None),
+                        };
+                        longest.insert(done, chain);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every path from one of the roots down to `package`, in order. Each path starts with the
+    /// root and ends with `package`; a package that is a root itself has a path of its own.
+    pub fn paths_to(&self, package: &str) -> Vec<Vec<String>> {
+        let mut paths = vec![];
+        for root in self.roots.iter() {
+            if root == package {
+                paths.push(vec![root.clone()]);
+                continue;
+            }
+            let mut path: Vec<&String> = vec![root];
+            let mut pending = vec![self.children(root)];
+            while let Some(children) = pending.last_mut() {
+                match children.next() {
+                    // the path can't go through a cycle
+                    Some(child) if path.contains(&child) => {}
+                    Some(child) if child == package => {
+                        let mut found: Vec<String> = path.iter().map(|p| p.to_string()).collect();
+                        found.push(child.clone());
+                        paths.push(found);
+                    }
+                    Some(child) => {
+                        path.push(child);
+                        pending.push(self.children(child));
+                    }
+                    None => {
+                        pending.pop();
+                        path.pop();
+                    }
+                }
+            }
+        }
+        paths
+    }
+
+    fn children<'a>(&'a self, package: &str) -> impl Iterator<Item = &'a String> {
+        self.dependencies.get(package).into_iter().flatten()
+    }
+}
+
+/// The fully qualified names of the dependencies listed in `manifest`.
+fn dependency_names(manifest: &Manifest) -> Vec<String> {
+    manifest
+        .dependencies
+        .iter()
+        .flat_map(|dependencies| dependencies.keys())
+        .map(|name| qualify(name))
+        .collect()
+}
+
+/// Add the default `_` namespace to names without one
+pub(crate) fn qualify(name: &str) -> String {
+    if name.contains('/') {
+        name.to_string()
+    } else {
+        format!("_/{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::manifest::Manifest;
+    use crate::dataflow::dependency_graph::DependencyGraph;
+    use crate::dataflow::PackageKey;
+    use std::fs;
+    use std::path::Path;
+
+    fn write_package(directory: &Path, name: &str, dependency: &str) {
+        let (namespace, name) = name.split_at(name.find('/').unwrap());
+        let package_dir = directory
+            .join("wapm_packages")
+            .join(namespace)
+            .join(format!("{}@1.0.0", &name[1..]));
+        fs::create_dir_all(&package_dir).unwrap();
+        let manifest = format!(
+            r#"
+[package]
+name = "{}{}"
+version = "1.0.0"
+description = "test package"
+
+[dependencies]
+"{}" = "1.0.0"
+"#,
+            namespace, name, dependency
+        );
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+    }
+
+    #[test]
+    fn cycle_names_each_package_in_the_loop() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let directory = tmp_dir.path();
+        write_package(directory, "ns/a", "ns/b");
+        write_package(directory, "ns/b", "ns/c");
+        write_package(directory, "ns/c", "ns/a");
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "root"
+version = "0.1.0"
+description = "project"
+
+[dependencies]
+"ns/a" = "1.0.0"
+"#,
+        )
+        .unwrap();
+        let package_keys: Vec<PackageKey> = ["ns/a", "ns/b", "ns/c"]
+            .iter()
+            .map(|name| PackageKey::new_registry_package(*name, semver::Version::new(1, 0, 0)))
+            .collect();
+
+        let graph =
+            DependencyGraph::from_installed_packages(directory, Some(&manifest), &package_keys);
+        let error = graph.detect_cycles().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Dependency cycle detected: ns/a → ns/b → ns/c → ns/a"
+        );
+
+        let mut acyclic = graph.clone();
+        acyclic.dependencies.remove("ns/c");
+        assert!(acyclic.detect_cycles().is_ok());
+    }
+
+    #[test]
+    fn chains_longer_than_the_maximum_depth_are_named() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let directory = tmp_dir.path();
+        write_package(directory, "ns/a", "ns/b");
+        write_package(directory, "ns/b", "ns/c");
+        write_package(directory, "ns/c", "ns/d");
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "root"
+version = "0.1.0"
+description = "project"
+
+[dependencies]
+"ns/a" = "1.0.0"
+"#,
+        )
+        .unwrap();
+        let package_keys: Vec<PackageKey> = ["ns/a", "ns/b", "ns/c"]
+            .iter()
+            .map(|name| PackageKey::new_registry_package(*name, semver::Version::new(1, 0, 0)))
+            .collect();
+
+        let graph =
+            DependencyGraph::from_installed_packages(directory, Some(&manifest), &package_keys);
+        assert!(graph.check_depth(4).is_ok());
+        let error = graph.check_depth(3).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The dependency tree is deeper than the maximum depth of 3: ns/a → ns/b → ns/c → ns/d"
+        );
+
+        let graph = DependencyGraph::from_installed_packages(directory, None, &package_keys);
+        assert!(graph.check_depth(4).is_ok());
+        assert!(graph.check_depth(3).is_err());
+    }
+
+    #[test]
+    fn shared_dependencies_are_only_walked_once() {
+        // a ladder of diamonds has 2^40 paths from top to bottom
+        let mut graph = DependencyGraph::default();
+        graph.roots.insert("ns/top-0".to_string());
+        for level in 0..40 {
+            let top = format!("ns/top-{}", level);
+            for side in &["left", "right"] {
+                let package = format!("ns/{}-{}", side, level);
+                graph
+                    .dependencies
+                    .entry(top.clone())
+                    .or_default()
+                    .insert(package.clone());
+                graph
+                    .dependencies
+                    .entry(package)
+                    .or_default()
+                    .insert(format!("ns/top-{}", level + 1));
+            }
+        }
+
+        assert!(graph.check_depth(81).is_ok());
+        let error = graph.check_depth(80).unwrap_err();
+        assert!(error.to_string().ends_with("ns/top-40"), "{}", error);
+    }
+}
@@ -3,6 +3,7 @@ use crate::data::lock::lockfile::{Lockfile, LockfileError};
 use crate::data::manifest::Manifest;
 use crate::dataflow::lockfile_packages::LockfileResult;
 use crate::dataflow::manifest_packages::ManifestResult;
+use semver::Version;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -232,80 +233,300 @@ pub struct Command {
     pub module_name: String,
     /// whether the command was found in the global context
     pub is_global: bool,
+    /// the directory whose lockfile the command was found in
+    pub directory: PathBuf,
     /// the prehashed module key
     pub prehashed_cache_key: Option<String>,
 }
 
 /// Get a command from anywhere, where anywhere is the set of packages in the local lockfile and the global lockfile.
-/// A flag indicating global run is also returned. Commands are found in local lockfile first.
-pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Command, Error> {
+/// A flag indicating global run is also returned. Commands are found in local lockfile first,
+/// unless `global_only` is set, in which case the local lockfile is skipped.
+pub fn get_command<S: AsRef<str>>(command_name: S, global_only: bool) -> Result<Command, Error> {
     // look in the local directory, update if necessary
     let current_directory = crate::config::Config::get_current_dir().unwrap();
-    let local_command_result =
-        FindCommandResult::find_command_in_directory(&current_directory, &command_name);
-
-    match local_command_result {
-        FindCommandResult::CommandNotFound(_cmd) => {} // continue
-        FindCommandResult::CommandFound {
-            source,
-            manifest_dir,
-            args,
-            module_name,
-            prehashed_cache_key,
-        } => {
-            return Ok(Command {
+    let local_directory = if global_only {
+        None
+    } else {
+        Some(current_directory.as_path())
+    };
+    find_command(
+        local_directory,
+        Config::get_global_directories,
+        command_name,
+    )
+}
+
+/// The package and version that provide `command_name` in the lockfile of `directory`.
+fn installed_package<S: AsRef<str>>(
+    directory: &Path,
+    command_name: S,
+) -> Option<(String, Version)> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => {
+            lockfile
+                .get_command(command_name.as_ref())
+                .ok()
+                .map(|command| {
+                    (
+                        command.package_name.clone(),
+                        command.package_version.clone(),
+                    )
+                })
+        }
+        _ => None,
+    }
+}
+
+/// Describe the global install of `command_name` that the one in `local_directory` shadows, if
+/// the first global directory providing the command has a different package or version.
+pub fn describe_shadowing<S, F, E>(
+    local_directory: &Path,
+    global_directories: F,
+    command_name: S,
+) -> Option<String>
+where
+    S: AsRef<str>,
+    F: FnOnce() -> Result<Vec<PathBuf>, E>,
+{
+    let (local_package, local_version) = installed_package(local_directory, &command_name)?;
+    let (global_directory, (global_package, global_version)) = global_directories()
+        .ok()?
+        .into_iter()
+        .find_map(|directory| {
+            installed_package(&directory, &command_name).map(|installed| (directory, installed))
+        })?;
+    if local_package == global_package && local_version == global_version {
+        return None;
+    }
+    Some(format!(
+        "Command `{}` is provided locally by {}@{}, which shadows the global {}@{} in {}",
+        command_name.as_ref(),
+        local_package,
+        local_version,
+        global_package,
+        global_version,
+        global_directory.display()
+    ))
+}
+
+/// Look for the command in `local_directory`, then in each of the directories returned by
+/// `global_directories` in order.
+fn find_command<S, F, E>(
+    local_directory: Option<&Path>,
+    global_directories: F,
+    command_name: S,
+) -> Result<Command, Error>
+where
+    S: AsRef<str>,
+    F: FnOnce() -> Result<Vec<PathBuf>, E>,
+    E: std::fmt::Display,
+{
+    if let Some(local_directory) = local_directory {
+        let local_command_result =
+            FindCommandResult::find_command_in_directory(local_directory, &command_name);
+
+        match local_command_result {
+            FindCommandResult::CommandNotFound(_cmd) => {} // continue
+            FindCommandResult::CommandFound {
                 source,
                 manifest_dir,
                 args,
                 module_name,
-                is_global: false,
                 prehashed_cache_key,
-            });
-        }
-        FindCommandResult::Error(e) => {
-            return Err(Error::ErrorReadingLocalDirectory(
-                command_name.as_ref().to_string(),
-                e.to_string(),
-            ));
-        }
-    };
-    trace!("Local command not found");
+            } => {
+                return Ok(Command {
+                    source,
+                    manifest_dir,
+                    args,
+                    module_name,
+                    is_global: false,
+                    directory: local_directory.to_path_buf(),
+                    prehashed_cache_key,
+                });
+            }
+            FindCommandResult::Error(e) => {
+                return Err(Error::ErrorReadingLocalDirectory(
+                    command_name.as_ref().to_string(),
+                    e.to_string(),
+                ));
+            }
+        };
+        trace!("Local command not found");
+    }
 
-    // look in the global directory
-    let global_directory = Config::get_globals_directory().map_err(|e| {
+    // look in the global directories
+    let global_directories = global_directories().map_err(|e| {
         Error::CouldNotOpenGlobalsDirectory(command_name.as_ref().to_string(), e.to_string())
     })?;
-    let global_command_result =
-        FindCommandResult::find_command_in_directory(&global_directory, &command_name);
-
-    match global_command_result {
-        FindCommandResult::CommandNotFound(_) => {} // continue
-        FindCommandResult::CommandFound {
-            source,
-            manifest_dir,
-            args,
-            module_name,
-            prehashed_cache_key,
-        } => {
-            return Ok(Command {
+    for global_directory in global_directories {
+        let global_command_result =
+            FindCommandResult::find_command_in_directory(&global_directory, &command_name);
+
+        match global_command_result {
+            FindCommandResult::CommandNotFound(_) => {} // continue
+            FindCommandResult::CommandFound {
                 source,
                 manifest_dir,
                 args,
                 module_name,
-                is_global: true,
                 prehashed_cache_key,
-            });
-        }
-        FindCommandResult::Error(e) => {
-            return Err(
-                Error::CommandNotFoundInLocalDirectoryAndErrorReadingGlobalDirectory(
-                    command_name.as_ref().to_string(),
-                    e.to_string(),
-                ),
-            );
-        }
-    };
+            } => {
+                return Ok(Command {
+                    source,
+                    manifest_dir,
+                    args,
+                    module_name,
+                    is_global: true,
+                    directory: global_directory,
+                    prehashed_cache_key,
+                });
+            }
+            FindCommandResult::Error(e) => {
+                return Err(
+                    Error::CommandNotFoundInLocalDirectoryAndErrorReadingGlobalDirectory(
+                        command_name.as_ref().to_string(),
+                        e.to_string(),
+                    ),
+                );
+            }
+        };
+    }
     trace!("Global command not found");
 
     return Err(Error::CommandNotFound(command_name.as_ref().to_string()));
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn install_command(directory: &Path, package_name: &str, command_name: &str) {
+        install_command_version(directory, package_name, "1.0.0", command_name);
+    }
+
+    fn install_command_version(
+        directory: &Path,
+        package_name: &str,
+        version: &str,
+        command_name: &str,
+    ) {
+        let lockfile = format!(
+            r#"
+[modules."{package}"."{version}".hello]
+name = "hello"
+package_version = "{version}"
+package_name = "{package}"
+package_path = "{package}@{version}"
+resolved = ""
+resolved_source = "registry+hello"
+abi = "wasi"
+source = "hello.wasm"
+
+[commands.{command}]
+name = "{command}"
+package_name = "{package}"
+package_version = "{version}"
+module = "hello"
+is_top_level_dependency = true
+"#,
+            package = package_name,
+            command = command_name,
+            version = version
+        );
+        let lockfile: Lockfile = toml::from_str(&lockfile).unwrap();
+        lockfile.save(directory).unwrap();
+    }
+
+    #[test]
+    fn local_commands_take_precedence_over_global_ones() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let local_dir = tmp_dir.path().join("project");
+        let global_dir = tmp_dir.path().join("global");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::create_dir_all(&global_dir).unwrap();
+        install_command(&local_dir, "local/hello", "hello");
+        install_command(&global_dir, "global/hello", "hello");
+        let globals = || Ok::<_, Error>(vec![global_dir.clone()]);
+
+        let command = find_command(Some(&local_dir), globals, "hello").unwrap();
+        assert!(!command.is_global);
+        assert!(
+            command.source.starts_with(&local_dir),
+            "{:?}",
+            command.source
+        );
+
+        let command = find_command(None, globals, "hello").unwrap();
+        assert!(command.is_global);
+        assert!(
+            command.source.starts_with(&global_dir),
+            "{:?}",
+            command.source
+        );
+    }
+
+    #[test]
+    fn commands_missing_locally_are_found_globally() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let local_dir = tmp_dir.path().join("project");
+        let global_dir = tmp_dir.path().join("global");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::create_dir_all(&global_dir).unwrap();
+        install_command(&global_dir, "global/hello", "hello");
+        let globals = || Ok::<_, Error>(vec![global_dir.clone()]);
+
+        let command = find_command(Some(&local_dir), globals, "hello").unwrap();
+        assert!(command.is_global);
+        let command = find_command(None, globals, "hello").unwrap();
+        assert!(command.is_global);
+
+        assert!(matches!(
+            find_command(Some(&local_dir), globals, "goodbye"),
+            Err(Error::CommandNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn commands_are_found_in_the_overlay_and_the_system_globals() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let system_dir = tmp_dir.path().join("system");
+        let overlay_dir = tmp_dir.path().join("overlay");
+        std::fs::create_dir_all(&system_dir).unwrap();
+        std::fs::create_dir_all(&overlay_dir).unwrap();
+        install_command(&system_dir, "system/hello", "hello");
+        install_command(&overlay_dir, "user/greet", "greet");
+        let globals = || Ok::<_, Error>(vec![overlay_dir.clone(), system_dir.clone()]);
+
+        let command = find_command(None, globals, "hello").unwrap();
+        assert_eq!(command.directory, system_dir);
+        let command = find_command(None, globals, "greet").unwrap();
+        assert_eq!(command.directory, overlay_dir);
+        assert!(command.is_global);
+    }
+
+    #[test]
+    fn local_commands_shadowing_a_global_version_are_described() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let local_dir = tmp_dir.path().join("project");
+        let global_dir = tmp_dir.path().join("global");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::create_dir_all(&global_dir).unwrap();
+        install_command_version(&local_dir, "_/hello", "2.0.0", "hello");
+        install_command_version(&global_dir, "_/hello", "1.0.0", "hello");
+        let globals = || Ok::<_, Error>(vec![global_dir.clone()]);
+
+        let note = describe_shadowing(&local_dir, globals, "hello").unwrap();
+        assert_eq!(
+            note,
+            format!(
+                "Command `hello` is provided locally by _/hello@2.0.0, which shadows the global _/hello@1.0.0 in {}",
+                global_dir.display()
+            )
+        );
+
+        install_command_version(&global_dir, "_/hello", "2.0.0", "hello");
+        assert_eq!(describe_shadowing(&local_dir, globals, "hello"), None);
+        assert_eq!(describe_shadowing(&local_dir, globals, "goodbye"), None);
+    }
+}
@@ -2,16 +2,16 @@
     not(feature = "full"),
     allow(dead_code, unused_imports, unused_variables)
 )]
+use crate::config::{default_user_agent, Config};
 use crate::data::manifest::Manifest;
 #[cfg(feature = "full")]
 use crate::database;
 use crate::dataflow::manifest_packages::ManifestResult;
 use crate::dataflow::resolved_packages::ResolvedPackages;
 use crate::dataflow::WapmPackageKey;
-use crate::graphql::VERSION;
 #[allow(unused_imports)]
 use crate::keys;
-use crate::util::whoami_distro;
+use crate::progress::{self, ProgressReader};
 #[allow(unused_imports)]
 use crate::util::{
     self, create_package_dir, create_temp_dir, fully_qualified_package_display_name,
@@ -72,7 +72,9 @@ impl<'a> InstalledPackages<'a> {
                 .packages
                 .into_iter()
                 .map(|(key, (download_url, signature))| {
-                    info!("Installing {}@{}", key.name, key.version);
+                    progress::report(|sink| {
+                        sink.on_download_start(&key.name, &key.version, &download_url)
+                    });
                     Installer::install_package(
                         &directory,
                         key,
@@ -102,6 +104,7 @@ impl<'a> InstalledPackages<'a> {
                             ));
                         }
                     };
+                    progress::report(|sink| sink.on_installed(&key.name, &key.version));
                     Ok((key.clone(), manifest, download_url))
                 })
                 .collect();
@@ -303,38 +306,24 @@ impl<'a> Install<'a> for RegistryInstaller {
             fully_qualified_package_display_name(pkg_name, &key.version);
         let package_dir = create_package_dir(&directory, namespace, &fully_qualified_package_name)
             .map_err(|err| Error::IoErrorCreatingDirectory(key.to_string(), err.to_string()))?;
-        let client = {
-            let builder = ClientBuilder::new().gzip(false);
-            #[cfg(not(target_os = "wasi"))]
-            let builder = if let Some(proxy) = proxy::maybe_set_up_proxy()
-                .map_err(|e| Error::IoConnectionError(format!("{}", e)))?
-            {
-                builder.proxy(proxy)
-            } else {
-                builder
-            };
-
-            builder.build().unwrap()
+        let mut fetch =
+            registry_fetch(download_url).map_err(|e| Error::IoConnectionError(e.to_string()))?;
+        let fetch = |offset: u64| {
+            fetch(offset).map(|download| Download {
+                resumed: download.resumed,
+                body: Box::new(ProgressReader::new(download.body, &key.name, offset)),
+            })
         };
-        let user_agent = format!(
-            "wapm/{} {} {}",
-            VERSION,
-            whoami::platform(),
-            whoami_distro(),
-        );
-        let mut response = client
-            .get(download_url)
-            .header(header::USER_AGENT, user_agent)
-            .send()
-            .map_err(|e| {
-                let error_message = e.to_string();
-                #[cfg(feature = "telemetry")]
-                {
-                    let e = e.into();
-                    sentry::integrations::anyhow::capture_anyhow(&e);
-                }
-                Error::DownloadError(key.to_string(), error_message)
-            })?;
+        let part_path = package_dir.with_file_name(format!(
+            "{}.tar.gz{}",
+            fully_qualified_package_name, PART_SUFFIX
+        ));
+        download_with_resume(&part_path, fetch)
+            .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
+        // the archive stays next to the package directory, renaming it elsewhere can cross
+        // filesystems
+        let tar_gz_path =
+            package_dir.with_file_name(format!("{}.tar.gz", fully_qualified_package_name));
 
         // step to perform after package is decompressed: may be a no-op or may
         // execute side effects such as logging to the user.
@@ -385,30 +374,157 @@ impl<'a> Install<'a> for RegistryInstaller {
         let mut key_sign_end_step: Box<dyn FnMut(&mut fs::File) -> Result<(), Error>> =
             Box::new(|_dest| Ok(()));
 
-        let temp_dir =
-            create_temp_dir().map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
-        let tmp_dir_path: &std::path::Path = temp_dir.as_ref();
-        fs::create_dir(tmp_dir_path.join("wapm_package_install"))
-            .map_err(|e| Error::IoErrorCreatingDirectory(key.to_string(), e.to_string()))?;
-        let temp_tar_gz_path = tmp_dir_path
-            .join("wapm_package_install")
-            .join("package.tar.gz");
-        let mut dest = OpenOptions::new()
+        fs::rename(&part_path, &tar_gz_path)
+            .map_err(|e| Error::IoCopyError(key.to_string(), e.to_string()))?;
+        let extracted = OpenOptions::new()
             .read(true)
             .write(true)
-            .create(true)
-            .open(&temp_tar_gz_path)
-            .map_err(|e| Error::IoCopyError(key.to_string(), e.to_string()))?;
+            .open(&tar_gz_path)
+            .map_err(|e| Error::IoCopyError(key.to_string(), e.to_string()))
+            .and_then(|mut dest| {
+                key_sign_end_step(&mut dest)?;
+                Self::decompress_and_extract_archive(dest, &package_dir, &key)
+                    .map_err(|e| Error::DecompressionError(key.to_string(), e.to_string()))
+            });
+        let _ = fs::remove_file(&tar_gz_path);
+        extracted?;
+        Ok((key, package_dir, download_url.to_string()))
+    }
+}
 
-        io::copy(&mut response, &mut dest)
-            .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
+/// Requests the package archive at `download_url` from the given byte offset, for
+/// `download_with_resume`
+fn registry_fetch(download_url: &str) -> io::Result<impl FnMut(u64) -> io::Result<Download> + '_> {
+    let client = {
+        let builder = ClientBuilder::new().gzip(false);
+        #[cfg(not(target_os = "wasi"))]
+        let builder = if let Some(proxy) = proxy::maybe_set_up_proxy()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
+        {
+            builder.proxy(proxy)
+        } else {
+            builder
+        };
+
+        builder.build().unwrap()
+    };
+    // locked and cached download URLs don't go through the registry client, which checks this
+    let user_agent = match Config::from_file() {
+        Ok(config) => {
+            config
+                .registry
+                .check_not_default()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            config.registry.get_user_agent()
+        }
+        Err(_) => default_user_agent(),
+    };
+    Ok(move |offset: u64| -> io::Result<Download> {
+        let send = |offset: u64| {
+            let mut request = client
+                .get(download_url)
+                .header(header::USER_AGENT, user_agent.as_str());
+            if offset > 0 {
+                request = request.header(header::RANGE, format!("bytes={}-", offset));
+            }
+            request.send().map_err(|e| {
+                let error_message = e.to_string();
+                #[cfg(feature = "telemetry")]
+                {
+                    let e = e.into();
+                    sentry::integrations::anyhow::capture_anyhow(&e);
+                }
+                io::Error::new(io::ErrorKind::Other, error_message)
+            })
+        };
+        let mut response = send(offset)?;
+        // the partial download is already complete, or the package changed since
+        if offset > 0 && response.status().as_u16() == 416 {
+            response = send(0)?;
+        }
+        if !response.status().is_success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("the registry responded with {}", response.status()),
+            ));
+        }
+        Ok(Download {
+            resumed: response.status().as_u16() == 206,
+            body: Box::new(response),
+        })
+    })
+}
+
+/// Download the package archive at `download_url` to `destination`, resuming an interrupted
+/// download of it
+pub(crate) fn download_archive(download_url: &str, destination: &Path) -> io::Result<()> {
+    let mut part_path = destination.as_os_str().to_owned();
+    part_path.push(PART_SUFFIX);
+    let part_path = PathBuf::from(part_path);
+    download_with_resume(&part_path, registry_fetch(download_url)?)?;
+    fs::rename(&part_path, destination)
+}
 
-        key_sign_end_step(&mut dest)?;
+/// The suffix of a package archive that is still being downloaded
+const PART_SUFFIX: &str = ".part";
 
-        Self::decompress_and_extract_archive(dest, &package_dir, &key)
-            .map_err(|e| Error::DecompressionError(key.to_string(), e.to_string()))?;
-        Ok((key, package_dir, download_url.to_string()))
+/// The response to a download request
+struct Download {
+    /// Whether the body continues from the requested offset rather than starting over
+    resumed: bool,
+    body: Box<dyn io::Read>,
+}
+
+/// Download a gzipped package archive into `part_path`. `fetch` requests the archive from the
+/// given byte offset, so that an interrupted download is resumed from the bytes already in
+/// `part_path` when the server supports range requests. If the completed archive fails its gzip
+/// checksum after resuming, it is downloaded again from the start.
+fn download_with_resume<F>(part_path: &Path, mut fetch: F) -> io::Result<()>
+where
+    F: FnMut(u64) -> io::Result<Download>,
+{
+    let offset = fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+    let mut download = fetch(offset)?;
+    let resumed = offset > 0 && download.resumed;
+    if resumed {
+        debug!(
+            "Resuming the download of {} at byte {}",
+            part_path.display(),
+            offset
+        );
     }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .create(true)
+        .append(resumed)
+        .write(!resumed)
+        .truncate(!resumed)
+        .open(part_path)?;
+    io::copy(&mut download.body, &mut file)?;
+
+    if let Err(e) = verify_gzip_checksum(&mut file) {
+        drop(file);
+        fs::remove_file(part_path)?;
+        if resumed {
+            warn!("The resumed download is corrupt, downloading it again");
+            return download_with_resume(part_path, fetch);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Decompress the whole archive, which fails if the gzip checksum doesn't match
+fn verify_gzip_checksum(file: &mut fs::File) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    io::copy(&mut GzDecoder::new(file), &mut io::sink())
+        .map(|_| ())
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The downloaded archive is corrupt: {}", e),
+            )
+        })
 }
 
 /// Verifies the signature of a downloaded package archive
@@ -427,3 +543,67 @@ fn verify_signature_on_package(
     minisign::verify(&public_key, &sig_box, dest, true, false)
         .map_err(|e| anyhow!("Could not validate signature: {}", e.to_string()))
 }
+
+#[cfg(test)]
+mod test {
+    use super::{download_with_resume, Download, PART_SUFFIX};
+    use flate2::{write::GzEncoder, Compression};
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io::{self, Write};
+
+    fn gzipped(contents: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn partial_downloads_are_resumed() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let part_path = tmp_dir
+            .path()
+            .join(format!("pkg@1.0.0.tar.gz{}", PART_SUFFIX));
+        let archive = gzipped(&[7; 4096]);
+        let split = archive.len() / 2;
+        fs::write(&part_path, &archive[..split]).unwrap();
+
+        let offsets = RefCell::new(vec![]);
+        download_with_resume(&part_path, |offset| {
+            offsets.borrow_mut().push(offset);
+            Ok(Download {
+                resumed: true,
+                body: Box::new(io::Cursor::new(archive[offset as usize..].to_vec())),
+            })
+        })
+        .unwrap();
+        assert_eq!(*offsets.borrow(), vec![split as u64]);
+        assert_eq!(fs::read(&part_path).unwrap(), archive);
+
+        // a server without range support sends everything again
+        fs::write(&part_path, &archive[..split]).unwrap();
+        download_with_resume(&part_path, |_| {
+            Ok(Download {
+                resumed: false,
+                body: Box::new(io::Cursor::new(archive.clone())),
+            })
+        })
+        .unwrap();
+        assert_eq!(fs::read(&part_path).unwrap(), archive);
+
+        // a partial download of a different archive fails the checksum and starts over
+        let stale = gzipped(&[1; 4096]);
+        fs::write(&part_path, &stale[..split]).unwrap();
+        let offsets = RefCell::new(vec![]);
+        download_with_resume(&part_path, |offset| {
+            offsets.borrow_mut().push(offset);
+            Ok(Download {
+                resumed: true,
+                body: Box::new(io::Cursor::new(archive[offset as usize..].to_vec())),
+            })
+        })
+        .unwrap();
+        assert_eq!(*offsets.borrow(), vec![split as u64, 0]);
+        assert_eq!(fs::read(&part_path).unwrap(), archive);
+    }
+}
@@ -7,7 +7,8 @@ use crate::data::lock::migrate::{
 use crate::data::lock::LOCKFILE_NAME;
 use crate::dataflow::installed_packages::InstalledPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
-use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::dataflow::{PackageKey, Target, WapmPackageKey};
+use crate::util;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
 use std::fs;
@@ -26,6 +27,10 @@ pub enum LockfileError {
     InvalidOrMissingVersion,
     #[error("Lockfile version is too high, update wapm or delete `wapm.lock` and try again.")]
     VersionTooHigh,
+    #[error("Package {0} has no variant of the module \"{1}\" for {2}. Pick another target with `--platform` and `--arch`.")]
+    NoMatchingVariant(String, String, Target),
+    #[error("The interface of module \"{1}\" of package {0} changed without a new version. Remove the package and install it again if the change is expected.")]
+    ContractChanged(String, String),
 }
 
 /// A ternary for a lockfile: Some, None, Error.
@@ -96,26 +101,43 @@ pub struct LockfilePackages<'a> {
 }
 
 impl<'a> LockfilePackages<'a> {
+    /// The lockfile data of the installed packages. Of the variants of a module, the first one
+    /// matching `target` is used.
     pub fn from_installed_packages(
         installed_manifest_packages: &'a InstalledPackages<'a>,
+        target: &Target,
     ) -> Result<Self, LockfileError> {
         let mut packages = HashMap::default();
         for (k, manifest, download_url) in installed_manifest_packages.packages.iter() {
-            let modules: Vec<LockfileModule> = match manifest.module {
-                Some(ref modules) => modules
-                    .iter()
-                    .map(|module| {
-                        LockfileModule::from_module(
-                            &manifest.base_directory_path,
-                            k.name.as_ref(),
-                            &k.version,
-                            module,
-                            download_url,
-                        )
-                    })
-                    .collect(),
-                _ => vec![],
-            };
+            let mut modules: Vec<LockfileModule> = vec![];
+            for module in manifest.module.iter().flatten() {
+                if modules.iter().any(|m| m.name == module.name) || !target.matches(module) {
+                    continue;
+                }
+                let mut lockfile_module = LockfileModule::from_module(
+                    &manifest.base_directory_path,
+                    k.name.as_ref(),
+                    &k.version,
+                    module,
+                    download_url,
+                );
+                if module.platform.is_some() || module.arch.is_some() {
+                    lockfile_module.target = Some(target.to_string());
+                }
+                modules.push(lockfile_module);
+            }
+            if let Some(missing) = manifest
+                .module
+                .iter()
+                .flatten()
+                .find(|module| !modules.iter().any(|m| m.name == module.name))
+            {
+                return Err(LockfileError::NoMatchingVariant(
+                    k.name.to_string(),
+                    missing.name.clone(),
+                    target.clone(),
+                ));
+            }
             let commands: Vec<LockfileCommand> = match manifest.command {
                 Some(ref modules) => {
                     let commands = modules
@@ -208,6 +230,106 @@ impl<'a> LockfilePackages<'a> {
         missing_packages
     }
 
+    /// Compare the contract hashes of the packages in `installed` with the hashes this
+    /// lockfile has for the same version of the package, so that a package whose interface
+    /// changed without a new version is caught when it's downloaded again. Packages that aren't
+    /// in this lockfile at that version and modules without a hash are skipped.
+    pub fn verify_contracts(&self, installed: &LockfilePackages<'a>) -> Result<(), LockfileError> {
+        for (key, data) in installed.packages.iter() {
+            let locked = match self.packages.get(key) {
+                Some(locked) => locked,
+                None => continue,
+            };
+            for module in data.modules.iter() {
+                let locked_module = locked.modules.iter().find(|m| m.name == module.name);
+                match (
+                    locked_module.and_then(|m| m.contract_hash.as_ref()),
+                    module.contract_hash.as_ref(),
+                ) {
+                    (Some(expected), Some(actual)) if expected != actual => {
+                        debug!(
+                            "The lockfile has contract hash {} for module \"{}\" of {}, the installed module has {}",
+                            expected, module.name, key, actual
+                        );
+                        return Err(LockfileError::ContractChanged(
+                            key.to_string(),
+                            module.name.clone(),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the exports of the installed modules of the packages in `updated` with those of
+    /// the version of the package in this lockfile, describing every export that an updated
+    /// module no longer provides or provides with another signature. Modules that can't be read
+    /// are skipped.
+    pub fn incompatible_exports(
+        &self,
+        updated: &LockfilePackages<'a>,
+        directory: &Path,
+    ) -> Vec<String> {
+        let mut problems = vec![];
+        for (key, data) in updated.packages.iter() {
+            let (name, version) = match key {
+                PackageKey::WapmPackage(WapmPackageKey { name, version }) => (name, version),
+                _ => continue,
+            };
+            let locked =
+                self.packages
+                    .iter()
+                    .find_map(|(locked_key, locked_data)| match locked_key {
+                        PackageKey::WapmPackage(WapmPackageKey {
+                            name: locked_name,
+                            version: locked_version,
+                        }) if locked_name == name && locked_version != version => {
+                            Some((locked_version, locked_data))
+                        }
+                        _ => None,
+                    });
+            let (locked_version, locked_data) = match locked {
+                Some(locked) => locked,
+                None => continue,
+            };
+            for old_module in locked_data.modules.iter() {
+                let new_module = match data.modules.iter().find(|m| m.name == old_module.name) {
+                    Some(new_module) => new_module,
+                    None => continue,
+                };
+                let interface = |module: &LockfileModule| {
+                    util::get_module_interface(
+                        &module.get_canonical_source_path_from_lockfile_dir(directory.into()),
+                    )
+                };
+                let (old, new) = match (interface(old_module), interface(new_module)) {
+                    (Some(old), Some(new)) => (old, new),
+                    _ => continue,
+                };
+                let diff = old.diff(&new);
+                let context = format!(
+                    "{} {} → {}: module \"{}\"",
+                    name, locked_version, version, old_module.name
+                );
+                for export in diff.removed_exports.iter() {
+                    problems.push(format!("{} no longer provides {}", context, export));
+                }
+                for (old_export, new_export) in diff.changed_exports.iter() {
+                    problems.push(format!(
+                        "{} changed {} to {}",
+                        context,
+                        old_export,
+                        new_export.normalized_signature()
+                    ));
+                }
+            }
+        }
+        problems.sort();
+        problems
+    }
+
     pub fn remove_packages(&mut self, removed_packages: RemovedPackages<'a>) {
         let removed_package_keys = removed_packages
             .packages
@@ -234,3 +356,75 @@ impl<'a> LockfilePackages<'a> {
         self.packages.extend(other_packages.packages);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use crate::data::manifest::PACKAGES_DIR_NAME;
+    use crate::dataflow::lockfile_packages::{LockfileError, LockfilePackage, LockfilePackages};
+    use crate::dataflow::PackageKey;
+    use crate::util;
+    use std::fs;
+
+    /// `(module (func (export "add") (param i32 i32) (result i32) ...))`
+    const ADD_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f,
+        0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00,
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+    ];
+
+    #[test]
+    fn changed_exports_with_the_same_version_fail_verification() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let directory = tmp_dir.path();
+        let package_dir = directory.join(PACKAGES_DIR_NAME).join("ns/math@1.0.0");
+        fs::create_dir_all(&package_dir).unwrap();
+        let module_path = package_dir.join("math.wasm");
+        fs::write(&module_path, ADD_MODULE).unwrap();
+
+        let module = || LockfileModule {
+            name: "math".to_string(),
+            package_name: "ns/math".to_string(),
+            package_version: "1.0.0".to_string(),
+            package_path: "ns/math@1.0.0".to_string(),
+            source: "math.wasm".to_string(),
+            contract_hash: util::get_contract_hash(&module_path),
+            ..LockfileModule::default()
+        };
+        let packages = |version: semver::Version| {
+            let mut packages = LockfilePackages::default();
+            packages.packages.insert(
+                PackageKey::new_registry_package("ns/math", version),
+                LockfilePackage {
+                    modules: vec![module()],
+                    commands: vec![],
+                },
+            );
+            packages
+        };
+        let locked = packages(semver::Version::new(1, 0, 0));
+        assert!(locked
+            .packages
+            .values()
+            .all(|p| p.modules[0].contract_hash.is_some()));
+        locked
+            .verify_contracts(&packages(semver::Version::new(1, 0, 0)))
+            .unwrap();
+
+        // the same version, but the module no longer exports anything
+        fs::write(&module_path, b"\0asm\x01\0\0\0").unwrap();
+        // a new version may change the interface
+        locked
+            .verify_contracts(&packages(semver::Version::new(1, 1, 0)))
+            .unwrap();
+        let error = locked
+            .verify_contracts(&packages(semver::Version::new(1, 0, 0)))
+            .unwrap_err();
+        assert!(
+            matches!(error, LockfileError::ContractChanged(ref package, ref module)
+                if package == "ns/math 1.0.0" && module == "math"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::data::manifest::{FeatureSelection, Manifest, MANIFEST_FILE_NAME};
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
 use crate::dataflow::{normalize_global_namespace, PackageKey, WapmPackageKey};
@@ -18,6 +18,8 @@ pub enum Error {
         "Version {0} for package {1} must be a semantic version or a semantic version requirement."
     )]
     SemVerError(String, String),
+    #[error("Could not select the dependencies to install. {0}")]
+    InvalidFeatures(String),
 }
 
 /// A ternary for a manifest: Some, None, Error.
@@ -63,13 +65,15 @@ pub struct ManifestPackages<'a> {
 }
 
 impl<'a> ManifestPackages<'a> {
-    /// Construct package keys from the manifest and any other additional packages.
+    /// Construct package keys from the manifest and any other additional packages. Only the
+    /// optional dependencies of the features in `features` are included.
     /// Short-hand package names are transformed.
     pub fn new_from_manifest_and_added_packages(
         manifest: &'a Manifest,
         added_packages: &AddedPackages<'a>,
+        features: &FeatureSelection,
     ) -> Result<Self, Error> {
-        let packages = Self::extract_package_keys(&manifest)?;
+        let packages = Self::extract_package_keys(manifest, features)?;
         let mut packages: HashSet<PackageKey> = packages
             .into_iter()
             .map(normalize_global_namespace)
@@ -83,7 +87,7 @@ impl<'a> ManifestPackages<'a> {
         self.packages.iter().cloned().collect()
     }
 
-    pub fn remove_packages(&mut self, removed_packages: &'a RemovedPackages<'a>) {
+    pub fn remove_packages(&mut self, removed_packages: &RemovedPackages<'a>) {
         let removed_package_keys = removed_packages
             .packages
             .iter()
@@ -107,18 +111,16 @@ impl<'a> ManifestPackages<'a> {
     }
 
     /// Extract package keys from the manifest
-    fn extract_package_keys(manifest: &'a Manifest) -> Result<Vec<PackageKey<'a>>, Error> {
-        match manifest.dependencies {
-            Some(ref dependencies) => {
-                let result = dependencies
-                    .iter()
-                    .map(|(name, value)| (name.as_str(), value.as_str()))
-                    .map(Self::parse_wapm_package_key)
-                    .collect::<Result<Vec<_>, Error>>()?;
-                Ok(result)
-            }
-            None => Ok(vec![]),
-        }
+    fn extract_package_keys(
+        manifest: &'a Manifest,
+        features: &FeatureSelection,
+    ) -> Result<Vec<PackageKey<'a>>, Error> {
+        manifest
+            .selected_dependencies(features)
+            .map_err(|e| Error::InvalidFeatures(e.to_string()))?
+            .into_iter()
+            .map(Self::parse_wapm_package_key)
+            .collect()
     }
 
     /// Parse a raw pair of strings as an exact wapm package or a range. May fail with a semver
@@ -1,10 +1,13 @@
-use crate::data::lock::lockfile::{CommandMap, Lockfile, ModuleMap};
-use crate::dataflow::bin_script::save_bin_script;
+use crate::data::lock::lockfile::{CommandMap, Lockfile, LockfileFeatures, ModuleMap};
+use crate::data::manifest::FeatureSelection;
+use crate::dataflow::bin_script::save_lockfile_bin_scripts;
+use crate::dataflow::dependency_graph::qualify;
 use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages};
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
 use crate::dataflow::{PackageKey, WapmPackageKey};
 use std::collections::btree_map::BTreeMap;
 use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
 use thiserror::Error;
 
@@ -12,6 +15,20 @@ use thiserror::Error;
 pub enum Error {
     #[error("Could not save generated lockfile because {0}.")]
     FailedToSaveLockfile(String),
+    #[error(
+        "Packages {first} and {second} both have a command named \"{command}\". Install one of them with `--alias <name>` to expose its command under a different name."
+    )]
+    CommandCollision {
+        command: String,
+        first: String,
+        second: String,
+    },
+    #[error("Cannot alias the command of package {package} as \"{alias}\" because it has {count} commands.")]
+    AmbiguousCommandAlias {
+        package: String,
+        alias: String,
+        count: usize,
+    },
 }
 
 /// Merge two sets, and keep upgraded packages and all other unchanged packages.
@@ -19,6 +36,11 @@ pub enum Error {
 #[derive(Clone, Debug)]
 pub struct MergedLockfilePackages<'a> {
     pub packages: HashMap<PackageKey<'a>, LockfilePackage>,
+    /// The names of the packages kept unchanged from the old lockfile. Command collisions
+    /// between them predate the collision check, so they are only warned about.
+    unchanged: HashSet<String>,
+    /// The manifest features to record in the lockfile
+    features: Option<LockfileFeatures>,
 }
 
 impl<'a> MergedLockfilePackages<'a> {
@@ -27,11 +49,13 @@ impl<'a> MergedLockfilePackages<'a> {
         old_packages: RetainedLockfilePackages<'a>,
     ) -> Self {
         let mut unique_packages = HashMap::new();
+        let mut unchanged = HashSet::new();
         for (key, data) in old_packages.packages {
             let name = match key {
                 PackageKey::WapmPackage(ref k) => k.name.clone(),
                 _ => panic!("Non wapm registry keys are unsupported."),
             };
+            unchanged.insert(name.to_string());
             unique_packages.insert(name, (key, data));
         }
         for (key, data) in new_packages.packages {
@@ -39,6 +63,7 @@ impl<'a> MergedLockfilePackages<'a> {
                 PackageKey::WapmPackage(ref k) => k.name.clone(),
                 _ => panic!("Non wapm registry keys are unsupported."),
             };
+            unchanged.remove(name.as_ref());
             unique_packages.insert(name, (key, data));
         }
         let packages: HashMap<_, _> = unique_packages
@@ -46,10 +71,60 @@ impl<'a> MergedLockfilePackages<'a> {
             .map(|(_, (key, data))| (key, data))
             .collect();
 
-        Self { packages }
+        Self {
+            packages,
+            unchanged,
+            features: None,
+        }
+    }
+
+    /// Record `features` in the lockfile, so that later updates select the same ones. The
+    /// default selection isn't written.
+    pub fn with_features(mut self, features: &FeatureSelection) -> Self {
+        self.features = Some(features.into()).filter(|_| *features != FeatureSelection::default());
+        self
+    }
+
+    /// Rename the command of each package in `aliases`, which maps package names to the name
+    /// the command is exposed under.
+    pub fn with_command_aliases(
+        mut self,
+        aliases: &HashMap<String, String>,
+    ) -> Result<Self, Error> {
+        let aliases: HashMap<String, &String> = aliases
+            .iter()
+            .map(|(package, alias)| (qualify(package), alias))
+            .collect();
+        for (key, package) in self.packages.iter_mut() {
+            let name = match key {
+                PackageKey::WapmPackage(k) => qualify(&k.name),
+                PackageKey::WapmPackageRange(k) => qualify(&k.name),
+            };
+            let alias = match aliases.get(&name) {
+                Some(alias) => alias,
+                None => continue,
+            };
+            match package.commands.as_mut_slice() {
+                [command] => command.name = alias.to_string(),
+                commands => {
+                    return Err(Error::AmbiguousCommandAlias {
+                        package: name,
+                        alias: alias.to_string(),
+                        count: commands.len(),
+                    })
+                }
+            }
+        }
+        Ok(self)
     }
 
-    pub fn generate_lockfile(self, directory: &'a Path) -> Result<(), Error> {
+    /// Write the lockfile to `directory`. With `create_commands`, the bin scripts for the
+    /// commands are saved as well.
+    pub fn generate_lockfile(
+        self,
+        directory: &'a Path,
+        create_commands: bool,
+    ) -> Result<(), Error> {
         let mut modules: ModuleMap = BTreeMap::new();
         let mut commands: CommandMap = BTreeMap::new();
         for (key, package) in self.packages {
@@ -67,20 +142,29 @@ impl<'a> MergedLockfilePackages<'a> {
                         modules.insert(name, module);
                     }
                     for command in package.commands {
-                        if let Some(module) = modules.get(&command.module) {
-                            let name = command.name.clone();
-                            let script_name = command.name.clone();
-                            let module_path = format!("{}/{}", module.package_path, module.source);
-                            commands.insert(name, command);
-                            // save the bin script to execute this command from the terminal
-                            save_bin_script(
-                                directory,
-                                script_name,
-                                module.package_path.clone(),
-                                module_path,
-                            )
-                            .map_err(|e| Error::FailedToSaveLockfile(e.to_string()))?;
+                        if !modules.contains_key(&command.module) {
+                            continue;
+                        }
+                        if let Some(existing) = commands.get(&command.name) {
+                            if existing.package_name != command.package_name {
+                                let mut packages =
+                                    [existing.package_name.clone(), command.package_name.clone()];
+                                packages.sort();
+                                let [first, second] = packages;
+                                let error = Error::CommandCollision {
+                                    command: command.name.clone(),
+                                    first,
+                                    second,
+                                };
+                                if !self.unchanged.contains(&existing.package_name)
+                                    || !self.unchanged.contains(&command.package_name)
+                                {
+                                    return Err(error);
+                                }
+                                warn!("{}", error);
+                            }
                         }
+                        commands.insert(command.name.clone(), command);
                     }
                 }
                 PackageKey::WapmPackageRange(_) => {
@@ -89,8 +173,17 @@ impl<'a> MergedLockfilePackages<'a> {
             }
         }
 
-        let lockfile = Lockfile { modules, commands };
+        let lockfile = Lockfile {
+            modules,
+            commands,
+            features: self.features,
+        };
 
+        if create_commands {
+            // save the bin scripts to execute the commands from the terminal
+            save_lockfile_bin_scripts(directory, &lockfile)
+                .map_err(|e| Error::FailedToSaveLockfile(e.to_string()))?;
+        }
         lockfile
             .save(directory)
             .map_err(|e| Error::FailedToSaveLockfile(e.to_string()))?;
@@ -100,11 +193,44 @@ impl<'a> MergedLockfilePackages<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages};
-    use crate::dataflow::merged_lockfile_packages::MergedLockfilePackages;
+    use crate::data::lock::lockfile_command::LockfileCommand;
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages, LockfileResult};
+    use crate::dataflow::merged_lockfile_packages::{Error, MergedLockfilePackages};
     use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
     use crate::dataflow::PackageKey;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
+
+    fn server_package(name: &'static str) -> (PackageKey<'static>, LockfilePackage) {
+        let version = semver::Version::new(1, 0, 0);
+        let package = LockfilePackage {
+            modules: vec![LockfileModule {
+                name: "server".to_string(),
+                package_name: name.to_string(),
+                package_version: version.to_string(),
+                ..Default::default()
+            }],
+            commands: vec![LockfileCommand {
+                name: "server".to_string(),
+                package_name: name.to_string(),
+                package_version: version.clone(),
+                module: "server".to_string(),
+                is_top_level_dependency: true,
+                main_args: None,
+            }],
+        };
+        (PackageKey::new_registry_package(name, version), package)
+    }
+
+    fn merged(
+        packages: Vec<(PackageKey<'static>, LockfilePackage)>,
+    ) -> MergedLockfilePackages<'static> {
+        MergedLockfilePackages {
+            packages: packages.into_iter().collect(),
+            unchanged: HashSet::new(),
+            features: None,
+        }
+    }
 
     #[test]
     fn test_merge() {
@@ -183,4 +309,75 @@ mod test {
 
         assert_eq!(3, result.packages.len());
     }
+
+    #[test]
+    fn aliased_commands_are_exposed_under_the_alias() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let mut aliases = HashMap::new();
+        aliases.insert("bar".to_string(), "bar-server".to_string());
+
+        merged(vec![server_package("_/foo"), server_package("_/bar")])
+            .with_command_aliases(&aliases)
+            .unwrap()
+            .generate_lockfile(tmp_dir.path(), false)
+            .unwrap();
+
+        let lockfile = match LockfileResult::find_in_directory(tmp_dir.path()) {
+            LockfileResult::Lockfile(lockfile) => lockfile,
+            _ => panic!("the lockfile should have been generated"),
+        };
+        assert_eq!(
+            lockfile.get_command("server").unwrap().package_name,
+            "_/foo"
+        );
+        assert_eq!(
+            lockfile.get_command("bar-server").unwrap().package_name,
+            "_/bar"
+        );
+    }
+
+    #[test]
+    fn colliding_commands_without_an_alias_are_an_error() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+
+        let error = merged(vec![server_package("_/foo"), server_package("_/bar")])
+            .generate_lockfile(tmp_dir.path(), false)
+            .unwrap_err();
+
+        match error {
+            Error::CommandCollision {
+                ref command,
+                ref first,
+                ref second,
+            } => {
+                assert_eq!(command, "server");
+                assert_eq!(first, "_/bar");
+                assert_eq!(second, "_/foo");
+            }
+            _ => panic!("unexpected error: {}", error),
+        }
+        assert!(error.to_string().contains("--alias"));
+        assert!(!tmp_dir.path().join("wapm.lock").exists());
+    }
+
+    #[test]
+    fn collisions_between_previously_installed_packages_are_kept() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let installed = |names: &[&'static str]| RetainedLockfilePackages {
+            packages: names.iter().map(|name| server_package(name)).collect(),
+        };
+        let added = |names: &[&'static str]| LockfilePackages {
+            packages: names.iter().map(|name| server_package(name)).collect(),
+        };
+
+        MergedLockfilePackages::merge(added(&[]), installed(&["_/foo", "_/bar"]))
+            .generate_lockfile(tmp_dir.path(), false)
+            .unwrap();
+        assert!(tmp_dir.path().join("wapm.lock").exists());
+
+        let error = MergedLockfilePackages::merge(added(&["_/foo"]), installed(&["_/bar"]))
+            .generate_lockfile(tmp_dir.path(), false)
+            .unwrap_err();
+        assert!(matches!(error, Error::CommandCollision { .. }));
+    }
 }
@@ -1,15 +1,14 @@
-use crate::data::manifest::Manifest;
+use crate::data::manifest::{FeatureSelection, Manifest, Module};
 use crate::dataflow::added_packages::AddedPackages;
-use crate::dataflow::changed_manifest_packages::ChangedManifestPackages;
-use crate::dataflow::installed_packages::{InstalledPackages, RegistryInstaller};
-use crate::dataflow::local_package::LocalPackage;
-use crate::dataflow::lockfile_packages::{LockfileError, LockfilePackages, LockfileResult};
-use crate::dataflow::manifest_packages::{ManifestPackages, ManifestResult};
+use crate::dataflow::dependency_graph::DependencyGraph;
+use crate::dataflow::installed_packages::{Install, InstalledPackages, RegistryInstaller};
+use crate::dataflow::lockfile_packages::{LockfileError, LockfilePackages};
+use crate::dataflow::manifest_packages::ManifestResult;
 use crate::dataflow::merged_lockfile_packages::MergedLockfilePackages;
-use crate::dataflow::removed_lockfile_packages::RemovedLockfilePackages;
 use crate::dataflow::removed_packages::RemovedPackages;
-use crate::dataflow::resolved_packages::{RegistryResolver, ResolvedPackages};
-use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
+use crate::dataflow::resolved_packages::{RegistryResolver, Resolve};
+use crate::dataflow::update_plan::{PendingUpdate, UpdatePlan};
+use crate::progress;
 use semver::{Version, VersionReq};
 use std::borrow::{Borrow, Cow};
 use std::collections::{HashMap, HashSet};
@@ -19,6 +18,7 @@ use std::path::Path;
 pub mod added_packages;
 pub mod bin_script;
 pub mod changed_manifest_packages;
+pub mod dependency_graph;
 pub mod find_command_result;
 pub mod installed_packages;
 pub mod interfaces;
@@ -30,6 +30,7 @@ pub mod removed_lockfile_packages;
 pub mod removed_packages;
 pub mod resolved_packages;
 pub mod retained_lockfile_packages;
+pub mod update_plan;
 use thiserror::Error;
 
 #[derive(Clone, Debug, Error)]
@@ -54,6 +55,83 @@ pub enum Error {
     CleanupError(removed_lockfile_packages::Error),
     #[error("Attempting to install multiple versions of package {0} ({1} and {2})")]
     DuplicatePackage(String, String, String),
+    #[error("Could not resolve package(s). {0}")]
+    DependencyCycle(dependency_graph::Error),
+    #[error("Could not resolve package(s). {0}")]
+    DependencyTreeTooDeep(dependency_graph::Error),
+    #[error("Updated package(s) no longer provide exports they used to:\n{0}\nInstall without `--strict` to update anyway.")]
+    IncompatibleExports(String),
+}
+
+/// Options for what an update installs, and writes besides the lockfile.
+#[derive(Clone, Debug)]
+pub struct UpdateOptions {
+    /// Create the entry points in `wapm_packages/.bin` for the commands of installed packages
+    pub create_commands: bool,
+    /// The target whose variant of a module is installed, if a package has several
+    pub target: Target,
+    /// Add the modules and commands of the manifest's own package to the lockfile. Unset to only
+    /// install the dependencies
+    pub include_local_package: bool,
+    /// Fail if a chain of dependencies is longer than this, the project's own dependencies
+    /// being at depth 1
+    pub max_depth: Option<usize>,
+    /// Fail instead of warning when an updated dependency removed or changed an export that the
+    /// previously installed version provided
+    pub strict_exports: bool,
+    /// The features of the manifest whose optional dependencies are installed. Unset to select
+    /// the features recorded in the lockfile, or else the default ones
+    pub features: Option<FeatureSelection>,
+    /// Command aliases to apply on top of the manifest's, keyed by package name. `install
+    /// --alias` only saves its alias to the manifest once the install succeeded
+    pub command_aliases: HashMap<String, String>,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            create_commands: true,
+            target: Target::host(),
+            include_local_package: true,
+            max_depth: None,
+            strict_exports: false,
+            features: None,
+            command_aliases: HashMap::new(),
+        }
+    }
+}
+
+/// The platform and architecture modules are installed for, see `Module::platform`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub platform: String,
+    pub arch: String,
+}
+
+impl Target {
+    /// The target wapm is running on
+    pub fn host() -> Self {
+        Target {
+            platform: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// Whether `module` can be installed for this target. Modules that don't declare a
+    /// platform or an architecture match every target.
+    pub fn matches(&self, module: &Module) -> bool {
+        module
+            .platform
+            .as_ref()
+            .map_or(true, |p| *p == self.platform)
+            && module.arch.as_ref().map_or(true, |a| *a == self.arch)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.platform, self.arch)
+    }
 }
 
 /// A package key for a package in the wapm.io registry.
@@ -87,11 +165,13 @@ pub fn detect_duplicate_packages(packages: &HashSet<PackageKey>) -> Result<(), E
                     // we sort the versions so that output is stable
                     let mut versions = [old_version.to_string(), version.to_string()];
                     versions.sort();
-                    return Err(Error::DuplicatePackage(
+                    let error = Error::DuplicatePackage(
                         name.to_string(),
                         versions[0].clone(),
                         versions[1].clone(),
-                    ));
+                    );
+                    progress::report(|sink| sink.on_resolve_conflict(&error.to_string()));
+                    return Err(error);
                 }
             }
             // ignore package ranges for now
@@ -181,64 +261,89 @@ pub fn normalize_global_namespace(key: PackageKey) -> PackageKey {
     new_key
 }
 
+/// Fail on dependency cycles, and on chains of dependencies longer than `options.max_depth`
+fn check_dependency_graph(graph: &DependencyGraph, options: &UpdateOptions) -> Result<(), Error> {
+    graph.detect_cycles().map_err(Error::DependencyCycle)?;
+    if let Some(max_depth) = options.max_depth {
+        graph
+            .check_depth(max_depth)
+            .map_err(Error::DependencyTreeTooDeep)?;
+    }
+    Ok(())
+}
+
+/// Warn about the exports that the packages in `updated` removed or changed since the version in
+/// `locked`, or fail with them if `options.strict_exports` is set
+fn check_updated_exports(
+    directory: &Path,
+    locked: &LockfilePackages,
+    updated: &LockfilePackages,
+    options: &UpdateOptions,
+) -> Result<(), Error> {
+    let problems = locked.incompatible_exports(updated, directory);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if options.strict_exports {
+        return Err(Error::IncompatibleExports(problems.join("\n")));
+    }
+    for problem in problems.iter() {
+        warn!("{}", problem);
+    }
+    Ok(())
+}
+
 /// If there is no mainfest, then this is a non-manifest project. All installations are retained
 /// in the lockfile, and installs are additive.
 /// This function returns a bool on success indicating if any changes were applied
-pub fn update_with_no_manifest<P: AsRef<Path>>(
+pub fn update_with_no_manifest<Resolver, Installer, P>(
     directory: P,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
-) -> Result<bool, Error> {
+    options: UpdateOptions,
+) -> Result<bool, Error>
+where
+    Resolver: for<'a> Resolve<'a>,
+    Installer: for<'a> Install<'a>,
+    P: AsRef<Path>,
+{
     let directory = directory.as_ref();
-    // get lockfile data
-    let lockfile_result = LockfileResult::find_in_directory(&directory);
-    let mut lockfile_packages =
-        LockfilePackages::new_from_result(lockfile_result).map_err(Error::LockfileError)?;
-    detect_duplicate_packages(&added_packages.packages)?;
-
-    // capture the initial lockfile keys before any modifications
-    let initial_package_keys: HashSet<_> = lockfile_packages.package_keys();
-
-    let removed_lockfile_packages = RemovedLockfilePackages::from_removed_packages_and_lockfile(
-        &removed_packages,
-        &lockfile_packages,
-    );
+    let PendingUpdate {
+        initial_package_keys,
+        locked_packages,
+        removed,
+        retained,
+        resolved,
+        ..
+    } = PendingUpdate::with_no_manifest::<Resolver>(directory, added_packages, removed_packages)?;
 
     // cleanup any old artifacts
-    removed_lockfile_packages
+    removed
         .cleanup_old_packages(&directory)
         .map_err(Error::CleanupError)?;
 
-    // remove/uninstall packages
-    lockfile_packages.remove_packages(removed_packages);
-
-    // check that the added packages are not already installed
-    let lockfile_package_keys = lockfile_packages.package_keys();
-    let added_packages = added_packages.prune_already_installed_packages(lockfile_package_keys);
-    // check for missing packages e.g. deleting stuff from wapm_packages
-    // install any missing or newly added packages
-    let missing_packages = lockfile_packages.find_missing_packages(&directory);
-    let added_packages = added_packages.add_missing_packages(missing_packages);
-
-    let resolved_packages =
-        ResolvedPackages::new_from_added_packages::<RegistryResolver>(added_packages)
-            .map_err(Error::ResolveError)?;
-    let installed_packages =
-        InstalledPackages::install::<RegistryInstaller>(&directory, resolved_packages, false)
-            .map_err(Error::InstallError)?;
-    let added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
+    let installed_packages = InstalledPackages::install::<Installer>(directory, resolved, false)
+        .map_err(Error::InstallError)?;
+    let added_lockfile_data =
+        LockfilePackages::from_installed_packages(&installed_packages, &options.target)
+            .map_err(Error::LockfileError)?;
+    locked_packages
+        .verify_contracts(&added_lockfile_data)
         .map_err(Error::LockfileError)?;
-
-    let retained_lockfile_packages =
-        RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
+    check_updated_exports(directory, &locked_packages, &added_lockfile_data, &options)?;
 
     // merge the lockfile data, and generate the new lockfile
-    let final_lockfile_data =
-        MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
+    let final_lockfile_data = MergedLockfilePackages::merge(added_lockfile_data, retained);
+    let graph = DependencyGraph::from_installed_packages(
+        directory,
+        None,
+        final_lockfile_data.packages.keys(),
+    );
+    check_dependency_graph(&graph, &options)?;
     let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
     if final_package_keys != initial_package_keys {
         final_lockfile_data
-            .generate_lockfile(&directory)
+            .generate_lockfile(directory, options.create_commands)
             .map_err(Error::GenerateLockfileError)?;
         Ok(true)
     } else {
@@ -249,80 +354,78 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
 /// If there is a manifest, then we construct lockfile data from manifest dependencies, and merge
 /// with existing lockfile data.
 /// This function returns a bool on success indicating if any changes were applied
-pub fn update_with_manifest<P: AsRef<Path>>(
+pub fn update_with_manifest<Resolver, Installer, P>(
     directory: P,
     manifest: Manifest,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
-) -> Result<bool, Error> {
+    options: UpdateOptions,
+) -> Result<bool, Error>
+where
+    Resolver: for<'a> Resolve<'a>,
+    Installer: for<'a> Install<'a>,
+    P: AsRef<Path>,
+{
     let directory = directory.as_ref();
-
-    let mut manifest_packages =
-        ManifestPackages::new_from_manifest_and_added_packages(&manifest, &added_packages)
-            .map_err(Error::ManifestError)?;
-
-    detect_duplicate_packages(&manifest_packages.packages)?;
-
-    // remove/uninstall packages
-    manifest_packages.remove_packages(&removed_packages);
-
-    // get lockfile data
-    let lockfile_result = LockfileResult::find_in_directory(&directory);
-    let lockfile_packages =
-        LockfilePackages::new_from_result(lockfile_result).map_err(Error::LockfileError)?;
-    // store lockfile package keys before updating it
-    let initial_package_keys = lockfile_packages.package_keys();
-
-    // get the local package modules and commands from the manifest
-    let local_package = LocalPackage::new_from_local_package_in_manifest(&manifest)
-        .map_err(Error::LocalPackageError)?;
-
-    let changed_manifest_data =
-        ChangedManifestPackages::get_changed_packages_from_manifest_and_lockfile(
-            &manifest_packages,
-            &lockfile_packages,
-        );
-
-    let packages_to_install = AddedPackages {
-        packages: changed_manifest_data.packages,
-    };
-
-    let missing_lockfile_packages = lockfile_packages.find_missing_packages(&directory);
-    let new_added_packages = packages_to_install.add_missing_packages(missing_lockfile_packages);
-
-    let removed_lockfile_packages =
-        RemovedLockfilePackages::from_manifest_and_lockfile(&manifest_packages, &lockfile_packages);
+    let PendingUpdate {
+        initial_package_keys,
+        locked_packages,
+        removed,
+        retained,
+        resolved,
+        local_package,
+        features,
+    } = PendingUpdate::with_manifest::<Resolver>(
+        directory,
+        &manifest,
+        &added_packages,
+        &removed_packages,
+        &options,
+    )?;
 
     // cleanup any old artifacts
-    removed_lockfile_packages
+    removed
         .cleanup_old_packages(&directory)
         .map_err(Error::CleanupError)?;
 
-    let retained_lockfile_packages =
-        RetainedLockfilePackages::from_manifest_and_lockfile(&manifest_packages, lockfile_packages);
-
-    let resolved_manifest_packages =
-        ResolvedPackages::new_from_added_packages::<RegistryResolver>(new_added_packages)
-            .map_err(Error::ResolveError)?;
-    let installed_manifest_packages = InstalledPackages::install::<RegistryInstaller>(
-        &directory,
-        resolved_manifest_packages,
-        false,
-    )
-    .map_err(Error::InstallError)?;
+    let installed_manifest_packages =
+        InstalledPackages::install::<Installer>(directory, resolved, false)
+            .map_err(Error::InstallError)?;
     let mut manifest_lockfile_data =
-        LockfilePackages::from_installed_packages(&installed_manifest_packages)
+        LockfilePackages::from_installed_packages(&installed_manifest_packages, &options.target)
             .map_err(Error::LockfileError)?;
-
-    manifest_lockfile_data.extend(local_package.into());
+    locked_packages
+        .verify_contracts(&manifest_lockfile_data)
+        .map_err(Error::LockfileError)?;
+    check_updated_exports(
+        directory,
+        &locked_packages,
+        &manifest_lockfile_data,
+        &options,
+    )?;
+
+    if let Some(local_package) = local_package {
+        manifest_lockfile_data.extend(local_package);
+    }
 
     // merge the lockfile data, and generate the new lockfile
-    let final_lockfile_data =
-        MergedLockfilePackages::merge(manifest_lockfile_data, retained_lockfile_packages);
+    let final_lockfile_data = MergedLockfilePackages::merge(manifest_lockfile_data, retained);
+    let graph = DependencyGraph::from_installed_packages(
+        directory,
+        Some(&manifest),
+        final_lockfile_data.packages.keys(),
+    );
+    check_dependency_graph(&graph, &options)?;
     let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
 
+    let mut aliases = manifest.command_aliases.clone().unwrap_or_default();
+    aliases.extend(options.command_aliases.clone());
+    let final_lockfile_data = final_lockfile_data
+        .with_command_aliases(&aliases)
+        .map_err(Error::GenerateLockfileError)?;
     final_lockfile_data
-        .generate_lockfile(&directory)
+        .with_features(&features)
+        .generate_lockfile(directory, options.create_commands)
         .map_err(Error::GenerateLockfileError)?;
 
     // update the manifest, if applicable
@@ -341,22 +444,78 @@ pub fn update<P: AsRef<Path>>(
     removed_packages: Vec<&str>,
     directory: P,
 ) -> Result<bool, Error> {
+    update_with::<RegistryResolver, RegistryInstaller, P>(
+        added_packages,
+        removed_packages,
+        directory,
+        UpdateOptions::default(),
+    )
+}
+
+/// Like `update`, but resolves and installs packages with the given `Resolve` and `Install`
+/// implementations instead of talking to the registry.
+pub fn update_with<Resolver, Installer, P>(
+    added_packages: Vec<(&str, &str)>,
+    removed_packages: Vec<&str>,
+    directory: P,
+    options: UpdateOptions,
+) -> Result<bool, Error>
+where
+    Resolver: for<'a> Resolve<'a>,
+    Installer: for<'a> Install<'a>,
+    P: AsRef<Path>,
+{
     let directory = directory.as_ref();
     let added_packages =
         AddedPackages::new_from_str_pairs(added_packages).map_err(Error::AddError)?;
     let removed_packages = RemovedPackages::new_from_package_names(removed_packages);
     let manifest_result = ManifestResult::find_in_directory(&directory);
     match manifest_result {
-        ManifestResult::NoManifest => {
-            update_with_no_manifest(directory, added_packages, removed_packages)
-        }
-        ManifestResult::Manifest(manifest) => {
-            update_with_manifest(directory, manifest, added_packages, removed_packages)
-        }
+        ManifestResult::NoManifest => update_with_no_manifest::<Resolver, Installer, _>(
+            directory,
+            added_packages,
+            removed_packages,
+            options,
+        ),
+        ManifestResult::Manifest(manifest) => update_with_manifest::<Resolver, Installer, _>(
+            directory,
+            manifest,
+            added_packages,
+            removed_packages,
+            options,
+        ),
         ManifestResult::ManifestError(e) => return Err(Error::ManifestError(e)),
     }
 }
 
+/// Computes the changes that `update` would make without downloading any packages or writing
+/// to the manifest or lockfile.
+pub fn plan_update<P: AsRef<Path>>(
+    added_packages: Vec<(&str, &str)>,
+    removed_packages: Vec<&str>,
+    directory: P,
+    options: &UpdateOptions,
+) -> Result<UpdatePlan, Error> {
+    plan_update_with::<RegistryResolver, P>(added_packages, removed_packages, directory, options)
+}
+
+/// Like `plan_update`, but resolves packages with the given `Resolve` implementation.
+pub fn plan_update_with<Resolver, P>(
+    added_packages: Vec<(&str, &str)>,
+    removed_packages: Vec<&str>,
+    directory: P,
+    options: &UpdateOptions,
+) -> Result<UpdatePlan, Error>
+where
+    Resolver: for<'a> Resolve<'a>,
+    P: AsRef<Path>,
+{
+    let added_packages =
+        AddedPackages::new_from_str_pairs(added_packages).map_err(Error::AddError)?;
+    let removed_packages = RemovedPackages::new_from_package_names(removed_packages);
+    UpdatePlan::new::<Resolver, _>(directory, added_packages, removed_packages, options)
+}
+
 /// Updates the manifest and saves it
 pub fn update_manifest(
     manifest: Manifest,
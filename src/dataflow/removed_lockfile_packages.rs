@@ -21,8 +21,8 @@ pub struct RemovedLockfilePackages<'a> {
 
 impl<'a> RemovedLockfilePackages<'a> {
     pub fn from_manifest_and_lockfile(
-        manifest_packages: &'a ManifestPackages<'a>,
-        lockfile_packages: &'a LockfilePackages<'a>,
+        manifest_packages: &ManifestPackages<'a>,
+        lockfile_packages: &LockfilePackages<'a>,
     ) -> Self {
         // collect all removed packages
         let old_package_keys: HashSet<_> = lockfile_packages.packages.keys().cloned().collect();
@@ -39,8 +39,8 @@ impl<'a> RemovedLockfilePackages<'a> {
     }
 
     pub fn from_removed_packages_and_lockfile(
-        removed_packages: &'a RemovedPackages<'a>,
-        lockfile_packages: &'a LockfilePackages<'a>,
+        removed_packages: &RemovedPackages<'a>,
+        lockfile_packages: &LockfilePackages<'a>,
     ) -> Self {
         let packages = removed_packages
             .packages
@@ -1,13 +1,17 @@
+use crate::config::Config;
 use crate::constants::*;
+use crate::data::metadata_cache::{MetadataCache, PublishedVersion};
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::{PackageKey, WapmPackageKey, WapmPackageRange};
 use crate::graphql::{execute_query, DateTime};
 use crate::keys;
+use crate::progress;
 use graphql_client::*;
 use semver::Version;
 use std::borrow::Cow::Owned;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 #[derive(GraphQLQuery)]
@@ -76,23 +80,11 @@ pub trait Resolve<'a> {
 
 pub struct RegistryResolver;
 
-impl<'a> RegistryResolver {
-    fn get_response(added_pkgs: Vec<PackageKey<'a>>) -> get_packages_query::ResponseData {
-        let names = added_pkgs
-            .into_iter()
-            .map(|key| match key {
-                PackageKey::WapmPackageRange(WapmPackageRange { name, .. }) => name.to_string(),
-                PackageKey::WapmPackage(WapmPackageKey { name, .. }) => name.to_string(),
-            })
-            .collect();
-        let q = GetPackagesQuery::build_query(get_packages_query::Variables { names });
-        execute_query(&q).unwrap()
-    }
-}
+/// A resolver that never talks to the registry. Exact versions resolve to themselves without a
+/// download URL, and version ranges can't be resolved.
+pub struct OfflineResolver;
 
-/// The Registry Resolver will resolve dependencies on a wapm.io server
-impl<'a> Resolve<'a> for RegistryResolver {
-    /// This gross function queries the GraphQL server. See the schema in `/graphql/queries/get_packages.graphql`
+impl<'a> Resolve<'a> for OfflineResolver {
     fn sync_packages(
         added_packages: Vec<PackageKey<'a>>,
     ) -> Result<
@@ -102,14 +94,66 @@ impl<'a> Resolve<'a> for RegistryResolver {
         )>,
         Error,
     > {
+        resolve_each(added_packages, |key| {
+            match key {
+            PackageKey::WapmPackage(key) => Ok(Some((key, (String::new(), None)))),
+            PackageKey::WapmPackageRange(WapmPackageRange { name, version_req }) => {
+                Err(Error::CouldNotResolvePackages(format!(
+                    "the version requirement {} for package {} can't be resolved without network access",
+                    version_req, name
+                )))
+            }
+        }
+        })
+    }
+}
+
+/// A package key with its download URL and signature
+pub type ResolvedPackage<'a> = (
+    WapmPackageKey<'a>,
+    (String, Option<keys::WapmPackageSignature>),
+);
+
+/// Resolve the packages one at a time with `resolve`, reporting each of them to the progress
+/// sink as it is resolved. Packages that `resolve` can't find are left out.
+///
+/// Implementations of [`Resolve`] use this so that progress is reported per package.
+pub fn resolve_each<'a, F>(
+    added_packages: Vec<PackageKey<'a>>,
+    mut resolve: F,
+) -> Result<Vec<ResolvedPackage<'a>>, Error>
+where
+    F: FnMut(PackageKey<'a>) -> Result<Option<ResolvedPackage<'a>>, Error>,
+{
+    let mut resolved = vec![];
+    for key in added_packages {
+        match &key {
+            PackageKey::WapmPackage(WapmPackageKey { name, version }) => {
+                progress::report(|sink| sink.on_resolving(name, &version.to_string()))
+            }
+            PackageKey::WapmPackageRange(WapmPackageRange { name, version_req }) => {
+                progress::report(|sink| sink.on_resolving(name, &version_req.to_string()))
+            }
+        }
+        if let Some((key, data)) = resolve(key)? {
+            progress::report(|sink| sink.on_resolved(&key.name, &key.version));
+            resolved.push((key, data));
+        }
+    }
+    Ok(resolved)
+}
+
+impl RegistryResolver {
+    fn get_response(names: Vec<String>) -> Result<get_packages_query::ResponseData, Error> {
+        let q = GetPackagesQuery::build_query(get_packages_query::Variables { names });
+        execute_query(&q).map_err(|e| Error::CouldNotResolvePackages(e.to_string()))
+    }
+
+    /// Query the registry for the published versions of the packages in `names`
+    fn fetch_published_versions(names: Vec<String>) -> Result<Vec<PublishedVersion>, Error> {
         // fetch data from graphql server
-        let response = Self::get_response(added_packages.clone());
-        let all_packages_and_download_urls: Vec<(
-            String,
-            Version,
-            String,
-            Option<keys::WapmPackageSignature>,
-        )> = response
+        let response = Self::get_response(names)?;
+        response
             .package
             .into_iter()
             .filter_map(|p| p)
@@ -150,81 +194,196 @@ impl<'a> Resolve<'a> for RegistryResolver {
                     .map(|version| (name, version, download_url, signature))
                     .map_err(|e| Error::CouldNotResolvePackages(e.to_string()))
             })
-            .collect::<Result<Vec<(_, _, _, _)>, Error>>()?;
-
-        // lookup by exact package key
-        let exact_package_lookup: HashMap<_, _> = all_packages_and_download_urls
-            .iter()
-            .cloned()
-            .map(|(name, version, download_url, signature)| {
-                (
-                    WapmPackageKey {
-                        name: Owned(name),
-                        version,
-                    },
-                    (download_url, signature),
-                )
-            })
-            .collect();
+            .collect()
+    }
 
-        // lookup versions by name, used for matching package version ranges
-        let mut package_versions_lookup: HashMap<String, Vec<Version>> = HashMap::new();
-        for (name, version, _, _) in all_packages_and_download_urls {
-            let versions = package_versions_lookup.entry(name).or_default();
-            versions.push(version);
+    /// The published versions of the packages in `names`, using the metadata cache when it is
+    /// fresh enough, unless `refresh` is set
+    fn published_versions(
+        config: &Config,
+        names: Vec<String>,
+        refresh: bool,
+    ) -> Result<Vec<PublishedVersion>, Error> {
+        let registry_url = config
+            .registry
+            .get_url()
+            .map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
+        let cache_path = match Config::get_metadata_cache_file_path() {
+            Ok(cache_path) => cache_path,
+            Err(_) => return Self::fetch_published_versions(names),
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut cache = MetadataCache::open(&cache_path, registry_url);
+        let published = cache.published_versions(
+            names,
+            now,
+            config.registry.get_metadata_ttl(),
+            refresh,
+            Self::fetch_published_versions,
+        )?;
+        if let Err(e) = cache.save(&cache_path) {
+            debug!("Could not save the metadata cache: {}", e);
         }
+        Ok(published)
+    }
+}
 
-        // filter all the package-versions + download_urls by exact version or version range
-        let packages_and_download_urls: Vec<(
-            WapmPackageKey,
+/// The Registry Resolver will resolve dependencies on a wapm.io server
+impl<'a> Resolve<'a> for RegistryResolver {
+    /// This gross function queries the GraphQL server. See the schema in `/graphql/queries/get_packages.graphql`
+    /// The versions of all the packages are fetched with one query, and each package is reported
+    /// as its version is picked from the response.
+    fn sync_packages(
+        added_packages: Vec<PackageKey<'a>>,
+    ) -> Result<
+        Vec<(
+            WapmPackageKey<'a>,
             (String, Option<keys::WapmPackageSignature>),
-        )> = added_packages
-            .into_iter()
-            .filter_map(|added_package| match added_package {
-                // if exact, then use the lookup table
-                PackageKey::WapmPackage(wapm_package_key) => exact_package_lookup
-                    .get(&wapm_package_key)
-                    .map(|(d, s)| (wapm_package_key, (d.clone(), s.clone()))),
-                // if a range, then filter by the requirements, and find the max version
-                PackageKey::WapmPackageRange(range) => {
-                    let matching_version: Option<Version> = package_versions_lookup
-                        .get(range.name.as_ref())
-                        .and_then(|versions| {
-                            let max_version: Option<Version> = versions
-                                .iter()
-                                .cloned()
-                                .filter(|v| range.version_req.matches(v))
-                                .max(); // get the max version number after filtering by version requirement
-                            max_version
-                        });
-                    // join the key with the download url by using the package-key lookup table
-                    let key_and_data: Option<(
-                        WapmPackageKey,
-                        (String, Option<keys::WapmPackageSignature>),
-                    )> = matching_version.and_then(|version| {
-                        let key = WapmPackageKey {
-                            name: range.name,
-                            version,
-                        };
-                        let data = exact_package_lookup.get(&key);
-                        data.cloned()
-                            .map(|(dl_url, signature)| (key, (dl_url, signature)))
-                    });
-                    key_and_data
+        )>,
+        Error,
+    > {
+        let config =
+            Config::from_file().map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
+        let refresh = config.registry.command_line.refresh_metadata;
+        let names: Vec<String> = added_packages.iter().map(package_name).collect();
+        let mut published = Self::published_versions(&config, names.clone(), refresh)?;
+        let mut refreshed = refresh;
+        resolve_each(added_packages, |key| {
+            let name = package_name(&key);
+            select_published_version(key, refresh, |refresh| {
+                // an exact version that isn't cached refreshes all of the packages in one query
+                if refresh && !refreshed {
+                    published = Self::published_versions(&config, names.clone(), true)?;
+                    refreshed = true;
                 }
+                Ok(published
+                    .iter()
+                    .filter(|(published_name, ..)| *published_name == name)
+                    .cloned()
+                    .collect())
             })
-            .collect();
-        Ok(packages_and_download_urls)
+        })
+    }
+}
+
+fn package_name(key: &PackageKey) -> String {
+    match key {
+        PackageKey::WapmPackageRange(WapmPackageRange { name, .. }) => name.to_string(),
+        PackageKey::WapmPackage(WapmPackageKey { name, .. }) => name.to_string(),
+    }
+}
+
+/// Select the version of `key` from the versions that `published` returns. `published` is told
+/// whether to skip the metadata cache, which it is when `refresh` is set or when `key` is an
+/// exact version that isn't cached: it may have been published after the cache was filled.
+fn select_published_version<'a, F>(
+    key: PackageKey<'a>,
+    refresh: bool,
+    mut published: F,
+) -> Result<Option<ResolvedPackage<'a>>, Error>
+where
+    F: FnMut(bool) -> Result<Vec<PublishedVersion>, Error>,
+{
+    let exact = matches!(key, PackageKey::WapmPackage(_));
+    match select_versions(vec![key.clone()], published(refresh)?).pop() {
+        None if exact && !refresh => Ok(select_versions(vec![key], published(true)?).pop()),
+        selected => Ok(selected),
     }
 }
 
+/// Match the added packages against the published versions. Exact versions must have been
+/// published, and version ranges resolve to the highest published version they match.
+/// Packages without a match are left out.
+fn select_versions<'a>(
+    added_packages: Vec<PackageKey<'a>>,
+    all_packages_and_download_urls: Vec<PublishedVersion>,
+) -> Vec<(
+    WapmPackageKey<'a>,
+    (String, Option<keys::WapmPackageSignature>),
+)> {
+    // lookup by exact package key
+    let exact_package_lookup: HashMap<_, _> = all_packages_and_download_urls
+        .iter()
+        .cloned()
+        .map(|(name, version, download_url, signature)| {
+            (
+                WapmPackageKey {
+                    name: Owned(name),
+                    version,
+                },
+                (download_url, signature),
+            )
+        })
+        .collect();
+
+    // lookup versions by name, used for matching package version ranges
+    let mut package_versions_lookup: HashMap<String, Vec<Version>> = HashMap::new();
+    for (name, version, _, _) in all_packages_and_download_urls {
+        let versions = package_versions_lookup.entry(name).or_default();
+        versions.push(version);
+    }
+
+    // filter all the package-versions + download_urls by exact version or version range
+    let packages_and_download_urls: Vec<(
+        WapmPackageKey,
+        (String, Option<keys::WapmPackageSignature>),
+    )> = added_packages
+        .into_iter()
+        .filter_map(|added_package| match added_package {
+            // if exact, then use the lookup table
+            PackageKey::WapmPackage(wapm_package_key) => exact_package_lookup
+                .get(&wapm_package_key)
+                .map(|(d, s)| (wapm_package_key, (d.clone(), s.clone()))),
+            // if a range, then filter by the requirements, and find the max version
+            PackageKey::WapmPackageRange(range) => {
+                let matching_version: Option<Version> = package_versions_lookup
+                    .get(range.name.as_ref())
+                    .and_then(|versions| {
+                        let max_version: Option<Version> = versions
+                            .iter()
+                            .filter(|v| range.version_req.matches(v))
+                            .max()
+                            .cloned(); // get the max version number after filtering by version requirement
+                        max_version
+                    });
+                // join the key with the download url by using the package-key lookup table
+                let key_and_data: Option<(
+                    WapmPackageKey,
+                    (String, Option<keys::WapmPackageSignature>),
+                )> = matching_version.and_then(|version| {
+                    let key = WapmPackageKey {
+                        name: range.name,
+                        version,
+                    };
+                    let data = exact_package_lookup.get(&key);
+                    data.cloned()
+                        .map(|(dl_url, signature)| (key, (dl_url, signature)))
+                });
+                key_and_data
+            }
+        })
+        .collect();
+    packages_and_download_urls
+}
+
 #[cfg(test)]
 mod test {
     use crate::dataflow::added_packages::AddedPackages;
-    use crate::dataflow::resolved_packages::{Error, Resolve, ResolvedPackages};
-    use crate::dataflow::{PackageKey, WapmPackageKey, WapmPackageRange};
+    use crate::dataflow::resolved_packages::{
+        select_published_version, select_versions, Error, OfflineResolver, Resolve,
+        ResolvedPackages,
+    };
+    use crate::dataflow::{
+        detect_duplicate_packages, PackageKey, WapmPackageKey, WapmPackageRange,
+    };
+    use crate::fixtures::RecordingSink;
     use crate::keys;
+    use crate::progress::set_progress_sink;
     use std::collections::HashSet;
+    use std::rc::Rc;
 
     struct TestResolver;
 
@@ -332,4 +491,135 @@ mod test {
             .find(|(p, _s)| p.name == "_/foo")
             .unwrap();
     }
+
+    #[test]
+    fn every_package_reports_its_resolution() {
+        let sink = Rc::new(RecordingSink::default());
+        let previous = set_progress_sink(Some(sink.clone()));
+
+        let added_packages =
+            AddedPackages::new_from_str_pairs(vec![("_/foo", "1.0.0"), ("_/qux", "2.0.0")])
+                .unwrap();
+        ResolvedPackages::new_from_added_packages::<OfflineResolver>(added_packages).unwrap();
+        // each package is resolved before the next one is looked up
+        let mut events: Vec<_> = sink
+            .events
+            .replace(vec![])
+            .chunks(2)
+            .map(|pair| pair.join(", "))
+            .collect();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                "resolving _/foo@1.0.0, resolved _/foo@1.0.0",
+                "resolving _/qux@2.0.0, resolved _/qux@2.0.0",
+            ]
+        );
+
+        let missing = AddedPackages::new_from_str_pairs(vec![("_/baz", "*")]).unwrap();
+        ResolvedPackages::new_from_added_packages::<OfflineResolver>(missing).unwrap_err();
+        assert_eq!(sink.events.replace(vec![]), vec!["resolving _/baz@*"]);
+
+        let duplicates =
+            AddedPackages::new_from_str_pairs(vec![("_/foo", "1.0.0"), ("_/foo", "2.0.0")])
+                .unwrap();
+        assert!(detect_duplicate_packages(&duplicates.packages).is_err());
+        set_progress_sink(previous);
+        assert_eq!(
+            *sink.events.borrow(),
+            vec!["conflict: Attempting to install multiple versions of package _/foo (1.0.0 and 2.0.0)"]
+        );
+    }
+
+    #[test]
+    fn ranges_resolve_to_the_highest_matching_version() {
+        let published: Vec<_> = ["1.1.0", "1.2.0", "1.2.5", "1.3.0", "2.0.0"]
+            .iter()
+            .map(|version| {
+                (
+                    "namespace/foo".to_string(),
+                    semver::Version::parse(version).unwrap(),
+                    format!("https://registry/foo-{}.tar.gz", version),
+                    None,
+                )
+            })
+            .collect();
+        let resolve = |range: &str| {
+            let added = AddedPackages::new_from_str_pairs(vec![("namespace/foo", range)])
+                .unwrap()
+                .packages
+                .into_iter()
+                .collect();
+            select_versions(added, published.clone())
+                .into_iter()
+                .map(|(key, (url, _))| (key.version.to_string(), url))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            resolve("^1.2"),
+            vec![(
+                "1.3.0".to_string(),
+                "https://registry/foo-1.3.0.tar.gz".to_string()
+            )]
+        );
+        assert_eq!(resolve("~1.2.3")[0].0, "1.2.5");
+        assert_eq!(resolve(">=1.0, <2.0")[0].0, "1.3.0");
+        assert_eq!(resolve(">=1.0,<1.2")[0].0, "1.1.0");
+        assert_eq!(resolve("1.2.0")[0].0, "1.2.0");
+        assert!(resolve("^3").is_empty());
+        assert!(AddedPackages::new_from_str_pairs(vec![("namespace/foo", "latest")]).is_err());
+    }
+
+    #[test]
+    fn exact_versions_missing_from_the_cache_are_fetched_again() {
+        let published = |versions: &[&str]| -> Vec<_> {
+            versions
+                .iter()
+                .map(|version| {
+                    (
+                        "namespace/foo".to_string(),
+                        semver::Version::parse(version).unwrap(),
+                        format!("https://registry/foo-{}.tar.gz", version),
+                        None,
+                    )
+                })
+                .collect()
+        };
+        let key = |version: &'static str| {
+            AddedPackages::new_from_str_pairs(vec![("namespace/foo", version)])
+                .unwrap()
+                .packages
+                .into_iter()
+                .next()
+                .unwrap()
+        };
+        // the cache was filled before 1.1.0 was published
+        let lookups = std::cell::RefCell::new(vec![]);
+        let registry = |refresh: bool| {
+            lookups.borrow_mut().push(refresh);
+            Ok(match refresh {
+                true => published(&["1.0.0", "1.1.0"]),
+                false => published(&["1.0.0"]),
+            })
+        };
+
+        let selected = select_published_version(key("1.1.0"), false, registry).unwrap();
+        assert_eq!(selected.unwrap().0.version.to_string(), "1.1.0");
+        assert_eq!(*lookups.borrow(), vec![false, true]);
+
+        // cached exact versions and ranges don't skip the cache
+        lookups.borrow_mut().clear();
+        let selected = select_published_version(key("1.0.0"), false, registry).unwrap();
+        assert_eq!(selected.unwrap().0.version.to_string(), "1.0.0");
+        let selected = select_published_version(key("^2"), false, registry).unwrap();
+        assert!(selected.is_none());
+        assert_eq!(*lookups.borrow(), vec![false, false]);
+
+        // `--refresh` skips it from the start
+        lookups.borrow_mut().clear();
+        select_published_version(key("1.1.0"), true, registry).unwrap();
+        assert_eq!(*lookups.borrow(), vec![true]);
+    }
 }
@@ -10,7 +10,7 @@ pub struct RetainedLockfilePackages<'a> {
 
 impl<'a> RetainedLockfilePackages<'a> {
     pub fn from_manifest_and_lockfile(
-        manifest_packages: &'a ManifestPackages<'a>,
+        manifest_packages: &ManifestPackages<'a>,
         lockfile_packages: LockfilePackages<'a>,
     ) -> Self {
         let manifest_keys = manifest_packages.keys();
@@ -0,0 +1,306 @@
+use crate::data::manifest::{FeatureSelection, Manifest};
+use crate::dataflow::added_packages::AddedPackages;
+use crate::dataflow::changed_manifest_packages::ChangedManifestPackages;
+use crate::dataflow::local_package::LocalPackage;
+use crate::dataflow::lockfile_packages::{LockfilePackages, LockfileResult};
+use crate::dataflow::manifest_packages::{ManifestPackages, ManifestResult};
+use crate::dataflow::removed_lockfile_packages::RemovedLockfilePackages;
+use crate::dataflow::removed_packages::RemovedPackages;
+use crate::dataflow::resolved_packages::{Resolve, ResolvedPackages};
+use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
+use crate::dataflow::{
+    detect_duplicate_packages, Error, PackageKey, UpdateOptions, WapmPackageKey,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The changes that an update would apply to a directory, computed without downloading any
+/// packages or writing to the lockfile.
+#[derive(Clone, Debug, Default)]
+pub struct UpdatePlan {
+    /// Packages that would be downloaded from the registry.
+    pub to_download: Vec<WapmPackageKey<'static>>,
+    /// Packages that would be added to the lockfile.
+    pub added: Vec<WapmPackageKey<'static>>,
+    /// Packages that would be removed from the lockfile.
+    pub removed: Vec<WapmPackageKey<'static>>,
+}
+
+/// An update worked out from the manifest, the lockfile and the added and removed packages in a
+/// directory, before anything is downloaded, uninstalled or written. `UpdatePlan` summarizes it
+/// and `update_with` applies it.
+pub(crate) struct PendingUpdate<'a> {
+    /// The package keys of the lockfile before the update
+    pub initial_package_keys: HashSet<PackageKey<'a>>,
+    /// The packages of the lockfile before the update
+    pub locked_packages: LockfilePackages<'a>,
+    /// Packages to uninstall
+    pub removed: RemovedLockfilePackages<'a>,
+    /// Packages kept in the lockfile as they are
+    pub retained: RetainedLockfilePackages<'a>,
+    /// Packages to download and install
+    pub resolved: ResolvedPackages<'a>,
+    /// The modules and commands of the manifest's own package, if they go in the lockfile
+    pub local_package: Option<LockfilePackages<'a>>,
+    /// The manifest features whose optional dependencies are installed
+    pub features: FeatureSelection,
+}
+
+impl<'a> PendingUpdate<'a> {
+    /// Without a manifest, the added packages are installed next to the ones in the lockfile.
+    pub fn with_no_manifest<Resolver: Resolve<'a>>(
+        directory: &Path,
+        added_packages: AddedPackages<'a>,
+        removed_packages: RemovedPackages<'a>,
+    ) -> Result<Self, Error> {
+        let lockfile_result = LockfileResult::find_in_directory(directory);
+        let mut lockfile_packages =
+            LockfilePackages::new_from_result(lockfile_result).map_err(Error::LockfileError)?;
+        detect_duplicate_packages(&added_packages.packages)?;
+        let initial_package_keys = lockfile_packages.package_keys();
+        let locked_packages = lockfile_packages.clone();
+
+        let removed = RemovedLockfilePackages::from_removed_packages_and_lockfile(
+            &removed_packages,
+            &lockfile_packages,
+        );
+        lockfile_packages.remove_packages(removed_packages);
+
+        // check that the added packages are not already installed
+        let added_packages =
+            added_packages.prune_already_installed_packages(lockfile_packages.package_keys());
+        // check for missing packages e.g. deleting stuff from wapm_packages
+        let missing_packages = lockfile_packages.find_missing_packages(directory);
+        let added_packages = added_packages.add_missing_packages(missing_packages);
+
+        let resolved = ResolvedPackages::new_from_added_packages::<Resolver>(added_packages)
+            .map_err(Error::ResolveError)?;
+
+        Ok(Self {
+            initial_package_keys,
+            locked_packages,
+            removed,
+            retained: RetainedLockfilePackages::from_lockfile_packages(lockfile_packages),
+            resolved,
+            local_package: None,
+            features: FeatureSelection::default(),
+        })
+    }
+
+    /// With a manifest, the lockfile is brought in line with the manifest's dependencies and the
+    /// added and removed packages.
+    pub fn with_manifest<Resolver: Resolve<'a>>(
+        directory: &Path,
+        manifest: &'a Manifest,
+        added_packages: &AddedPackages<'a>,
+        removed_packages: &RemovedPackages<'a>,
+        options: &UpdateOptions,
+    ) -> Result<Self, Error> {
+        let lockfile_result = LockfileResult::find_in_directory(directory);
+        let features = match (&options.features, &lockfile_result) {
+            (Some(features), _) => features.clone(),
+            (None, LockfileResult::Lockfile(lockfile)) => {
+                lockfile.features.clone().unwrap_or_default().into()
+            }
+            (None, _) => FeatureSelection::default(),
+        };
+
+        let mut manifest_packages = ManifestPackages::new_from_manifest_and_added_packages(
+            manifest,
+            added_packages,
+            &features,
+        )
+        .map_err(Error::ManifestError)?;
+        detect_duplicate_packages(&manifest_packages.packages)?;
+        manifest_packages.remove_packages(removed_packages);
+
+        let lockfile_packages =
+            LockfilePackages::new_from_result(lockfile_result).map_err(Error::LockfileError)?;
+        let initial_package_keys = lockfile_packages.package_keys();
+        let locked_packages = lockfile_packages.clone();
+
+        let local_package = LocalPackage::new_from_local_package_in_manifest(manifest)
+            .map_err(Error::LocalPackageError)?;
+
+        let changed_manifest_data =
+            ChangedManifestPackages::get_changed_packages_from_manifest_and_lockfile(
+                &manifest_packages,
+                &lockfile_packages,
+            );
+        let packages_to_install = AddedPackages {
+            packages: changed_manifest_data.packages,
+        };
+        let missing_lockfile_packages = lockfile_packages.find_missing_packages(directory);
+        let packages_to_install =
+            packages_to_install.add_missing_packages(missing_lockfile_packages);
+
+        let removed = RemovedLockfilePackages::from_manifest_and_lockfile(
+            &manifest_packages,
+            &lockfile_packages,
+        );
+        let retained = RetainedLockfilePackages::from_manifest_and_lockfile(
+            &manifest_packages,
+            lockfile_packages,
+        );
+
+        let resolved = ResolvedPackages::new_from_added_packages::<Resolver>(packages_to_install)
+            .map_err(Error::ResolveError)?;
+
+        Ok(Self {
+            initial_package_keys,
+            locked_packages,
+            removed,
+            retained,
+            resolved,
+            local_package: Some(local_package.into()).filter(|_| options.include_local_package),
+            features,
+        })
+    }
+}
+
+impl UpdatePlan {
+    /// Work out the update of the lockfile in `directory` with the added and removed packages,
+    /// and summarize it without downloading or writing anything.
+    pub fn new<'a, Resolver: for<'b> Resolve<'b>, P: AsRef<Path>>(
+        directory: P,
+        added_packages: AddedPackages<'a>,
+        removed_packages: RemovedPackages<'a>,
+        options: &UpdateOptions,
+    ) -> Result<Self, Error> {
+        let directory = directory.as_ref();
+        match ManifestResult::find_in_directory(directory) {
+            ManifestResult::NoManifest => {
+                Ok(Self::from(&PendingUpdate::with_no_manifest::<Resolver>(
+                    directory,
+                    added_packages,
+                    removed_packages,
+                )?))
+            }
+            ManifestResult::Manifest(manifest) => {
+                Ok(Self::from(&PendingUpdate::with_manifest::<Resolver>(
+                    directory,
+                    &manifest,
+                    &added_packages,
+                    &removed_packages,
+                    options,
+                )?))
+            }
+            ManifestResult::ManifestError(e) => Err(Error::ManifestError(e)),
+        }
+    }
+
+    /// Compute the lockfile diff the same way `MergedLockfilePackages::merge` does: resolved
+    /// packages replace retained packages of the same name.
+    fn from_keys(
+        initial_package_keys: &HashSet<PackageKey>,
+        retained_package_keys: HashSet<PackageKey>,
+        resolved_packages: &ResolvedPackages,
+    ) -> Self {
+        let to_download: Vec<WapmPackageKey<'static>> = resolved_packages
+            .packages
+            .iter()
+            .map(|(key, _)| into_owned_key(key))
+            .collect();
+
+        let mut final_keys: Vec<WapmPackageKey<'static>> = retained_package_keys
+            .iter()
+            .filter_map(as_wapm_package_key)
+            .filter(|key| !to_download.iter().any(|d| d.name == key.name))
+            .map(into_owned_key)
+            .collect();
+        final_keys.extend(to_download.iter().cloned());
+
+        let initial_keys: Vec<WapmPackageKey<'static>> = initial_package_keys
+            .iter()
+            .filter_map(as_wapm_package_key)
+            .map(into_owned_key)
+            .collect();
+
+        let mut added: Vec<_> = final_keys
+            .iter()
+            .filter(|key| !initial_keys.contains(key))
+            .cloned()
+            .collect();
+        let mut removed: Vec<_> = initial_keys
+            .into_iter()
+            .filter(|key| !final_keys.contains(key))
+            .collect();
+        let mut to_download = to_download;
+
+        // sort so that output is stable
+        to_download.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        added.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        removed.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+        Self {
+            to_download,
+            added,
+            removed,
+        }
+    }
+
+    /// Returns true if applying the plan would not change anything.
+    pub fn is_empty(&self) -> bool {
+        self.to_download.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<'a> From<&PendingUpdate<'a>> for UpdatePlan {
+    fn from(update: &PendingUpdate<'a>) -> Self {
+        let mut retained_package_keys: HashSet<_> =
+            update.retained.packages.keys().cloned().collect();
+        if let Some(ref local_package) = update.local_package {
+            retained_package_keys.extend(local_package.package_keys());
+        }
+        Self::from_keys(
+            &update.initial_package_keys,
+            retained_package_keys,
+            &update.resolved,
+        )
+    }
+}
+
+fn as_wapm_package_key<'a, 'b>(key: &'b PackageKey<'a>) -> Option<&'b WapmPackageKey<'a>> {
+    match key {
+        PackageKey::WapmPackage(k) => Some(k),
+        _ => None,
+    }
+}
+
+fn into_owned_key(key: &WapmPackageKey) -> WapmPackageKey<'static> {
+    WapmPackageKey {
+        name: Cow::Owned(key.name.to_string()),
+        version: key.version.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dataflow::added_packages::AddedPackages;
+    use crate::dataflow::removed_packages::RemovedPackages;
+    use crate::dataflow::update_plan::UpdatePlan;
+    use crate::dataflow::UpdateOptions;
+    use crate::fixtures::TestResolver;
+
+    #[test]
+    fn plan_does_not_write_anything() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let added_packages =
+            AddedPackages::new_from_str_pairs(vec![("foo", "1.2.3"), ("bar/baz", "1.2.3")])
+                .unwrap();
+        let plan = UpdatePlan::new::<TestResolver, _>(
+            tmp_dir.path(),
+            added_packages,
+            RemovedPackages::default(),
+            &UpdateOptions::default(),
+        )
+        .unwrap();
+
+        let added: Vec<String> = plan.added.iter().map(|k| k.to_string()).collect();
+        assert_eq!(added, vec!["_/foo 1.2.3", "bar/baz 1.2.3"]);
+        assert_eq!(plan.to_download, plan.added);
+        assert!(plan.removed.is_empty());
+        assert_eq!(0, std::fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+}
@@ -0,0 +1,108 @@
+//! The exit codes of the `wapm` binary.
+//!
+//! | Code | Meaning                                              |
+//! |------|------------------------------------------------------|
+//! | 0    | Success                                              |
+//! | 1    | Any error not covered below                          |
+//! | 2    | Usage error, e.g. an unknown flag or missing argument |
+//! | 3    | The registry could not be reached or is unavailable  |
+//! | 4    | The registry rejected the credentials                |
+
+use crate::commands::install::InstallError;
+use crate::graphql::GraphQLError;
+
+/// The kinds of errors that have their own exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Generic,
+    Usage,
+    Network,
+    Auth,
+}
+
+impl ErrorKind {
+    /// Classify an error by the errors in its chain of causes.
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<structopt::clap::Error>() {
+                return ErrorKind::Usage;
+            }
+            if let Some(InstallError::MustSupplyPackagesWithGlobalFlag) =
+                cause.downcast_ref::<InstallError>()
+            {
+                return ErrorKind::Usage;
+            }
+            if let Some(GraphQLError::Unauthorized(_)) = cause.downcast_ref::<GraphQLError>() {
+                return ErrorKind::Auth;
+            }
+            if let Some(GraphQLError::Timeout { .. } | GraphQLError::Maintenance { .. }) =
+                cause.downcast_ref::<GraphQLError>()
+            {
+                return ErrorKind::Network;
+            }
+            #[cfg(not(target_os = "wasi"))]
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                let unavailable = e.status().map_or(false, |status| status.is_server_error());
+                if e.is_connect() || e.is_timeout() || unavailable {
+                    return ErrorKind::Network;
+                }
+            }
+        }
+        ErrorKind::Generic
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Generic => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Network => 3,
+            ErrorKind::Auth => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::InstallOpt;
+    use crate::exit_code::ErrorKind;
+    use crate::graphql::GraphQLError;
+    use structopt::StructOpt;
+
+    #[test]
+    fn auth_errors_use_the_auth_exit_code() {
+        let error = anyhow::Error::from(GraphQLError::Unauthorized("401 Unauthorized".into()))
+            .context("Could not publish the package");
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Auth);
+        assert_eq!(ErrorKind::of(&error).exit_code(), 4);
+    }
+
+    #[test]
+    fn usage_errors_use_the_usage_exit_code() {
+        let error = InstallOpt::from_iter_safe(&["install", "--no-such-flag"]).unwrap_err();
+        let error = anyhow::Error::from(error);
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Usage);
+        assert_eq!(ErrorKind::of(&error).exit_code(), 2);
+
+        let error =
+            crate::commands::install(InstallOpt::from_iter(&["install", "--global"])).unwrap_err();
+        assert_eq!(ErrorKind::of(&error).exit_code(), 2);
+
+        assert_eq!(
+            ErrorKind::of(&anyhow!("something went wrong")).exit_code(),
+            1
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "wasi"))]
+    fn only_unreachable_registries_use_the_network_exit_code() {
+        let client = reqwest::blocking::Client::new();
+        // nothing listens on port 1
+        let error =
+            anyhow::Error::from(client.get("http://127.0.0.1:1/graphql").send().unwrap_err());
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Network);
+
+        let error = anyhow::Error::from(client.get("not a url").send().unwrap_err());
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Generic);
+    }
+}
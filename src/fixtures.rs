@@ -0,0 +1,220 @@
+//! Resolvers, installers and progress sinks shared by the tests, so that they can install
+//! packages without a registry.
+
+use crate::data::manifest::PACKAGES_DIR_NAME;
+use crate::dataflow::installed_packages::{Error as InstallError, Install};
+use crate::dataflow::resolved_packages::{resolve_each, Error as ResolveError, Resolve};
+use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::keys::WapmPackageSignature;
+use crate::progress::ProgressSink;
+use semver::Version;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(crate) struct TestResolver;
+
+/// Resolves every package to the version it was requested with. Version ranges are not resolved.
+impl<'a> Resolve<'a> for TestResolver {
+    fn sync_packages(
+        added_packages: Vec<PackageKey<'a>>,
+    ) -> Result<Vec<(WapmPackageKey<'a>, (String, Option<WapmPackageSignature>))>, ResolveError>
+    {
+        resolve_each(added_packages, |key| match key {
+            PackageKey::WapmPackage(key) => Ok(Some((key, ("url".to_string(), None)))),
+            PackageKey::WapmPackageRange(_) => Ok(None),
+        })
+    }
+}
+
+/// Create the directory that `key` is installed into, like the registry installer would
+fn package_directory(directory: &Path, key: &WapmPackageKey) -> PathBuf {
+    let (namespace, name) = key.name.split_once('/').unwrap();
+    let package_dir = directory
+        .join(PACKAGES_DIR_NAME)
+        .join(namespace)
+        .join(format!("{}@{}", name, key.version));
+    fs::create_dir_all(&package_dir).unwrap();
+    package_dir
+}
+
+pub(crate) struct TestInstaller;
+
+/// Writes a package with a single `hello` module and command instead of downloading one.
+impl<'a> Install<'a> for TestInstaller {
+    fn install_package(
+        directory: &Path,
+        key: WapmPackageKey<'a>,
+        download_url: &str,
+        #[cfg(feature = "full")] _signature: Option<WapmPackageSignature>,
+        _force_insecure_install: bool,
+    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), InstallError> {
+        let package_dir = package_directory(directory, &key);
+        fs::write(package_dir.join("hello.wasm"), b"\0asm").unwrap();
+        let manifest = format!(
+            r#"
+[package]
+name = "{}"
+version = "{}"
+description = "test package"
+
+[[module]]
+name = "hello"
+source = "hello.wasm"
+abi = "wasi"
+
+[[command]]
+name = "hello"
+module = "hello"
+"#,
+            key.name, key.version
+        );
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+        Ok((key, package_dir, download_url.to_string()))
+    }
+}
+
+pub(crate) struct DescribedInstaller;
+
+/// Writes a package described as "Tools for <name>" with a `<name>-cli` command instead of
+/// downloading one.
+impl<'a> Install<'a> for DescribedInstaller {
+    fn install_package(
+        directory: &Path,
+        key: WapmPackageKey<'a>,
+        download_url: &str,
+        #[cfg(feature = "full")] _signature: Option<WapmPackageSignature>,
+        _force_insecure_install: bool,
+    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), InstallError> {
+        let package_dir = package_directory(directory, &key);
+        let short_name = key.name.split_once('/').unwrap().1.to_string();
+        fs::write(package_dir.join("main.wasm"), b"\0asm").unwrap();
+        let manifest = format!(
+            r#"
+[package]
+name = "{name}"
+version = "{version}"
+description = "Tools for {short_name}"
+
+[[module]]
+name = "main"
+source = "main.wasm"
+abi = "wasi"
+
+[[command]]
+name = "{short_name}-cli"
+module = "main"
+"#,
+            name = key.name,
+            version = key.version,
+            short_name = short_name
+        );
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+        Ok((key, package_dir, download_url.to_string()))
+    }
+}
+
+pub(crate) struct VariantInstaller;
+
+/// Writes a package with a variant of its module per platform instead of downloading one.
+impl<'a> Install<'a> for VariantInstaller {
+    fn install_package(
+        directory: &Path,
+        key: WapmPackageKey<'a>,
+        download_url: &str,
+        #[cfg(feature = "full")] _signature: Option<WapmPackageSignature>,
+        _force_insecure_install: bool,
+    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), InstallError> {
+        let package_dir = package_directory(directory, &key);
+        let mut manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\ndescription = \"test package\"\n",
+            key.name, key.version
+        );
+        for (platform, arch) in &[("linux", "x86_64"), ("windows", "x86_64")] {
+            let source = format!("hello-{}-{}.wasm", platform, arch);
+            fs::write(package_dir.join(&source), b"\0asm").unwrap();
+            manifest.push_str(&format!(
+                "\n[[module]]\nname = \"hello\"\nsource = \"{}\"\nplatform = \"{}\"\narch = \"{}\"\n",
+                source, platform, arch
+            ));
+        }
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+        Ok((key, package_dir, download_url.to_string()))
+    }
+}
+
+pub(crate) struct ExportsInstaller;
+
+/// Writes a package whose module exports `add` before version 2 and nothing from then on.
+impl<'a> Install<'a> for ExportsInstaller {
+    fn install_package(
+        directory: &Path,
+        key: WapmPackageKey<'a>,
+        download_url: &str,
+        #[cfg(feature = "full")] _signature: Option<WapmPackageSignature>,
+        _force_insecure_install: bool,
+    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), InstallError> {
+        let package_dir = package_directory(directory, &key);
+        let module = if key.version.major < 2 {
+            r#"(module (func (export "add") (param i32 i32) (result i32) local.get 0))"#
+        } else {
+            "(module)"
+        };
+        fs::write(
+            package_dir.join("math.wasm"),
+            wat::parse_str(module).unwrap(),
+        )
+        .unwrap();
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\ndescription = \"test package\"\n\n[[module]]\nname = \"math\"\nsource = \"math.wasm\"\n",
+            key.name, key.version
+        );
+        fs::write(package_dir.join("wapm.toml"), manifest).unwrap();
+        Ok((key, package_dir, download_url.to_string()))
+    }
+}
+
+/// Records the progress events it receives, except for download progress
+#[derive(Default)]
+pub(crate) struct RecordingSink {
+    pub(crate) events: RefCell<Vec<String>>,
+}
+
+impl ProgressSink for RecordingSink {
+    fn on_resolving(&self, name: &str, requirement: &str) {
+        self.events
+            .borrow_mut()
+            .push(format!("resolving {}@{}", name, requirement));
+    }
+
+    fn on_resolved(&self, name: &str, version: &Version) {
+        self.events
+            .borrow_mut()
+            .push(format!("resolved {}@{}", name, version));
+    }
+
+    fn on_resolve_conflict(&self, description: &str) {
+        self.events
+            .borrow_mut()
+            .push(format!("conflict: {}", description));
+    }
+
+    fn on_download_start(&self, name: &str, version: &Version, download_url: &str) {
+        self.events.borrow_mut().push(format!(
+            "download {}@{} from {}",
+            name, version, download_url
+        ));
+    }
+
+    fn on_installed(&self, name: &str, version: &Version) {
+        self.events
+            .borrow_mut()
+            .push(format!("installed {}@{}", name, version));
+    }
+
+    fn on_published(&self, name: &str, version: &Version) {
+        self.events
+            .borrow_mut()
+            .push(format!("published {}@{}", name, version));
+    }
+}
@@ -79,8 +79,13 @@ pub fn init(dir: PathBuf, force_yes: bool) -> anyhow::Result<()> {
         Manifest::find_in_directory(dir)?
     } else {
         Manifest {
+            wapm_version: None,
             base_directory_path: dir.clone(),
             fs: None,
+            workspace: None,
+            command_aliases: None,
+            features: None,
+            dependency_aliases: None,
             package: Package {
                 name: dir
                     .clone()
@@ -99,6 +104,8 @@ pub fn init(dir: PathBuf, force_yes: bool) -> anyhow::Result<()> {
                 readme: None,
                 disable_command_rename: false,
                 rename_commands_to_raw_command_name: false,
+                default_command: None,
+                source: None,
             },
             dependencies: None,
             module: Some(vec![Module {
@@ -106,8 +113,11 @@ pub fn init(dir: PathBuf, force_yes: bool) -> anyhow::Result<()> {
                 source: "entry.wasm".into(),
                 abi: Abi::default(),
                 interfaces: None,
+                interface_file: None,
                 kind: None,
                 bindings: None,
+                platform: None,
+                arch: None,
             }]),
             command: None,
         }
@@ -158,8 +168,11 @@ Press ^C at any time to quit."
                         source: PathBuf::from("none"),
                         abi: Abi::default(),
                         interfaces: None,
+                        interface_file: None,
                         kind: None,
                         bindings: None,
+                        platform: None,
+                        arch: None,
                     }
                 }
             };
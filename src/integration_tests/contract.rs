@@ -0,0 +1,78 @@
+#![cfg(test)]
+
+use crate::commands::contract::{merge, show, validate};
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/integration_tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn show_prints_the_interface_of_a_module_or_an_interface_file() {
+    assert_eq!(
+        show(&fixture("logger.wat"), false).unwrap(),
+        "Imports:
+  env
+    log  func (i32)->()
+Exports:
+  run  func (i32)->(i32)
+"
+    );
+    assert_eq!(
+        show(&fixture("logger.interface"), false).unwrap(),
+        "Interface logger
+Imports:
+  env
+    log  func (i32)->()
+Exports:
+  run  func (i32)->(i32)
+"
+    );
+}
+
+#[test]
+fn validate_checks_a_module_against_an_interface() {
+    let tmp_dir = tempfile::TempDir::new().unwrap();
+    let wasm = tmp_dir.path().join("logger.wasm");
+    std::fs::write(&wasm, wat::parse_file(fixture("logger.wat")).unwrap()).unwrap();
+
+    let message = validate(&wasm, &fixture("logger.interface")).unwrap();
+    assert!(message.contains("satisfies"), "{}", message);
+
+    let error = validate(&wasm, &fixture("clock.interface")).unwrap_err();
+    assert!(error.to_string().contains("does not satisfy"), "{}", error);
+}
+
+#[test]
+fn merge_prints_the_merged_interface_or_the_conflict() {
+    assert_eq!(
+        merge(
+            &fixture("logger.interface"),
+            &fixture("clock.interface"),
+            false
+        )
+        .unwrap(),
+        "Interface logger
+Imports:
+  env
+    log  func (i32)->()
+    now  func ()->(i64)
+Exports:
+  run  func (i32)->(i32)
+"
+    );
+
+    let error = merge(
+        &fixture("logger.interface"),
+        &fixture("conflicting.interface"),
+        false,
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("the export \"run\""),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,7 @@
+(module
+  (import "env" "log" (func $log (param i32)))
+  (func (export "run") (param i32) (result i32)
+    local.get 0
+    call $log
+    local.get 0)
+  (memory (export "memory") 1))
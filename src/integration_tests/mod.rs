@@ -1,5 +1,6 @@
 //! An experimental way to write integration tests
 
 mod add_remove_init;
+mod contract;
 pub mod data;
 pub mod prelude;
@@ -49,7 +49,7 @@ pub struct WapmPublicKey {
 }
 
 /// Information about a package signature downloaded from the registry
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WapmPackageSignature {
     pub public_key_id: String,
     pub public_key: String,
@@ -19,6 +19,7 @@ extern crate toml;
 pub mod integration_tests;
 
 mod abi;
+pub mod api;
 pub mod commands;
 mod config;
 mod constants;
@@ -26,14 +27,19 @@ pub mod data;
 #[cfg(feature = "full")]
 mod database;
 mod dataflow;
+pub mod exit_code;
+#[cfg(test)]
+mod fixtures;
 mod graphql;
 mod init;
 #[cfg(feature = "full")]
 mod interfaces;
 mod keys;
 pub mod logging;
+pub mod progress;
 #[cfg(not(target_os = "wasi"))]
 mod proxy;
+pub mod registry;
 mod sql;
 #[cfg(feature = "update-notifications")]
 pub mod update_notifier;
@@ -90,7 +90,9 @@ pub fn set_up_logging(count_lines: bool) -> anyhow::Result<()> {
                 // stdout
                 .chain(
                     fern::Dispatch::new()
-                        .filter(|metadata| metadata.level() == log::LevelFilter::Info)
+                        .filter(|metadata| {
+                            metadata.level() == log::LevelFilter::Info && !util::is_quiet()
+                        })
                         .chain(std::io::stdout()),
                 )
                 // stderr
@@ -0,0 +1,103 @@
+//! Progress events for installs and publishes, so that embedders can observe them without
+//! parsing what wapm prints.
+//!
+//! The sink is set per thread with [`set_progress_sink`]; the `wapm` binary sets
+//! [`ConsoleProgress`].
+
+use semver::Version;
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+/// Receives progress events. Every method does nothing by default.
+pub trait ProgressSink {
+    /// The registry is about to be asked for a version of the package matching `requirement`
+    fn on_resolving(&self, _name: &str, _requirement: &str) {}
+    /// A package was resolved to `version`
+    fn on_resolved(&self, _name: &str, _version: &Version) {}
+    /// A conflict or dependency cycle was found while resolving, and the update will fail with it
+    fn on_resolve_conflict(&self, _description: &str) {}
+    /// The package is about to be downloaded from `download_url`
+    fn on_download_start(&self, _name: &str, _version: &Version, _download_url: &str) {}
+    /// `downloaded` bytes of the package's archive are on disk
+    fn on_download_progress(&self, _name: &str, _downloaded: u64) {}
+    /// The package was extracted into the packages directory
+    fn on_installed(&self, _name: &str, _version: &Version) {}
+    /// The package was uploaded to the registry
+    fn on_published(&self, _name: &str, _version: &Version) {}
+}
+
+thread_local! {
+    static SINK: RefCell<Option<Rc<dyn ProgressSink>>> = RefCell::new(None);
+}
+
+/// Send the progress events of this thread to `sink`, returning the previous sink
+pub fn set_progress_sink(sink: Option<Rc<dyn ProgressSink>>) -> Option<Rc<dyn ProgressSink>> {
+    SINK.with(|current| current.replace(sink))
+}
+
+/// Call `event` with the sink of this thread, if there is one
+pub(crate) fn report<F: FnOnce(&dyn ProgressSink)>(event: F) {
+    // the sink is cloned so that it can set another sink while handling the event
+    if let Some(sink) = SINK.with(|current| current.borrow().clone()) {
+        event(sink.as_ref());
+    }
+}
+
+/// Logs the progress events, used by the `wapm` binary
+pub struct ConsoleProgress;
+
+impl ProgressSink for ConsoleProgress {
+    fn on_resolving(&self, name: &str, requirement: &str) {
+        trace!("Resolving {}@{}", name, requirement);
+    }
+
+    fn on_resolved(&self, name: &str, version: &Version) {
+        debug!("Resolved {}@{}", name, version);
+    }
+
+    fn on_resolve_conflict(&self, description: &str) {
+        debug!("{}", description);
+    }
+
+    fn on_download_start(&self, name: &str, version: &Version, _download_url: &str) {
+        info!("Installing {}@{}", name, version);
+    }
+
+    fn on_download_progress(&self, name: &str, downloaded: u64) {
+        trace!("Downloaded {} bytes of {}", downloaded, name);
+    }
+
+    fn on_published(&self, name: &str, version: &Version) {
+        debug!("Published {}@{}", name, version);
+    }
+}
+
+/// Reports the bytes read from a package archive as download progress
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    name: String,
+    downloaded: u64,
+}
+
+impl<R> ProgressReader<R> {
+    /// Report reads from `inner`, which continues a download of `name` at byte `offset`
+    pub(crate) fn new(inner: R, name: &str, offset: u64) -> Self {
+        ProgressReader {
+            inner,
+            name: name.to_string(),
+            downloaded: offset,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.downloaded += read as u64;
+            report(|sink| sink.on_download_progress(&self.name, self.downloaded));
+        }
+        Ok(read)
+    }
+}
@@ -0,0 +1,1201 @@
+//! A client for the wapm registry's GraphQL API.
+//!
+//! Every request to the registry goes through a [`RegistryClient`], which takes care of the
+//! registry URL, authentication, timeouts, rate limits and retrying requests that failed before
+//! reaching the registry or that the registry turned away because of its rate limit or
+//! maintenance.
+
+use crate::config::{default_user_agent, Config};
+use crate::graphql::{DateTime, GraphQLError};
+use graphql_client::{GraphQLQuery, QueryBody, Response};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+#[cfg(not(target_os = "wasi"))]
+use {
+    crate::proxy,
+    reqwest::{
+        blocking::{multipart::Form, Client},
+        header::{RETRY_AFTER, USER_AGENT},
+        StatusCode,
+    },
+};
+#[cfg(not(target_os = "wasi"))]
+use {
+    serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    std::sync::mpsc::{channel, Receiver, Sender},
+};
+#[cfg(target_os = "wasi")]
+use {wasm_bus_reqwest::prelude::header::*, wasm_bus_reqwest::prelude::*};
+
+/// A response of the registry, before its body has been read
+#[cfg(not(target_os = "wasi"))]
+type HttpResponse = reqwest::blocking::Response;
+#[cfg(target_os = "wasi")]
+type HttpResponse = wasm_bus_reqwest::prelude::Response;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_package_version.graphql",
+    response_derives = "Debug"
+)]
+struct GetPackageVersionQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/search.graphql",
+    response_derives = "Debug"
+)]
+struct SearchQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/whoami.graphql",
+    response_derives = "Debug"
+)]
+struct WhoAmIQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/publish_package.graphql",
+    response_derives = "Debug"
+)]
+pub struct PublishPackageMutation;
+
+/// The number of times a request is retried when it fails before reaching the registry
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// The longest `Retry-After` delay that is waited for before retrying a rate limited request
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// A package version the registry resolved a name and optional version to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// A package version matching a search query
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub description: String,
+    /// The date the version was published, as `YYYY-MM-DD`
+    pub date: String,
+    pub version: String,
+    /// The total number of downloads of the package, if the registry reported it
+    pub downloads: Option<i64>,
+}
+
+/// The search results of a [`RegistryClient::search_stream`], in the order the registry
+/// returned them
+#[cfg(not(target_os = "wasi"))]
+pub struct SearchStream {
+    results: Receiver<anyhow::Result<SearchResult>>,
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl Iterator for SearchStream {
+    type Item = anyhow::Result<SearchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.recv().ok()
+    }
+}
+
+/// A package version that was published
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishedPackage {
+    pub version: Option<String>,
+}
+
+/// Sends requests to a registry and deserializes its responses
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    graphql_url: String,
+    token: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    user_agent: String,
+    /// The shortest time between two requests, from `with_rate_limit`
+    min_request_interval: Option<Duration>,
+    /// When the last request was sent, shared between clones of the client
+    last_request: Arc<Mutex<Option<Instant>>>,
+    /// Waits out retry delays and the rate limit
+    sleep: fn(Duration),
+    #[cfg(not(target_os = "wasi"))]
+    proxy: Option<reqwest::Proxy>,
+}
+
+impl RegistryClient {
+    /// A client for the registry at `registry_url`, without a token or proxy
+    pub fn new(registry_url: &str) -> Self {
+        let registry = crate::config::Registry {
+            url: registry_url.to_string(),
+            token: None,
+            timeout: None,
+            user_agent: None,
+            metadata_ttl: None,
+            no_default: None,
+            command_line: Default::default(),
+        };
+        RegistryClient {
+            graphql_url: registry.get_graphql_url(),
+            token: None,
+            timeout: Duration::from_secs(crate::config::DEFAULT_REGISTRY_TIMEOUT_SECS),
+            retries: DEFAULT_RETRIES,
+            user_agent: default_user_agent(),
+            min_request_interval: None,
+            last_request: Arc::default(),
+            sleep: std::thread::sleep,
+            #[cfg(not(target_os = "wasi"))]
+            proxy: None,
+        }
+    }
+
+    /// A client for the registry, token, timeout and proxy in the config
+    pub fn from_config() -> anyhow::Result<Self> {
+        Self::from_config_with_token_file(None)
+    }
+
+    /// Like `from_config`, but authenticated with the token in `token_file` if one is given
+    pub fn from_config_with_token_file(token_file: Option<&Path>) -> anyhow::Result<Self> {
+        let config = Config::from_file()?;
+        config.registry.check_not_default()?;
+        Ok(RegistryClient {
+            graphql_url: config.registry.get_graphql_url(),
+            token: config.registry.get_token(token_file)?,
+            timeout: config.registry.get_timeout(),
+            retries: DEFAULT_RETRIES,
+            user_agent: config.registry.get_user_agent(),
+            min_request_interval: None,
+            last_request: Arc::default(),
+            sleep: std::thread::sleep,
+            #[cfg(not(target_os = "wasi"))]
+            proxy: proxy::maybe_set_up_proxy()?,
+        })
+    }
+
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Send at most `requests_per_second` requests, waiting before a request when needed
+    pub fn with_rate_limit(mut self, requests_per_second: f64) -> Self {
+        self.min_request_interval = Some(Duration::from_secs_f64(1.0 / requests_per_second));
+        self
+    }
+
+    /// Wait with `sleep` instead of blocking the thread, so that tests don't have to wait
+    #[cfg(test)]
+    fn with_sleep(mut self, sleep: fn(Duration)) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Find the version of `name` matching `version`, or the latest version if no version is
+    /// given. Returns `None` if there is no such package version.
+    pub fn resolve_package(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<Option<ResolvedPackage>> {
+        let q = GetPackageVersionQuery::build_query(get_package_version_query::Variables {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        });
+        let response: get_package_version_query::ResponseData = self.execute(&q)?;
+        Ok(response.package_version.map(|pv| ResolvedPackage {
+            name: name.to_string(),
+            version: pv.version,
+        }))
+    }
+
+    /// Find the package versions matching `query`, most relevant first
+    pub fn search(&self, query: &str) -> anyhow::Result<Vec<SearchResult>> {
+        let q = SearchQuery::build_query(search_query::Variables {
+            query: query.to_string(),
+        });
+        let response: search_query::ResponseData = self.execute(&q)?;
+
+        let results = response
+            .search
+            .edges
+            .into_iter()
+            .filter_map(|edge| edge.and_then(|edge| edge.node))
+            .filter_map(search_result)
+            .collect();
+        Ok(results)
+    }
+
+    /// Like [`RegistryClient::search`], but yield each result as soon as it has been read from
+    /// the response instead of waiting for the whole response. Errors that happen before the
+    /// registry starts responding are returned right away, later ones are yielded last.
+    #[cfg(not(target_os = "wasi"))]
+    pub fn search_stream(&self, query: &str) -> anyhow::Result<SearchStream> {
+        let q = SearchQuery::build_query(search_query::Variables {
+            query: query.to_string(),
+        });
+        let res = self.send_query(&q, Ok)?;
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let mut deserializer =
+                serde_json::Deserializer::from_reader(std::io::BufReader::new(res));
+            let errors = SearchResponseSeed { results: &sender }
+                .deserialize(&mut deserializer)
+                .map_err(anyhow::Error::from);
+            let error = match errors {
+                Ok(Some(errors)) => GraphQLError::Error {
+                    message: errors
+                        .into_iter()
+                        .map(|err| err.message)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                }
+                .into(),
+                Ok(None) => return,
+                Err(e) => e,
+            };
+            // the stream may have been dropped already
+            let _ = sender.send(Err(error));
+        });
+        Ok(SearchStream { results: receiver })
+    }
+
+    /// Upload the package tarball at `archive_path` along with its metadata
+    pub fn publish(
+        &self,
+        package: publish_package_mutation::Variables,
+        archive_path: &Path,
+    ) -> anyhow::Result<PublishedPackage> {
+        let file_name = package
+            .file_name
+            .clone()
+            .unwrap_or_else(|| "package.tar.gz".to_string());
+        let q = PublishPackageMutation::build_query(package);
+        let response: publish_package_mutation::ResponseData =
+            self.execute_with_form(&q, |f| f.file(file_name.clone(), archive_path))?;
+        Ok(PublishedPackage {
+            version: response
+                .publish_package
+                .map(|published| published.package_version.version),
+        })
+    }
+
+    /// Upload several packages like [`RegistryClient::publish`], at most `jobs` at a time. The
+    /// rate limit applies to all of the uploads together. The results are in the order of
+    /// `packages`.
+    pub fn publish_all(
+        &self,
+        packages: Vec<(publish_package_mutation::Variables, PathBuf)>,
+        jobs: usize,
+    ) -> Vec<anyhow::Result<PublishedPackage>> {
+        if jobs <= 1 || cfg!(target_os = "wasi") {
+            return packages
+                .into_iter()
+                .map(|(package, archive_path)| self.publish(package, &archive_path))
+                .collect();
+        }
+        let count = packages.len();
+        let queue = Arc::new(Mutex::new(packages.into_iter().enumerate()));
+        let results = Arc::new(Mutex::new(Vec::with_capacity(count)));
+        // every worker gets its own clone of the client, which shares the rate limit
+        let workers: Vec<_> = (0..jobs.min(count))
+            .map(|_| {
+                let client = self.clone();
+                let queue = Arc::clone(&queue);
+                let results = Arc::clone(&results);
+                std::thread::spawn(move || loop {
+                    let next = queue.lock().unwrap().next();
+                    let (i, (package, archive_path)) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let published = client.publish(package, &archive_path);
+                    results.lock().unwrap().push((i, published));
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("a publishing thread panicked");
+        }
+        let mut results = std::mem::take(&mut *results.lock().unwrap());
+        results.sort_by_key(|(i, _)| *i);
+        results
+            .into_iter()
+            .map(|(_, published)| published)
+            .collect()
+    }
+
+    /// The name of the user the token belongs to, or `None` if not logged in
+    pub fn whoami(&self) -> anyhow::Result<Option<String>> {
+        let q = WhoAmIQuery::build_query(who_am_i_query::Variables {});
+        let response: who_am_i_query::ResponseData = self.execute(&q)?;
+        Ok(response.viewer.map(|viewer| viewer.username))
+    }
+
+    pub fn execute<R, V>(&self, query: &QueryBody<V>) -> anyhow::Result<R>
+    where
+        for<'de> R: serde::Deserialize<'de>,
+        V: serde::Serialize,
+    {
+        self.execute_with_form(query, Ok)
+    }
+
+    /// Send `query`, letting `form_modifier` add to the multipart form, e.g. to attach files.
+    /// The form is rebuilt for every attempt.
+    pub fn execute_with_form<R, V, F>(
+        &self,
+        query: &QueryBody<V>,
+        form_modifier: F,
+    ) -> anyhow::Result<R>
+    where
+        for<'de> R: serde::Deserialize<'de>,
+        V: serde::Serialize,
+        F: Fn(Form) -> std::io::Result<Form>,
+    {
+        let res = self.send_query(query, form_modifier)?;
+        let response_body: Response<R> = res
+            .json()
+            .map_err(|e| request_error(e, &self.graphql_url, self.timeout))?;
+        if let Some(errors) = response_body.errors {
+            let error_messages: Vec<String> = errors.into_iter().map(|err| err.message).collect();
+            return Err(GraphQLError::Error {
+                message: error_messages.join(", "),
+            }
+            .into());
+        }
+        Ok(response_body.data.expect("missing response data"))
+    }
+
+    /// Send `query` like [`RegistryClient::execute_with_form`], returning the response before
+    /// its body has been read
+    fn send_query<V, F>(
+        &self,
+        query: &QueryBody<V>,
+        form_modifier: F,
+    ) -> anyhow::Result<HttpResponse>
+    where
+        V: serde::Serialize,
+        F: Fn(Form) -> std::io::Result<Form>,
+    {
+        let client = self.client()?;
+        let vars = serde_json::to_string(&query.variables)?;
+        let mut attempt = 0;
+        let res = loop {
+            let form = Form::new()
+                .text("query", query.query.to_string())
+                .text("operationName", query.operation_name.to_string())
+                .text("variables", vars.clone());
+            let form = form_modifier(form)?;
+
+            self.wait_for_rate_limit();
+            let result = client
+                .post(&self.graphql_url)
+                .multipart(form)
+                .bearer_auth(self.token.clone().unwrap_or_default())
+                .header(USER_AGENT, self.user_agent.as_str())
+                .send();
+            attempt += 1;
+            if attempt <= self.retries {
+                if let Some(delay) = retry_delay(&result, attempt) {
+                    debug!(
+                        "Request to {} failed, retrying in {}ms ({}/{})",
+                        self.graphql_url,
+                        delay.as_millis(),
+                        attempt,
+                        self.retries
+                    );
+                    (self.sleep)(delay);
+                    continue;
+                }
+            }
+            break result.map_err(|e| request_error(e, &self.graphql_url, self.timeout))?;
+        };
+
+        #[cfg(not(target_os = "wasi"))]
+        if res.status() == StatusCode::UNAUTHORIZED || res.status() == StatusCode::FORBIDDEN {
+            return Err(GraphQLError::Unauthorized(res.status().to_string()).into());
+        }
+        #[cfg(not(target_os = "wasi"))]
+        if res.status() == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = retry_after_seconds(&res);
+            let body = res.text().unwrap_or_default();
+            return Err(GraphQLError::Maintenance {
+                message: error_envelope_message(&body)
+                    .unwrap_or_else(|| "the registry is temporarily unavailable".to_string()),
+                retry_after,
+            }
+            .into());
+        }
+        Ok(res)
+    }
+
+    /// Sleep until the rate limit allows another request
+    fn wait_for_rate_limit(&self) {
+        let min_request_interval = match self.min_request_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let mut last_request = self.last_request.lock().unwrap();
+        if let Some(last_request) = *last_request {
+            let elapsed = last_request.elapsed();
+            if elapsed < min_request_interval {
+                (self.sleep)(min_request_interval - elapsed);
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    fn client(&self) -> anyhow::Result<Client> {
+        let builder = Client::builder();
+
+        #[cfg(not(target_os = "wasi"))]
+        let builder = match self.proxy.clone() {
+            Some(proxy) => builder.proxy(proxy),
+            None => builder,
+        };
+        #[cfg(not(target_os = "wasi"))]
+        let builder = builder.timeout(self.timeout);
+        Ok(builder.build()?)
+    }
+}
+
+/// The search result for a node of the search response, if it is a package version
+fn search_result(node: search_query::SearchQuerySearchEdgesNode) -> Option<SearchResult> {
+    match node {
+        search_query::SearchQuerySearchEdgesNode::PackageVersion(version) => Some(SearchResult {
+            downloads: version.package.downloads_count,
+            name: version.package.display_name,
+            description: version.description,
+            date: version.created_at.chars().take(10).collect(),
+            version: version.version,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads a search response, sending the result of each edge under `data.search.edges` as soon
+/// as the edge has been parsed. Evaluates to the GraphQL errors of the response, if any.
+#[cfg(not(target_os = "wasi"))]
+struct SearchResponseSeed<'a> {
+    results: &'a Sender<anyhow::Result<SearchResult>>,
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl<'de, 'a> DeserializeSeed<'de> for SearchResponseSeed<'a> {
+    type Value = Option<Vec<graphql_client::Error>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl<'de, 'a> Visitor<'de> for SearchResponseSeed<'a> {
+    type Value = Option<Vec<graphql_client::Error>>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a GraphQL response")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut errors = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "data" => map.next_value_seed(SearchEdgesSeed {
+                    path: &["search", "edges"],
+                    results: self.results,
+                })?,
+                "errors" => errors = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(errors)
+    }
+}
+
+/// Follows `path` through nested objects to the list of search edges, see
+/// [`SearchResponseSeed`]. A `null` anywhere on the way has no results.
+#[cfg(not(target_os = "wasi"))]
+struct SearchEdgesSeed<'a> {
+    path: &'a [&'a str],
+    results: &'a Sender<anyhow::Result<SearchResult>>,
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl<'de, 'a> DeserializeSeed<'de> for SearchEdgesSeed<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl<'de, 'a> Visitor<'de> for SearchEdgesSeed<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.path.first() {
+            Some(field) => write!(f, "an object with a `{}` field", field),
+            None => write!(f, "a list of search edges"),
+        }
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match self.path.split_first() {
+                Some((field, path)) if key == *field => map.next_value_seed(SearchEdgesSeed {
+                    path,
+                    results: self.results,
+                })?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        if !self.path.is_empty() {
+            return Err(serde::de::Error::invalid_type(
+                serde::de::Unexpected::Seq,
+                &self,
+            ));
+        }
+        while let Some(edge) = seq.next_element::<Option<search_query::SearchQuerySearchEdges>>()? {
+            if let Some(result) = edge.and_then(|edge| edge.node).and_then(search_result) {
+                self.results
+                    .send(Ok(result))
+                    .map_err(|_| serde::de::Error::custom("the search stream was dropped"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a request failed in a way that retrying might fix: the registry couldn't be reached,
+/// or a gateway in front of it failed
+#[cfg(not(target_os = "wasi"))]
+fn should_retry(result: &reqwest::Result<reqwest::blocking::Response>) -> bool {
+    match result {
+        Ok(res) => matches!(
+            res.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(e) => e.is_connect(),
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn should_retry<T, E>(_result: &Result<T, E>) -> bool {
+    false
+}
+
+/// How long to wait before retrying the `attempt`th request, or `None` if it shouldn't be
+/// retried. A request the registry rate limited, or turned away while under maintenance, is
+/// retried after its `Retry-After` delay.
+#[cfg(not(target_os = "wasi"))]
+fn retry_delay(
+    result: &reqwest::Result<reqwest::blocking::Response>,
+    attempt: u32,
+) -> Option<Duration> {
+    let backoff = Duration::from_millis(100 * 2u64.pow(attempt));
+    match result {
+        Ok(res)
+            if matches!(
+                res.status(),
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+            ) =>
+        {
+            Some(
+                retry_after_seconds(res)
+                    .map(|seconds| Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+                    .unwrap_or(backoff),
+            )
+        }
+        _ if should_retry(result) => Some(backoff),
+        _ => None,
+    }
+}
+
+/// The number of seconds in the response's `Retry-After` header
+#[cfg(not(target_os = "wasi"))]
+fn retry_after_seconds(res: &reqwest::blocking::Response) -> Option<u64> {
+    res.headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+}
+
+/// The message in the registry's error envelope, either `{"message": ...}` or the GraphQL
+/// `{"errors": [{"message": ...}]}`, falling back to a plain text body
+#[cfg(not(target_os = "wasi"))]
+fn error_envelope_message(body: &str) -> Option<String> {
+    let message = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(envelope) => {
+            let messages: Vec<&str> = envelope
+                .get("errors")
+                .and_then(|errors| errors.as_array())
+                .map(|errors| {
+                    errors
+                        .iter()
+                        .filter_map(|error| error.get("message")?.as_str())
+                        .collect()
+                })
+                .or_else(|| Some(vec![envelope.get("message")?.as_str()?]))?;
+            messages.join(", ")
+        }
+        Err(_) => body.trim().to_string(),
+    };
+    Some(message).filter(|message| !message.is_empty())
+}
+
+#[cfg(target_os = "wasi")]
+fn retry_delay<T, E>(result: &Result<T, E>, attempt: u32) -> Option<Duration> {
+    Some(Duration::from_millis(100 * 2u64.pow(attempt))).filter(|_| should_retry(result))
+}
+
+/// Report requests that took longer than `timeout` as a [`GraphQLError::Timeout`] so they can be
+/// told apart from the registry being unreachable.
+#[cfg(not(target_os = "wasi"))]
+fn request_error(error: reqwest::Error, url: &str, timeout: Duration) -> anyhow::Error {
+    if error.is_timeout() {
+        GraphQLError::Timeout {
+            url: url.to_string(),
+            seconds: timeout.as_secs(),
+        }
+        .into()
+    } else {
+        error.into()
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn request_error<E>(error: E, _url: &str, _timeout: Duration) -> E {
+    error
+}
+
+#[cfg(all(test, not(target_os = "wasi")))]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Receiver};
+
+    /// Serve `responses` in order, one per connection, sending each request received back over
+    /// the channel
+    fn mock_registry(responses: Vec<(u16, &'static str)>) -> (RegistryClient, Receiver<String>) {
+        mock_registry_with_headers(
+            responses
+                .into_iter()
+                .map(|(status, body)| (status, "", body))
+                .collect(),
+        )
+    }
+
+    /// Like `mock_registry`, with extra header lines for each response
+    fn mock_registry_with_headers(
+        responses: Vec<(u16, &'static str, &'static str)>,
+    ) -> (RegistryClient, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = sender.send(read_request(&mut stream));
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let client = RegistryClient::new(&url)
+            .with_token(Some("secret-token".to_string()))
+            .with_timeout(Duration::from_secs(5));
+        (client, receiver)
+    }
+
+    /// Serve a single response whose body is sent in `chunks`, waiting for a message on `gate`
+    /// before sending each chunk after the first
+    fn mock_registry_chunked(chunks: Vec<&'static str>, gate: Receiver<()>) -> RegistryClient {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            stream
+                .write_all(b"HTTP/1.1 200 Mock\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                if i > 0 && gate.recv().is_err() {
+                    return;
+                }
+                let chunk = format!("{:x}\r\n{}\r\n", chunk.len(), chunk);
+                stream.write_all(chunk.as_bytes()).unwrap();
+                stream.flush().unwrap();
+            }
+            stream.write_all(b"0\r\n\r\n").unwrap();
+        });
+        RegistryClient::new(&url).with_timeout(Duration::from_secs(5))
+    }
+
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        let header_end = loop {
+            let read = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..read]);
+            if let Some(end) = find(&request, b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+        let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+        let content_length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|length| length.trim().parse::<usize>().unwrap());
+        loop {
+            let done = match content_length {
+                Some(length) => request.len() >= header_end + length,
+                None => request.ends_with(b"0\r\n\r\n"),
+            };
+            if done {
+                break;
+            }
+            let read = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..read]);
+        }
+        String::from_utf8_lossy(&request).to_string()
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+
+    fn graphql_error(error: &anyhow::Error) -> &GraphQLError {
+        error
+            .downcast_ref::<GraphQLError>()
+            .unwrap_or_else(|| panic!("not a GraphQLError: {}", error))
+    }
+
+    #[test]
+    fn resolve_package() {
+        let (client, requests) = mock_registry(vec![
+            (200, r#"{"data": {"packageVersion": {"version": "1.2.3"}}}"#),
+            (200, r#"{"data": {"packageVersion": null}}"#),
+        ]);
+
+        let resolved = client.resolve_package("namespace/pkg", Some("^1")).unwrap();
+        assert_eq!(
+            resolved,
+            Some(ResolvedPackage {
+                name: "namespace/pkg".to_string(),
+                version: "1.2.3".to_string(),
+            })
+        );
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("POST /graphql "), "{}", request);
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret-token"));
+        assert!(request.contains("GetPackageVersionQuery"));
+        assert!(request.contains(r#""version":"^1""#));
+
+        assert_eq!(
+            client.resolve_package("namespace/missing", None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn search() {
+        let (client, _requests) = mock_registry(vec![
+            (
+                200,
+                r#"{"data": {"search": {"edges": [
+                    {"node": {"__typename": "User"}},
+                    {"node": {
+                        "__typename": "PackageVersion",
+                        "package": {"displayName": "namespace/pkg", "downloadsCount": 42},
+                        "createdAt": "2021-03-04T05:06:07Z",
+                        "version": "0.1.0",
+                        "description": "a package"
+                    }}
+                ]}}}"#,
+            ),
+            (401, r#"{"errors": [{"message": "Unauthorized"}]}"#),
+        ]);
+
+        assert_eq!(
+            client.search("pkg").unwrap(),
+            vec![SearchResult {
+                name: "namespace/pkg".to_string(),
+                description: "a package".to_string(),
+                date: "2021-03-04".to_string(),
+                version: "0.1.0".to_string(),
+                downloads: Some(42),
+            }]
+        );
+
+        let error = client.search("pkg").unwrap_err();
+        assert!(matches!(
+            graphql_error(&error),
+            GraphQLError::Unauthorized(_)
+        ));
+    }
+
+    #[test]
+    fn search_results_are_streamed_as_they_arrive() {
+        let (gate, gate_receiver) = channel();
+        let client = mock_registry_chunked(
+            vec![
+                r#"{"data": {"search": {"edges": [
+                    {"node": {
+                        "__typename": "PackageVersion",
+                        "package": {"displayName": "namespace/first", "downloadsCount": 1},
+                        "createdAt": "2021-03-04T05:06:07Z",
+                        "version": "0.1.0",
+                        "description": "the first package"
+                    }}"#,
+                r#", {"node": {"__typename": "User"}}, {"node": {
+                        "__typename": "PackageVersion",
+                        "package": {"displayName": "namespace/second", "downloadsCount": null},
+                        "createdAt": "2022-01-02T03:04:05Z",
+                        "version": "2.0.0",
+                        "description": "the second package"
+                    }}"#,
+                "]}}}",
+            ],
+            gate_receiver,
+        );
+
+        let mut results = client.search_stream("pkg").unwrap();
+        // the first result is yielded before the rest of the response has been sent
+        assert_eq!(
+            results.next().unwrap().unwrap(),
+            SearchResult {
+                name: "namespace/first".to_string(),
+                description: "the first package".to_string(),
+                date: "2021-03-04".to_string(),
+                version: "0.1.0".to_string(),
+                downloads: Some(1),
+            }
+        );
+        gate.send(()).unwrap();
+        assert_eq!(
+            results.next().unwrap().unwrap(),
+            SearchResult {
+                name: "namespace/second".to_string(),
+                description: "the second package".to_string(),
+                date: "2022-01-02".to_string(),
+                version: "2.0.0".to_string(),
+                downloads: None,
+            }
+        );
+        gate.send(()).unwrap();
+        assert!(results.next().is_none());
+
+        let (client, _requests) = mock_registry(vec![(
+            200,
+            r#"{"data": null, "errors": [{"message": "search is disabled"}]}"#,
+        )]);
+        let errors: Vec<String> = client
+            .search_stream("pkg")
+            .unwrap()
+            .map(|result| result.unwrap_err().to_string())
+            .collect();
+        assert_eq!(errors, vec!["search is disabled"]);
+    }
+
+    fn package_variables(name: &str) -> publish_package_mutation::Variables {
+        publish_package_mutation::Variables {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: "a package".to_string(),
+            manifest: "".to_string(),
+            license: None,
+            license_file: None,
+            readme: None,
+            repository: None,
+            homepage: None,
+            file_name: Some("package.tar.gz".to_string()),
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn publish() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let archive_path = tmp_dir.path().join("package.tar.gz");
+        std::fs::write(&archive_path, "tarball contents").unwrap();
+        let variables = || package_variables("namespace/pkg");
+        let (client, requests) = mock_registry(vec![
+            (
+                200,
+                r#"{"data": {"publishPackage": {"success": true, "packageVersion": {"version": "1.0.0"}}}}"#,
+            ),
+            (
+                200,
+                r#"{"data": null, "errors": [{"message": "Version 1.0.0 already exists"}]}"#,
+            ),
+        ]);
+
+        let published = client.publish(variables(), &archive_path).unwrap();
+        assert_eq!(published.version.as_deref(), Some("1.0.0"));
+        let request = requests.recv().unwrap();
+        assert!(request.contains("PublishPackageMutation"));
+        assert!(request.contains("tarball contents"));
+
+        let error = client.publish(variables(), &archive_path).unwrap_err();
+        match graphql_error(&error) {
+            GraphQLError::Error { message } => assert_eq!(message, "Version 1.0.0 already exists"),
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn whoami() {
+        let (client, _requests) = mock_registry(vec![
+            (200, r#"{"data": {"viewer": {"username": "someone"}}}"#),
+            (200, r#"{"data": {"viewer": null}}"#),
+        ]);
+
+        assert_eq!(client.whoami().unwrap().as_deref(), Some("someone"));
+        assert_eq!(client.whoami().unwrap(), None);
+
+        // accept the connection but never respond
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _connection = listener.accept();
+            std::thread::sleep(Duration::from_secs(10));
+        });
+        let error = RegistryClient::new(&url)
+            .with_timeout(Duration::from_millis(200))
+            .whoami()
+            .unwrap_err();
+        assert!(matches!(
+            graphql_error(&error),
+            GraphQLError::Timeout { .. }
+        ));
+    }
+
+    #[test]
+    fn gateway_errors_are_retried() {
+        let (client, requests) = mock_registry(vec![
+            (502, "Bad Gateway"),
+            (200, r#"{"data": {"viewer": {"username": "someone"}}}"#),
+        ]);
+        assert_eq!(client.whoami().unwrap().as_deref(), Some("someone"));
+        assert_eq!(requests.iter().count(), 2);
+
+        let (client, _requests) = mock_registry(vec![(502, "Bad Gateway")]);
+        assert!(client.with_retries(0).whoami().is_err());
+
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let error = RegistryClient::new(&unreachable)
+            .with_retries(0)
+            .whoami()
+            .unwrap_err();
+        assert!(error.downcast_ref::<GraphQLError>().is_none(), "{}", error);
+        assert!(error.is::<reqwest::Error>());
+    }
+
+    #[test]
+    fn rate_limited_publishes_wait_for_retry_after() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let archive_path = tmp_dir.path().join("package.tar.gz");
+        std::fs::write(&archive_path, "tarball contents").unwrap();
+        let (client, requests) = mock_registry_with_headers(vec![
+            (429, "Retry-After: 1\r\n", "Too Many Requests"),
+            (
+                200,
+                "",
+                r#"{"data": {"publishPackage": {"success": true, "packageVersion": {"version": "1.0.0"}}}}"#,
+            ),
+        ]);
+        let variables = package_variables("namespace/pkg");
+
+        thread_local! {
+            static SLEPT: std::cell::RefCell<Vec<Duration>> = Default::default();
+        }
+        let client = client.with_sleep(|delay| SLEPT.with(|slept| slept.borrow_mut().push(delay)));
+        let published = client.publish(variables, &archive_path).unwrap();
+        assert_eq!(
+            SLEPT.with(|slept| slept.take()),
+            vec![Duration::from_secs(1)]
+        );
+        assert_eq!(published.version.as_deref(), Some("1.0.0"));
+        let requests: Vec<String> = requests.iter().collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("tarball contents"));
+
+        let (client, _requests) =
+            mock_registry_with_headers(vec![(429, "Retry-After: 0\r\n", "Too Many Requests")]);
+        assert!(client.with_retries(0).whoami().is_err());
+    }
+
+    #[test]
+    fn publish_all_uploads_at_most_jobs_packages_at_a_time() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        {
+            let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+            std::thread::spawn(move || {
+                for stream in listener.incoming().take(4) {
+                    let mut stream = stream.unwrap();
+                    let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+                    std::thread::spawn(move || {
+                        read_request(&mut stream);
+                        let uploading = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        most_in_flight.fetch_max(uploading, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(100));
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        let body = r#"{"data": {"publishPackage": {"success": true, "packageVersion": {"version": "1.0.0"}}}}"#;
+                        let response = format!(
+                            "HTTP/1.1 200 Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                        stream.write_all(response.as_bytes()).unwrap();
+                    });
+                }
+            });
+        }
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let packages = (0..4)
+            .map(|i| {
+                let archive_path = tmp_dir.path().join(format!("package-{}.tar.gz", i));
+                std::fs::write(&archive_path, "tarball contents").unwrap();
+                (
+                    package_variables(&format!("namespace/pkg-{}", i)),
+                    archive_path,
+                )
+            })
+            .collect();
+
+        let client = RegistryClient::new(&url).with_timeout(Duration::from_secs(5));
+        let published = client.publish_all(packages, 2);
+        assert_eq!(published.len(), 4);
+        assert!(published.iter().all(Result::is_ok));
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn maintenance_is_retried_and_reported() {
+        let maintenance = r#"{"errors": [{"message": "upgrading the database"}]}"#;
+        let (client, requests) = mock_registry_with_headers(vec![
+            (503, "Retry-After: 0\r\n", maintenance),
+            (200, "", r#"{"data": {"viewer": {"username": "someone"}}}"#),
+        ]);
+        assert_eq!(client.whoami().unwrap().as_deref(), Some("someone"));
+        assert_eq!(requests.iter().count(), 2);
+
+        let (client, requests) = mock_registry_with_headers(vec![
+            (503, "Retry-After: 0\r\n", maintenance),
+            (503, "Retry-After: 120\r\n", r#"{"message": "back soon"}"#),
+        ]);
+        let error = client.with_retries(1).whoami().unwrap_err();
+        assert_eq!(requests.iter().count(), 2);
+        assert!(matches!(
+            graphql_error(&error),
+            GraphQLError::Maintenance {
+                retry_after: Some(120),
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "registry is under maintenance: back soon (try again in 120 seconds)"
+        );
+
+        let (client, _requests) = mock_registry(vec![(503, "Service Unavailable")]);
+        assert_eq!(
+            client.with_retries(0).whoami().unwrap_err().to_string(),
+            "registry is under maintenance: Service Unavailable (try again later)"
+        );
+    }
+
+    #[test]
+    fn requests_are_spaced_out_by_the_rate_limit() {
+        let body = r#"{"data": {"viewer": {"username": "someone"}}}"#;
+        let (client, _requests) = mock_registry(vec![(200, body), (200, body), (200, body)]);
+        let client = client.with_rate_limit(10.0);
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            client.clone().whoami().unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn user_agent_defaults_to_the_cli_version_and_can_be_overridden() {
+        let body = r#"{"data": {"viewer": {"username": "someone"}}}"#;
+        let (client, requests) = mock_registry(vec![(200, body), (200, body)]);
+        let user_agent = |request: String| {
+            request
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    Some(value.trim().to_string())
+                        .filter(|_| name.eq_ignore_ascii_case("user-agent"))
+                })
+                .unwrap()
+        };
+
+        client.whoami().unwrap();
+        let default = user_agent(requests.recv().unwrap());
+        assert!(
+            default.starts_with(&format!("wapm-cli/{} ", env!("CARGO_PKG_VERSION"))),
+            "{}",
+            default
+        );
+
+        let client = client.with_user_agent("my-tool/1.0".to_string());
+        client.whoami().unwrap();
+        assert_eq!(user_agent(requests.recv().unwrap()), "my-tool/1.0");
+    }
+}
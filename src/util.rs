@@ -1,9 +1,9 @@
 use crate::constants::{DEFAULT_RUNTIME, WAPM_RUNTIME_ENV_KEY};
 use crate::data::manifest::PACKAGES_DIR_NAME;
-use crate::graphql::execute_query;
-use graphql_client::*;
+use crate::registry::RegistryClient;
 use license_exprs;
 use semver::Version;
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use std::{env, fs, io};
 use thiserror::Error;
@@ -67,18 +67,8 @@ pub fn validate_license(license: &str) -> Result<String, LicenseError> {
     }
 }
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/whoami.graphql",
-    response_derives = "Debug"
-)]
-struct WhoAmIQuery;
-
 pub fn get_username() -> anyhow::Result<Option<String>> {
-    let q = WhoAmIQuery::build_query(who_am_i_query::Variables {});
-    let response: who_am_i_query::ResponseData = execute_query(&q)?;
-    Ok(response.viewer.map(|viewer| viewer.username))
+    RegistryClient::from_config()?.whoami()
 }
 
 #[cfg(feature = "telemetry")]
@@ -188,6 +178,59 @@ pub fn set_wapm_should_accept_all_prompts(val: bool) -> Option<()> {
     guard.set(val)
 }
 
+/// The settings given with the global command line flags. They only apply to this process and
+/// are never saved to the config.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CommandLineSettings {
+    /// `--registry-timeout`, in seconds
+    pub registry_timeout: Option<u64>,
+    /// `--user-agent`
+    pub user_agent: Option<String>,
+    /// `--refresh`
+    pub refresh_metadata: bool,
+    /// `--quiet`
+    pub quiet: bool,
+    /// `--verbose`
+    pub verbose: bool,
+    /// `--no-default-registry`
+    pub no_default_registry: bool,
+}
+
+lazy_static! {
+    static ref COMMAND_LINE_SETTINGS: Mutex<Option<CommandLineSettings>> = Mutex::new(None);
+}
+
+/// Apply `settings` to the config loaded for the rest of this process, see `Config::from_file`
+pub fn set_command_line_settings(settings: CommandLineSettings) {
+    *COMMAND_LINE_SETTINGS.lock().unwrap() = Some(settings);
+}
+
+/// The settings given with `set_command_line_settings`
+pub fn command_line_settings() -> CommandLineSettings {
+    COMMAND_LINE_SETTINGS
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Whether `--quiet` was passed
+pub fn is_quiet() -> bool {
+    command_line_settings().quiet
+}
+
+/// Whether `--verbose` was passed
+pub fn is_verbose() -> bool {
+    command_line_settings().verbose
+}
+
+/// Print the outcome of a successful operation, unless `--quiet` was passed
+pub fn print_success(message: &str) {
+    if !is_quiet() {
+        println!("{}", message);
+    }
+}
+
 /// Asks the user to confirm something. Returns a boolean indicating if the user consented
 /// or if the `WAPM_FORCE_YES_TO_PROMPTS` variable is set
 pub fn prompt_user_for_yes(prompt: &str) -> anyhow::Result<bool> {
@@ -234,6 +277,34 @@ pub fn get_hashed_module_key(_path: &Path) -> Option<String> {
     None
 }
 
+/// The hex encoded SHA-256 of the canonical form of the interface the wasm module at `path`
+/// implies, or `None` if the module can't be read or parsed
+pub fn get_contract_hash(path: &Path) -> Option<String> {
+    get_module_interface(path).map(|interface| {
+        format!(
+            "{:x}",
+            Sha256::digest(interface.canonical_form().as_bytes())
+        )
+    })
+}
+
+/// The interface the wasm module at `path` implies, or `None` if the module can't be read or
+/// parsed
+pub fn get_module_interface(path: &Path) -> Option<wasmer_wasm_interface::Interface> {
+    let bytes = fs::read(path).ok()?;
+    match wasmer_wasm_interface::Interface::from_wasm(&bytes) {
+        Ok(interface) => Some(interface),
+        Err(e) => {
+            debug!(
+                "Could not read the interface of {}: {:?}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
 #[cfg(feature = "update-notifications")]
 pub fn get_latest_runtime_version(runtime: &str) -> Result<String, String> {
     use std::process::Command;
@@ -286,7 +357,7 @@ fn get_runtime() -> String {
 }
 
 /// Splits the runtime from the rest of arguments
-fn split_runtime_and_args(runtime: String) -> (String, Vec<String>) {
+pub fn split_runtime_and_args(runtime: String) -> (String, Vec<String>) {
     let runtime_split = runtime.split_whitespace();
     if let Some((split_runtime, split_runtime_args)) = runtime_split
         .map(|s| s.to_string())
@@ -329,10 +400,90 @@ pub fn whoami_distro() -> String {
     whoami::distro().to_lowercase()
 }
 
+/// Write `contents` to `path` so that readers see either the old file or the new one, never a
+/// partially written file: the contents go to a temporary file next to `path`, which then
+/// replaces it.
+pub fn write_file_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = write_temp_file(path, contents)?;
+    fs::rename(&temp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        e
+    })
+}
+
+/// Write `contents` to a temporary file in the same directory as `path`, so it can be renamed
+/// over `path`.
+fn write_temp_file(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let mut file = fs::File::create(&temp_path)?;
+    io::Write::write_all(&mut file, contents)?;
+    file.sync_all()?;
+    Ok(temp_path)
+}
+
+/// Match `text` against a shell-style `pattern` where `*` matches any run of characters and
+/// `?` matches a single character.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the position of the last `*` seen and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn glob_matching() {
+        assert!(glob_matches("foo", "foo"));
+        assert!(!glob_matches("foo", "foobar"));
+        assert!(glob_matches("foo*", "foobar"));
+        assert!(glob_matches("*bar", "foobar"));
+        assert!(glob_matches("f*o*r", "foobar"));
+        assert!(glob_matches("f??bar", "foobar"));
+        assert!(!glob_matches("f?bar", "foobar"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("?", ""));
+    }
+
+    #[test]
+    fn interrupted_writes_leave_the_original_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("wapm.lock");
+        fs::write(&path, "original").unwrap();
+
+        // interrupted after writing the temporary file, before the rename
+        let temp_path = write_temp_file(&path, b"replacement").unwrap();
+        assert_eq!(temp_path.parent(), path.parent());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+
+        write_file_atomically(&path, b"replacement").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "replacement");
+        assert!(!temp_path.exists());
+    }
+
     #[cfg(feature = "update-notifications")]
     #[test]
     pub fn compare_ver_test() {
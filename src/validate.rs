@@ -7,9 +7,13 @@ use crate::database;
 use crate::dataflow::{interfaces::InterfaceFromServer, manifest_packages::ManifestResult};
 #[cfg(feature = "full")]
 use crate::interfaces;
-use std::{fs, io::Read, path::PathBuf};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
-use wasmer_wasm_interface::{validate, Interface};
+use wasmer_wasm_interface::{parser, validate, Interface};
 
 #[cfg(feature = "full")]
 pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
@@ -39,6 +43,14 @@ pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
                 }
             })?;
 
+            if let Some(interface_file) = &module.interface_file {
+                validate_interface_file(
+                    &wasm_buffer,
+                    &source_path_string,
+                    &manifest.base_directory_path.join(interface_file),
+                )?;
+            }
+
             // hack, short circuit if no interface for now
             if module.interfaces.is_none() {
                 return validate_wasm_and_report_errors_old(
@@ -87,6 +99,30 @@ pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Check the module `wasm` against the interface in `interface_file`
+#[cfg(feature = "full")]
+fn validate_interface_file(
+    wasm: &[u8],
+    source_path_string: &str,
+    interface_file: &Path,
+) -> anyhow::Result<()> {
+    let interface = parser::parse_interface_file(interface_file)?;
+    interface.validate_module(wasm).map_err(|e| match e {
+        validate::WasmValidationError::InterfaceViolated { errors } => {
+            ValidationError::InterfaceNotSatisfied {
+                file: source_path_string.to_string(),
+                interface: interface_file.to_string_lossy().to_string(),
+                errors: errors.join("\n  - "),
+            }
+        }
+        e => ValidationError::InvalidWasm {
+            file: source_path_string.to_string(),
+            error: format!("{:?}", e),
+        },
+    })?;
+    Ok(())
+}
+
 #[cfg(not(feature = "full"))]
 pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
     Ok(())
@@ -102,6 +138,12 @@ pub enum ValidationError {
     MiscCannotRead { file: String, error: String },
     #[error("Failed to unpack archive \"{file}\"! {error}")]
     CannotUnpackArchive { file: String, error: String },
+    #[error("WASM file \"{file}\" does not satisfy the interface \"{interface}\":\n  - {errors}")]
+    InterfaceNotSatisfied {
+        file: String,
+        interface: String,
+        errors: String,
+    },
 }
 
 // legacy function, validates wasm.  TODO: clean up
@@ -3,6 +3,7 @@
 use semver::Version;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -58,6 +59,11 @@ impl Default for Abi {
 pub static MANIFEST_FILE_NAME: &str = "wapm.toml";
 pub static PACKAGES_DIR_NAME: &str = "wapm_packages";
 
+/// The version of the manifest format that this crate understands, as `(major, minor)`.
+/// Manifests with a newer minor version can still be read, minus the fields that were added
+/// since. A different major version can't be read at all.
+pub const MANIFEST_SCHEMA_VERSION: (u64, u64) = (1, 0);
+
 pub static README_PATHS: &[&str; 5] = &[
     "README",
     "README.md",
@@ -100,6 +106,18 @@ pub struct Package {
         skip_serializing_if = "std::ops::Not::not"
     )]
     pub rename_commands_to_raw_command_name: bool,
+    /// The command `wapm run` runs when no command name is given. Packages with a single
+    /// command run it by default.
+    #[serde(
+        rename = "default-command",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub default_command: Option<String>,
+    /// Patterns for the source files that `wapm publish --include-source` bundles under `src/`
+    /// in the package, in the same format as `.wapmignore`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -309,7 +327,22 @@ pub struct Module {
     pub fs: Option<toml::value::Table>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub interfaces: Option<HashMap<String, String>>,
+    /// A local interface file the module must satisfy, checked before publishing
+    #[serde(
+        rename = "interface-file",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interface_file: Option<PathBuf>,
     pub bindings: Option<Bindings>,
+    /// The operating system this variant of the module is built for, e.g. `linux`. Modules
+    /// with the same name but a different `platform` or `arch` are variants of each other,
+    /// and only the one matching the install target is installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// The CPU architecture this variant of the module is built for, e.g. `x86_64`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
 }
 
 /// The interface exposed by a [`Module`].
@@ -338,6 +371,150 @@ impl Bindings {
     }
 }
 
+/// The `workspace` section of a manifest.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Workspace {
+    /// Directories of the member packages, relative to the manifest. A path component may
+    /// contain `*` and `?` wildcards, e.g. `packages/*`.
+    pub members: Vec<String>,
+}
+
+/// A dependency as it is written in the `dependencies` section of a manifest: either a version,
+/// or a table like `mylib = { package = "owner/realpkg", version = "1.0" }` that depends on a
+/// package under a local alias.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+enum Dependency {
+    Version(String),
+    Renamed { package: String, version: String },
+}
+
+/// The features of a manifest that are enabled, see `Manifest::features`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureSelection {
+    /// Features enabled by name
+    pub features: Vec<String>,
+    /// Enable every feature
+    pub all_features: bool,
+    /// Enable the `default` feature
+    pub default_features: bool,
+}
+
+impl Default for FeatureSelection {
+    fn default() -> Self {
+        Self {
+            features: vec![],
+            all_features: false,
+            default_features: true,
+        }
+    }
+}
+
+/// The name of the feature that is enabled unless asked not to
+pub const DEFAULT_FEATURE: &str = "default";
+
+/// TOML needs the aliased dependencies, which are tables, after the plain versions
+fn serialize_dependencies<S: serde::Serializer>(
+    dependencies: &Option<HashMap<String, Dependency>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match dependencies {
+        Some(dependencies) => toml::ser::tables_last(dependencies, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A `Manifest` as it is written in `wapm.toml`, where a dependency listed under a local alias
+/// also names the package it resolves to
+#[derive(Deserialize, Serialize)]
+struct ManifestToml {
+    #[serde(rename = "wapm-version", skip_serializing_if = "Option::is_none")]
+    wapm_version: Option<String>,
+    package: Package,
+    #[serde(serialize_with = "serialize_dependencies")]
+    dependencies: Option<HashMap<String, Dependency>>,
+    module: Option<Vec<Module>>,
+    command: Option<Vec<Command>>,
+    fs: Option<HashMap<String, PathBuf>>,
+    workspace: Option<Workspace>,
+    #[serde(
+        rename = "command-aliases",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    command_aliases: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    features: Option<HashMap<String, Vec<String>>>,
+}
+
+impl TryFrom<ManifestToml> for Manifest {
+    type Error = String;
+
+    fn try_from(manifest: ManifestToml) -> Result<Self, Self::Error> {
+        let mut dependencies = HashMap::new();
+        let mut dependency_aliases = HashMap::new();
+        for (key, dependency) in manifest.dependencies.iter().flatten() {
+            let (package, version) = match dependency {
+                Dependency::Version(version) => (key, version),
+                Dependency::Renamed { package, version } => {
+                    dependency_aliases.insert(key.clone(), package.clone());
+                    (package, version)
+                }
+            };
+            if dependencies
+                .insert(package.clone(), version.clone())
+                .is_some()
+            {
+                return Err(format!("The dependency \"{}\" is listed twice", package));
+            }
+        }
+        Ok(Manifest {
+            wapm_version: manifest.wapm_version,
+            package: manifest.package,
+            dependencies: manifest.dependencies.map(|_| dependencies),
+            dependency_aliases: Some(dependency_aliases).filter(|aliases| !aliases.is_empty()),
+            module: manifest.module,
+            command: manifest.command,
+            fs: manifest.fs,
+            workspace: manifest.workspace,
+            command_aliases: manifest.command_aliases,
+            features: manifest.features,
+            base_directory_path: PathBuf::new(),
+        })
+    }
+}
+
+impl From<Manifest> for ManifestToml {
+    fn from(manifest: Manifest) -> Self {
+        let aliases: HashMap<String, String> = manifest
+            .dependency_aliases
+            .into_iter()
+            .flatten()
+            .map(|(alias, package)| (package, alias))
+            .collect();
+        let dependencies = manifest.dependencies.map(|dependencies| {
+            dependencies
+                .into_iter()
+                .map(|(package, version)| match aliases.get(&package) {
+                    Some(alias) => (alias.clone(), Dependency::Renamed { package, version }),
+                    None => (package, Dependency::Version(version)),
+                })
+                .collect()
+        });
+        ManifestToml {
+            wapm_version: manifest.wapm_version,
+            package: manifest.package,
+            dependencies,
+            module: manifest.module,
+            command: manifest.command,
+            fs: manifest.fs,
+            workspace: manifest.workspace,
+            command_aliases: manifest.command_aliases,
+            features: manifest.features,
+        }
+    }
+}
+
 /// The manifest represents the file used to describe a Wasm package.
 ///
 /// The `module` field represents the wasm file to be published.
@@ -348,16 +525,31 @@ impl Bindings {
 /// program relative to its starting current directory (there may be issues with WASI).
 /// These are pairs of paths.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "ManifestToml", into = "ManifestToml")]
 pub struct Manifest {
+    /// The version of the manifest format, e.g. `"1.0"`. Unversioned manifests are read as
+    /// `MANIFEST_SCHEMA_VERSION`.
+    pub wapm_version: Option<String>,
     pub package: Package,
+    /// Of the form package name -> version, including the dependencies listed under an alias
     pub dependencies: Option<HashMap<String, String>>,
+    /// Of the form local alias -> package name, for the dependencies listed under an alias like
+    /// `mylib = { package = "owner/realpkg", version = "1.0" }`
+    pub dependency_aliases: Option<HashMap<String, String>>,
     pub module: Option<Vec<Module>>,
     pub command: Option<Vec<Command>>,
     /// Of the form Guest -> Host path
     pub fs: Option<HashMap<String, PathBuf>>,
+    /// Other packages that are managed together with this one
+    pub workspace: Option<Workspace>,
+    /// Of the form dependency name -> the name its command is exposed under
+    pub command_aliases: Option<HashMap<String, String>>,
+    /// Of the form feature name -> the dependencies and other features it enables. A dependency
+    /// that a feature lists is only installed when the feature is enabled. The `default` feature
+    /// is enabled unless `FeatureSelection::default_features` is unset
+    pub features: Option<HashMap<String, Vec<String>>>,
     /// private data
     /// store the directory path of the manifest file for use later accessing relative path fields
-    #[serde(skip)]
     pub base_directory_path: PathBuf,
 }
 
@@ -413,11 +605,49 @@ impl Manifest {
         if manifest.package.license_file.is_none() {
             manifest.package.license_file = Self::locate_file(path.as_ref(), &LICENSE_PATHS[..]);
         }
+        if manifest.schema_compatibility()? == SchemaCompatibility::Newer {
+            log::warn!(
+                "{} declares wapm-version {}, which is newer than this version of wapm understands. Some fields may be ignored.",
+                manifest_path_buf.to_string_lossy(),
+                manifest.wapm_version.as_deref().unwrap_or_default(),
+            );
+        }
         manifest.validate()?;
         Ok(manifest)
     }
 
+    /// Check the declared `wapm-version` against `MANIFEST_SCHEMA_VERSION`.
+    pub fn schema_compatibility(&self) -> Result<SchemaCompatibility, ManifestError> {
+        let declared = match self.wapm_version {
+            Some(ref declared) => declared,
+            None => return Ok(SchemaCompatibility::Current),
+        };
+        let (major, minor) = parse_schema_version(declared)
+            .ok_or_else(|| ManifestError::InvalidSchemaVersion(declared.clone()))?;
+        let (current_major, current_minor) = MANIFEST_SCHEMA_VERSION;
+        if major != current_major {
+            Err(ManifestError::IncompatibleSchemaVersion(
+                declared.clone(),
+                current_major,
+            ))
+        } else if minor > current_minor {
+            Ok(SchemaCompatibility::Newer)
+        } else {
+            Ok(SchemaCompatibility::Current)
+        }
+    }
+
     pub fn validate(&self) -> Result<(), ManifestError> {
+        self.schema_compatibility()?;
+        match self.validation_errors().into_iter().next() {
+            Some(error) => Err(ManifestError::ValidationError(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem with the commands of the manifest, rather than only the first one like
+    /// [`Manifest::validate`]
+    pub fn validation_errors(&self) -> Vec<ValidationError> {
         let module_map = self
             .module
             .as_ref()
@@ -429,26 +659,57 @@ impl Manifest {
             })
             .unwrap_or_default();
 
+        let mut errors = vec![];
         if let Some(ref commands) = self.command {
             for command in commands {
                 if let Some(module) = module_map.get(&command.get_module()) {
                     if module.abi == Abi::None && module.interfaces.is_none() {
-                        return Err(ManifestError::ValidationError(ValidationError::MissingABI(
-                            command.get_name(),
+                        errors.push(ValidationError::MissingABI(
                             module.name.clone(),
-                        )));
+                            command.get_name(),
+                        ));
                     }
                 } else {
-                    return Err(ManifestError::ValidationError(
-                        ValidationError::MissingModuleForCommand(
-                            command.get_name(),
-                            command.get_module(),
-                        ),
+                    errors.push(ValidationError::MissingModuleForCommand(
+                        command.get_module(),
+                        command.get_name(),
                     ));
                 }
             }
         }
-        Ok(())
+        if let Some(ref default_command) = self.package.default_command {
+            let commands = self.command.as_deref().unwrap_or_default();
+            if !commands
+                .iter()
+                .any(|command| &command.get_name() == default_command)
+            {
+                errors.push(ValidationError::MissingDefaultCommand(
+                    default_command.clone(),
+                ));
+            }
+        }
+        errors
+    }
+
+    /// The command `wapm run` runs when no command name is given: the `default-command`, or
+    /// the only command if the package has exactly one.
+    pub fn default_command(&self) -> Option<String> {
+        if let Some(ref default_command) = self.package.default_command {
+            return Some(default_command.clone());
+        }
+        match self.command.as_deref() {
+            Some([command]) => Some(command.get_name()),
+            _ => None,
+        }
+    }
+
+    /// The names of the package's commands
+    pub fn command_names(&self) -> Vec<String> {
+        self.command
+            .iter()
+            .flatten()
+            .map(Command::get_name)
+            .collect()
     }
 
     /// add a dependency
@@ -457,10 +718,94 @@ impl Manifest {
         dependencies.insert(dependency_name, dependency_version);
     }
 
-    /// remove dependency by package name
+    /// remove dependency by package name or alias, along with its command alias
     pub fn remove_dependency(&mut self, dependency_name: &str) -> Option<String> {
+        let package = self
+            .dependency_alias(dependency_name)
+            .unwrap_or(dependency_name)
+            .to_string();
+        if let Some(aliases) = self.command_aliases.as_mut() {
+            aliases.remove(&package);
+        }
+        if let Some(aliases) = self.dependency_aliases.as_mut() {
+            aliases.retain(|_, aliased| *aliased != package);
+        }
         let dependencies = self.dependencies.get_or_insert(Default::default());
-        dependencies.remove(dependency_name)
+        dependencies.remove(&package)
+    }
+
+    /// The package the dependency listed under `alias` resolves to, if it is an alias
+    pub fn dependency_alias(&self, alias: &str) -> Option<&str> {
+        self.dependency_aliases
+            .as_ref()?
+            .get(alias)
+            .map(String::as_str)
+    }
+
+    /// The dependencies to install with the features in `selection` enabled, as package name
+    /// and version: those that no feature lists, and those listed by an enabled feature. A
+    /// feature can list a dependency by its package name or its alias.
+    pub fn selected_dependencies(
+        &self,
+        selection: &FeatureSelection,
+    ) -> Result<Vec<(&str, &str)>, ManifestError> {
+        let dependencies = match self.dependencies {
+            Some(ref dependencies) => dependencies,
+            None => return Ok(vec![]),
+        };
+        let empty = HashMap::new();
+        let features = self.features.as_ref().unwrap_or(&empty);
+
+        let mut pending: Vec<&str> = if selection.all_features {
+            features.keys().map(String::as_str).collect()
+        } else {
+            selection.features.iter().map(String::as_str).collect()
+        };
+        if selection.default_features && features.contains_key(DEFAULT_FEATURE) {
+            pending.push(DEFAULT_FEATURE);
+        }
+        let mut enabled = HashSet::new();
+        while let Some(feature) = pending.pop() {
+            if !enabled.insert(feature) {
+                continue;
+            }
+            let entries = features
+                .get(feature)
+                .ok_or_else(|| ManifestError::UnknownFeature(feature.to_string()))?;
+            for entry in entries {
+                if features.contains_key(entry) {
+                    pending.push(entry);
+                } else if !dependencies.contains_key(self.dependency_alias(entry).unwrap_or(entry))
+                {
+                    return Err(ManifestError::InvalidFeature(
+                        feature.to_string(),
+                        entry.to_string(),
+                    ));
+                }
+            }
+        }
+
+        let optional: HashSet<&str> = features
+            .values()
+            .flatten()
+            .map(|entry| self.dependency_alias(entry).unwrap_or(entry))
+            .collect();
+        let enabled_dependencies: HashSet<&str> = enabled
+            .iter()
+            .flat_map(|feature| &features[*feature])
+            .map(|entry| self.dependency_alias(entry).unwrap_or(entry))
+            .collect();
+        Ok(dependencies
+            .iter()
+            .map(|(name, version)| (name.as_str(), version.as_str()))
+            .filter(|(name, _)| !optional.contains(name) || enabled_dependencies.contains(name))
+            .collect())
+    }
+
+    /// expose the command of a dependency under `alias`
+    pub fn set_command_alias(&mut self, dependency_name: String, alias: String) {
+        let aliases = self.command_aliases.get_or_insert(Default::default());
+        aliases.insert(dependency_name, alias);
     }
 
     pub fn to_string(&self) -> anyhow::Result<String> {
@@ -510,6 +855,26 @@ impl Manifest {
     }
 }
 
+/// How the `wapm-version` a manifest declares relates to the version this crate understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaCompatibility {
+    /// The manifest is unversioned or uses the current format or an older minor version of it
+    Current,
+    /// The manifest uses a newer minor version; fields added since may be ignored
+    Newer,
+}
+
+/// Parse a `MAJOR.MINOR` schema version. A bare `MAJOR` is read as `MAJOR.0`.
+fn parse_schema_version(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.trim().splitn(2, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some((major, minor))
+}
+
 #[derive(Debug, Error)]
 pub enum ManifestError {
     #[error("Manifest file not found at {0}")]
@@ -524,6 +889,14 @@ pub enum ManifestError {
     SemVerError(String),
     #[error("There was an error validating the manifest: {0}")]
     ValidationError(ValidationError),
+    #[error("The manifest declares wapm-version \"{0}\", which is not of the form MAJOR.MINOR.")]
+    InvalidSchemaVersion(String),
+    #[error("The manifest declares wapm-version \"{0}\", but this version of wapm only supports version {1}.x. Try updating wapm.")]
+    IncompatibleSchemaVersion(String, u64),
+    #[error("The manifest has no feature \"{0}\".")]
+    UnknownFeature(String),
+    #[error("Feature \"{0}\" enables \"{1}\", which is neither a feature nor a dependency.")]
+    InvalidFeature(String, String),
 }
 
 #[derive(Debug, Error)]
@@ -534,6 +907,8 @@ pub enum ValidationError {
     MissingABI(String, String),
     #[error("missing module {0} in manifest used by command {1}")]
     MissingModuleForCommand(String, String),
+    #[error("the default-command {0} is not one of the package's commands")]
+    MissingDefaultCommand(String),
 }
 
 #[cfg(test)]
@@ -639,6 +1014,50 @@ mod dependency_tests {
         );
         assert_eq!(2, manifest.dependencies.as_ref().unwrap().len());
     }
+
+    #[test]
+    fn aliased_dependencies_resolve_to_the_real_package() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[dependencies]
+"owner/plain" = "0.1.0"
+mylib = { package = "owner/realpkg", version = "1.0" }
+"#;
+        let mut manifest: Manifest = toml::from_str(manifest_str).unwrap();
+        assert_eq!(
+            manifest.dependencies,
+            Some(
+                vec![
+                    ("owner/plain".to_string(), "0.1.0".to_string()),
+                    ("owner/realpkg".to_string(), "1.0".to_string()),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(manifest.dependency_alias("mylib"), Some("owner/realpkg"));
+        assert_eq!(manifest.dependency_alias("owner/plain"), None);
+
+        // updating the real package keeps the alias
+        manifest.add_dependency("owner/realpkg".to_string(), "1.1.0".to_string());
+        let round_tripped: Manifest = toml::from_str(&manifest.to_string().unwrap()).unwrap();
+        assert_eq!(round_tripped.dependencies, manifest.dependencies);
+        assert_eq!(
+            round_tripped.dependency_alias("mylib"),
+            Some("owner/realpkg")
+        );
+
+        assert_eq!(
+            manifest.remove_dependency("mylib"),
+            Some("1.1.0".to_string())
+        );
+        assert_eq!(manifest.dependency_alias("mylib"), None);
+        assert_eq!(manifest.dependencies.as_ref().unwrap().len(), 1);
+    }
 }
 
 #[cfg(test)]
@@ -683,13 +1102,69 @@ module = "mod"
                 abi: Abi::None,
                 kind: None,
                 interfaces: None,
+                interface_file: None,
                 #[cfg(feature = "package")]
                 fs: None,
                 bindings: Some(Bindings {
                     wit_exports: PathBuf::from("exports.wit"),
                     wit_bindgen: "0.0.0".parse().unwrap()
                 }),
+                platform: None,
+                arch: None,
             },
         );
     }
+
+    fn manifest_with_version(version: &str) -> Manifest {
+        toml::from_str(&format!(
+            r#"
+wapm-version = "{}"
+
+[package]
+name = "test"
+version = "0.0.0"
+description = "This is a test package"
+"#,
+            version
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn compatible_schema_version() {
+        let manifest = manifest_with_version("1.0");
+        assert_eq!(
+            manifest.schema_compatibility().unwrap(),
+            SchemaCompatibility::Current
+        );
+        manifest.validate().unwrap();
+
+        let mut unversioned = manifest;
+        unversioned.wapm_version = None;
+        assert_eq!(
+            unversioned.schema_compatibility().unwrap(),
+            SchemaCompatibility::Current
+        );
+    }
+
+    #[test]
+    fn newer_schema_version_is_a_warning() {
+        let manifest = manifest_with_version("1.7");
+        assert_eq!(
+            manifest.schema_compatibility().unwrap(),
+            SchemaCompatibility::Newer
+        );
+        manifest.validate().unwrap();
+    }
+
+    #[test]
+    fn incompatible_schema_version_is_an_error() {
+        let manifest = manifest_with_version("2.0");
+        let error = manifest.validate().unwrap_err();
+        assert!(matches!(
+            error,
+            ManifestError::IncompatibleSchemaVersion(ref version, 1) if version == "2.0"
+        ));
+        assert!(manifest_with_version("one").validate().is_err());
+    }
 }